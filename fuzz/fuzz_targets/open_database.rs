@@ -0,0 +1,20 @@
+#![no_main]
+
+use keepass_ng::{limits::OpenLimits, Database, DatabaseKey};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // the seeds of the corpus use this password, so that the fuzzer starts out with files that open
+    let key = DatabaseKey::new().with_password("demopass");
+
+    // keep the key derivation cheap, so that the fuzzer spends its time on the parsers
+    let limits = OpenLimits {
+        max_aes_rounds: Some(100_000),
+        max_argon2_memory: Some(1024 * 1024),
+        max_argon2_iterations: Some(10),
+        max_argon2_parallelism: Some(2),
+        ..OpenLimits::untrusted()
+    };
+
+    _ = Database::open_with_limits(&mut &data[..], key, &limits);
+});
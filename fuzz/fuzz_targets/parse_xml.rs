@@ -0,0 +1,8 @@
+#![no_main]
+
+use keepass_ng::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    _ = fuzzing::parse_xml(data);
+});
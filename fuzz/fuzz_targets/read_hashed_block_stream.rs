@@ -0,0 +1,9 @@
+#![no_main]
+
+use keepass_ng::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // the blocks of the corpus are hashed, so their mutations mostly exercise the error paths
+    _ = fuzzing::read_hashed_block_stream(data);
+});
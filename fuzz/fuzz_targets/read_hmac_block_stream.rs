@@ -0,0 +1,9 @@
+#![no_main]
+
+use keepass_ng::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // the key is fixed, so mutations of the blocks in the corpus mostly exercise the error paths
+    _ = fuzzing::read_hmac_block_stream(data);
+});
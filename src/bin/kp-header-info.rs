@@ -0,0 +1,57 @@
+/// utility to show the encryption settings of a `KeePass` database without the key
+use std::fs::File;
+
+use clap::Parser;
+
+use keepass_ng::{config::HeaderAlgorithm, BoxError, Database};
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Provide a .kdbx database
+    in_kdbx: String,
+
+    /// Print the settings as a JSON document
+    #[arg(short = 'j', long)]
+    json: bool,
+}
+
+pub fn main() -> Result<(), BoxError> {
+    let args = Args::parse();
+
+    let mut source = File::open(args.in_kdbx)?;
+
+    let info = Database::get_header_info(&mut source)?;
+
+    if args.json {
+        let stdout = std::io::stdout().lock();
+        serde_json::ser::to_writer(stdout, &info)?;
+        println!();
+        return Ok(());
+    }
+
+    println!("Version: {}", info.version);
+    match info.outer_cipher_config {
+        HeaderAlgorithm::Known(outer_cipher_config) => println!("Outer cipher: {:?}", outer_cipher_config),
+        HeaderAlgorithm::Unknown(id) => println!("Outer cipher: unknown ({})", hex::encode(id)),
+    }
+    println!("Compression: {:?}", info.compression_config);
+    match info.inner_cipher_config {
+        Some(inner_cipher_config) => println!("Inner cipher: {:?}", inner_cipher_config),
+        None => println!("Inner cipher: (in the encrypted inner header)"),
+    }
+    match info.kdf_config {
+        HeaderAlgorithm::Known(kdf_config) => println!("KDF: {:?}", kdf_config),
+        HeaderAlgorithm::Unknown(uuid) => println!("KDF: unknown ({})", hex::encode(uuid)),
+    }
+    if let Some(comment) = info.comment {
+        println!("Comment: {}", comment);
+    }
+    let mut public_custom_data: Vec<_> = info.public_custom_data.data.iter().collect();
+    public_custom_data.sort_by_key(|(key, _)| *key);
+    for (key, value) in public_custom_data {
+        println!("Public custom data: {} = {:?}", key, value);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+/// utility to generate a new `KeePass` 2.x XML keyfile (version 2.0)
+use clap::Parser;
+use keepass_ng::{BoxError, DatabaseKey};
+use std::fs::File;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Path of the keyfile to create, e.g. "database.keyx"
+    out_keyfile: String,
+
+    /// Overwrite the keyfile if it exists already
+    #[arg(short = 'f', long)]
+    force: bool,
+}
+
+pub fn main() -> Result<(), BoxError> {
+    let args = Args::parse();
+
+    let mut keyfile = if args.force {
+        File::create(&args.out_keyfile)?
+    } else {
+        File::options()
+            .write(true)
+            .create_new(true)
+            .open(&args.out_keyfile)
+            .map_err(|e| format!("Could not create {}: {e}", args.out_keyfile))?
+    };
+
+    DatabaseKey::new().with_generated_keyfile(&mut keyfile)?;
+    keyfile.sync_all()?;
+
+    println!("Generated keyfile {}", args.out_keyfile);
+    Ok(())
+}
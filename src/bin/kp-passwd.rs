@@ -0,0 +1,120 @@
+/// utility to change the master key (password, keyfile, Yubikey) of a database
+use clap::Parser;
+#[cfg(feature = "challenge_response")]
+use keepass_ng::ChallengeResponseKey;
+use keepass_ng::{BoxError, Database, DatabaseKey};
+use std::fs::File;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Provide a .kdbx database
+    in_kdbx: String,
+
+    /// Output file to write, the input database is replaced if omitted
+    out_kdbx: Option<String>,
+
+    /// Provide a keyfile
+    #[arg(short = 'k', long)]
+    keyfile: Option<String>,
+
+    /// Do not use a password to decrypt the database
+    #[arg(short = 'n', long)]
+    no_password: bool,
+
+    /// The slot number of the yubikey used to decrypt the database
+    #[cfg(feature = "challenge_response")]
+    #[arg(long)]
+    slot: Option<String>,
+
+    /// Use this keyfile in the new key
+    #[arg(long, conflicts_with = "generate_keyfile")]
+    new_keyfile: Option<String>,
+
+    /// Generate a new keyfile at this path and use it in the new key
+    #[arg(long)]
+    generate_keyfile: Option<String>,
+
+    /// Do not use a password in the new key
+    #[arg(long)]
+    new_no_password: bool,
+
+    /// The slot number of the yubikey to use in the new key
+    #[cfg(feature = "challenge_response")]
+    #[arg(long)]
+    new_slot: Option<String>,
+
+    /// The serial number of the yubikey
+    #[cfg(feature = "challenge_response")]
+    #[arg(long)]
+    serial_number: Option<u32>,
+
+    /// Keep a copy of the previous database next to it, with a .bak extension
+    #[arg(short = 'b', long)]
+    backup: bool,
+}
+
+pub fn main() -> Result<(), BoxError> {
+    let args = Args::parse();
+
+    let mut source = File::open(&args.in_kdbx)?;
+    let mut key = DatabaseKey::new();
+
+    if let Some(f) = &args.keyfile {
+        key = key.with_keyfile(&mut File::open(f)?)?;
+    }
+
+    if !args.no_password {
+        key = key.with_password_from_prompt("Current password: ")?;
+    }
+
+    #[cfg(feature = "challenge_response")]
+    if let Some(slot) = &args.slot {
+        let yubikey = ChallengeResponseKey::get_yubikey(args.serial_number)?;
+        key = key.with_challenge_response_key(ChallengeResponseKey::YubikeyChallenge(yubikey, slot.clone()));
+    }
+
+    if key.is_empty() {
+        return Err("No database key was provided.".into());
+    }
+
+    let mut db = Database::open(&mut source, key)?;
+
+    let mut new_key = DatabaseKey::new();
+
+    if let Some(f) = &args.new_keyfile {
+        new_key = new_key.with_keyfile(&mut File::open(f)?)?;
+    }
+
+    if let Some(f) = &args.generate_keyfile {
+        let mut keyfile = File::options()
+            .write(true)
+            .create_new(true)
+            .open(f)
+            .map_err(|e| format!("Could not create {f}: {e}"))?;
+        new_key = new_key.with_generated_keyfile(&mut keyfile)?;
+        keyfile.sync_all()?;
+        println!("Generated keyfile {f}");
+    }
+
+    if !args.new_no_password {
+        let password = rpassword::prompt_password("New password: ")?;
+        if password != rpassword::prompt_password("Repeat new password: ")? {
+            return Err("The new passwords do not match.".into());
+        }
+        new_key = new_key.with_password(&password);
+    }
+
+    #[cfg(feature = "challenge_response")]
+    if let Some(slot) = &args.new_slot {
+        let yubikey = ChallengeResponseKey::get_yubikey(args.serial_number)?;
+        new_key = new_key.with_challenge_response_key(ChallengeResponseKey::YubikeyChallenge(yubikey, slot.clone()));
+    }
+
+    let out_kdbx = args.out_kdbx.as_ref().unwrap_or(&args.in_kdbx);
+    db.rekey_and_save_to_path(out_kdbx, new_key, args.backup)?;
+
+    println!("The master key of the database was changed.");
+
+    Ok(())
+}
@@ -16,6 +16,10 @@ struct Args {
     /// Do not use a password to decrypt the database
     #[arg(short = 'n', long)]
     no_password: bool,
+
+    /// Keep a copy of the previous database next to it, with a .bak extension
+    #[arg(short = 'b', long)]
+    backup: bool,
 }
 
 pub fn main() -> Result<(), BoxError> {
@@ -40,7 +44,7 @@ pub fn main() -> Result<(), BoxError> {
 
     purge_history(&db.root)?;
 
-    db.save(&mut File::options().write(true).open(&args.in_kdbx)?, key)?;
+    db.save_to_path(&args.in_kdbx, key, args.backup)?;
 
     Ok(())
 }
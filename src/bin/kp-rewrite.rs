@@ -43,8 +43,7 @@ pub fn main() -> Result<(), BoxError> {
 
     let db = Database::open(&mut source, key.clone())?;
 
-    let mut out_file = File::create(args.out_kdbx)?;
-    db.save(&mut out_file, key)?;
+    db.save_to_path(args.out_kdbx, key, false)?;
 
     Ok(())
 }
@@ -46,15 +46,13 @@ pub fn main() -> Result<(), BoxError> {
         return Err("No database key was provided.".into());
     }
 
-    let db = Database::open(&mut source, key.clone())?;
+    let mut db = Database::open(&mut source, key.clone())?;
 
     let yubikey = ChallengeResponseKey::get_yubikey(args.serial_number)?;
 
     let new_key = key.with_challenge_response_key(ChallengeResponseKey::YubikeyChallenge(yubikey, args.slot));
 
-    let mut out_file = File::create(args.out_kdbx)?;
-
-    db.save(&mut out_file, new_key)?;
+    db.rekey_and_save_to_path(args.out_kdbx, new_key, false)?;
 
     println!("Yubikey was added to the database key.");
 
@@ -45,9 +45,7 @@ pub fn main() -> Result<(), BoxError> {
 
     let db = Database::open(&mut source, key.clone())?;
 
-    let mut out_file = File::create(args.out_kdbx)?;
-
-    db.save(&mut out_file, key_without_yubikey)?;
+    db.save_to_path(args.out_kdbx, key_without_yubikey, false)?;
 
     println!("Yubikey was removed from the database key.");
 
@@ -54,11 +54,9 @@ pub fn main() -> Result<(), BoxError> {
 
     key = key.with_challenge_response_key(ChallengeResponseKey::YubikeyChallenge(yubikey, args.slot));
 
-    let db = Database::open(&mut source, key.clone())?;
+    let mut db = Database::open(&mut source, key.clone())?;
 
-    let mut out_file = File::create(args.out_kdbx)?;
-
-    db.save(&mut out_file, key_without_yubikey)?;
+    db.rekey_and_save_to_path(args.out_kdbx, key_without_yubikey, false)?;
 
     println!("Yubikey was removed from the database key.");
 
@@ -3,9 +3,17 @@ use flate2::write::GzEncoder;
 use flate2::Compression as Flate2Compression;
 use std::io::{Read, Write};
 
+use crate::io::FinishWrite;
+
 pub trait Compression {
     fn compress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error>;
     fn decompress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error>;
+
+    /// Wrap a reader so that the data read from it is decompressed on the fly
+    fn decompress_reader<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a>;
+
+    /// Wrap a writer so that the data written to it is compressed on the fly
+    fn compress_writer<'a>(&self, writer: Box<dyn FinishWrite + 'a>) -> Box<dyn FinishWrite + 'a>;
 }
 
 pub struct NoCompression;
@@ -17,6 +25,12 @@ impl Compression for NoCompression {
     fn decompress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error> {
         Ok(in_buffer.to_vec())
     }
+    fn decompress_reader<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+        reader
+    }
+    fn compress_writer<'a>(&self, writer: Box<dyn FinishWrite + 'a>) -> Box<dyn FinishWrite + 'a> {
+        writer
+    }
 }
 
 pub struct GZipCompression;
@@ -36,4 +50,16 @@ impl Compression for GZipCompression {
         decoder.read_to_end(&mut res)?;
         Ok(res)
     }
+    fn decompress_reader<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+        Box::new(GzDecoder::new(reader))
+    }
+    fn compress_writer<'a>(&self, writer: Box<dyn FinishWrite + 'a>) -> Box<dyn FinishWrite + 'a> {
+        Box::new(GzEncoder::new(writer, Flate2Compression::default()))
+    }
+}
+
+impl<'a> FinishWrite for GzEncoder<Box<dyn FinishWrite + 'a>> {
+    fn finish(self: Box<Self>) -> Result<(), std::io::Error> {
+        GzEncoder::finish(*self)?.finish()
+    }
 }
@@ -1,7 +1,12 @@
 //! Configuration options for how to compress and encrypt databases
+use cipher::KeyIvInit;
 use hex_literal::hex;
 
-use std::convert::TryFrom;
+use std::{
+    convert::TryFrom,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
 
 pub use crate::format::DatabaseVersion;
 
@@ -13,10 +18,11 @@ use crate::{
     },
     error::{CompressionConfigError, CryptographyError, InnerCipherConfigError, KdfConfigError, OuterCipherConfigError},
     format::KDBX4_CURRENT_MINOR_VERSION,
+    registry,
     variant_dictionary::VariantDictionary,
 };
 
-const _CIPHERSUITE_AES128: [u8; 16] = hex!("61ab05a1946441c38d743a563df8dd35");
+const CIPHERSUITE_AES128: [u8; 16] = hex!("61ab05a1946441c38d743a563df8dd35");
 const CIPHERSUITE_AES256: [u8; 16] = hex!("31c1f2e6bf714350be5805216afc5aff");
 const CIPHERSUITE_TWOFISH: [u8; 16] = hex!("ad68f29f576f4bb9a36ad47af965346c");
 const CIPHERSUITE_CHACHA20: [u8; 16] = hex!("d6038a2b8b6f4cb5a524339a31dbb59a");
@@ -46,6 +52,89 @@ pub struct DatabaseConfig {
     pub kdf_config: KdfConfig,
 }
 
+/// The settings of a database that can be read from its outer header, without the key
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct DatabaseHeaderInfo {
+    /// Version of the outer database file
+    pub version: DatabaseVersion,
+
+    /// The encryption of the payload
+    pub outer_cipher_config: HeaderAlgorithm<OuterCipherConfig>,
+
+    /// The compression of the payload
+    pub compression_config: CompressionConfig,
+
+    /// The encryption of protected values, which KDBX4 only stores in the encrypted inner header
+    pub inner_cipher_config: Option<InnerCipherConfig>,
+
+    /// The key derivation function and its parameters
+    pub kdf_config: HeaderAlgorithm<KdfConfig>,
+
+    /// The comment field of KDBX headers, which is not written by common `KeePass` clients
+    pub comment: Option<String>,
+
+    /// The unencrypted custom data of KDBX4 databases, see [`Database::public_custom_data`]
+    ///
+    /// [`Database::public_custom_data`]: crate::db::Database::public_custom_data
+    pub public_custom_data: VariantDictionary,
+}
+
+/// A cipher or key derivation function named in the header of a database, which might be neither
+/// built in nor added to the [`registry`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum HeaderAlgorithm<T> {
+    /// An algorithm that databases can be opened with
+    Known(T),
+
+    /// The ID of an algorithm that is not known, e.g. of a plugin that is not registered
+    Unknown(Vec<u8>),
+}
+
+impl<T> HeaderAlgorithm<T> {
+    /// The algorithm, if it is known
+    pub fn known(&self) -> Option<&T> {
+        match self {
+            HeaderAlgorithm::Known(algorithm) => Some(algorithm),
+            HeaderAlgorithm::Unknown(_) => None,
+        }
+    }
+}
+
+impl HeaderAlgorithm<OuterCipherConfig> {
+    pub(crate) fn outer_cipher(id: &[u8]) -> Self {
+        OuterCipherConfig::try_from(id).map_or_else(|_| HeaderAlgorithm::Unknown(id.to_vec()), HeaderAlgorithm::Known)
+    }
+
+    /// The cipher, failing for an unknown one as when the database is opened
+    pub(crate) fn into_known(self) -> Result<OuterCipherConfig, OuterCipherConfigError> {
+        match self {
+            HeaderAlgorithm::Known(outer_cipher_config) => Ok(outer_cipher_config),
+            HeaderAlgorithm::Unknown(cid) => Err(OuterCipherConfigError::InvalidOuterCipherID { cid }),
+        }
+    }
+}
+
+impl HeaderAlgorithm<(KdfConfig, Vec<u8>)> {
+    /// The KDF with its seed from the KDF parameters of a KDBX4 header
+    pub(crate) fn kdf(parameters: VariantDictionary) -> Result<Self, KdfConfigError> {
+        match parameters.try_into() {
+            Ok(kdf) => Ok(HeaderAlgorithm::Known(kdf)),
+            Err(KdfConfigError::InvalidKDFUUID { uuid }) => Ok(HeaderAlgorithm::Unknown(uuid)),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The KDF and its seed, failing for an unknown KDF as when the database is opened
+    pub(crate) fn into_known(self) -> Result<(KdfConfig, Vec<u8>), KdfConfigError> {
+        match self {
+            HeaderAlgorithm::Known(kdf) => Ok(kdf),
+            HeaderAlgorithm::Unknown(uuid) => Err(KdfConfigError::InvalidKDFUUID { uuid }),
+        }
+    }
+}
+
 /// Sensible default configuration for new databases
 impl Default for DatabaseConfig {
     fn default() -> Self {
@@ -68,33 +157,83 @@ impl Default for DatabaseConfig {
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 pub enum OuterCipherConfig {
+    /// AES-128 in CBC mode, keyed with the first 16 bytes of the master key
+    AES128,
     AES256,
     Twofish,
     ChaCha20,
+    /// A cipher added with [`registry::register_outer_cipher`]
+    Registered(Uuid),
 }
 
 impl OuterCipherConfig {
-    pub(crate) fn get_cipher(&self, key: &[u8], iv: &[u8]) -> Result<Box<dyn ciphers::Cipher>, CryptographyError> {
+    fn registered(uuid: &Uuid) -> Result<registry::OuterCipher, OuterCipherConfigError> {
+        registry::outer_cipher(uuid).ok_or_else(|| OuterCipherConfigError::InvalidOuterCipherID {
+            cid: uuid.as_bytes().to_vec(),
+        })
+    }
+
+    pub(crate) fn get_cipher(&self, key: &[u8], iv: &[u8]) -> Result<Box<dyn ciphers::Cipher>, OuterCipherConfigError> {
         match self {
+            OuterCipherConfig::AES128 => Ok(Box::new(ciphers::AES128Cipher::new(key, iv))),
             OuterCipherConfig::AES256 => Ok(Box::new(ciphers::AES256Cipher::new(key, iv))),
             OuterCipherConfig::Twofish => Ok(Box::new(ciphers::TwofishCipher::new(key, iv))),
             OuterCipherConfig::ChaCha20 => Ok(Box::new(ciphers::ChaCha20Cipher::new_key_iv(key, iv)?)),
+            OuterCipherConfig::Registered(uuid) => Ok((Self::registered(uuid)?.factory)(key, iv)?),
+        }
+    }
+
+    /// Get a cipher decrypting the payload chunk by chunk
+    pub(crate) fn get_decryptor(&self, key: &[u8], iv: &[u8]) -> Result<Box<dyn ciphers::StreamingCipher>, OuterCipherConfigError> {
+        match self {
+            OuterCipherConfig::AES128 => Ok(Box::new(ciphers::CbcDecryptor::new(
+                cbc::Decryptor::<aes::Aes128>::new_from_slices(ciphers::AES128Cipher::key(key), iv).map_err(CryptographyError::from)?,
+            ))),
+            OuterCipherConfig::AES256 => Ok(Box::new(ciphers::CbcDecryptor::new(
+                cbc::Decryptor::<aes::Aes256>::new_from_slices(key, iv).map_err(CryptographyError::from)?,
+            ))),
+            OuterCipherConfig::Twofish => Ok(Box::new(ciphers::CbcDecryptor::new(
+                cbc::Decryptor::<twofish::Twofish>::new_from_slices(key, iv).map_err(CryptographyError::from)?,
+            ))),
+            OuterCipherConfig::ChaCha20 => Ok(Box::new(ciphers::ChaCha20Cipher::new_key_iv(key, iv)?)),
+            OuterCipherConfig::Registered(_) => Ok(Box::new(ciphers::BufferedDecryptor::new(self.get_cipher(key, iv)?))),
+        }
+    }
+
+    /// Get a cipher encrypting the payload chunk by chunk
+    pub(crate) fn get_encryptor(&self, key: &[u8], iv: &[u8]) -> Result<Box<dyn ciphers::StreamingCipher>, OuterCipherConfigError> {
+        match self {
+            OuterCipherConfig::AES128 => Ok(Box::new(ciphers::CbcEncryptor::new(
+                cbc::Encryptor::<aes::Aes128>::new_from_slices(ciphers::AES128Cipher::key(key), iv).map_err(CryptographyError::from)?,
+            ))),
+            OuterCipherConfig::AES256 => Ok(Box::new(ciphers::CbcEncryptor::new(
+                cbc::Encryptor::<aes::Aes256>::new_from_slices(key, iv).map_err(CryptographyError::from)?,
+            ))),
+            OuterCipherConfig::Twofish => Ok(Box::new(ciphers::CbcEncryptor::new(
+                cbc::Encryptor::<twofish::Twofish>::new_from_slices(key, iv).map_err(CryptographyError::from)?,
+            ))),
+            OuterCipherConfig::ChaCha20 => Ok(Box::new(ciphers::ChaCha20Cipher::new_key_iv(key, iv)?)),
+            OuterCipherConfig::Registered(_) => Ok(Box::new(ciphers::BufferedEncryptor::new(self.get_cipher(key, iv)?))),
         }
     }
 
-    pub(crate) fn get_iv_size(&self) -> usize {
+    pub(crate) fn get_iv_size(&self) -> Result<usize, OuterCipherConfigError> {
         match self {
-            OuterCipherConfig::AES256 => ciphers::AES256Cipher::iv_size(),
-            OuterCipherConfig::Twofish => ciphers::TwofishCipher::iv_size(),
-            OuterCipherConfig::ChaCha20 => ciphers::ChaCha20Cipher::iv_size(),
+            OuterCipherConfig::AES128 => Ok(ciphers::AES128Cipher::iv_size()),
+            OuterCipherConfig::AES256 => Ok(ciphers::AES256Cipher::iv_size()),
+            OuterCipherConfig::Twofish => Ok(ciphers::TwofishCipher::iv_size()),
+            OuterCipherConfig::ChaCha20 => Ok(ciphers::ChaCha20Cipher::iv_size()),
+            OuterCipherConfig::Registered(uuid) => Ok(Self::registered(uuid)?.iv_size),
         }
     }
 
     pub(crate) fn dump(&self) -> [u8; 16] {
         match self {
+            OuterCipherConfig::AES128 => CIPHERSUITE_AES128,
             OuterCipherConfig::AES256 => CIPHERSUITE_AES256,
             OuterCipherConfig::Twofish => CIPHERSUITE_TWOFISH,
             OuterCipherConfig::ChaCha20 => CIPHERSUITE_CHACHA20,
+            OuterCipherConfig::Registered(uuid) => *uuid.as_bytes(),
         }
     }
 }
@@ -102,14 +241,19 @@ impl OuterCipherConfig {
 impl TryFrom<&[u8]> for OuterCipherConfig {
     type Error = OuterCipherConfigError;
     fn try_from(v: &[u8]) -> Result<OuterCipherConfig, Self::Error> {
-        if v == CIPHERSUITE_AES256 {
+        if v == CIPHERSUITE_AES128 {
+            Ok(OuterCipherConfig::AES128)
+        } else if v == CIPHERSUITE_AES256 {
             Ok(OuterCipherConfig::AES256)
         } else if v == CIPHERSUITE_TWOFISH {
             Ok(OuterCipherConfig::Twofish)
         } else if v == CIPHERSUITE_CHACHA20 {
             Ok(OuterCipherConfig::ChaCha20)
         } else {
-            Err(OuterCipherConfigError::InvalidOuterCipherID { cid: v.to_vec() })
+            match Uuid::from_slice(v) {
+                Ok(uuid) if registry::outer_cipher(&uuid).is_some() => Ok(OuterCipherConfig::Registered(uuid)),
+                _ => Err(OuterCipherConfigError::InvalidOuterCipherID { cid: v.to_vec() }),
+            }
         }
     }
 }
@@ -121,14 +265,21 @@ pub enum InnerCipherConfig {
     Plain,
     Salsa20,
     ChaCha20,
+    /// A cipher added with [`registry::register_inner_cipher`]
+    Registered(u32),
 }
 
 impl InnerCipherConfig {
-    pub(crate) fn get_cipher(&self, key: &[u8]) -> Box<dyn ciphers::Cipher> {
+    fn registered(id: u32) -> Result<registry::InnerCipher, InnerCipherConfigError> {
+        registry::inner_cipher(id).ok_or(InnerCipherConfigError::InvalidInnerCipherID { cid: id })
+    }
+
+    pub(crate) fn get_cipher(&self, key: &[u8]) -> Result<Box<dyn ciphers::Cipher>, InnerCipherConfigError> {
         match self {
-            InnerCipherConfig::Plain => Box::new(ciphers::PlainCipher::new(key)),
-            InnerCipherConfig::Salsa20 => Box::new(ciphers::Salsa20Cipher::new(key)),
-            InnerCipherConfig::ChaCha20 => Box::new(ciphers::ChaCha20Cipher::new(key)),
+            InnerCipherConfig::Plain => Ok(Box::new(ciphers::PlainCipher::new(key))),
+            InnerCipherConfig::Salsa20 => Ok(Box::new(ciphers::Salsa20Cipher::new(key))),
+            InnerCipherConfig::ChaCha20 => Ok(Box::new(ciphers::ChaCha20Cipher::new(key))),
+            InnerCipherConfig::Registered(id) => Ok((Self::registered(*id)?.factory)(key)?),
         }
     }
 
@@ -137,14 +288,16 @@ impl InnerCipherConfig {
             InnerCipherConfig::Plain => PLAIN,
             InnerCipherConfig::Salsa20 => SALSA_20,
             InnerCipherConfig::ChaCha20 => CHA_CHA_20,
+            InnerCipherConfig::Registered(id) => *id,
         }
     }
 
-    pub(crate) fn get_key_size(&self) -> usize {
+    pub(crate) fn get_key_size(&self) -> Result<usize, InnerCipherConfigError> {
         match self {
-            InnerCipherConfig::Plain => ciphers::PlainCipher::key_size(),
-            InnerCipherConfig::Salsa20 => ciphers::Salsa20Cipher::key_size(),
-            InnerCipherConfig::ChaCha20 => ciphers::ChaCha20Cipher::key_size(),
+            InnerCipherConfig::Plain => Ok(ciphers::PlainCipher::key_size()),
+            InnerCipherConfig::Salsa20 => Ok(ciphers::Salsa20Cipher::key_size()),
+            InnerCipherConfig::ChaCha20 => Ok(ciphers::ChaCha20Cipher::key_size()),
+            InnerCipherConfig::Registered(id) => Ok(Self::registered(*id)?.key_size),
         }
     }
 }
@@ -157,6 +310,7 @@ impl TryFrom<u32> for InnerCipherConfig {
             PLAIN => Ok(InnerCipherConfig::Plain),
             SALSA_20 => Ok(InnerCipherConfig::Salsa20),
             CHA_CHA_20 => Ok(InnerCipherConfig::ChaCha20),
+            _ if registry::inner_cipher(v).is_some() => Ok(InnerCipherConfig::Registered(v)),
             _ => Err(InnerCipherConfigError::InvalidInnerCipherID { cid: v }),
         }
     }
@@ -174,6 +328,30 @@ const KDF_VERSION: &str = "V";
 const KDF_SEED: &str = "S";
 const KDF_ROUNDS: &str = "R";
 
+/// Number of AES rounds to start calibrating with
+const AES_CALIBRATION_ROUNDS: u64 = 10_000;
+/// Lower bound for the Argon2 memory when calibrating
+const ARGON2_CALIBRATION_MIN_MEMORY: u64 = 1024 * 1024;
+
+/// Find the cost parameter of a KDF that makes `run` take about `target`. The cost is doubled from
+/// `start` until a run takes a noticeable part of `target`, then scaled linearly.
+fn calibrate_cost(
+    target: Duration,
+    start: u64,
+    mut run: impl FnMut(u64) -> Result<Duration, CryptographyError>,
+) -> Result<u64, CryptographyError> {
+    let mut cost = start.max(1);
+    loop {
+        let elapsed = run(cost)?;
+        if elapsed >= target / 8 || cost >= u64::MAX / 2 {
+            let scaled = cost as f64 * target.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON);
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            return Ok((scaled as u64).max(1));
+        }
+        cost *= 2;
+    }
+}
+
 /// Choices for Key Derivation Functions (KDFs)
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -198,6 +376,9 @@ pub enum KdfConfig {
         #[cfg_attr(feature = "serialization", serde(serialize_with = "serialize_argon2_version"))]
         version: argon2::Version,
     },
+    /// Derive keys with a KDF added with [`registry::register_kdf`], with its parameters apart
+    /// from the UUID and the seed
+    Registered { uuid: Uuid, parameters: VariantDictionary },
 }
 
 #[cfg(feature = "serialization")]
@@ -214,23 +395,20 @@ impl KdfConfig {
             KdfConfig::Aes { .. } => 32,
             KdfConfig::Argon2 { .. } => 32,
             KdfConfig::Argon2id { .. } => 32,
+            KdfConfig::Registered { .. } => 32,
         }
     }
 
-    /// For writing out a database, generate a new KDF seed from the config and return the KDF
-    /// and the generated seed
-    pub(crate) fn get_kdf_and_seed(&self) -> Result<(Box<dyn kdf::Kdf>, Vec<u8>), getrandom::Error> {
+    /// For writing out a database, generate a new KDF seed
+    pub(crate) fn generate_seed(&self) -> Result<Vec<u8>, getrandom::Error> {
         let mut kdf_seed = vec![0; self.seed_size()];
         getrandom::getrandom(&mut kdf_seed)?;
-
-        let kdf = self.get_kdf_seeded(&kdf_seed);
-
-        Ok((kdf, kdf_seed))
+        Ok(kdf_seed)
     }
 
     /// For reading a database, generate a KDF from the KDF config and a provided seed
-    pub(crate) fn get_kdf_seeded(&self, seed: &[u8]) -> Box<dyn kdf::Kdf> {
-        match self {
+    pub(crate) fn get_kdf_seeded(&self, seed: &[u8]) -> Result<Box<dyn kdf::Kdf>, KdfConfigError> {
+        Ok(match self {
             KdfConfig::Aes { rounds } => Box::new(kdf::AesKdf {
                 seed: seed.to_vec(),
                 rounds: *rounds,
@@ -261,13 +439,94 @@ impl KdfConfig {
                 version: *version,
                 variant: argon2::Variant::Argon2id,
             }),
+            KdfConfig::Registered { uuid, .. } => {
+                let factory = registry::kdf(uuid).ok_or_else(|| KdfConfigError::InvalidKDFUUID {
+                    uuid: uuid.as_bytes().to_vec(),
+                })?;
+                factory(&self.to_variant_dictionary(seed))?
+            }
+        })
+    }
+
+    /// Tune the parameters of this KDF so that transforming a key takes about `target` on the
+    /// current machine, like the "1 second delay" button of `KeePass`.
+    ///
+    /// For AES, the number of rounds is adjusted. For Argon2, the memory is capped at `max_memory`
+    /// bytes and halved further while a single iteration exceeds `target`, then the number of
+    /// iterations is adjusted. The parallelism and version are kept. Registered KDFs are returned
+    /// unchanged.
+    ///
+    /// Fails if `max_memory` is below the 8 KiB per lane that Argon2 needs at least.
+    pub fn calibrate(&self, target: Duration, max_memory: u64) -> Result<KdfConfig, CryptographyError> {
+        match self {
+            KdfConfig::Registered { .. } => Ok(self.clone()),
+            KdfConfig::Aes { .. } => {
+                let rounds = calibrate_cost(target, AES_CALIBRATION_ROUNDS, |rounds| KdfConfig::Aes { rounds }.benchmark())?;
+                Ok(KdfConfig::Aes { rounds })
+            }
+            KdfConfig::Argon2 {
+                memory,
+                parallelism,
+                version,
+                ..
+            }
+            | KdfConfig::Argon2id {
+                memory,
+                parallelism,
+                version,
+                ..
+            } => {
+                let with_params = |iterations, memory| match self {
+                    KdfConfig::Argon2id { .. } => KdfConfig::Argon2id {
+                        iterations,
+                        memory,
+                        parallelism: *parallelism,
+                        version: *version,
+                    },
+                    _ => KdfConfig::Argon2 {
+                        iterations,
+                        memory,
+                        parallelism: *parallelism,
+                        version: *version,
+                    },
+                };
+
+                // Argon2 needs at least 8 KiB per lane, which cannot be capped any further
+                let lane_memory = u64::from(*parallelism) * 8 * 1024;
+                if max_memory < lane_memory {
+                    return Err(argon2::Error::MemoryTooLittle.into());
+                }
+                let min_memory = ARGON2_CALIBRATION_MIN_MEMORY.min(max_memory).max(lane_memory);
+                let mut memory = (*memory).max(min_memory).min(max_memory);
+                memory = (memory / 1024 * 1024).max(lane_memory);
+                while memory / 2 >= min_memory && with_params(1, memory).benchmark()? > target {
+                    memory = memory / 2 / 1024 * 1024;
+                }
+
+                let iterations = calibrate_cost(target, 1, |iterations| with_params(iterations, memory).benchmark())?;
+                Ok(with_params(iterations, memory))
+            }
         }
     }
 
+    /// Measure the time it takes to transform a key with this KDF
+    fn benchmark(&self) -> Result<Duration, CryptographyError> {
+        // the seed does not affect the running time
+        let kdf = self.get_kdf_seeded(&[0; 32]).map_err(|e| CryptographyError::Other(e.into()))?;
+        let start = Instant::now();
+        kdf.transform_key(&Default::default())?;
+        Ok(start.elapsed())
+    }
+
     pub(crate) fn to_variant_dictionary(&self, seed: &[u8]) -> VariantDictionary {
         let mut vd = VariantDictionary::new();
 
         match self {
+            KdfConfig::Registered { uuid, parameters } => {
+                vd = parameters.clone();
+                vd.set(KDF_ID, uuid.as_bytes().to_vec());
+                vd.set(KDF_SEED, seed.to_vec());
+            }
             KdfConfig::Aes { rounds } => {
                 vd.set(KDF_ID, KDF_AES_KDBX4.to_vec());
                 vd.set(KDF_ROUNDS, *rounds);
@@ -366,7 +625,16 @@ impl TryFrom<VariantDictionary> for (KdfConfig, Vec<u8>) {
 
             Ok((KdfConfig::Aes { rounds }, seed))
         } else {
-            Err(KdfConfigError::InvalidKDFUUID { uuid: uuid.clone() })
+            let uuid = match Uuid::from_slice(uuid) {
+                Ok(uuid) if registry::kdf(&uuid).is_some() => uuid,
+                _ => return Err(KdfConfigError::InvalidKDFUUID { uuid: uuid.clone() }),
+            };
+            let seed: Vec<u8> = vd.get::<Vec<u8>>(KDF_SEED)?.clone();
+
+            let mut parameters = vd;
+            parameters.data.remove(KDF_ID);
+            parameters.data.remove(KDF_SEED);
+            Ok((KdfConfig::Registered { uuid, parameters }, seed))
         }
     }
 }
@@ -406,3 +674,50 @@ impl TryFrom<u32> for CompressionConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod config_tests {
+    use std::time::Duration;
+
+    use super::KdfConfig;
+    use crate::error::CryptographyError;
+
+    #[test]
+    fn calibrate_aes() {
+        let config = KdfConfig::Aes { rounds: 1 }.calibrate(Duration::from_millis(50), 0).unwrap();
+        assert!(matches!(config, KdfConfig::Aes { rounds } if rounds > 1));
+    }
+
+    #[test]
+    fn calibrate_argon2() {
+        let config = KdfConfig::Argon2id {
+            iterations: 100,
+            memory: 1024 * 1024 * 1024,
+            parallelism: 2,
+            version: argon2::Version::Version13,
+        };
+        let calibrated = config.calibrate(Duration::from_millis(50), 8 * 1024 * 1024).unwrap();
+
+        let KdfConfig::Argon2id {
+            iterations,
+            memory,
+            parallelism,
+            version,
+        } = calibrated
+        else {
+            panic!("KDF type changed: {calibrated:?}");
+        };
+        assert!(iterations >= 1);
+        assert!((1024 * 1024..=8 * 1024 * 1024).contains(&memory));
+        assert_eq!(memory % 1024, 0);
+        assert_eq!(parallelism, 2);
+        assert_eq!(version, argon2::Version::Version13);
+
+        // the ceiling wins over the minimum memory of the calibration
+        let calibrated = config.calibrate(Duration::from_millis(50), 64 * 1024).unwrap();
+        assert!(matches!(calibrated, KdfConfig::Argon2id { memory, .. } if memory <= 64 * 1024));
+
+        let result = config.calibrate(Duration::from_millis(50), 8 * 1024);
+        assert!(matches!(result, Err(CryptographyError::Argon2(argon2::Error::MemoryTooLittle))));
+    }
+}
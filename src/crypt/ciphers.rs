@@ -1,13 +1,21 @@
-use aes::Aes256;
-use cipher::{block_padding::Pkcs7, generic_array::GenericArray, BlockDecryptMut, BlockEncryptMut};
+use std::io::{Read, Write};
+
+use aes::{Aes128, Aes256};
+use cipher::{
+    block_padding::{Padding, Pkcs7, UnpadError},
+    generic_array::GenericArray,
+    BlockDecryptMut, BlockEncryptMut,
+};
 use salsa20::{
     cipher::{KeyIvInit, StreamCipher},
     Salsa20,
 };
 
-use crate::crypt::CryptographyError;
+use crate::{crypt::CryptographyError, io::FinishWrite};
 
-pub(crate) trait Cipher {
+/// A cipher for the payload or the protected values of a database, which can be added to the
+/// [`registry`](crate::registry)
+pub trait Cipher {
     fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, CryptographyError>;
     fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, CryptographyError>;
 
@@ -22,6 +30,49 @@ pub(crate) trait Cipher {
         Self: Sized;
 }
 
+type Aes128CbcEncryptor = cbc::Encryptor<Aes128>;
+type Aes128CbcDecryptor = cbc::Decryptor<Aes128>;
+pub(crate) struct AES128Cipher {
+    key: Vec<u8>,
+    iv: Vec<u8>,
+}
+
+impl AES128Cipher {
+    pub(crate) fn new(key: &[u8], iv: &[u8]) -> Self {
+        AES128Cipher {
+            key: Vec::from(AES128Cipher::key(key)),
+            iv: Vec::from(iv),
+        }
+    }
+
+    /// The master key has 32 bytes, of which AES-128 uses the first 16
+    pub(crate) fn key(key: &[u8]) -> &[u8] {
+        &key[..key.len().min(16)]
+    }
+}
+
+impl Cipher for AES128Cipher {
+    fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        let cipher = Aes128CbcEncryptor::new_from_slices(&self.key, &self.iv)?;
+
+        Ok(cipher.encrypt_padded_vec_mut::<Pkcs7>(plaintext))
+    }
+
+    fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        let cipher = Aes128CbcDecryptor::new_from_slices(&self.key, &self.iv)?;
+
+        Ok(cipher.decrypt_padded_vec_mut::<Pkcs7>(ciphertext)?)
+    }
+
+    fn iv_size() -> usize {
+        16
+    }
+
+    fn key_size() -> usize {
+        16
+    }
+}
+
 type Aes256CbcEncryptor = cbc::Encryptor<Aes256>;
 type Aes256CbcDecryptor = cbc::Decryptor<Aes256>;
 pub(crate) struct AES256Cipher {
@@ -214,3 +265,240 @@ impl Cipher for PlainCipher {
         1
     }
 }
+
+/// A cipher processing its input in chunks, so that a payload never has to be held in memory at once
+pub(crate) trait StreamingCipher {
+    /// Process the next chunk of input, returning the output that is available so far
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, CryptographyError>;
+
+    /// Process the end of the input, returning the remaining output
+    fn finish(&mut self) -> Result<Vec<u8>, CryptographyError>;
+}
+
+/// CBC decryption with PKCS7 padding. The last block is held back until the end of the input,
+/// since only then it is known to contain the padding.
+pub(crate) struct CbcDecryptor<D: BlockDecryptMut> {
+    decryptor: D,
+    pending: Vec<u8>,
+}
+
+impl<D: BlockDecryptMut> CbcDecryptor<D> {
+    pub(crate) fn new(decryptor: D) -> Self {
+        CbcDecryptor {
+            decryptor,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<D: BlockDecryptMut> StreamingCipher for CbcDecryptor<D> {
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        self.pending.extend_from_slice(data);
+
+        let block_size = D::block_size();
+        let available = self.pending.len().saturating_sub(1) / block_size * block_size;
+        let mut out: Vec<u8> = self.pending.drain(..available).collect();
+        for block in out.chunks_exact_mut(block_size) {
+            self.decryptor.decrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        Ok(out)
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, CryptographyError> {
+        if self.pending.len() != D::block_size() {
+            return Err(UnpadError.into());
+        }
+        let block = GenericArray::from_mut_slice(&mut self.pending);
+        self.decryptor.decrypt_block_mut(block);
+        let out = Pkcs7::unpad(block)?.to_vec();
+        self.pending.clear();
+        Ok(out)
+    }
+}
+
+/// CBC encryption with PKCS7 padding
+pub(crate) struct CbcEncryptor<E: BlockEncryptMut> {
+    encryptor: E,
+    pending: Vec<u8>,
+}
+
+impl<E: BlockEncryptMut> CbcEncryptor<E> {
+    pub(crate) fn new(encryptor: E) -> Self {
+        CbcEncryptor {
+            encryptor,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<E: BlockEncryptMut> StreamingCipher for CbcEncryptor<E> {
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        self.pending.extend_from_slice(data);
+
+        let block_size = E::block_size();
+        let available = self.pending.len() / block_size * block_size;
+        let mut out: Vec<u8> = self.pending.drain(..available).collect();
+        for block in out.chunks_exact_mut(block_size) {
+            self.encryptor.encrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        Ok(out)
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, CryptographyError> {
+        let position = self.pending.len();
+        self.pending.resize(E::block_size(), 0);
+        let block = GenericArray::from_mut_slice(&mut self.pending);
+        Pkcs7::pad(block, position);
+        self.encryptor.encrypt_block_mut(block);
+        Ok(std::mem::take(&mut self.pending))
+    }
+}
+
+/// Decryption with a cipher that cannot process its input in chunks, so the whole input is
+/// collected until the end
+pub(crate) struct BufferedDecryptor {
+    cipher: Box<dyn Cipher>,
+    pending: Vec<u8>,
+}
+
+impl BufferedDecryptor {
+    pub(crate) fn new(cipher: Box<dyn Cipher>) -> Self {
+        BufferedDecryptor {
+            cipher,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl StreamingCipher for BufferedDecryptor {
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        self.pending.extend_from_slice(data);
+        Ok(Vec::new())
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, CryptographyError> {
+        self.cipher.decrypt(&std::mem::take(&mut self.pending))
+    }
+}
+
+/// Encryption with a cipher that cannot process its input in chunks, so the whole input is
+/// collected until the end
+pub(crate) struct BufferedEncryptor {
+    cipher: Box<dyn Cipher>,
+    pending: Vec<u8>,
+}
+
+impl BufferedEncryptor {
+    pub(crate) fn new(cipher: Box<dyn Cipher>) -> Self {
+        BufferedEncryptor {
+            cipher,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl StreamingCipher for BufferedEncryptor {
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        self.pending.extend_from_slice(data);
+        Ok(Vec::new())
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, CryptographyError> {
+        self.cipher.encrypt(&std::mem::take(&mut self.pending))
+    }
+}
+
+impl StreamingCipher for ChaCha20Cipher {
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+        self.decrypt(data)
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, CryptographyError> {
+        Ok(Vec::new())
+    }
+}
+
+/// Reader decrypting the data of an underlying reader
+pub(crate) struct CipherReader<R: Read> {
+    inner: R,
+    cipher: Box<dyn StreamingCipher>,
+    buffer: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> CipherReader<R> {
+    pub(crate) fn new(inner: R, cipher: Box<dyn StreamingCipher>) -> Self {
+        CipherReader {
+            inner,
+            cipher,
+            buffer: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<R: Read> Read for CipherReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let mut chunk = [0u8; CIPHER_CHUNK_SIZE];
+        while self.position == self.buffer.len() && !self.finished {
+            let count = self.inner.read(&mut chunk)?;
+            self.buffer = if count == 0 {
+                self.finished = true;
+                self.cipher.finish()
+            } else {
+                self.cipher.update(&chunk[..count])
+            }
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            self.position = 0;
+        }
+
+        let count = buf.len().min(self.buffer.len() - self.position);
+        buf[..count].copy_from_slice(&self.buffer[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// Writer encrypting its input into an underlying writer
+pub(crate) struct CipherWriter<'a> {
+    inner: Box<dyn FinishWrite + 'a>,
+    cipher: Box<dyn StreamingCipher>,
+}
+
+impl<'a> CipherWriter<'a> {
+    pub(crate) fn new(inner: Box<dyn FinishWrite + 'a>, cipher: Box<dyn StreamingCipher>) -> Self {
+        CipherWriter { inner, cipher }
+    }
+}
+
+impl Write for CipherWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        // encrypt at most one chunk at a time, so that large writes are not copied as a whole
+        let chunk = &buf[..buf.len().min(CIPHER_CHUNK_SIZE)];
+        let out = self
+            .cipher
+            .update(chunk)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        self.inner.write_all(&out)?;
+        Ok(chunk.len())
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.inner.flush()
+    }
+}
+
+impl FinishWrite for CipherWriter<'_> {
+    fn finish(mut self: Box<Self>) -> Result<(), std::io::Error> {
+        let out = self
+            .cipher
+            .finish()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        self.inner.write_all(&out)?;
+        self.inner.finish()
+    }
+}
+
+const CIPHER_CHUNK_SIZE: usize = 64 * 1024;
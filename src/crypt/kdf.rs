@@ -4,11 +4,45 @@ use cipher::{
     BlockEncrypt, KeyInit,
 };
 use sha2::{Digest, Sha256};
+use std::{
+    sync::{mpsc, Mutex, PoisonError},
+    thread,
+    time::Duration,
+};
+use zeroize::Zeroizing;
 
 use super::CryptographyError;
 
-pub(crate) trait Kdf {
+/// Interval at which the progress of the AES-KDF is reported
+const AES_PROGRESS_ROUNDS: u64 = 1 << 16;
+
+/// Interval at which a running Argon2 transformation checks for cancellation
+const ARGON2_PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+
+type Argon2Result = Result<GenericArray<u8, U32>, CryptographyError>;
+
+/// Cancelled Argon2 transformations that are still running in the background, by the hash of
+/// their parameters and key, so that a retry picks them up instead of starting another one
+static PENDING_ARGON2: Mutex<Vec<([u8; 32], mpsc::Receiver<Argon2Result>)>> = Mutex::new(Vec::new());
+
+/// A key derivation function transforming the composite key of a database, which can be added to
+/// the [`registry`](crate::registry)
+pub trait Kdf {
     fn transform_key(&self, composite_key: &GenericArray<u8, U32>) -> Result<GenericArray<u8, U32>, CryptographyError>;
+
+    /// Transform the key like `transform_key`, calling `progress` with the completed and the total
+    /// amount of work along the way. Returns `None` as soon as `progress` returns `false`.
+    fn transform_key_with_progress(
+        &self,
+        composite_key: &GenericArray<u8, U32>,
+        progress: &mut dyn FnMut(u64, u64) -> bool,
+    ) -> Result<Option<GenericArray<u8, U32>>, CryptographyError> {
+        if !progress(0, 1) {
+            return Ok(None);
+        }
+        let key = self.transform_key(composite_key)?;
+        Ok(progress(1, 1).then_some(key))
+    }
 }
 
 pub struct AesKdf {
@@ -18,12 +52,34 @@ pub struct AesKdf {
 
 impl Kdf for AesKdf {
     fn transform_key(&self, composite_key: &GenericArray<u8, U32>) -> Result<GenericArray<u8, U32>, CryptographyError> {
-        let cipher = Aes256::new(&GenericArray::clone_from_slice(&self.seed));
+        // the transformation only stops early if the progress callback asks for it
+        Ok(self
+            .transform_key_with_progress(composite_key, &mut |_, _| true)?
+            .unwrap_or_default())
+    }
+
+    fn transform_key_with_progress(
+        &self,
+        composite_key: &GenericArray<u8, U32>,
+        progress: &mut dyn FnMut(u64, u64) -> bool,
+    ) -> Result<Option<GenericArray<u8, U32>>, CryptographyError> {
+        let cipher = Aes256::new_from_slice(&self.seed)?;
         let mut block1 = GenericArray::clone_from_slice(&composite_key[..16]);
         let mut block2 = GenericArray::clone_from_slice(&composite_key[16..]);
-        for _ in 0..self.rounds {
-            cipher.encrypt_block(&mut block1);
-            cipher.encrypt_block(&mut block2);
+        let mut completed = 0;
+        while completed < self.rounds {
+            if !progress(completed, self.rounds) {
+                return Ok(None);
+            }
+            let rounds = (self.rounds - completed).min(AES_PROGRESS_ROUNDS);
+            for _ in 0..rounds {
+                cipher.encrypt_block(&mut block1);
+                cipher.encrypt_block(&mut block2);
+            }
+            completed += rounds;
+        }
+        if !progress(self.rounds, self.rounds) {
+            return Ok(None);
         }
 
         let mut digest = Sha256::new();
@@ -31,7 +87,7 @@ impl Kdf for AesKdf {
         digest.update(block1);
         digest.update(block2);
 
-        Ok(digest.finalize())
+        Ok(Some(digest.finalize()))
     }
 }
 
@@ -58,10 +114,91 @@ impl Kdf for Argon2Kdf {
             version: self.version,
         };
 
-        let key = argon2::hash_raw(composite_key, &self.salt, &config)?;
+        let key = Zeroizing::new(argon2::hash_raw(composite_key, &self.salt, &config)?);
 
         Ok(*GenericArray::from_slice(&key))
     }
+
+    /// Argon2 cannot be interrupted, so the transformation runs on a separate thread while the
+    /// progress callback is polled.
+    ///
+    /// Cancelling only detaches the thread: it keeps running and holds on to the memory of the
+    /// transformation until it is done. Transforming the same key with the same parameters again,
+    /// e.g. when opening the same database again, waits for that thread instead of starting another one.
+    fn transform_key_with_progress(
+        &self,
+        composite_key: &GenericArray<u8, U32>,
+        progress: &mut dyn FnMut(u64, u64) -> bool,
+    ) -> Result<Option<GenericArray<u8, U32>>, CryptographyError> {
+        if !progress(0, 1) {
+            return Ok(None);
+        }
+
+        let id = self.transformation_id(composite_key);
+        let receiver = match take_pending_argon2(&id) {
+            Some(receiver) => receiver,
+            None => {
+                let kdf = Argon2Kdf {
+                    salt: self.salt.clone(),
+                    ..*self
+                };
+                // the thread gets its own copy of the key, which is wiped once the thread is done
+                let thread_key = Zeroizing::new(<[u8; 32]>::from(*composite_key));
+                let (sender, receiver) = mpsc::channel();
+                let spawned = thread::Builder::new().spawn(move || {
+                    _ = sender.send(kdf.transform_key(GenericArray::from_slice(thread_key.as_slice())));
+                });
+                if spawned.is_err() {
+                    // no threads on this platform
+                    let key = self.transform_key(composite_key)?;
+                    return Ok(progress(1, 1).then_some(key));
+                }
+                receiver
+            }
+        };
+
+        loop {
+            match receiver.recv_timeout(ARGON2_PROGRESS_INTERVAL) {
+                Ok(key) => {
+                    let key = key?;
+                    return Ok(progress(1, 1).then_some(key));
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if !progress(0, 1) {
+                        PENDING_ARGON2.lock().unwrap_or_else(PoisonError::into_inner).push((id, receiver));
+                        return Ok(None);
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    // the thread panicked, run the transformation here to surface the error
+                    let key = self.transform_key(composite_key)?;
+                    return Ok(progress(1, 1).then_some(key));
+                }
+            }
+        }
+    }
+}
+
+impl Argon2Kdf {
+    /// Identify a transformation by a hash of its parameters, salt and key
+    fn transformation_id(&self, composite_key: &GenericArray<u8, U32>) -> [u8; 32] {
+        let mut digest = Sha256::new();
+        digest.update(self.memory.to_le_bytes());
+        digest.update(self.iterations.to_le_bytes());
+        digest.update(self.parallelism.to_le_bytes());
+        digest.update(self.version.as_u32().to_le_bytes());
+        digest.update(self.variant.as_u32().to_le_bytes());
+        digest.update(&self.salt);
+        digest.update(composite_key);
+        digest.finalize().into()
+    }
+}
+
+/// Take the cancelled transformation `id` if it is still pending
+fn take_pending_argon2(id: &[u8; 32]) -> Option<mpsc::Receiver<Argon2Result>> {
+    let mut pending = PENDING_ARGON2.lock().unwrap_or_else(PoisonError::into_inner);
+    let index = pending.iter().position(|(pending_id, _)| pending_id == id)?;
+    Some(pending.swap_remove(index).1)
 }
 
 /*
@@ -75,3 +212,41 @@ pub(crate) fn transform_key_argon2(
     };
 }
 */
+
+#[cfg(test)]
+mod kdf_tests {
+    use super::{Argon2Kdf, Kdf, PENDING_ARGON2};
+    use cipher::generic_array::GenericArray;
+
+    fn is_pending(id: &[u8; 32]) -> bool {
+        PENDING_ARGON2.lock().unwrap().iter().any(|(pending_id, _)| pending_id == id)
+    }
+
+    #[test]
+    fn retry_cancelled_argon2() {
+        let kdf = Argon2Kdf {
+            memory: 64 * 1024 * 1024,
+            salt: b"retry_cancelled_argon2".to_vec(),
+            iterations: 4,
+            parallelism: 1,
+            version: argon2::Version::Version13,
+            variant: argon2::Variant::Argon2id,
+        };
+        let composite_key = GenericArray::from([7u8; 32]);
+        let id = kdf.transformation_id(&composite_key);
+
+        // cancel at the first poll after the transformation started
+        let mut polls = 0;
+        let cancelled = kdf.transform_key_with_progress(&composite_key, &mut |_, _| {
+            polls += 1;
+            polls < 2
+        });
+        assert_eq!(cancelled.unwrap(), None);
+        assert!(is_pending(&id));
+
+        // the retry picks up the running transformation
+        let key = kdf.transform_key_with_progress(&composite_key, &mut |_, _| true).unwrap();
+        assert!(!is_pending(&id));
+        assert_eq!(key, Some(kdf.transform_key(&composite_key).unwrap()));
+    }
+}
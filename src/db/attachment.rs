@@ -0,0 +1,202 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    crypt::calculate_sha256,
+    db::{
+        node::{NodeIterator, NodePtr},
+        Entry,
+    },
+};
+
+/// Content and protection flag of an item in a binary pool
+pub(crate) type PoolItem = (Vec<u8>, bool);
+
+/// A file attached to an entry
+#[derive(Debug, Default, Eq, Clone)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct Attachment {
+    pub(crate) name: String,
+    pub(crate) content: Vec<u8>,
+    pub(crate) protected: bool,
+
+    /// Reference into the binary pool of the file, only used while reading and writing
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) pool_ref: Option<String>,
+}
+
+impl PartialEq for Attachment {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.content == other.content && self.protected == other.protected
+    }
+}
+
+impl Attachment {
+    pub fn new(name: &str, content: Vec<u8>, protected: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            content,
+            protected,
+            pool_ref: None,
+        }
+    }
+
+    /// File name of the attachment
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Raw content of the attachment
+    pub fn get_content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// Whether the attachment should be kept protected in memory
+    pub fn is_protected(&self) -> bool {
+        self.protected
+    }
+}
+
+/// Call `f` for every attachment of every entry below `root`, including the entries in histories
+fn for_each_attachment_mut(root: &NodePtr, f: &mut dyn FnMut(&mut Attachment)) {
+    for node in NodeIterator::new(root) {
+        if let Some(entry) = node.borrow_mut().as_any_mut().downcast_mut::<Entry>() {
+            entry.attachments.iter_mut().for_each(&mut *f);
+            if let Some(history) = entry.history.as_mut() {
+                for history_entry in &mut history.entries {
+                    history_entry.attachments.iter_mut().for_each(&mut *f);
+                }
+            }
+        }
+    }
+}
+
+/// Replace the pool references left by the XML parser with the content they point to.
+///
+/// `lookup` maps a reference to the content and protection flag of a pool item. Attachments with
+/// a dangling reference are dropped. Returns the set of references that were used.
+pub(crate) fn resolve_attachment_refs(root: &NodePtr, lookup: &dyn Fn(&str) -> Option<PoolItem>) -> HashSet<String> {
+    let mut used_refs = HashSet::new();
+    for_each_attachment_mut(root, &mut |attachment| {
+        if let Some(pool_ref) = attachment.pool_ref.take() {
+            if let Some((content, protected)) = lookup(&pool_ref) {
+                attachment.content = content;
+                attachment.protected = protected;
+                used_refs.insert(pool_ref);
+            } else {
+                // mark the attachment for removal below
+                attachment.pool_ref = Some(pool_ref);
+            }
+        }
+    });
+
+    for node in NodeIterator::new(root) {
+        if let Some(entry) = node.borrow_mut().as_any_mut().downcast_mut::<Entry>() {
+            entry.attachments.retain(|a| a.pool_ref.is_none());
+            if let Some(history) = entry.history.as_mut() {
+                for history_entry in &mut history.entries {
+                    history_entry.attachments.retain(|a| a.pool_ref.is_none());
+                }
+            }
+        }
+    }
+
+    used_refs
+}
+
+/// A deduplicated pool of attachment contents, as stored in the KDBX4 inner header
+/// or the `Binaries` element of the KDBX3 metadata
+#[derive(Debug, Default)]
+pub(crate) struct AttachmentPool {
+    pub(crate) items: Vec<PoolItem>,
+    /// Positions of the items, keyed by the SHA-256 of their content
+    index: HashMap<(Vec<u8>, bool), usize>,
+}
+
+impl AttachmentPool {
+    /// Add an item to the pool without deduplication, keeping its position stable
+    pub(crate) fn push(&mut self, content: Vec<u8>, protected: bool) -> usize {
+        let position = self.items.len();
+        self.index
+            .entry((calculate_sha256(&[&content]).to_vec(), protected))
+            .or_insert(position);
+        self.items.push((content, protected));
+        position
+    }
+
+    /// Add an item to the pool, returning the position of an identical item if present
+    pub(crate) fn insert(&mut self, content: &[u8], protected: bool) -> usize {
+        if let Some(position) = self.index.get(&(calculate_sha256(&[content]).to_vec(), protected)) {
+            return *position;
+        }
+        self.push(content.to_vec(), protected)
+    }
+
+    /// Create a deep copy of the tree below `root` in which every attachment references its
+    /// position in the pool, adding the attachment contents to the pool as needed.
+    pub(crate) fn assign_refs(&mut self, root: &NodePtr) -> NodePtr {
+        let root = root.borrow().duplicate();
+        for_each_attachment_mut(&root, &mut |attachment| {
+            let position = self.insert(&attachment.content, attachment.protected);
+            attachment.pool_ref = Some(position.to_string());
+        });
+        root
+    }
+}
+
+#[cfg(test)]
+mod attachment_tests {
+    use super::{resolve_attachment_refs, Attachment, AttachmentPool};
+    use crate::{
+        db::{group_add_child, group_get_children, Entry, Group, NodePtr},
+        rc_refcell_node,
+    };
+
+    #[test]
+    fn pool_roundtrip() {
+        let root = rc_refcell_node!(Group::new("Root"));
+        for content in [b"first".to_vec(), b"second".to_vec(), b"first".to_vec()] {
+            let mut entry = Entry::default();
+            entry.add_attachment("file.txt", content, false);
+            group_add_child(&root, rc_refcell_node!(entry), 0).unwrap();
+        }
+
+        let mut pool = AttachmentPool::default();
+        let annotated = pool.assign_refs(&root);
+        assert_eq!(pool.items.len(), 2);
+
+        let items = pool.items.clone();
+        let used = resolve_attachment_refs(&annotated, &|pool_ref| items.get(pool_ref.parse::<usize>().ok()?).cloned());
+        assert_eq!(used.len(), 2);
+
+        for (original, resolved) in group_get_children(&root)
+            .unwrap()
+            .iter()
+            .zip(group_get_children(&annotated).unwrap().iter())
+        {
+            let original = original.borrow();
+            let resolved = resolved.borrow();
+            let original = original.as_any().downcast_ref::<Entry>().unwrap();
+            let resolved = resolved.as_any().downcast_ref::<Entry>().unwrap();
+            assert_eq!(original.get_attachments(), resolved.get_attachments());
+        }
+    }
+
+    #[test]
+    fn dangling_refs_are_dropped() {
+        let root = rc_refcell_node!(Group::new("Root"));
+        let mut entry = Entry::default();
+        entry.attachments.push(Attachment {
+            name: "missing.txt".to_string(),
+            pool_ref: Some("42".to_string()),
+            ..Default::default()
+        });
+        group_add_child(&root, rc_refcell_node!(entry), 0).unwrap();
+
+        let used = resolve_attachment_refs(&root, &|_| None);
+        assert!(used.is_empty());
+
+        let children = group_get_children(&root).unwrap();
+        let entry = children[0].borrow();
+        assert!(entry.as_any().downcast_ref::<Entry>().unwrap().get_attachments().is_empty());
+    }
+}
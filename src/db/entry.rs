@@ -2,17 +2,21 @@
 use crate::db::otp::{TOTPError, TOTP};
 use crate::{
     db::{
+        attachment::Attachment,
         group::MergeLog,
         node::{Node, NodePtr},
-        Color, CustomData, IconId, Times,
+        Color, CustomData, IconId, MemoryProtection, Times, UnknownXml,
     },
+    protected::{ProtectedValue, Revealed},
     rc_refcell_node,
 };
 use chrono::NaiveDateTime;
-use secstr::SecStr;
 use std::{collections::HashMap, thread, time};
 use uuid::Uuid;
 
+/// The string fields every `KeePass` entry has
+pub(crate) const STANDARD_FIELDS: [&str; 5] = ["Title", "UserName", "Password", "URL", "Notes"];
+
 /// A database entry containing several key-value fields.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -35,8 +39,14 @@ pub struct Entry {
     pub(crate) override_url: Option<String>,
     pub(crate) quality_check: Option<bool>,
 
+    pub(crate) attachments: Vec<Attachment>,
+
+    pub(crate) previous_parent_group: Option<Uuid>,
+
     pub(crate) history: Option<History>,
 
+    pub(crate) unknown_xml: UnknownXml,
+
     pub(crate) parent: Option<Uuid>,
 }
 
@@ -55,7 +65,10 @@ impl Default for Entry {
             background_color: None,
             override_url: None,
             quality_check: None,
+            attachments: Vec::new(),
+            previous_parent_group: None,
             history: None,
+            unknown_xml: UnknownXml::default(),
             parent: None,
         }
     }
@@ -75,7 +88,10 @@ impl PartialEq for Entry {
             && self.background_color == other.background_color
             && self.override_url == other.override_url
             && self.quality_check == other.quality_check
+            && self.attachments == other.attachments
+            && self.previous_parent_group == other.previous_parent_group
             && self.history == other.history
+            && self.unknown_xml == other.unknown_xml
         // && self.parent == other.parent
     }
 }
@@ -102,7 +118,7 @@ impl Node for Entry {
     }
 
     fn set_title(&mut self, title: Option<&str>) {
-        self.set_unprotected_field_pair("Title", title);
+        self.set_field_pair("Title", title);
     }
 
     fn get_notes(&self) -> Option<&str> {
@@ -110,7 +126,7 @@ impl Node for Entry {
     }
 
     fn set_notes(&mut self, notes: Option<&str>) {
-        self.set_unprotected_field_pair("Notes", notes);
+        self.set_field_pair("Notes", notes);
     }
 
     fn get_icon_id(&self) -> Option<IconId> {
@@ -140,6 +156,14 @@ impl Node for Entry {
     fn set_parent(&mut self, parent: Option<Uuid>) {
         self.parent = parent;
     }
+
+    fn get_previous_parent(&self) -> Option<Uuid> {
+        self.previous_parent_group
+    }
+
+    fn set_previous_parent(&mut self, previous_parent: Option<Uuid>) {
+        self.previous_parent_group = previous_parent;
+    }
 }
 
 #[allow(dead_code)]
@@ -189,26 +213,99 @@ impl Entry {
         Ok((response, log.merge_with(&history_merge_log)))
     }
 
+    /// Whether `entry` was modified without knowing about the changes in `older`, i.e. whether
+    /// the last modification of `older` is missing from the history of `entry`.
+    pub(crate) fn diverged_from(entry: &NodePtr, older: &NodePtr) -> bool {
+        let Some(older_modification) = older.borrow().get_times().get_last_modification() else {
+            return false;
+        };
+        let entry = entry.borrow();
+        let Some(entry) = entry.as_any().downcast_ref::<Entry>() else {
+            return false;
+        };
+        !entry.history.as_ref().is_some_and(|history| {
+            history
+                .entries
+                .iter()
+                .any(|h| h.times.get_last_modification() == Some(older_modification))
+        })
+    }
+
     // Convenience function used in unit tests, to make sure that:
     // 1. The history gets updated after changing a field
     // 2. We wait a second before commiting the changes so that the timestamp is not the same
     //    as it previously was. This is necessary since the timestamps in the KDBX format
     //    do not preserve the msecs.
     pub(crate) fn set_field_and_commit(&mut self, field_name: &str, field_value: &str) {
-        self.set_unprotected_field_pair(field_name, Some(field_value));
+        self.set_field_pair(field_name, Some(field_value));
         thread::sleep(time::Duration::from_secs(1));
         self.update_history();
     }
 
-    fn set_unprotected_field_pair(&mut self, field_name: &str, field_value: Option<&str>) {
+    /// Set a string field, protecting it if it is protected already
+    fn set_field_pair(&mut self, field_name: &str, field_value: Option<&str>) {
+        self.set_string_field(field_name, field_value, false);
+    }
+
+    /// Set a string field, protecting it if `protect` is set or if it is protected already.
+    /// `None` removes the field.
+    pub(crate) fn set_string_field(&mut self, field_name: &str, field_value: Option<&str>, protect: bool) {
         if let Some(field_value) = field_value {
-            self.fields
-                .insert(field_name.to_string(), Value::Unprotected(field_value.to_string()));
+            let value = if protect || self.is_protected(field_name) {
+                self.protected_value(field_name, field_value)
+            } else {
+                Value::Unprotected(field_value.to_string())
+            };
+            self.fields.insert(field_name.to_string(), value);
         } else {
             self.fields.remove(field_name);
         }
     }
 
+    /// Protect a new value of the field, encrypting it in memory if the old value is encrypted
+    fn protected_value(&self, field_name: &str, field_value: &str) -> Value {
+        match self.fields.get(field_name) {
+            Some(Value::Protected(old)) => Value::Protected(old.replace_with(field_value)),
+            _ => Value::Protected(field_value.into()),
+        }
+    }
+
+    /// Encrypt or decrypt the protected fields in memory, in this entry and its history
+    pub(crate) fn set_memory_encryption(&mut self, enabled: bool) -> Result<(), getrandom::Error> {
+        for value in self.fields.values_mut() {
+            if let Value::Protected(protected) = value {
+                if enabled {
+                    protected.seal()?;
+                } else {
+                    protected.unseal();
+                }
+            }
+        }
+        if let Some(history) = self.history.as_mut() {
+            for history_entry in &mut history.entries {
+                history_entry.set_memory_encryption(enabled)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Protect the string fields that `memory_protection` asks to protect, in this entry and its
+    /// history
+    pub(crate) fn apply_memory_protection(&mut self, memory_protection: &MemoryProtection) {
+        for (field_name, value) in &mut self.fields {
+            if let Value::Unprotected(unprotected) = value {
+                if memory_protection.protects(field_name) {
+                    *value = Value::Protected(std::mem::take(unprotected).into());
+                }
+            }
+        }
+        if let Some(history) = self.history.as_mut() {
+            for history_entry in &mut history.entries {
+                history_entry.apply_memory_protection(memory_protection);
+            }
+        }
+    }
+
     pub(crate) fn entry_replaced_with(entry: &NodePtr, other: &NodePtr) -> Option<()> {
         let mut success = false;
         if let Some(entry) = entry.borrow_mut().as_any_mut().downcast_mut::<Entry>() {
@@ -225,7 +322,10 @@ impl Entry {
                 entry.background_color = other.background_color;
                 entry.override_url = other.override_url.clone();
                 entry.quality_check = other.quality_check;
+                entry.attachments = other.attachments.clone();
+                entry.previous_parent_group = other.previous_parent_group;
                 entry.history = other.history.clone();
+                entry.unknown_xml = other.unknown_xml.clone();
                 // entry.parent = other.parent;
                 success = true;
             }
@@ -238,15 +338,53 @@ impl Entry {
 }
 
 impl<'a> Entry {
-    /// Get a field by name, taking care of unprotecting Protected values automatically
+    /// Get a field by name, taking care of unprotecting Protected values automatically.
+    ///
+    /// Protected values that are encrypted in memory, which only happens after
+    /// [`Database::set_memory_encryption`] was enabled for the database, are not returned. Use
+    /// [`Entry::reveal`] for them.
+    ///
+    /// [`Database::set_memory_encryption`]: crate::Database::set_memory_encryption
     pub fn get(&'a self, key: &str) -> Option<&'a str> {
         match self.fields.get(key) {
             None | Some(&Value::Bytes(_)) => None,
-            Some(Value::Protected(pv)) => std::str::from_utf8(pv.unsecure()).ok(),
+            Some(Value::Protected(pv)) => pv.as_plain(),
             Some(Value::Unprotected(uv)) => Some(uv),
         }
     }
 
+    /// Get a field by name, decrypting it if it is a protected value that is encrypted in memory
+    ///
+    /// The returned guard wipes the decrypted value when it is dropped.
+    pub fn reveal(&'a self, key: &str) -> Option<Revealed<'a>> {
+        match self.fields.get(key) {
+            None | Some(&Value::Bytes(_)) => None,
+            Some(Value::Protected(pv)) => Some(pv.reveal()),
+            Some(Value::Unprotected(uv)) => Some(Revealed::borrowed(uv)),
+        }
+    }
+
+    /// Set a string field, protecting it in memory and in the saved file if `protected` is set.
+    ///
+    /// Use [`Database::set_entry_field`] to protect the field as the [`Meta::memory_protection`]
+    /// settings of the database ask for.
+    ///
+    /// [`Database::set_entry_field`]: crate::Database::set_entry_field
+    /// [`Meta::memory_protection`]: crate::db::Meta::memory_protection
+    pub fn set_field(&mut self, key: &str, value: &str, protected: bool) {
+        let value = if protected {
+            self.protected_value(key, value)
+        } else {
+            Value::Unprotected(value.to_string())
+        };
+        self.fields.insert(key.to_string(), value);
+    }
+
+    /// Whether the field `key` is a protected value
+    pub fn is_protected(&self, key: &str) -> bool {
+        matches!(self.fields.get(key), Some(Value::Protected(_)))
+    }
+
     /// Get a bytes field by name
     pub fn get_bytes(&'a self, key: &str) -> Option<&'a [u8]> {
         match self.fields.get(key) {
@@ -258,14 +396,14 @@ impl<'a> Entry {
     /// Convenience method for getting a TOTP from this entry
     #[cfg(feature = "totp")]
     pub fn get_otp(&'a self) -> Result<TOTP, TOTPError> {
-        self.get_raw_otp_value().ok_or(TOTPError::NoRecord)?.parse()
+        self.reveal("otp").ok_or(TOTPError::NoRecord)?.parse()
     }
 
     /// Convenience method for setting a TOTP to this entry
     #[cfg(feature = "totp")]
     pub fn set_otp(&mut self, value: &str) {
-        self.fields.insert("otp".to_string(), Value::Protected(value.as_bytes().into()));
-        // self.set_unprotected_field_pair("otp", Some(value));
+        self.fields.insert("otp".to_string(), self.protected_value("otp", value));
+        // self.set_field_pair("otp", Some(value));
     }
 
     /// Convenience method for getting the raw value of the 'otp' field
@@ -291,24 +429,39 @@ impl<'a> Entry {
         self.tags.as_mut()
     }
 
+    /// XML elements and attributes of the entry that are not known to this crate
+    pub fn get_unknown_xml(&self) -> &UnknownXml {
+        &self.unknown_xml
+    }
+
+    pub fn get_unknown_xml_mut(&mut self) -> &mut UnknownXml {
+        &mut self.unknown_xml
+    }
+
     /// Convenience method for getting the value of the `UserName` field
     pub fn get_username(&'a self) -> Option<&'a str> {
         self.get("UserName")
     }
 
     pub fn set_username(&mut self, username: Option<&str>) {
-        self.set_unprotected_field_pair("UserName", username);
+        self.set_field_pair("UserName", username);
     }
 
-    /// Convenience method for getting the value of the 'Password' field
+    /// Convenience method for getting the value of the 'Password' field, which is `None` if it is
+    /// encrypted in memory, see [`Entry::get`]
     pub fn get_password(&self) -> Option<&str> {
         self.get("Password")
     }
 
+    /// Convenience method for revealing the value of the 'Password' field
+    pub fn reveal_password(&self) -> Option<Revealed<'_>> {
+        self.reveal("Password")
+    }
+
     pub fn set_password(&mut self, password: Option<&str>) {
         if let Some(password) = password {
             self.fields
-                .insert("Password".to_string(), Value::Protected(password.as_bytes().into()));
+                .insert("Password".to_string(), self.protected_value("Password", password));
         } else {
             self.fields.remove("Password");
         }
@@ -320,7 +473,48 @@ impl<'a> Entry {
     }
 
     pub fn set_url(&mut self, url: Option<&str>) {
-        self.set_unprotected_field_pair("URL", url);
+        self.set_field_pair("URL", url);
+    }
+
+    /// Get the attachments of this entry, in file order
+    pub fn get_attachments(&self) -> &Vec<Attachment> {
+        &self.attachments
+    }
+
+    /// Get an attachment by its file name
+    pub fn get_attachment(&self, name: &str) -> Option<&Attachment> {
+        self.attachments.iter().find(|a| a.name == name)
+    }
+
+    /// Add an attachment, replacing any existing attachment with the same file name
+    pub fn add_attachment(&mut self, name: &str, content: Vec<u8>, protected: bool) {
+        let attachment = Attachment::new(name, content, protected);
+        if let Some(existing) = self.attachments.iter_mut().find(|a| a.name == name) {
+            *existing = attachment;
+        } else {
+            self.attachments.push(attachment);
+        }
+    }
+
+    /// Rename an attachment. Fails if there is no attachment named `old_name` or if
+    /// another attachment is already named `new_name`.
+    pub fn rename_attachment(&mut self, old_name: &str, new_name: &str) -> crate::Result<()> {
+        if old_name != new_name && self.get_attachment(new_name).is_some() {
+            return Err(format!("an attachment named '{new_name}' already exists").into());
+        }
+        let attachment = self
+            .attachments
+            .iter_mut()
+            .find(|a| a.name == old_name)
+            .ok_or_else(|| format!("no attachment named '{old_name}'"))?;
+        attachment.name = new_name.to_string();
+        Ok(())
+    }
+
+    /// Remove an attachment by its file name, returning it if it existed
+    pub fn remove_attachment(&mut self, name: &str) -> Option<Attachment> {
+        let position = self.attachments.iter().position(|a| a.name == name)?;
+        Some(self.attachments.remove(position))
     }
 
     /// Adds the current version of the entry to the entry's history
@@ -381,7 +575,7 @@ impl<'a> Entry {
 pub enum Value {
     Bytes(Vec<u8>),
     Unprotected(String),
-    Protected(SecStr),
+    Protected(ProtectedValue),
 }
 
 impl Value {
@@ -389,7 +583,7 @@ impl Value {
         match self {
             Value::Bytes(b) => b.is_empty(),
             Value::Unprotected(u) => u.is_empty(),
-            Value::Protected(p) => p.unsecure().is_empty(),
+            Value::Protected(p) => p.is_empty(),
         }
     }
 }
@@ -403,7 +597,7 @@ impl serde::Serialize for Value {
         match self {
             Value::Bytes(b) => serializer.serialize_bytes(b),
             Value::Unprotected(u) => serializer.serialize_str(u),
-            Value::Protected(p) => serializer.serialize_str(String::from_utf8_lossy(p.unsecure()).as_ref()),
+            Value::Protected(p) => serializer.serialize_str(&p.reveal()),
         }
     }
 }
@@ -513,7 +707,6 @@ impl History {
 #[cfg(test)]
 mod entry_tests {
     use super::{Entry, Node, Value};
-    use secstr::SecStr;
     use std::{thread, time};
 
     #[test]
@@ -525,9 +718,7 @@ mod entry_tests {
             .fields
             .insert("a-unprotected".to_string(), Value::Unprotected("asdf".to_string()));
 
-        entry
-            .fields
-            .insert("a-protected".to_string(), Value::Protected(SecStr::new("asdf".as_bytes().to_vec())));
+        entry.fields.insert("a-protected".to_string(), Value::Protected("asdf".into()));
 
         assert_eq!(entry.get_bytes("a-bytes"), Some(&[1, 2, 3][..]));
         assert_eq!(entry.get_bytes("a-unprotected"), None);
@@ -538,6 +729,37 @@ mod entry_tests {
         assert!(!entry.fields["a-bytes"].is_empty());
     }
 
+    #[test]
+    fn memory_protection() {
+        use crate::db::MemoryProtection;
+
+        let mut entry = Entry::default();
+        entry.set_title(Some("title"));
+        entry.set_username(Some("user"));
+        entry.set_password(Some("password"));
+        entry.update_history();
+
+        let memory_protection = MemoryProtection {
+            protect_username: true,
+            ..MemoryProtection::default()
+        };
+        entry.apply_memory_protection(&memory_protection);
+        assert!(matches!(entry.fields["Title"], Value::Unprotected(_)));
+        assert!(matches!(entry.fields["UserName"], Value::Protected(_)));
+        assert!(matches!(
+            entry.history.as_ref().unwrap().entries[0].fields["UserName"],
+            Value::Protected(_)
+        ));
+
+        // setting a protected field keeps it protected
+        entry.set_username(Some("other user"));
+        assert!(matches!(entry.fields["UserName"], Value::Protected(_)));
+        assert_eq!(entry.get_username(), Some("other user"));
+        assert_eq!(entry.reveal("UserName").as_deref(), Some("other user"));
+        assert_eq!(entry.reveal_password().as_deref(), Some("password"));
+        assert_eq!(entry.reveal("Title").as_deref(), Some("title"));
+    }
+
     #[test]
     fn update_history() {
         let mut entry = Entry::default();
@@ -613,6 +835,28 @@ mod entry_tests {
         assert!(entry.get_otp().is_ok());
     }
 
+    #[test]
+    fn attachments() {
+        let mut entry = Entry::default();
+        entry.add_attachment("a.txt", b"first".to_vec(), false);
+        entry.add_attachment("b.txt", b"second".to_vec(), true);
+        entry.add_attachment("a.txt", b"replaced".to_vec(), false);
+
+        let names: Vec<&str> = entry.get_attachments().iter().map(|a| a.get_name()).collect();
+        assert_eq!(names, vec!["a.txt", "b.txt"]);
+        assert_eq!(entry.get_attachment("a.txt").unwrap().get_content(), b"replaced");
+        assert!(entry.get_attachment("b.txt").unwrap().is_protected());
+
+        assert!(entry.rename_attachment("a.txt", "b.txt").is_err());
+        assert!(entry.rename_attachment("missing.txt", "c.txt").is_err());
+        entry.rename_attachment("a.txt", "c.txt").unwrap();
+        assert!(entry.get_attachment("a.txt").is_none());
+
+        assert_eq!(entry.remove_attachment("c.txt").unwrap().get_content(), b"replaced");
+        assert!(entry.remove_attachment("c.txt").is_none());
+        assert_eq!(entry.get_attachments().len(), 1);
+    }
+
     #[cfg(feature = "serialization")]
     #[test]
     fn serialization() {
@@ -627,7 +871,7 @@ mod entry_tests {
         );
 
         assert_eq!(
-            serde_json::to_string(&Value::Protected(SecStr::new("ABC".as_bytes().to_vec()))).unwrap(),
+            serde_json::to_string(&Value::Protected("ABC".into())).unwrap(),
             "\"ABC\"".to_string()
         );
     }
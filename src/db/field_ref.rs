@@ -0,0 +1,298 @@
+//! Resolution of field references of the form `{REF:<Wanted>@<SearchIn>:<Text>}`
+//!
+//! `<Wanted>` and `<SearchIn>` are one of `T` (title), `U` (user name), `P` (password), `A` (URL),
+//! `N` (notes) and `I` (UUID). `<SearchIn>` additionally accepts `O` to search the custom string
+//! fields of the entries. The first entry whose field contains `<Text>` (case-insensitive) is used,
+//! except for `I`, which compares the UUID exactly.
+
+use uuid::Uuid;
+
+use crate::{
+    db::{entry::STANDARD_FIELDS, node::NodeIterator, Database, Entry, Node},
+    error::FieldReferenceError,
+};
+
+/// Maximum number of nested field references followed before giving up
+pub const FIELD_REFERENCE_MAX_DEPTH: usize = 12;
+
+const REF_PREFIX: &str = "{REF:";
+
+/// Field selectors allowed in a field reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldSelector {
+    Title,
+    UserName,
+    Password,
+    Url,
+    Notes,
+    Uuid,
+    Other,
+}
+
+impl FieldSelector {
+    fn parse(code: u8) -> Option<Self> {
+        match code.to_ascii_uppercase() {
+            b'T' => Some(FieldSelector::Title),
+            b'U' => Some(FieldSelector::UserName),
+            b'P' => Some(FieldSelector::Password),
+            b'A' => Some(FieldSelector::Url),
+            b'N' => Some(FieldSelector::Notes),
+            b'I' => Some(FieldSelector::Uuid),
+            b'O' => Some(FieldSelector::Other),
+            _ => None,
+        }
+    }
+
+    fn field_name(self) -> Option<&'static str> {
+        match self {
+            FieldSelector::Title => Some("Title"),
+            FieldSelector::UserName => Some("UserName"),
+            FieldSelector::Password => Some("Password"),
+            FieldSelector::Url => Some("URL"),
+            FieldSelector::Notes => Some("Notes"),
+            FieldSelector::Uuid | FieldSelector::Other => None,
+        }
+    }
+}
+
+/// A parsed `{REF:...}` placeholder
+struct FieldReference<'a> {
+    wanted: FieldSelector,
+    search_in: FieldSelector,
+    text: &'a str,
+}
+
+impl<'a> FieldReference<'a> {
+    /// Parse the part of a placeholder between `{REF:` and the closing brace
+    fn parse(body: &'a str) -> Option<Self> {
+        let bytes = body.as_bytes();
+        if bytes.len() < 4 || bytes[1] != b'@' || bytes[3] != b':' {
+            return None;
+        }
+        let wanted = FieldSelector::parse(bytes[0])?;
+        if wanted == FieldSelector::Other {
+            return None;
+        }
+        Some(FieldReference {
+            wanted,
+            search_in: FieldSelector::parse(bytes[2])?,
+            text: &body[4..],
+        })
+    }
+
+    fn matches(&self, entry: &Entry) -> bool {
+        let needle = self.text.to_lowercase();
+        let contains = |value: Option<&str>| value.is_some_and(|v| v.to_lowercase().contains(&needle));
+        match self.search_in {
+            FieldSelector::Uuid => Uuid::parse_str(self.text).is_ok_and(|uuid| uuid == entry.get_uuid()),
+            FieldSelector::Other => entry
+                .fields
+                .keys()
+                .filter(|key| !STANDARD_FIELDS.contains(&key.as_str()))
+                .any(|key| contains(entry.reveal(key).as_deref())),
+            selector => contains(entry.reveal(selector.field_name().unwrap_or_default()).as_deref()),
+        }
+    }
+}
+
+impl Database {
+    /// Replace all field references in `value` with the fields they point to.
+    ///
+    /// References that cannot be resolved are kept as they are. Nested references are resolved
+    /// up to [`FIELD_REFERENCE_MAX_DEPTH`] levels, and references that end up pointing back to
+    /// themselves are reported as [`FieldReferenceError::Cycle`].
+    pub fn resolve_field_references(&self, value: &str) -> Result<String, FieldReferenceError> {
+        self.resolve_field_references_with_stack(value, &mut Vec::new())
+    }
+
+    /// Resolve references in `value`, where `stack` holds the fields that are being resolved already
+    fn resolve_field_references_with_stack(&self, value: &str, stack: &mut Vec<(Uuid, String)>) -> Result<String, FieldReferenceError> {
+        // ASCII uppercasing keeps the byte offsets of both strings in sync
+        let upper = value.to_ascii_uppercase();
+        let mut result = String::with_capacity(value.len());
+        let mut position = 0;
+
+        while let Some(offset) = upper[position..].find(REF_PREFIX) {
+            let start = position + offset;
+            let body_start = start + REF_PREFIX.len();
+            let Some(length) = value[body_start..].find('}') else {
+                break;
+            };
+            let end = body_start + length + 1;
+            result.push_str(&value[position..start]);
+            position = end;
+
+            let placeholder = &value[start..end];
+            match FieldReference::parse(&value[body_start..end - 1]) {
+                Some(reference) => result.push_str(&self.resolve_reference(placeholder, &reference, stack)?),
+                None => result.push_str(placeholder),
+            }
+        }
+
+        result.push_str(&value[position..]);
+        Ok(result)
+    }
+
+    fn resolve_reference(
+        &self,
+        placeholder: &str,
+        reference: &FieldReference,
+        stack: &mut Vec<(Uuid, String)>,
+    ) -> Result<String, FieldReferenceError> {
+        if reference.wanted == FieldSelector::Uuid {
+            let uuid = NodeIterator::new(&self.root).find_map(|node| {
+                let node = node.borrow();
+                let entry = node.as_any().downcast_ref::<Entry>()?;
+                reference.matches(entry).then(|| entry.get_uuid())
+            });
+            return Ok(match uuid {
+                Some(uuid) => uuid.as_simple().to_string().to_uppercase(),
+                None => placeholder.to_string(),
+            });
+        }
+
+        let target = NodeIterator::new(&self.root).find_map(|node| {
+            let node = node.borrow();
+            let entry = node.as_any().downcast_ref::<Entry>()?;
+            if !reference.matches(entry) {
+                return None;
+            }
+            let field = reference.wanted.field_name()?;
+            Some((entry.get_uuid(), field, entry.reveal(field).map(|value| value.to_string())))
+        });
+
+        let Some((uuid, field, value)) = target else {
+            return Ok(placeholder.to_string());
+        };
+        let value = value.unwrap_or_default();
+
+        if stack.iter().any(|(u, f)| *u == uuid && f == field) {
+            return Err(FieldReferenceError::Cycle {
+                reference: placeholder.to_string(),
+            });
+        }
+        if stack.len() >= FIELD_REFERENCE_MAX_DEPTH {
+            return Err(FieldReferenceError::RecursionLimit {
+                limit: FIELD_REFERENCE_MAX_DEPTH,
+            });
+        }
+
+        stack.push((uuid, field.to_string()));
+        let resolved = self.resolve_field_references_with_stack(&value, stack);
+        stack.pop();
+        resolved
+    }
+}
+
+impl Entry {
+    /// Get a field by name with all field references in it resolved against `db`
+    pub fn get_resolved(&self, db: &Database, key: &str) -> Result<Option<String>, FieldReferenceError> {
+        let Some(value) = self.reveal(key) else {
+            return Ok(None);
+        };
+        let mut stack = vec![(self.get_uuid(), key.to_string())];
+        db.resolve_field_references_with_stack(&value, &mut stack).map(Some)
+    }
+
+    /// Get the title with all field references resolved
+    pub fn get_title_resolved(&self, db: &Database) -> Result<Option<String>, FieldReferenceError> {
+        self.get_resolved(db, "Title")
+    }
+
+    /// Get the user name with all field references resolved
+    pub fn get_username_resolved(&self, db: &Database) -> Result<Option<String>, FieldReferenceError> {
+        self.get_resolved(db, "UserName")
+    }
+
+    /// Get the password with all field references resolved
+    pub fn get_password_resolved(&self, db: &Database) -> Result<Option<String>, FieldReferenceError> {
+        self.get_resolved(db, "Password")
+    }
+
+    /// Get the URL with all field references resolved
+    pub fn get_url_resolved(&self, db: &Database) -> Result<Option<String>, FieldReferenceError> {
+        self.get_resolved(db, "URL")
+    }
+
+    /// Get the notes with all field references resolved
+    pub fn get_notes_resolved(&self, db: &Database) -> Result<Option<String>, FieldReferenceError> {
+        self.get_resolved(db, "Notes")
+    }
+}
+
+#[cfg(test)]
+mod field_ref_tests {
+    use uuid::Uuid;
+
+    use crate::{
+        config::DatabaseConfig,
+        db::{group_add_child, Database, Entry, Node, NodePtr},
+        error::FieldReferenceError,
+        rc_refcell_node,
+    };
+
+    fn add_entry(db: &Database, title: &str, username: &str, password: &str) -> Uuid {
+        let mut entry = Entry::default();
+        entry.set_title(Some(title));
+        entry.set_username(Some(username));
+        entry.set_password(Some(password));
+        let uuid = entry.uuid;
+        group_add_child(&db.root, rc_refcell_node!(entry), 0).unwrap();
+        uuid
+    }
+
+    #[test]
+    fn resolve_by_title_and_uuid() {
+        let db = Database::new(DatabaseConfig::default());
+        let uuid = add_entry(&db, "Mail Server", "alice", "secret");
+        let id = uuid.as_simple().to_string().to_uppercase();
+
+        assert_eq!(db.resolve_field_references("{REF:P@T:mail}").unwrap(), "secret");
+        assert_eq!(db.resolve_field_references(&format!("{{ref:u@i:{id}}}")).unwrap(), "alice");
+        assert_eq!(db.resolve_field_references("{REF:I@U:alice}").unwrap(), id);
+        assert_eq!(
+            db.resolve_field_references("user {REF:U@T:Mail Server}, pass {REF:P@T:Mail Server}")
+                .unwrap(),
+            "user alice, pass secret"
+        );
+    }
+
+    #[test]
+    fn unresolvable_references_are_kept() {
+        let db = Database::new(DatabaseConfig::default());
+        add_entry(&db, "Mail", "alice", "secret");
+
+        for value in ["{REF:P@T:nothing}", "{REF:X@T:Mail}", "{REF:P@T:Mail", "{REF:}"] {
+            assert_eq!(db.resolve_field_references(value).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn nested_references_and_cycles() {
+        let db = Database::new(DatabaseConfig::default());
+        let base = add_entry(&db, "Base", "alice", "secret");
+        let id = base.as_simple().to_string();
+        let copy = add_entry(&db, "Copy of copy", &format!("{{REF:U@I:{id}}}"), "{REF:P@T:Copy of copy}");
+
+        let node = crate::db::search_node_by_uuid(&db.root, copy).unwrap();
+        let node = node.borrow();
+        let entry = node.as_any().downcast_ref::<Entry>().unwrap();
+        assert_eq!(entry.get_username_resolved(&db).unwrap().as_deref(), Some("alice"));
+        assert_eq!(entry.get_title_resolved(&db).unwrap().as_deref(), Some("Copy of copy"));
+        assert!(matches!(entry.get_password_resolved(&db), Err(FieldReferenceError::Cycle { .. })));
+        assert_eq!(entry.get_url_resolved(&db).unwrap(), None);
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let db = Database::new(DatabaseConfig::default());
+        for i in 0..=super::FIELD_REFERENCE_MAX_DEPTH + 1 {
+            add_entry(&db, &format!("level {i:02}"), &format!("{{REF:U@T:level {:02}}}", i + 1), "");
+        }
+
+        assert!(matches!(
+            db.resolve_field_references("{REF:U@T:level 00}"),
+            Err(FieldReferenceError::RecursionLimit { .. })
+        ));
+    }
+}
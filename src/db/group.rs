@@ -1,5 +1,5 @@
 use crate::{
-    db::{entry::Entry, node::*, CustomData, IconId, Times},
+    db::{entry::Entry, node::*, CustomData, IconId, Times, UnknownXml},
     rc_refcell_node, Result,
 };
 use uuid::Uuid;
@@ -13,36 +13,72 @@ impl SearchField {
     pub(crate) fn matches(&self, node: &NodePtr, field_value: &str) -> bool {
         match self {
             SearchField::Uuid => node.borrow().get_uuid().to_string() == field_value,
-            SearchField::Title => match node.borrow().get_title() {
-                Some(title) => title == field_value,
-                None => false,
+            SearchField::Title => match node.borrow().as_any().downcast_ref::<Entry>() {
+                // the title of an entry may be protected and encrypted in memory
+                Some(entry) => entry.reveal("Title").is_some_and(|title| &*title == field_value),
+                None => node.borrow().get_title() == Some(field_value),
             },
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MergeEventType {
     EntryCreated,
     EntryLocationUpdated,
 
     EntryUpdated,
+    EntryDeleted,
+
     GroupCreated,
+    GroupLocationUpdated,
+    GroupUpdated,
+    GroupDeleted,
+
+    /// A metadata field, named after its XML element, was taken from the other database
+    MetaUpdated {
+        field: String,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub struct MergeEvent {
     /// The uuid of the node (entry or group) affected by
-    /// the merge event.
+    /// the merge event. Nil for metadata events.
     pub node_uuid: Uuid,
 
     pub event_type: MergeEventType,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeConflictType {
+    /// The entry was modified in both databases. The newer version was kept and
+    /// the older one was added to its history.
+    EntryModifiedInBoth,
+
+    /// The node was deleted in one database but modified afterwards in the other one,
+    /// so it was kept.
+    ModifiedAfterDeletion,
+
+    /// The group was deleted in the other database but still contains nodes that were
+    /// not, so it was kept.
+    GroupNotEmpty,
+}
+
+/// A change made in both databases that could not be merged without losing one side
+#[derive(Debug, Clone)]
+pub struct MergeConflict {
+    /// The uuid of the node (entry or group) affected by the conflict
+    pub node_uuid: Uuid,
+
+    pub conflict_type: MergeConflictType,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct MergeLog {
     pub warnings: Vec<String>,
     pub events: Vec<MergeEvent>,
+    pub conflicts: Vec<MergeConflict>,
 }
 
 impl MergeLog {
@@ -52,6 +88,8 @@ impl MergeLog {
         response.warnings.append(other.warnings.clone().as_mut());
         response.events.append(self.events.clone().as_mut());
         response.events.append(other.events.clone().as_mut());
+        response.conflicts.append(self.conflicts.clone().as_mut());
+        response.conflicts.append(other.conflicts.clone().as_mut());
         response
     }
 }
@@ -118,6 +156,15 @@ pub struct Group {
     // something to do with restoring selected items when re-opening a database.
     pub(crate) last_top_visible_entry: Option<Uuid>,
 
+    /// Tags of the group (KDBX 4.1)
+    pub(crate) tags: Vec<String>,
+
+    /// UUID of the group this group was in before it was last moved (KDBX 4.1)
+    pub(crate) previous_parent_group: Option<Uuid>,
+
+    /// XML elements and attributes of the group that are not known to this crate
+    pub(crate) unknown_xml: UnknownXml,
+
     pub(crate) parent: Option<Uuid>,
 }
 
@@ -137,6 +184,9 @@ impl Default for Group {
             enable_autotype: None,
             enable_searching: None,
             last_top_visible_entry: None,
+            tags: Vec::new(),
+            previous_parent_group: None,
+            unknown_xml: UnknownXml::default(),
             parent: None,
         }
     }
@@ -156,7 +206,10 @@ impl PartialEq for Group {
             && self.enable_autotype == other.enable_autotype
             && self.enable_searching == other.enable_searching
             && self.last_top_visible_entry == other.last_top_visible_entry
+            && self.tags == other.tags
+            && self.previous_parent_group == other.previous_parent_group
             && self.custom_data == other.custom_data
+            && self.unknown_xml == other.unknown_xml
         // && self.parent == other.parent
     }
 }
@@ -230,6 +283,14 @@ impl Node for Group {
     fn set_parent(&mut self, parent: Option<Uuid>) {
         self.parent = parent;
     }
+
+    fn get_previous_parent(&self) -> Option<Uuid> {
+        self.previous_parent_group
+    }
+
+    fn set_previous_parent(&mut self, previous_parent: Option<Uuid>) {
+        self.previous_parent_group = previous_parent;
+    }
 }
 
 impl Group {
@@ -269,6 +330,24 @@ impl Group {
         self.name = name.to_string();
     }
 
+    /// Convenience method for getting tags
+    pub fn get_tags(&self) -> &Vec<String> {
+        self.tags.as_ref()
+    }
+
+    pub fn get_tags_mut(&mut self) -> &mut Vec<String> {
+        self.tags.as_mut()
+    }
+
+    /// XML elements and attributes of the group that are not known to this crate
+    pub fn get_unknown_xml(&self) -> &UnknownXml {
+        &self.unknown_xml
+    }
+
+    pub fn get_unknown_xml_mut(&mut self) -> &mut UnknownXml {
+        &mut self.unknown_xml
+    }
+
     pub fn add_child(&mut self, child: NodePtr, index: usize) {
         child.borrow_mut().set_parent(Some(self.get_uuid()));
         if index < self.children.len() {
@@ -331,6 +410,25 @@ impl Group {
         }
     }
 
+    /// Replace the properties of this group with the ones of `other`, keeping the identity,
+    /// position and children of this group
+    pub(crate) fn copy_properties_from(&mut self, other: &Group) {
+        self.name = other.name.clone();
+        self.notes = other.notes.clone();
+        self.icon_id = other.icon_id;
+        self.custom_icon_uuid = other.custom_icon_uuid;
+        self.times = other.times.clone();
+        self.custom_data = other.custom_data.clone();
+        self.is_expanded = other.is_expanded;
+        self.default_autotype_sequence = other.default_autotype_sequence.clone();
+        self.enable_autotype = other.enable_autotype.clone();
+        self.enable_searching = other.enable_searching.clone();
+        self.last_top_visible_entry = other.last_top_visible_entry;
+        self.tags = other.tags.clone();
+        self.previous_parent_group = other.previous_parent_group;
+        self.unknown_xml = other.unknown_xml.clone();
+    }
+
     pub fn entries(&self) -> Vec<NodePtr> {
         let mut response: Vec<NodePtr> = vec![];
         for node in &self.children {
@@ -358,7 +456,7 @@ impl Group {
     }
 
     pub(crate) fn has_group(root: &NodePtr, uuid: Uuid) -> bool {
-        group_get_children(root).map_or(false, |c| c.into_iter().any(|n| n.borrow().get_uuid() == uuid && node_is_group(&n)))
+        group_get_children(root).is_some_and(|c| c.into_iter().any(|n| n.borrow().get_uuid() == uuid && node_is_group(&n)))
     }
 
     pub(crate) fn get_group_mut(root: &NodePtr, location: &NodeLocation, create_groups: bool) -> Result<NodePtr> {
@@ -422,15 +520,9 @@ impl Group {
 
         let mut removed_entry: Option<NodePtr> = None;
         let mut new_nodes: Vec<NodePtr> = vec![];
-        println!(
-            "Searching for entry {} in {}",
-            uuid,
-            group.borrow().get_title().unwrap_or("No title")
-        );
         for node in group_get_children(&group).unwrap_or_default() {
             if node_is_entry(&node) {
                 let node_uuid = node.borrow().get_uuid();
-                println!("Saw entry {node_uuid}");
                 if node_uuid != uuid {
                     new_nodes.push(node.borrow().duplicate());
                     continue;
@@ -483,7 +575,6 @@ impl Group {
 
         let next_location = &remaining_location[0];
 
-        println!("Searching for group {} {:?}", next_location.name, next_location.uuid);
         for node in group_get_children(parent).unwrap_or_default() {
             if node_is_group(&node) {
                 if node.borrow().get_uuid() != next_location.uuid {
@@ -595,11 +686,18 @@ impl Group {
                     continue;
                 }
 
-                let (merged_entry, entry_merge_log) = if destination_last_modification > source_last_modification {
-                    Entry::merge(&existing_entry, entry)?
+                let (newer_entry, older_entry) = if destination_last_modification > source_last_modification {
+                    (&existing_entry, entry)
                 } else {
-                    Entry::merge(entry, &existing_entry)?
+                    (entry, &existing_entry)
                 };
+                if Entry::diverged_from(newer_entry, older_entry) {
+                    log.conflicts.push(MergeConflict {
+                        node_uuid: entry_uuid,
+                        conflict_type: MergeConflictType::EntryModifiedInBoth,
+                    });
+                }
+                let (merged_entry, entry_merge_log) = Entry::merge(newer_entry, older_entry)?;
                 // merged_entry.borrow_mut().set_parent(existing_entry.borrow().get_parent());
                 if node_is_equals_to(&existing_entry, &merged_entry) {
                     continue;
@@ -947,6 +1045,7 @@ mod group_tests {
         let merge_result = Group::merge(&destination_group, &source_group).unwrap();
         assert_eq!(merge_result.warnings.len(), 0);
         assert_eq!(merge_result.events.len(), 1);
+        assert!(merge_result.conflicts.is_empty());
 
         let entry = destination_group.borrow().as_any().downcast_ref::<Group>().unwrap().entries()[0].clone();
         assert_eq!(entry.borrow().get_title(), Some("entry1_updated"));
@@ -972,6 +1071,7 @@ mod group_tests {
         let merge_result = Group::merge(&destination_group, &source_group).unwrap();
         assert_eq!(merge_result.warnings.len(), 0);
         assert_eq!(merge_result.events.len(), 1);
+        assert_eq!(merge_result.conflicts.len(), 1);
 
         let entry = destination_group.borrow().as_any().downcast_ref::<Group>().unwrap().entries()[0].clone();
         assert_eq!(entry.borrow().get_title(), Some("entry1_updated_from_source"));
@@ -0,0 +1,464 @@
+//! Synchronization of two databases, following the rules used by KeePass
+//!
+//! Nodes are matched by UUID. For each node, the version with the newer `LastModificationTime`
+//! wins and its location is taken from the version with the newer `LocationChanged` time.
+//! Deletions recorded in `DeletedObjects` are applied in both directions unless the node was
+//! modified after it was deleted, and metadata fields are merged by their `*Changed` timestamps.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::{
+    db::{
+        group_add_child, group_get_children, group_remove_node_by_uuid, node_is_group, search_node_by_uuid,
+        search_node_by_uuid_with_specific_type, Database, DeletedObject, Group, MergeConflict, MergeConflictType, MergeEvent,
+        MergeEventType, MergeLog, Meta, NodeIterator, NodePtr, Times,
+    },
+    Result,
+};
+
+/// Most recent modification of `node` or any of its descendants
+fn newest_modification(node: &NodePtr) -> NaiveDateTime {
+    NodeIterator::new(node)
+        .filter_map(|n| n.borrow().get_times().get_last_modification())
+        .max()
+        .unwrap_or_else(Times::epoch)
+}
+
+fn node_event(node_uuid: Uuid, event_type: MergeEventType) -> MergeEvent {
+    MergeEvent { node_uuid, event_type }
+}
+
+fn meta_event(field: &str) -> MergeEvent {
+    MergeEvent {
+        node_uuid: Uuid::nil(),
+        event_type: MergeEventType::MetaUpdated { field: field.to_string() },
+    }
+}
+
+impl Database {
+    /// Merge the changes of `other` into this database.
+    ///
+    /// The returned log lists every change made to this database, and the conflicts that were
+    /// resolved by keeping the newer version of a node.
+    pub fn merge(&mut self, other: &Database) -> Result<MergeLog> {
+        let mut log = MergeLog::default();
+        let root: NodePtr = (&self.root).into();
+        let other_root = NodePtr::from(&other.root).borrow().duplicate();
+
+        self.remove_locally_deleted(&other_root, &mut log)?;
+        self.merge_groups(&other_root, &mut log)?;
+        log = log.merge_with(&Group::merge(&root, &other_root)?);
+        self.apply_deletions(other, &mut log)?;
+        self.meta.merge_from(&other.meta, &mut log);
+        // the merged entries are protected as the merged settings ask for
+        self.apply_memory_protection();
+
+        Ok(log)
+    }
+
+    /// Remove the nodes deleted in this database from `other_root`, so that they are not
+    /// created again, unless they were modified after their deletion.
+    fn remove_locally_deleted(&mut self, other_root: &NodePtr, log: &mut MergeLog) -> Result<()> {
+        let other_root_uuid = other_root.borrow().get_uuid();
+        let mut resurrected = Vec::new();
+
+        for deleted in &self.deleted_objects.objects {
+            if deleted.uuid == other_root_uuid || search_node_by_uuid(&self.root, deleted.uuid).is_some() {
+                continue;
+            }
+            let Some(node) = search_node_by_uuid(other_root, deleted.uuid) else {
+                continue;
+            };
+
+            if newest_modification(&node) > deleted.deletion_time {
+                log.conflicts.push(MergeConflict {
+                    node_uuid: deleted.uuid,
+                    conflict_type: MergeConflictType::ModifiedAfterDeletion,
+                });
+                resurrected.push(deleted.uuid);
+            } else {
+                group_remove_node_by_uuid(other_root, deleted.uuid)?;
+            }
+        }
+
+        self.deleted_objects.objects.retain(|deleted| !resurrected.contains(&deleted.uuid));
+        Ok(())
+    }
+
+    /// Create the groups of `other_root` missing in this database, and update the properties and
+    /// locations of the existing ones
+    fn merge_groups(&mut self, other_root: &NodePtr, log: &mut MergeLog) -> Result<()> {
+        let root: NodePtr = (&self.root).into();
+        let root_uuid = root.borrow().get_uuid();
+        let other_root_uuid = other_root.borrow().get_uuid();
+
+        // parents come before their children in the iteration order
+        for other_node in NodeIterator::new(other_root).filter(node_is_group) {
+            let other_node = other_node.borrow();
+            let Some(other_group) = other_node.as_any().downcast_ref::<Group>() else {
+                continue;
+            };
+            let uuid = if other_group.uuid == other_root_uuid {
+                root_uuid
+            } else {
+                other_group.uuid
+            };
+            let parent_uuid = match other_group.parent {
+                Some(parent) if parent != other_root_uuid => parent,
+                _ => root_uuid,
+            };
+
+            let Some(group) = search_node_by_uuid_with_specific_type::<Group>(&root, uuid) else {
+                let parent = search_node_by_uuid_with_specific_type::<Group>(&root, parent_uuid).unwrap_or_else(|| root.clone());
+                let mut new_group = other_group.clone();
+                new_group.children = Vec::new();
+                let count = group_get_children(&parent).map_or(0, |c| c.len());
+                group_add_child(&parent, crate::rc_refcell_node!(new_group), count)?;
+                log.events.push(node_event(uuid, MergeEventType::GroupCreated));
+                continue;
+            };
+
+            let times = group.borrow().get_times().clone();
+            if other_group.times.get_last_modification() > times.get_last_modification() {
+                if let Some(group) = group.borrow_mut().as_any_mut().downcast_mut::<Group>() {
+                    let previous_parent = group.previous_parent_group;
+                    group.copy_properties_from(other_group);
+                    // the location is merged separately below
+                    group.times.set_location_changed(times.get_location_changed());
+                    group.previous_parent_group = previous_parent;
+                }
+                log.events.push(node_event(uuid, MergeEventType::GroupUpdated));
+            }
+
+            if uuid == root_uuid
+                || group.borrow().get_parent() == Some(parent_uuid)
+                || other_group.times.get_location_changed() <= times.get_location_changed()
+            {
+                continue;
+            }
+            let Some(new_parent) = search_node_by_uuid_with_specific_type::<Group>(&root, parent_uuid) else {
+                continue;
+            };
+            if search_node_by_uuid(&group, parent_uuid).is_some() {
+                // moving the group would make it a descendant of itself
+                log.warnings
+                    .push(format!("Group {uuid} could not be moved into its descendant {parent_uuid}"));
+                continue;
+            }
+
+            let group = group_remove_node_by_uuid(&root, uuid)?;
+            {
+                let mut group = group.borrow_mut();
+                group.get_times_mut().set_location_changed(other_group.times.get_location_changed());
+                group.set_previous_parent(other_group.previous_parent_group);
+            }
+            let count = group_get_children(&new_parent).map_or(0, |c| c.len());
+            group_add_child(&new_parent, group, count)?;
+            log.events.push(node_event(uuid, MergeEventType::GroupLocationUpdated));
+        }
+
+        Ok(())
+    }
+
+    /// Apply the deletions recorded in `other` to this database and record them
+    fn apply_deletions(&mut self, other: &Database, log: &mut MergeLog) -> Result<()> {
+        let root: NodePtr = (&self.root).into();
+        let other_root: NodePtr = (&other.root).into();
+        let other_deleted = other
+            .deleted_objects
+            .objects
+            .iter()
+            .map(|deleted| (deleted.uuid, deleted.deletion_time))
+            .collect::<HashMap<_, _>>();
+
+        for deleted in &other.deleted_objects.objects {
+            if search_node_by_uuid(&other_root, deleted.uuid).is_some() {
+                // still present in the other database, e.g. moved to its recycle bin
+                continue;
+            }
+
+            if let Some(node) = search_node_by_uuid(&root, deleted.uuid) {
+                if deleted.uuid == root.borrow().get_uuid() {
+                    continue;
+                }
+                let is_group = node_is_group(&node);
+                if is_group && NodeIterator::new(&node).any(|n| !other_deleted.contains_key(&n.borrow().get_uuid())) {
+                    log.conflicts.push(MergeConflict {
+                        node_uuid: deleted.uuid,
+                        conflict_type: MergeConflictType::GroupNotEmpty,
+                    });
+                    continue;
+                }
+                if newest_modification(&node) > deleted.deletion_time {
+                    log.conflicts.push(MergeConflict {
+                        node_uuid: deleted.uuid,
+                        conflict_type: MergeConflictType::ModifiedAfterDeletion,
+                    });
+                    continue;
+                }
+
+                group_remove_node_by_uuid(&root, deleted.uuid)?;
+                let event_type = if is_group {
+                    MergeEventType::GroupDeleted
+                } else {
+                    MergeEventType::EntryDeleted
+                };
+                log.events.push(node_event(deleted.uuid, event_type));
+            }
+
+            match self.deleted_objects.objects.iter_mut().find(|d| d.uuid == deleted.uuid) {
+                Some(existing) => existing.deletion_time = existing.deletion_time.max(deleted.deletion_time),
+                None => self.deleted_objects.objects.push(DeletedObject {
+                    uuid: deleted.uuid,
+                    deletion_time: deleted.deletion_time,
+                }),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Meta {
+    /// Take the fields of `other` that were changed more recently than the ones of this metadata
+    pub(crate) fn merge_from(&mut self, other: &Meta, log: &mut MergeLog) {
+        fn is_newer(theirs: Option<NaiveDateTime>, ours: Option<NaiveDateTime>) -> bool {
+            theirs.is_some() && theirs > ours
+        }
+
+        if is_newer(other.database_name_changed, self.database_name_changed) {
+            self.database_name.clone_from(&other.database_name);
+            self.database_name_changed = other.database_name_changed;
+            log.events.push(meta_event("DatabaseName"));
+        }
+        if is_newer(other.database_description_changed, self.database_description_changed) {
+            self.database_description.clone_from(&other.database_description);
+            self.database_description_changed = other.database_description_changed;
+            log.events.push(meta_event("DatabaseDescription"));
+        }
+        if is_newer(other.default_username_changed, self.default_username_changed) {
+            self.default_username.clone_from(&other.default_username);
+            self.default_username_changed = other.default_username_changed;
+            log.events.push(meta_event("DefaultUserName"));
+        }
+        if is_newer(other.recyclebin_changed, self.recyclebin_changed) {
+            self.recyclebin_enabled = other.recyclebin_enabled;
+            self.recyclebin_uuid = other.recyclebin_uuid;
+            self.recyclebin_changed = other.recyclebin_changed;
+            log.events.push(meta_event("RecycleBinUUID"));
+        }
+        if is_newer(other.entry_templates_group_changed, self.entry_templates_group_changed) {
+            self.entry_templates_group = other.entry_templates_group;
+            self.entry_templates_group_changed = other.entry_templates_group_changed;
+            log.events.push(meta_event("EntryTemplatesGroup"));
+        }
+        if is_newer(other.settings_changed, self.settings_changed) {
+            self.maintenance_history_days = other.maintenance_history_days;
+            self.color = other.color;
+            self.master_key_change_rec = other.master_key_change_rec;
+            self.master_key_change_force = other.master_key_change_force;
+            self.memory_protection.clone_from(&other.memory_protection);
+            self.history_max_items = other.history_max_items;
+            self.history_max_size = other.history_max_size;
+            self.settings_changed = other.settings_changed;
+            log.events.push(meta_event("SettingsChanged"));
+        }
+
+        let mut icons_changed = false;
+        for icon in &other.custom_icons.icons {
+            match self.custom_icons.icons.iter_mut().find(|i| i.uuid == icon.uuid) {
+                Some(existing) if is_newer(icon.last_modification_time, existing.last_modification_time) => {
+                    *existing = icon.clone();
+                    icons_changed = true;
+                }
+                Some(_) => {}
+                None => {
+                    self.custom_icons.icons.push(icon.clone());
+                    icons_changed = true;
+                }
+            }
+        }
+        if icons_changed {
+            log.events.push(meta_event("CustomIcons"));
+        }
+
+        let mut custom_data_changed = false;
+        for (key, item) in &other.custom_data.items {
+            match self.custom_data.items.get(key) {
+                Some(existing) if !is_newer(item.last_modification_time, existing.last_modification_time) => {}
+                _ => {
+                    self.custom_data.items.insert(key.clone(), item.clone());
+                    custom_data_changed = true;
+                }
+            }
+        }
+        if custom_data_changed {
+            log.events.push(meta_event("CustomData"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod merge_tests {
+    use chrono::Duration;
+
+    use crate::{
+        config::DatabaseConfig,
+        db::{
+            group_add_child, search_node_by_uuid, CustomDataItem, Database, Entry, Group, MergeConflictType, MergeEventType, Node, NodePtr,
+            Times,
+        },
+        rc_refcell_node,
+    };
+
+    fn add_node(db: &Database, parent: Option<&NodePtr>, node: NodePtr) -> NodePtr {
+        let parent = parent.cloned().unwrap_or_else(|| (&db.root).into());
+        group_add_child(&parent, node.clone(), usize::MAX).unwrap();
+        node
+    }
+
+    fn sample_database() -> Database {
+        let mut db = Database::new(DatabaseConfig::default());
+        db.set_recycle_bin_enabled(false);
+        let internet = add_node(&db, None, rc_refcell_node!(Group::new("Internet")));
+        add_node(&db, None, rc_refcell_node!(Group::new("Work")));
+        let mut entry = Entry::default();
+        entry.set_title(Some("Webmail"));
+        add_node(&db, Some(&internet), rc_refcell_node!(entry));
+        db
+    }
+
+    fn set_times(node: &NodePtr, modification: Duration) {
+        let time = Times::now() + modification;
+        let mut node = node.borrow_mut();
+        node.get_times_mut().set_last_modification(Some(time));
+        node.get_times_mut().set_location_changed(Some(time));
+    }
+
+    #[test]
+    fn deletions_are_propagated() {
+        let mut destination = sample_database();
+        let mut source = destination.clone();
+        source.root = NodePtr::from(&destination.root).borrow().duplicate().into();
+
+        let webmail = Group::get(&source.root, &["Internet", "Webmail"]).unwrap().borrow().get_uuid();
+        set_times(
+            &Group::get(&destination.root, &["Internet", "Webmail"]).unwrap(),
+            Duration::hours(-1),
+        );
+        let before_deletion = NodePtr::from(&destination.root).borrow().duplicate();
+        source.remove_node_by_uuid(webmail).unwrap();
+
+        let log = destination.merge(&source).unwrap();
+        assert!(search_node_by_uuid(&destination.root, webmail).is_none());
+        assert!(log.events.iter().any(|e| e.event_type == MergeEventType::EntryDeleted));
+        assert!(destination.deleted_objects.objects.iter().any(|d| d.uuid == webmail));
+
+        // merging a copy made before the deletion does not create the entry again
+        let stale = Database {
+            root: before_deletion.into(),
+            ..destination.clone()
+        };
+        destination.merge(&stale).unwrap();
+        assert!(search_node_by_uuid(&destination.root, webmail).is_none());
+    }
+
+    #[test]
+    fn modified_after_deletion_is_kept() {
+        let mut destination = sample_database();
+        let mut source = destination.clone();
+        source.root = NodePtr::from(&destination.root).borrow().duplicate().into();
+
+        let webmail = Group::get(&source.root, &["Internet", "Webmail"]).unwrap();
+        let uuid = webmail.borrow().get_uuid();
+        source.remove_node_by_uuid(uuid).unwrap();
+        set_times(
+            &Group::get(&destination.root, &["Internet", "Webmail"]).unwrap(),
+            Duration::hours(1),
+        );
+
+        let log = destination.merge(&source).unwrap();
+        assert!(search_node_by_uuid(&destination.root, uuid).is_some());
+        assert_eq!(log.conflicts.len(), 1);
+        assert_eq!(log.conflicts[0].conflict_type, MergeConflictType::ModifiedAfterDeletion);
+    }
+
+    #[test]
+    fn group_properties_and_moves() {
+        let mut destination = sample_database();
+        let mut source = destination.clone();
+        source.root = NodePtr::from(&destination.root).borrow().duplicate().into();
+
+        let internet = Group::get(&source.root, &["Internet"]).unwrap();
+        let internet_uuid = internet.borrow().get_uuid();
+        internet.borrow_mut().set_notes(Some("Web sites"));
+        set_times(&internet, Duration::hours(1));
+        let internet = crate::db::group_remove_node_by_uuid(&source.root.clone().into(), internet_uuid).unwrap();
+        group_add_child(&Group::get(&source.root, &["Work"]).unwrap(), internet, 0).unwrap();
+
+        let mut new_group = Group::new("New");
+        new_group.tags.push("fresh".to_string());
+        add_node(&source, None, rc_refcell_node!(new_group));
+
+        let log = destination.merge(&source).unwrap();
+        let internet = Group::get(&destination.root, &["Work", "Internet"]).unwrap();
+        assert_eq!(internet.borrow().get_notes(), Some("Web sites"));
+        assert!(Group::get(&destination.root, &["Work", "Internet", "Webmail"]).is_some());
+        assert!(Group::get(&destination.root, &["New"]).is_some());
+
+        let event_types = log.events.iter().map(|e| e.event_type.clone()).collect::<Vec<_>>();
+        assert!(event_types.contains(&MergeEventType::GroupUpdated));
+        assert!(event_types.contains(&MergeEventType::GroupLocationUpdated));
+        assert!(event_types.contains(&MergeEventType::GroupCreated));
+
+        // merging again changes nothing
+        let log = destination.merge(&source).unwrap();
+        assert!(log.events.is_empty(), "{:?}", log.events);
+    }
+
+    #[test]
+    fn entry_modified_in_both() {
+        let mut destination = sample_database();
+        let mut source = destination.clone();
+        source.root = NodePtr::from(&destination.root).borrow().duplicate().into();
+
+        for (db, title, offset) in [(&destination, "mine", 1), (&source, "theirs", 2)] {
+            let entry = Group::get(&db.root, &["Internet", "Webmail"]).unwrap();
+            let mut entry = entry.borrow_mut();
+            let entry = entry.as_any_mut().downcast_mut::<Entry>().unwrap();
+            entry.set_title(Some(title));
+            entry.times.set_last_modification(Some(Times::now() + Duration::hours(offset)));
+        }
+
+        let log = destination.merge(&source).unwrap();
+        assert_eq!(log.conflicts.len(), 1);
+        assert_eq!(log.conflicts[0].conflict_type, MergeConflictType::EntryModifiedInBoth);
+        assert!(Group::get(&destination.root, &["Internet", "theirs"]).is_some());
+    }
+
+    #[test]
+    fn meta_fields() {
+        let mut destination = sample_database();
+        let mut source = destination.clone();
+        source.meta.database_name = Some("Theirs".to_string());
+        source.meta.database_name_changed = Some(Times::now());
+        destination.meta.default_username = Some("mine".to_string());
+        destination.meta.default_username_changed = Some(Times::now());
+        source.meta.custom_data.items.insert(
+            "key".to_string(),
+            CustomDataItem {
+                value: None,
+                last_modification_time: Some(Times::now()),
+            },
+        );
+
+        let log = destination.merge(&source).unwrap();
+        assert_eq!(destination.meta.database_name.as_deref(), Some("Theirs"));
+        assert_eq!(destination.meta.default_username.as_deref(), Some("mine"));
+        assert!(destination.meta.custom_data.items.contains_key("key"));
+        assert_eq!(log.events.len(), 2);
+    }
+}
@@ -1,7 +1,17 @@
 use chrono::NaiveDateTime;
 use uuid::Uuid;
 
-use crate::db::{Color, CustomData};
+use crate::db::{Color, CustomData, Times, UnknownXml};
+
+/// A meta-stream entry of a `KeePass` 1.x database
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct KdbMetaStream {
+    /// Name of the stream, e.g. `Simple UI State`
+    pub name: String,
+
+    pub content: Vec<u8>,
+}
 
 /// Database metadata
 #[derive(Debug, Default, Eq, PartialEq, Clone)]
@@ -10,6 +20,10 @@ pub struct Meta {
     /// the program that generated the database file.
     pub generator: Option<String>,
 
+    /// SHA-256 of the outer header of a KDBX3 database. Only present while reading and
+    /// writing, the hash is verified on opening and computed on saving.
+    pub(crate) header_hash: Option<Vec<u8>>,
+
     /// name of the database
     pub database_name: Option<String>,
 
@@ -37,8 +51,10 @@ pub struct Meta {
     /// time the master key was last changed
     pub master_key_changed: Option<NaiveDateTime>,
 
+    /// number of days after which changing the master key is recommended, -1 to disable
     pub master_key_change_rec: Option<isize>,
 
+    /// number of days after which changing the master key is required, -1 to disable
     pub master_key_change_force: Option<isize>,
 
     /// memory protection settings
@@ -82,6 +98,13 @@ pub struct Meta {
 
     /// Additional custom data fields
     pub custom_data: CustomData,
+
+    /// Meta-stream entries of a `KeePass` 1.x database, in which the application keeps its settings.
+    /// They are not part of the group tree and are written back when the database is saved as KDB.
+    pub kdb_meta_streams: Vec<KdbMetaStream>,
+
+    /// Unknown XML elements and attributes of the `Meta` element of KDBX databases
+    pub unknown_xml: UnknownXml,
 }
 
 impl Meta {
@@ -112,6 +135,31 @@ impl Meta {
         let time = chrono::Local::now().naive_local();
         self.recyclebin_changed = Some(time);
     }
+
+    /// Whether the master key is older than [`Meta::master_key_change_rec`] days
+    pub fn master_key_change_recommended(&self) -> bool {
+        self.master_key_change_due(self.master_key_change_rec)
+    }
+
+    /// Whether the master key is older than [`Meta::master_key_change_force`] days
+    pub fn master_key_change_required(&self) -> bool {
+        self.master_key_change_due(self.master_key_change_force)
+    }
+
+    fn master_key_change_due(&self, days: Option<isize>) -> bool {
+        let Some(days) = days.filter(|days| *days >= 0) else {
+            return false;
+        };
+        // a period too long to be represented never ends
+        let Some(period) = chrono::Duration::try_days(days as i64) else {
+            return false;
+        };
+        // a key without a known age is treated as due for a change
+        match self.master_key_changed {
+            Some(changed) => Times::now() - changed >= period,
+            None => true,
+        }
+    }
 }
 
 /// Database memory protection settings
@@ -146,6 +194,20 @@ impl Default for MemoryProtection {
     }
 }
 
+impl MemoryProtection {
+    /// Whether the standard field `field_name` should be protected
+    pub fn protects(&self, field_name: &str) -> bool {
+        match field_name {
+            "Title" => self.protect_title,
+            "UserName" => self.protect_username,
+            "Password" => self.protect_password,
+            "URL" => self.protect_url,
+            "Notes" => self.protect_notes,
+            _ => false,
+        }
+    }
+}
+
 /// Collection of custom icons
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -162,6 +224,12 @@ pub struct Icon {
 
     /// Image data
     pub data: Vec<u8>,
+
+    /// Display name of the icon (KDBX 4.1)
+    pub name: Option<String>,
+
+    /// Last time the icon was changed (KDBX 4.1)
+    pub last_modification_time: Option<NaiveDateTime>,
 }
 
 /// Collection of binary attachments in the metadata of an XML database
@@ -177,5 +245,7 @@ pub struct BinaryAttachments {
 pub struct BinaryAttachment {
     pub identifier: Option<String>,
     pub compressed: bool,
+    /// Whether the content is encrypted with the inner cipher in the XML document
+    pub protected: bool,
     pub content: Vec<u8>,
 }
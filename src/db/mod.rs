@@ -1,39 +1,58 @@
 //! Types for representing data contained in a `KeePass` database
 
+pub(crate) mod attachment;
 pub(crate) mod entry;
+pub(crate) mod field_ref;
 pub(crate) mod group;
 pub(crate) mod iconid;
+pub(crate) mod merge;
 pub(crate) mod meta;
 pub(crate) mod node;
+pub(crate) mod placeholder;
+pub(crate) mod query;
+pub(crate) mod shared;
 
 #[cfg(feature = "totp")]
 pub(crate) mod otp;
 
 pub use crate::db::{
+    attachment::Attachment,
     entry::{AutoType, AutoTypeAssociation, Entry, History, Value},
-    group::Group,
-    meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, Meta},
+    field_ref::FIELD_REFERENCE_MAX_DEPTH,
+    group::{Group, MergeConflict, MergeConflictType, MergeEvent, MergeEventType, MergeLog},
+    meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, KdbMetaStream, MemoryProtection, Meta},
     node::*,
+    placeholder::{PlaceholderCompiler, UnknownPlaceholderHandler},
+    query::{EntryQuery, SearchMatch, TextPattern},
+    shared::{NodeId, SharedDatabase, SharedGroup, SharedNode},
 };
+pub use crate::variant_dictionary::{VariantDictionary, VariantDictionaryValue};
 use chrono::NaiveDateTime;
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
 use uuid::Uuid;
 
 #[cfg(feature = "totp")]
 pub use crate::db::otp::{TOTPAlgorithm, TOTP};
 
 use crate::{
-    config::DatabaseConfig,
+    config::{DatabaseConfig, DatabaseHeaderInfo},
     db::iconid::IconId,
     error::{DatabaseIntegrityError, DatabaseOpenError, ParseColorError},
     format::{
-        kdb::parse_kdb,
-        kdbx3::{decrypt_kdbx3, parse_kdbx3},
-        kdbx4::{decrypt_kdbx4, parse_kdbx4},
+        kdb::{kdb_header_info, parse_kdb},
+        kdbx3::{decrypt_kdbx3, kdbx3_header_info, parse_kdbx3},
+        kdbx4::{decrypt_kdbx4, kdbx4_header_info, parse_kdbx4, parse_kdbx4_stream},
         DatabaseVersion,
     },
-    key::DatabaseKey,
+    key::{DatabaseKey, TransformedKey},
+    limits::OpenLimits,
+    progress::{CancellationToken, Progress, ProgressMonitor},
+    protected::ProtectedValue,
     rc_refcell_node,
+    xml_db::parse::reject_unknown_xml,
 };
 
 /// A decrypted `KeePass` database
@@ -43,7 +62,10 @@ pub struct Database {
     /// Configuration settings of the database such as encryption and compression algorithms
     pub config: DatabaseConfig,
 
-    /// Binary attachments in the inner header
+    /// Binary attachments in the inner header that are not referenced by any entry.
+    ///
+    /// Attachments referenced by entries are resolved into `Entry::get_attachments` when the
+    /// database is opened, and written back to the inner header when it is saved.
     pub header_attachments: Vec<HeaderAttachment>,
 
     /// Root node of the KeePass database
@@ -54,6 +76,13 @@ pub struct Database {
 
     /// Metadata of the KeePass database
     pub meta: Meta,
+
+    /// Custom data of applications and plugins in the unencrypted outer header of KDBX4 files,
+    /// which can be read without the key. It is not saved in other versions.
+    pub public_custom_data: VariantDictionary,
+
+    /// Unknown XML elements and attributes of the `Root` element of KDBX databases
+    pub unknown_xml: UnknownXml,
 }
 
 impl PartialEq for Database {
@@ -62,6 +91,8 @@ impl PartialEq for Database {
             && self.header_attachments == other.header_attachments
             && self.deleted_objects == other.deleted_objects
             && self.meta == other.meta
+            && self.public_custom_data == other.public_custom_data
+            && self.unknown_xml == other.unknown_xml
             && node_is_equals_to(&self.root, &other.root)
     }
 }
@@ -69,37 +100,241 @@ impl PartialEq for Database {
 impl Eq for Database {}
 
 impl Database {
-    /// Parse a database from a `std::io::Read`
+    /// Parse a database from a `std::io::Read`.
+    ///
+    /// KDBX4 databases are decrypted and parsed while they are being read, without buffering the
+    /// whole file in memory.
     pub fn open(source: &mut dyn std::io::Read, key: DatabaseKey) -> Result<Database, DatabaseOpenError> {
-        let mut data = Vec::new();
+        Database::open_monitored(source, key, &ProgressMonitor::default(), &OpenLimits::default()).map(|(db, _)| db)
+    }
+
+    /// Parse a database from a `std::io::Read` like [`Database::open`], also returning the key
+    /// transformed by the key derivation function.
+    ///
+    /// Keep the transformed key to unlock the database again without the slow key derivation, by
+    /// opening it with [`DatabaseKey::with_transformed_key`]. Saving the database with a key that
+    /// contains the transformed key reuses its KDF seed, so the transformed key stays valid.
+    pub fn open_for_quick_unlock(
+        source: &mut dyn std::io::Read,
+        key: DatabaseKey,
+    ) -> Result<(Database, TransformedKey), DatabaseOpenError> {
+        Database::open_monitored(source, key, &ProgressMonitor::default(), &OpenLimits::default())
+    }
+
+    /// Parse a database from a `std::io::Read` like [`Database::open`], failing with
+    /// [`DatabaseOpenError::ResourceLimit`] as soon as the database exceeds one of `limits`.
+    ///
+    /// Use this to open files from untrusted sources, which could otherwise make the key derivation
+    /// or the decompression of the payload take arbitrary amounts of memory and time.
+    pub fn open_with_limits(source: &mut dyn std::io::Read, key: DatabaseKey, limits: &OpenLimits) -> Result<Database, DatabaseOpenError> {
+        Database::open_monitored(source, key, &ProgressMonitor::default(), limits).map(|(db, _)| db)
+    }
+
+    /// Parse a database from a `std::io::Read` like [`Database::open_with_limits`], failing with
+    /// [`XmlParseError::UnknownXml`] on XML elements and attributes of the meta data, groups, entries
+    /// and the `Root` element that are not known to this crate, instead of keeping them to write
+    /// them back when saving.
+    ///
+    /// [`XmlParseError::UnknownXml`]: crate::error::XmlParseError::UnknownXml
+    pub fn open_strict(source: &mut dyn std::io::Read, key: DatabaseKey, limits: &OpenLimits) -> Result<Database, DatabaseOpenError> {
+        let db = Database::open_with_limits(source, key, limits)?;
+        if let Some(root) = db.root.borrow().as_any().downcast_ref::<Group>() {
+            reject_unknown_xml(&db.meta.unknown_xml, &db.unknown_xml, root)?;
+        }
+        Ok(db)
+    }
+
+    /// Parse a database from a `std::io::Read` like [`Database::open`], calling `progress` while the
+    /// key is transformed and the payload is decrypted and parsed.
+    ///
+    /// Once `cancel` is cancelled, e.g. from another thread or from within `progress`, opening the
+    /// database stops with [`DatabaseOpenError::Cancelled`]. Argon2 cannot be interrupted, so a
+    /// cancelled Argon2 key derivation keeps running on a background thread and holds on to its
+    /// memory until it is done. Opening the database again with the same key waits for that key
+    /// derivation instead of starting another one.
+    pub fn open_with_progress(
+        source: &mut dyn std::io::Read,
+        key: DatabaseKey,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<Database, DatabaseOpenError> {
+        let monitor = ProgressMonitor::new(progress, cancel);
+        Database::open_monitored(source, key, &monitor, &OpenLimits::default())
+            .map(|(db, _)| db)
+            .map_err(|err| {
+                if monitor.is_cancelled() {
+                    DatabaseOpenError::Cancelled
+                } else {
+                    err
+                }
+            })
+    }
+
+    fn open_monitored(
+        source: &mut dyn std::io::Read,
+        key: DatabaseKey,
+        monitor: &ProgressMonitor,
+        limits: &OpenLimits,
+    ) -> Result<(Database, TransformedKey), DatabaseOpenError> {
+        use std::io::Read;
+
+        let mut version_header = Vec::new();
+        (&mut *source)
+            .take(DatabaseVersion::get_version_header_size() as u64)
+            .read_to_end(&mut version_header)?;
+
+        if let DatabaseVersion::KDB4(_) = DatabaseVersion::parse(&version_header)? {
+            return parse_kdbx4_stream(&mut version_header.as_slice().chain(source), &key, monitor, limits);
+        }
+
+        let mut data = version_header;
         source.read_to_end(&mut data)?;
 
-        Database::parse(data.as_ref(), key)
+        Database::parse_monitored(data.as_ref(), key, monitor, limits)
     }
 
     pub fn parse(data: &[u8], key: DatabaseKey) -> Result<Database, DatabaseOpenError> {
+        Database::parse_monitored(data, key, &ProgressMonitor::default(), &OpenLimits::default()).map(|(db, _)| db)
+    }
+
+    fn parse_monitored(
+        data: &[u8],
+        key: DatabaseKey,
+        monitor: &ProgressMonitor,
+        limits: &OpenLimits,
+    ) -> Result<(Database, TransformedKey), DatabaseOpenError> {
         let database_version = DatabaseVersion::parse(data)?;
 
         match database_version {
-            DatabaseVersion::KDB(_) => parse_kdb(data, &key),
+            DatabaseVersion::KDB(_) => parse_kdb(data, &key, monitor, limits),
             DatabaseVersion::KDB2(_) => Err(DatabaseOpenError::UnsupportedVersion),
-            DatabaseVersion::KDB3(_) => parse_kdbx3(data, &key),
-            DatabaseVersion::KDB4(_) => parse_kdbx4(data, &key),
+            DatabaseVersion::KDB3(_) => parse_kdbx3(data, &key, monitor, limits),
+            DatabaseVersion::KDB4(_) => parse_kdbx4(data, &key, monitor, limits),
         }
     }
 
     /// Save a database to a `std::io::Write`
     #[cfg(feature = "save_kdbx4")]
     pub fn save(&self, destination: &mut dyn std::io::Write, key: DatabaseKey) -> Result<(), crate::error::DatabaseSaveError> {
+        self.save_monitored(destination, key, &ProgressMonitor::default())
+    }
+
+    /// Save a database to a `std::io::Write` like [`Database::save`], calling `progress` while the
+    /// key is transformed and the payload is encrypted and written.
+    ///
+    /// Once `cancel` is cancelled, saving stops with [`DatabaseSaveError::Cancelled`]. The data
+    /// written to `destination` up to that point is incomplete. As when opening, a cancelled Argon2
+    /// key derivation keeps running on a background thread and holds on to its memory until it is
+    /// done.
+    ///
+    /// [`DatabaseSaveError::Cancelled`]: crate::error::DatabaseSaveError::Cancelled
+    #[cfg(feature = "save_kdbx4")]
+    pub fn save_with_progress(
+        &self,
+        destination: &mut dyn std::io::Write,
+        key: DatabaseKey,
+        progress: &mut dyn FnMut(Progress),
+        cancel: &CancellationToken,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        let monitor = ProgressMonitor::new(progress, cancel);
+        self.save_monitored(destination, key, &monitor).map_err(|err| {
+            if monitor.is_cancelled() {
+                crate::error::DatabaseSaveError::Cancelled
+            } else {
+                err
+            }
+        })
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    fn save_monitored(
+        &self,
+        destination: &mut dyn std::io::Write,
+        key: DatabaseKey,
+        monitor: &ProgressMonitor,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
         use crate::error::DatabaseSaveError;
-        use crate::format::kdbx4::dump_kdbx4;
+        use crate::format::{kdb::dump_kdb, kdbx3::dump_kdbx3, kdbx4::dump_kdbx4};
 
         match self.config.version {
-            DatabaseVersion::KDB(_) => Err(DatabaseSaveError::UnsupportedVersion),
+            DatabaseVersion::KDB(_) => dump_kdb(self, &key, destination, monitor),
             DatabaseVersion::KDB2(_) => Err(DatabaseSaveError::UnsupportedVersion),
-            DatabaseVersion::KDB3(_) => Err(DatabaseSaveError::UnsupportedVersion),
-            DatabaseVersion::KDB4(_) => dump_kdbx4(self, &key, destination),
+            DatabaseVersion::KDB3(_) => dump_kdbx3(self, &key, destination, monitor),
+            DatabaseVersion::KDB4(_) => dump_kdbx4(self, &key, destination, monitor),
+        }
+    }
+
+    /// Save a database to a file, replacing it atomically.
+    ///
+    /// The database is written to a temporary file in the same directory, flushed to disk and then
+    /// renamed over `path`, so that a crash leaves either the old or the new database in place.
+    /// If `keep_backup` is set, the previous version of the file is copied to `<path>.bak` first.
+    #[cfg(feature = "save_kdbx4")]
+    pub fn save_to_path<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        key: DatabaseKey,
+        keep_backup: bool,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        use std::{
+            fs::{self, File},
+            io::BufWriter,
+        };
+
+        let path = path.as_ref();
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} is not a file path", path.display())))?;
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => std::path::Path::new("."),
+        };
+
+        let mut suffix = [0u8; 8];
+        getrandom::getrandom(&mut suffix)?;
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(".{}.tmp", hex::encode(suffix)));
+        let temp_path = directory.join(temp_name);
+
+        let existing_permissions = fs::metadata(path).ok().map(|metadata| metadata.permissions());
+
+        let write_temp = || -> Result<(), crate::error::DatabaseSaveError> {
+            let file = File::options().write(true).create_new(true).open(&temp_path)?;
+            let mut writer = BufWriter::new(file);
+            self.save(&mut writer, key)?;
+            let file = writer.into_inner().map_err(std::io::IntoInnerError::into_error)?;
+            file.sync_all()?;
+            if let Some(permissions) = existing_permissions {
+                fs::set_permissions(&temp_path, permissions)?;
+            }
+            Ok(())
+        };
+        if let Err(err) = write_temp() {
+            _ = fs::remove_file(&temp_path);
+            return Err(err);
+        }
+
+        if keep_backup && path.exists() {
+            let mut backup_name = file_name.to_os_string();
+            backup_name.push(".bak");
+            let backup_path = directory.join(backup_name);
+            if let Err(err) = fs::copy(path, &backup_path).and_then(|_| File::open(&backup_path)?.sync_all()) {
+                _ = fs::remove_file(&temp_path);
+                return Err(err.into());
+            }
+        }
+
+        if let Err(err) = fs::rename(&temp_path, path) {
+            _ = fs::remove_file(&temp_path);
+            return Err(err.into());
         }
+
+        // make the rename itself durable
+        #[cfg(unix)]
+        File::open(directory)?.sync_all()?;
+
+        Ok(())
     }
 
     /// Helper function to load a database into its internal XML chunks
@@ -112,13 +347,73 @@ impl Database {
         let data = match database_version {
             DatabaseVersion::KDB(_) => return Err(DatabaseOpenError::UnsupportedVersion),
             DatabaseVersion::KDB2(_) => return Err(DatabaseOpenError::UnsupportedVersion),
-            DatabaseVersion::KDB3(_) => decrypt_kdbx3(data.as_ref(), &key)?.2,
+            DatabaseVersion::KDB3(_) => decrypt_kdbx3(data.as_ref(), &key, &ProgressMonitor::default(), &OpenLimits::default())?.2,
             DatabaseVersion::KDB4(_) => decrypt_kdbx4(data.as_ref(), &key)?.3,
         };
 
         Ok(data)
     }
 
+    /// Change the master key of the database to `new_key`, e.g. to change the password or to add or
+    /// remove a keyfile or challenge-response key, and save the database with it to `destination`.
+    ///
+    /// The time of the change is recorded in [`Meta::master_key_changed`] unless saving fails.
+    /// Saving generates a fresh master seed, KDF seed and encryption IVs, so nothing derived from the
+    /// previous key is kept in the file. `new_key` must not contain a transformed key, which would
+    /// keep the previous key in use.
+    #[cfg(feature = "save_kdbx4")]
+    pub fn rekey_and_save(
+        &mut self,
+        destination: &mut dyn std::io::Write,
+        new_key: DatabaseKey,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        self.rekey(new_key, |db, new_key| db.save(destination, new_key))
+    }
+
+    /// Change the master key of the database to `new_key` like [`Database::rekey_and_save`], and save
+    /// the database with it to a file like [`Database::save_to_path`]
+    #[cfg(feature = "save_kdbx4")]
+    pub fn rekey_and_save_to_path<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+        new_key: DatabaseKey,
+        keep_backup: bool,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        self.rekey(new_key, |db, new_key| db.save_to_path(path, new_key, keep_backup))
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    fn rekey(
+        &mut self,
+        new_key: DatabaseKey,
+        save: impl FnOnce(&Database, DatabaseKey) -> Result<(), crate::error::DatabaseSaveError>,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        use crate::error::DatabaseKeyError;
+        if new_key.is_empty() {
+            return Err(DatabaseKeyError::EmptyKey.into());
+        }
+        if new_key.has_transformed_key() {
+            return Err(DatabaseKeyError::TransformedKeyMismatch.into());
+        }
+
+        let previous_change = self.meta.master_key_changed.replace(Times::now());
+        let result = save(self, new_key);
+        if result.is_err() {
+            self.meta.master_key_changed = previous_change;
+        }
+        result
+    }
+
+    /// Whether changing the master key is recommended by [`Meta::master_key_change_rec`]
+    pub fn key_change_recommended(&self) -> bool {
+        self.meta.master_key_change_recommended()
+    }
+
+    /// Whether changing the master key is required by [`Meta::master_key_change_force`]
+    pub fn key_change_required(&self) -> bool {
+        self.meta.master_key_change_required()
+    }
+
     /// Get the version of a database without decrypting it
     pub fn get_version(source: &mut dyn std::io::Read) -> Result<DatabaseVersion, DatabaseIntegrityError> {
         let mut data = vec![0; DatabaseVersion::get_version_header_size()];
@@ -126,6 +421,28 @@ impl Database {
         DatabaseVersion::parse(data.as_ref())
     }
 
+    /// Get the encryption, compression and key derivation settings of a database from its outer
+    /// header, without the key
+    ///
+    /// Only the header is read from `source`. A cipher or KDF that is neither built in nor
+    /// registered is reported as [`HeaderAlgorithm::Unknown`].
+    ///
+    /// [`HeaderAlgorithm::Unknown`]: crate::config::HeaderAlgorithm::Unknown
+    pub fn get_header_info(source: &mut dyn std::io::Read) -> Result<DatabaseHeaderInfo, DatabaseOpenError> {
+        let mut version_header = vec![0; DatabaseVersion::get_version_header_size()];
+        source.read_exact(&mut version_header)?;
+        let version = DatabaseVersion::parse(&version_header)?;
+
+        // hand the version header on to the parsers, which read the header from its start
+        let mut source = std::io::Read::chain(version_header.as_slice(), source);
+        match version {
+            DatabaseVersion::KDB(_) => kdb_header_info(&mut source),
+            DatabaseVersion::KDB2(_) => Err(DatabaseOpenError::UnsupportedVersion),
+            DatabaseVersion::KDB3(_) => kdbx3_header_info(&mut source),
+            DatabaseVersion::KDB4(_) => kdbx4_header_info(&mut source),
+        }
+    }
+
     /// Create a new, empty database
     pub fn new(config: DatabaseConfig) -> Database {
         Self {
@@ -133,10 +450,55 @@ impl Database {
             header_attachments: Vec::new(),
             root: rc_refcell_node!(Group::new("Root")).into(),
             deleted_objects: DeletedObjects::default(),
+            public_custom_data: VariantDictionary::new(),
+            unknown_xml: UnknownXml::default(),
             meta: Meta::new(),
         }
     }
 
+    /// Protect the fields of all entries that [`Meta::memory_protection`] asks to protect, e.g. after
+    /// the settings were changed. Fields that are protected already stay protected.
+    ///
+    /// This happens when a database is opened, and saving writes the fields protected anyway.
+    pub fn apply_memory_protection(&self) {
+        let memory_protection = self.meta.memory_protection.clone().unwrap_or_default();
+        for node in NodeIterator::new(&self.root) {
+            if let Some(entry) = node.borrow_mut().as_any_mut().downcast_mut::<Entry>() {
+                entry.apply_memory_protection(&memory_protection);
+            }
+        }
+    }
+
+    /// Set a string field of `entry`, protecting it if it is protected already or if
+    /// [`Meta::memory_protection`] asks to protect it. `None` removes the field.
+    ///
+    /// Fields that are set again keep being encrypted in memory, see
+    /// [`Database::set_memory_encryption`].
+    pub fn set_entry_field(&self, entry: &mut Entry, key: &str, value: Option<&str>) {
+        let protect = match &self.meta.memory_protection {
+            Some(memory_protection) => memory_protection.protects(key),
+            None => MemoryProtection::default().protects(key),
+        };
+        entry.set_string_field(key, value, protect);
+    }
+
+    /// Encrypt the protected fields of all entries and their history in memory, or decrypt them again.
+    ///
+    /// Encrypted fields are only returned by [`Entry::reveal`] and [`Entry::reveal_password`], while
+    /// [`Entry::get`] and the other getters returning `&str` return `None` for them. Fields that are
+    /// set again later on stay encrypted; call this again after adding entries or new protected fields.
+    ///
+    /// The key of the running process that the fields are encrypted with is generated the first time,
+    /// which fails if the system has no random numbers available.
+    pub fn set_memory_encryption(&self, enabled: bool) -> Result<(), getrandom::Error> {
+        for node in NodeIterator::new(&self.root) {
+            if let Some(entry) = node.borrow_mut().as_any_mut().downcast_mut::<Entry>() {
+                entry.set_memory_encryption(enabled)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn node_get_parents(&self, node: &NodePtr) -> Vec<Uuid> {
         let mut parents = Vec::new();
         let mut parent_uuid = node.borrow().get_parent();
@@ -158,7 +520,7 @@ impl Database {
 
     pub fn node_is_recycle_bin(&self, node: &NodePtr) -> bool {
         let uuid = node.borrow().get_uuid();
-        node_is_group(node) && self.get_recycle_bin().map_or(false, |bin| bin.borrow().get_uuid() == uuid)
+        node_is_group(node) && self.get_recycle_bin().is_some_and(|bin| bin.borrow().get_uuid() == uuid)
     }
 
     pub fn node_is_in_recycle_bin(&self, node: Uuid) -> bool {
@@ -166,7 +528,7 @@ impl Database {
             let parents = self.node_get_parents(&node);
             self.get_recycle_bin()
                 .map(|bin| bin.borrow().get_uuid())
-                .map_or(false, |uuid| parents.contains(&uuid))
+                .is_some_and(|uuid| parents.contains(&uuid))
         } else {
             false
         }
@@ -213,6 +575,8 @@ impl Database {
         let node = group_remove_node_by_uuid(&self.root, uuid)?;
         self.deleted_objects.add(uuid);
         if uuid != recycle_bin_uuid && !node_in_recycle_bin {
+            let previous_parent = node.borrow().get_parent();
+            node.borrow_mut().set_previous_parent(previous_parent);
             group_add_child(&recycle_bin, node.clone(), 0)?;
         }
         self.meta.set_recycle_bin_changed();
@@ -384,6 +748,43 @@ pub struct CustomDataItemDenormalized {
     pub custom_data_item: CustomDataItem,
 }
 
+/// XML elements and attributes of a database object that are not known to this crate, e.g. the
+/// ones of plugins or newer `KeePass` versions, which are written back when the database is saved.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct UnknownXml {
+    /// Unknown attributes of the element of the object by their qualified name (`prefix:name`),
+    /// including the `xmlns` declarations of the namespace prefixes they use
+    pub attributes: BTreeMap<String, String>,
+
+    /// Unknown child elements of the object in document order
+    pub elements: Vec<UnknownXmlElement>,
+}
+
+impl UnknownXml {
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty() && self.elements.is_empty()
+    }
+}
+
+/// A child element that is not known to this crate, which is written back at the position where it
+/// was read.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct UnknownXmlElement {
+    /// Name of the known sibling element that preceded the element, after which it is written back.
+    /// `None` for an element before all known siblings.
+    pub after: Option<String>,
+
+    /// The element as an XML fragment that declares the namespaces it uses. The values of elements
+    /// with a `Protected="True"` attribute are left out and kept in `protected_values`.
+    pub xml: String,
+
+    /// The values of the elements with a `Protected="True"` attribute in document order, which are
+    /// encrypted again when saving
+    pub protected_values: Vec<ProtectedValue>,
+}
+
 /// Binary attachments stored in a database inner header
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -463,7 +864,11 @@ impl std::fmt::Display for Color {
 #[cfg(test)]
 mod database_tests {
     #[cfg(feature = "save_kdbx4")]
-    use crate::{config::DatabaseConfig, db::Entry, db::NodePtr};
+    use crate::{
+        config::DatabaseConfig,
+        db::{Entry, NodePtr, Times},
+        error::{DatabaseKeyError, DatabaseOpenError, DatabaseSaveError},
+    };
     use crate::{Database, DatabaseKey, Result};
     use std::fs::File;
 
@@ -485,6 +890,54 @@ mod database_tests {
         assert!(Database::parse(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], DatabaseKey::new().with_password("testing")).is_err());
     }
 
+    #[test]
+    fn test_recycle_records_previous_parent() -> Result<()> {
+        use crate::DatabaseConfig;
+
+        let mut db = Database::new(DatabaseConfig::default());
+        let root_uuid = db.root.borrow().get_uuid();
+        let entry = db.create_new_entry(root_uuid, 0)?;
+        let entry_uuid = entry.borrow().get_uuid();
+
+        db.remove_node_by_uuid(entry_uuid)?;
+
+        assert_eq!(entry.borrow().get_previous_parent(), Some(root_uuid));
+        assert_eq!(entry.borrow().get_parent(), db.meta.recyclebin_uuid);
+        Ok(())
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn test_save_with_progress() -> Result<()> {
+        use crate::{
+            error::DatabaseSaveError,
+            progress::{CancellationToken, ProgressStage},
+        };
+
+        let db = Database::new(DatabaseConfig::default());
+        let key = DatabaseKey::new().with_password("test");
+
+        let mut written = 0;
+        let mut buffer = Vec::new();
+        let token = CancellationToken::new();
+        db.save_with_progress(
+            &mut buffer,
+            key.clone(),
+            &mut |progress| {
+                if progress.stage == ProgressStage::Encryption {
+                    written = progress.completed;
+                }
+            },
+            &token,
+        )?;
+        assert_eq!(written, buffer.len() as u64);
+
+        let mut buffer = Vec::new();
+        let result = db.save_with_progress(&mut buffer, key, &mut |_| token.cancel(), &token);
+        assert!(matches!(result, Err(DatabaseSaveError::Cancelled)));
+        Ok(())
+    }
+
     #[cfg(feature = "save_kdbx4")]
     #[test]
     fn test_save() -> Result<()> {
@@ -511,4 +964,201 @@ mod database_tests {
         assert_eq!(db, db_loaded);
         Ok(())
     }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn test_save_to_path() -> Result<()> {
+        let directory = std::env::temp_dir().join(format!("keepass-save-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&directory)?;
+        let path = directory.join("database.kdbx");
+        let key = DatabaseKey::new().with_password("testing");
+
+        // a longer previous file must not leave trailing data behind
+        std::fs::write(&path, vec![0xAB; 1 << 20])?;
+        let mut db = Database::new(DatabaseConfig::default());
+        db.save_to_path(&path, key.clone(), true)?;
+        assert_eq!(std::fs::read(directory.join("database.kdbx.bak"))?, vec![0xAB; 1 << 20]);
+        assert_eq!(Database::open(&mut File::open(&path)?, key.clone())?, db);
+
+        db.meta.database_name = Some("second version".to_string());
+        db.save_to_path(&path, key.clone(), false)?;
+        assert_eq!(Database::open(&mut File::open(&path)?, key)?, db);
+
+        // only the database and its backup are left in the directory
+        assert_eq!(std::fs::read_dir(&directory)?.count(), 2);
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn test_change_key() -> Result<()> {
+        let old_key = DatabaseKey::new().with_password("old");
+        let new_key = DatabaseKey::new().with_password("new");
+
+        let mut db = Database::new(DatabaseConfig {
+            kdf_config: crate::config::KdfConfig::Aes { rounds: 10 },
+            ..DatabaseConfig::default()
+        });
+        db.meta.master_key_change_rec = Some(30);
+        db.meta.master_key_change_force = Some(-1);
+        assert!(db.key_change_recommended());
+        assert!(!db.key_change_required());
+
+        let mut data = Vec::new();
+        assert!(matches!(
+            db.rekey_and_save(&mut data, DatabaseKey::new()),
+            Err(DatabaseSaveError::Key(DatabaseKeyError::EmptyKey))
+        ));
+        assert!(data.is_empty() && db.meta.master_key_changed.is_none());
+        db.rekey_and_save(&mut data, new_key.clone())?;
+        assert!(!db.key_change_recommended());
+
+        assert!(matches!(
+            Database::parse(&data, old_key),
+            Err(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey))
+        ));
+        let reopened = Database::parse(&data, new_key)?;
+        assert_eq!(reopened.meta.master_key_changed, db.meta.master_key_changed);
+
+        db.meta.master_key_changed = Some(Times::now() - chrono::Duration::days(31));
+        assert!(db.key_change_recommended());
+        Ok(())
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn test_memory_protection() -> Result<()> {
+        use crate::db::{group_add_child, search_node_by_uuid_with_specific_type, EntryQuery, MemoryProtection, Node, NodeIterator};
+        use crate::rc_refcell_node;
+
+        let mut db = Database::new(DatabaseConfig {
+            kdf_config: crate::config::KdfConfig::Aes { rounds: 10 },
+            ..DatabaseConfig::default()
+        });
+        db.meta.memory_protection = Some(MemoryProtection {
+            protect_username: true,
+            protect_url: true,
+            ..MemoryProtection::default()
+        });
+        let root_uuid = db.root.borrow().get_uuid();
+
+        // fields set through the database are protected as the settings ask for
+        let created = db.create_new_entry(root_uuid, 0)?;
+        {
+            let mut created = created.borrow_mut();
+            let created = created.as_any_mut().downcast_mut::<Entry>().unwrap();
+            assert_eq!(created.get_password(), None);
+            assert_eq!(created.get_url(), None);
+            db.set_entry_field(created, "UserName", Some("removed"));
+            db.set_entry_field(created, "UserName", None);
+            assert_eq!(created.get_username(), None);
+            db.set_entry_field(created, "Title", Some("created"));
+            db.set_entry_field(created, "UserName", Some("alice"));
+            db.set_entry_field(created, "URL", Some("https://alice.example.com"));
+            created.set_field("PIN", "1234", true);
+            created.set_field("Comment", "not secret", false);
+            assert!(!created.is_protected("Title"));
+            assert!(created.is_protected("UserName") && created.is_protected("URL") && created.is_protected("PIN"));
+            assert!(!created.is_protected("Comment"));
+
+            // the setters of the entry keep the protection of the fields
+            created.set_username(Some("alice"));
+            assert!(created.is_protected("UserName"));
+        }
+
+        // other entries are written protected, without changing them in memory
+        let mut added = Entry::default();
+        added.set_title(Some("added"));
+        added.set_username(Some("bob"));
+        let added_uuid = added.get_uuid();
+        group_add_child(&db.root, rc_refcell_node!(added), 1)?;
+
+        let key = DatabaseKey::new().with_password("testing");
+        let mut data = Vec::new();
+        db.save(&mut data, key.clone())?;
+        let xml = String::from_utf8(Database::get_xml(&mut data.as_slice(), key.clone())?).unwrap();
+        for secret in ["alice", "bob", "1234", "example.com"] {
+            assert!(!xml.contains(secret), "{secret} was written unprotected");
+        }
+        assert!(xml.contains("not secret"));
+
+        let added = search_node_by_uuid_with_specific_type::<Entry>(&db.root, added_uuid).unwrap();
+        assert!(!added.borrow().as_any().downcast_ref::<Entry>().unwrap().is_protected("UserName"));
+
+        let reopened = Database::parse(&data, key)?;
+        assert_eq!(reopened.search(&EntryQuery::new().field_contains("UserName", "bob")).len(), 1);
+        for node in NodeIterator::new(&reopened.root) {
+            if let Some(entry) = node.borrow().as_any().downcast_ref::<Entry>() {
+                assert!(entry.is_protected("UserName"));
+            }
+        }
+
+        // entries merged into the database are protected as its settings ask for
+        let other = Database::new(DatabaseConfig::default());
+        let mut merged = Entry::default();
+        merged.set_username(Some("carol"));
+        let merged_uuid = merged.get_uuid();
+        group_add_child(&other.root, rc_refcell_node!(merged), 0)?;
+        db.merge(&other)?;
+        let merged = search_node_by_uuid_with_specific_type::<Entry>(&db.root, merged_uuid).unwrap();
+        assert!(merged.borrow().as_any().downcast_ref::<Entry>().unwrap().is_protected("UserName"));
+        Ok(())
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn test_quick_unlock() -> Result<()> {
+        let key = DatabaseKey::new().with_password("testing");
+        let mut db = Database::new(DatabaseConfig {
+            kdf_config: crate::config::KdfConfig::Aes { rounds: 10 },
+            ..DatabaseConfig::default()
+        });
+
+        let mut data = Vec::new();
+        db.save(&mut data, key.clone())?;
+        let (_, transformed_key) = Database::open_for_quick_unlock(&mut data.as_slice(), key.clone())?;
+
+        // saving with the transformed key keeps its KDF seed, so it can be used again
+        let quick_key = DatabaseKey::new().with_transformed_key(transformed_key.clone());
+        assert_eq!(Database::parse(&data, quick_key.clone())?, db);
+        db.meta.database_name = Some("saved with quick unlock".to_string());
+        data.clear();
+        db.save(&mut data, quick_key.clone())?;
+        assert_eq!(Database::parse(&data, quick_key.clone())?, db);
+        assert_eq!(Database::parse(&data, key.clone())?, db);
+
+        // the transformed key is not used together with another password
+        let wrong_key = DatabaseKey::new()
+            .with_password("wrong")
+            .with_transformed_key(transformed_key.clone());
+        assert!(matches!(
+            Database::parse(&data, wrong_key.clone()),
+            Err(DatabaseOpenError::Key(DatabaseKeyError::TransformedKeyMismatch))
+        ));
+        assert!(matches!(
+            db.save(&mut Vec::new(), wrong_key),
+            Err(DatabaseSaveError::Key(DatabaseKeyError::TransformedKeyMismatch))
+        ));
+        assert_eq!(
+            Database::parse(&data, key.clone().with_transformed_key(transformed_key.clone()))?,
+            db
+        );
+
+        // a transformed key must not be used as a new key
+        assert!(matches!(
+            db.rekey_and_save(&mut Vec::new(), key.clone().with_transformed_key(transformed_key.clone())),
+            Err(DatabaseSaveError::Key(DatabaseKeyError::TransformedKeyMismatch))
+        ));
+
+        // saving without it generates a new seed, which only the full key can unlock
+        data.clear();
+        db.save(&mut data, key.clone())?;
+        assert!(matches!(
+            Database::parse(&data, quick_key),
+            Err(DatabaseOpenError::Key(DatabaseKeyError::TransformedKeyMismatch))
+        ));
+        assert_eq!(Database::parse(&data, key.with_transformed_key(transformed_key))?, db);
+        Ok(())
+    }
 }
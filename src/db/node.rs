@@ -197,6 +197,16 @@ pub trait Node: as_any::AsAny + std::fmt::Debug + erased_serde::Serialize {
 
     fn get_parent(&self) -> Option<Uuid>;
     fn set_parent(&mut self, parent: Option<Uuid>);
+
+    /// Get the UUID of the group the node was in before it was last moved (KDBX 4.1), or `None` for
+    /// nodes that do not keep track of it
+    fn get_previous_parent(&self) -> Option<Uuid> {
+        None
+    }
+
+    /// Set the UUID of the group the node was in before it was last moved, which nodes that do not
+    /// keep track of it ignore
+    fn set_previous_parent(&mut self, _previous_parent: Option<Uuid>) {}
 }
 
 #[cfg(feature = "serialization")]
@@ -218,6 +228,16 @@ pub trait Node: as_any::AsAny + std::fmt::Debug {
     fn get_times_mut(&mut self) -> &mut Times;
     fn get_parent(&self) -> Option<Uuid>;
     fn set_parent(&mut self, parent: Option<Uuid>);
+
+    /// Get the UUID of the group the node was in before it was last moved (KDBX 4.1), or `None` for
+    /// nodes that do not keep track of it
+    fn get_previous_parent(&self) -> Option<Uuid> {
+        None
+    }
+
+    /// Set the UUID of the group the node was in before it was last moved, which nodes that do not
+    /// keep track of it ignore
+    fn set_previous_parent(&mut self, _previous_parent: Option<Uuid>) {}
 }
 
 pub struct NodeIterator {
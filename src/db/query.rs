@@ -0,0 +1,427 @@
+//! Searching the entries of a database
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+use uuid::Uuid;
+
+use crate::db::{group_get_children, placeholder::UrlParts, search_node_by_uuid, Database, Entry, Group, Node, NodePtr, Times};
+#[cfg(feature = "regex")]
+use crate::Result;
+
+/// How the text of a field is matched
+#[derive(Debug, Clone)]
+pub enum TextPattern {
+    /// Case-insensitive substring match
+    Contains(String),
+
+    /// Regular expression match
+    #[cfg(feature = "regex")]
+    Regex(Regex),
+}
+
+impl TextPattern {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            TextPattern::Contains(text) => value.to_lowercase().contains(&text.to_lowercase()),
+            #[cfg(feature = "regex")]
+            TextPattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// A condition on the string fields of an entry
+#[derive(Debug, Clone)]
+struct FieldCondition {
+    /// Name of the field to match, or `None` to match any field
+    field: Option<String>,
+    pattern: TextPattern,
+}
+
+/// A query for entries, built by chaining conditions that all have to match. Conditions with
+/// regular expressions are available with the `regex` feature.
+///
+/// ```
+/// use keepass_ng::db::EntryQuery;
+///
+/// let query = EntryQuery::new()
+///     .field_contains("Title", "mail")
+///     .with_tag("work")
+///     .url_host("example.com");
+/// ```
+#[derive(Debug, Clone)]
+pub struct EntryQuery {
+    conditions: Vec<FieldCondition>,
+    tags: Vec<String>,
+    url_host: Option<String>,
+    expired: Option<bool>,
+    group: Option<Uuid>,
+    exclude_recycle_bin: bool,
+    exclude_search_disabled: bool,
+}
+
+impl Default for EntryQuery {
+    fn default() -> Self {
+        Self {
+            conditions: Vec::new(),
+            tags: Vec::new(),
+            url_host: None,
+            expired: None,
+            group: None,
+            exclude_recycle_bin: true,
+            exclude_search_disabled: true,
+        }
+    }
+}
+
+impl EntryQuery {
+    /// A query matching all entries outside of the recycle bin and of groups with searching disabled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require the field `field` to contain `text`, ignoring case
+    pub fn field_contains(mut self, field: &str, text: &str) -> Self {
+        self.conditions.push(FieldCondition {
+            field: Some(field.to_string()),
+            pattern: TextPattern::Contains(text.to_string()),
+        });
+        self
+    }
+
+    /// Require any field to contain `text`, ignoring case
+    pub fn any_field_contains(mut self, text: &str) -> Self {
+        self.conditions.push(FieldCondition {
+            field: None,
+            pattern: TextPattern::Contains(text.to_string()),
+        });
+        self
+    }
+
+    /// Require the field `field` to match the regular expression `pattern`
+    #[cfg(feature = "regex")]
+    pub fn field_matches(mut self, field: &str, pattern: &str) -> Result<Self> {
+        self.conditions.push(FieldCondition {
+            field: Some(field.to_string()),
+            pattern: TextPattern::Regex(Regex::new(pattern)?),
+        });
+        Ok(self)
+    }
+
+    /// Require any field to match the regular expression `pattern`
+    #[cfg(feature = "regex")]
+    pub fn any_field_matches(mut self, pattern: &str) -> Result<Self> {
+        self.conditions.push(FieldCondition {
+            field: None,
+            pattern: TextPattern::Regex(Regex::new(pattern)?),
+        });
+        Ok(self)
+    }
+
+    /// Require the entry to carry `tag`, ignoring case
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Require the host of the entry URL to be `host` or one of its subdomains
+    pub fn url_host(mut self, host: &str) -> Self {
+        self.url_host = Some(host.to_lowercase());
+        self
+    }
+
+    /// Only match entries that are expired (`true`) or not expired (`false`)
+    pub fn expired(mut self, expired: bool) -> Self {
+        self.expired = Some(expired);
+        self
+    }
+
+    /// Only match entries in the subtree of the group with the given UUID
+    pub fn in_group(mut self, group: Uuid) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    /// Whether to skip entries in the recycle bin, `true` by default
+    pub fn exclude_recycle_bin(mut self, exclude: bool) -> Self {
+        self.exclude_recycle_bin = exclude;
+        self
+    }
+
+    /// Whether to skip groups that have searching disabled, `true` by default
+    pub fn exclude_search_disabled(mut self, exclude: bool) -> Self {
+        self.exclude_search_disabled = exclude;
+        self
+    }
+
+    fn matches(&self, entry: &Entry) -> bool {
+        let conditions = self.conditions.iter().all(|condition| match &condition.field {
+            Some(field) => entry.reveal(field).is_some_and(|value| condition.pattern.matches(&value)),
+            None => entry
+                .fields
+                .keys()
+                .any(|field| entry.reveal(field).is_some_and(|value| condition.pattern.matches(&value))),
+        });
+
+        let tags = self
+            .tags
+            .iter()
+            .all(|tag| entry.get_tags().iter().any(|t| t.eq_ignore_ascii_case(tag)));
+
+        let url_host = match &self.url_host {
+            Some(host) => {
+                let url = entry.reveal("URL");
+                let entry_host = UrlParts::split(url.as_deref().unwrap_or_default()).host.to_lowercase();
+                entry_host == *host || entry_host.ends_with(&format!(".{host}"))
+            }
+            None => true,
+        };
+
+        let expired = match self.expired {
+            Some(expired) => {
+                let times = entry.get_times();
+                let is_expired = times.get_expires() && times.get_expiry_time().is_some_and(|time| time <= Times::now());
+                is_expired == expired
+            }
+            None => true,
+        };
+
+        conditions && tags && url_host && expired
+    }
+}
+
+/// An entry found by [`Database::search`]
+#[derive(Debug, Clone)]
+pub struct SearchMatch {
+    /// The matching entry
+    pub entry: NodePtr,
+
+    /// Titles of the groups from the root group (excluded) down to the group containing the entry,
+    /// as accepted by [`Group::get`]
+    pub group_path: Vec<String>,
+}
+
+impl Database {
+    /// Find all entries matching `query`, in tree order
+    pub fn search(&self, query: &EntryQuery) -> Vec<SearchMatch> {
+        let start = match query.group {
+            Some(uuid) => match search_node_by_uuid(&self.root, uuid) {
+                Some(group) => group,
+                None => return Vec::new(),
+            },
+            None => (&self.root).into(),
+        };
+
+        // the groups from the root group down to the parent of the start group
+        let ancestors = self
+            .node_get_parents(&start)
+            .into_iter()
+            .rev()
+            .filter_map(|uuid| search_node_by_uuid(&self.root, uuid))
+            .collect::<Vec<_>>();
+
+        let mut path = ancestors
+            .iter()
+            .skip(1)
+            .map(|group| group.borrow().get_title().unwrap_or_default().to_string())
+            .collect::<Vec<_>>();
+        if start.borrow().get_parent().is_some() {
+            path.push(start.borrow().get_title().unwrap_or_default().to_string());
+        }
+
+        let recycle_bin = if query.exclude_recycle_bin {
+            self.get_recycle_bin().map(|bin| bin.borrow().get_uuid())
+        } else {
+            None
+        };
+        if recycle_bin.is_some_and(|bin| ancestors.iter().any(|group| group.borrow().get_uuid() == bin)) {
+            return Vec::new();
+        }
+
+        let parent_searchable = ancestors.iter().fold(true, |parent_searchable, group| {
+            match group.borrow().as_any().downcast_ref::<Group>() {
+                Some(group) => group_searchable(group, parent_searchable),
+                None => parent_searchable,
+            }
+        });
+
+        let mut matches = Vec::new();
+        self.search_group(query, &start, &mut path, recycle_bin, parent_searchable, &mut matches);
+        matches
+    }
+
+    fn search_group(
+        &self,
+        query: &EntryQuery,
+        group: &NodePtr,
+        path: &mut Vec<String>,
+        recycle_bin: Option<Uuid>,
+        parent_searchable: bool,
+        matches: &mut Vec<SearchMatch>,
+    ) {
+        let searchable = {
+            let group = group.borrow();
+            let Some(group) = group.as_any().downcast_ref::<Group>() else {
+                return;
+            };
+            if Some(group.get_uuid()) == recycle_bin {
+                return;
+            }
+            group_searchable(group, parent_searchable)
+        };
+
+        for child in group_get_children(group).unwrap_or_default() {
+            if let Some(entry) = child.borrow().as_any().downcast_ref::<Entry>() {
+                if (searchable || !query.exclude_search_disabled) && query.matches(entry) {
+                    matches.push(SearchMatch {
+                        entry: child.clone(),
+                        group_path: path.clone(),
+                    });
+                }
+                continue;
+            }
+
+            path.push(child.borrow().get_title().unwrap_or_default().to_string());
+            self.search_group(query, &child, path, recycle_bin, searchable, matches);
+            path.pop();
+        }
+    }
+}
+
+/// Whether the entries of `group` are searchable, given whether the ones of its parent are
+fn group_searchable(group: &Group, parent_searchable: bool) -> bool {
+    // "null" inherits the setting of the parent group
+    match group.enable_searching.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        Some("true") => true,
+        Some("false") => false,
+        _ => parent_searchable,
+    }
+}
+
+#[cfg(test)]
+mod query_tests {
+    use chrono::Duration;
+
+    use super::EntryQuery;
+    use crate::{
+        config::DatabaseConfig,
+        db::{group_add_child, Database, Entry, Group, Node, NodePtr, Times},
+        rc_refcell_node,
+    };
+
+    fn add_entry(parent: &NodePtr, title: &str, url: &str, tags: &[&str]) -> NodePtr {
+        let mut entry = Entry::default();
+        entry.set_title(Some(title));
+        entry.set_url(Some(url));
+        entry.get_tags_mut().extend(tags.iter().map(|tag| tag.to_string()));
+        let entry = rc_refcell_node!(entry);
+        group_add_child(parent, entry.clone(), usize::MAX).unwrap();
+        entry
+    }
+
+    fn titles(db: &Database, query: &EntryQuery) -> Vec<String> {
+        db.search(query)
+            .iter()
+            .map(|m| m.entry.borrow().get_title().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    fn sample_database() -> Database {
+        let mut db = Database::new(DatabaseConfig::default());
+        add_entry(&db.root, "Root entry", "", &[]);
+        let internet = rc_refcell_node!(Group::new("Internet"));
+        group_add_child(&db.root, internet.clone(), usize::MAX).unwrap();
+        add_entry(&internet, "Webmail", "https://mail.example.com/", &["work", "mail"]);
+        add_entry(&internet, "Shop", "https://shop.example.org/", &["private"]);
+
+        let mut hidden = Group::new("Hidden");
+        hidden.enable_searching = Some("false".to_string());
+        let hidden = rc_refcell_node!(hidden);
+        group_add_child(&db.root, hidden.clone(), usize::MAX).unwrap();
+        add_entry(&hidden, "Hidden mail", "https://example.com", &[]);
+
+        db.set_recycle_bin_enabled(true);
+        let bin = db.create_recycle_bin().unwrap();
+        add_entry(&bin, "Deleted mail", "https://example.com", &[]);
+        db
+    }
+
+    #[test]
+    fn field_conditions() {
+        let db = sample_database();
+
+        assert_eq!(titles(&db, &EntryQuery::new().field_contains("Title", "MAIL")), ["Webmail"]);
+        assert_eq!(titles(&db, &EntryQuery::new().any_field_contains("example.org")), ["Shop"]);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_conditions() {
+        let db = sample_database();
+
+        let query = EntryQuery::new().field_matches("Title", "^(Root|Shop)").unwrap();
+        assert_eq!(titles(&db, &query), ["Root entry", "Shop"]);
+        assert!(EntryQuery::new().any_field_matches("(").is_err());
+    }
+
+    #[test]
+    fn tags_hosts_and_groups() {
+        let db = sample_database();
+
+        assert_eq!(titles(&db, &EntryQuery::new().with_tag("WORK")), ["Webmail"]);
+        assert!(titles(&db, &EntryQuery::new().with_tag("work").with_tag("private")).is_empty());
+        assert_eq!(titles(&db, &EntryQuery::new().url_host("example.com")), ["Webmail"]);
+
+        let internet = Group::get(&db.root, &["Internet"]).unwrap().borrow().get_uuid();
+        let matches = db.search(&EntryQuery::new().in_group(internet));
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].group_path, ["Internet"]);
+    }
+
+    #[test]
+    fn excluded_groups() {
+        let db = sample_database();
+        let query = EntryQuery::new().field_contains("Title", "mail");
+
+        assert_eq!(
+            titles(&db, &query.clone().exclude_search_disabled(false)),
+            ["Webmail", "Hidden mail"]
+        );
+        assert_eq!(titles(&db, &query.clone().exclude_recycle_bin(false)), ["Webmail", "Deleted mail"]);
+
+        // starting below an excluded group keeps excluding it
+        let hidden = Group::get(&db.root, &["Hidden"]).unwrap();
+        let archive = rc_refcell_node!(Group::new("Archive"));
+        group_add_child(&hidden, archive.clone(), usize::MAX).unwrap();
+        add_entry(&archive, "Archived mail", "", &[]);
+        let archive = archive.borrow().get_uuid();
+        assert!(titles(&db, &query.clone().in_group(archive)).is_empty());
+        assert_eq!(
+            titles(&db, &query.clone().in_group(archive).exclude_search_disabled(false)),
+            ["Archived mail"]
+        );
+
+        let bin = db.get_recycle_bin().unwrap();
+        let trash = rc_refcell_node!(Group::new("Trash"));
+        group_add_child(&bin, trash.clone(), usize::MAX).unwrap();
+        add_entry(&trash, "Trashed mail", "", &[]);
+        let trash = trash.borrow().get_uuid();
+        assert!(titles(&db, &query.clone().in_group(trash)).is_empty());
+        assert_eq!(
+            titles(&db, &query.clone().in_group(trash).exclude_recycle_bin(false)),
+            ["Trashed mail"]
+        );
+    }
+
+    #[test]
+    fn expired_entries() {
+        let db = sample_database();
+        let shop = Group::get(&db.root, &["Internet", "Shop"]).unwrap();
+        let times = shop.borrow_mut().get_times_mut().clone();
+        let mut times = Times { expires: true, ..times };
+        times.set_expiry_time(Some(Times::now() - Duration::days(1)));
+        *shop.borrow_mut().get_times_mut() = times;
+
+        assert_eq!(titles(&db, &EntryQuery::new().expired(true)), ["Shop"]);
+        assert_eq!(titles(&db, &EntryQuery::new().expired(false)), ["Root entry", "Webmail"]);
+    }
+}
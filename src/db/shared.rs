@@ -0,0 +1,433 @@
+//! A thread-safe representation of a database
+//!
+//! [`Database`] stores its tree as `Rc<RefCell<dyn Node>>`, which can neither be sent to another
+//! thread nor shared between threads. [`SharedDatabase`] holds the same data in an arena of nodes
+//! that refer to their children by [`NodeId`], so it is `Send + Sync` and can be put behind an
+//! `Arc` (or an `Arc<RwLock<_>>` for concurrent writes).
+//!
+//! ```no_run
+//! use std::{fs::File, sync::Arc};
+//!
+//! use keepass_ng::{
+//!     db::{Node, SharedDatabase},
+//!     DatabaseKey,
+//! };
+//!
+//! let worker = std::thread::spawn(|| {
+//!     let mut file = File::open("tests/resources/test_db_with_password.kdbx")?;
+//!     SharedDatabase::open(&mut file, DatabaseKey::new().with_password("demopass"))
+//! });
+//! let db = Arc::new(worker.join().unwrap()?);
+//! let titles: Vec<String> = db.entries().filter_map(|entry| entry.get_title().map(str::to_string)).collect();
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::{
+    config::DatabaseConfig,
+    db::{
+        group_add_child, iconid::IconId, CustomData, Database, DeletedObjects, Entry, Group, HeaderAttachment, Meta, Node, NodePtr, Times,
+        UnknownXml,
+    },
+    error::DatabaseOpenError,
+    key::DatabaseKey,
+    rc_refcell_node,
+    variant_dictionary::VariantDictionary,
+};
+
+/// Index of a node in a [`SharedDatabase`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct NodeId(usize);
+
+/// A group of a [`SharedDatabase`], referring to its children by their [`NodeId`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct SharedGroup {
+    /// The unique identifier of the group
+    pub uuid: Uuid,
+
+    /// The name of the group
+    pub name: String,
+
+    /// Notes for the group
+    pub notes: Option<String>,
+
+    /// ID of the group's icon
+    pub icon_id: Option<IconId>,
+
+    /// UUID for a custom group icon
+    pub custom_icon_uuid: Option<Uuid>,
+
+    /// The child nodes (Groups or Entries), in order
+    pub children: Vec<NodeId>,
+
+    /// The list of time fields for this group
+    pub times: Times,
+
+    /// Custom Data
+    pub custom_data: CustomData,
+
+    /// Whether the group is expanded in the user interface
+    pub is_expanded: bool,
+
+    /// Default autotype sequence
+    pub default_autotype_sequence: Option<String>,
+
+    /// Whether autotype is enabled
+    pub enable_autotype: Option<String>,
+
+    /// Whether searching is enabled
+    pub enable_searching: Option<String>,
+
+    /// UUID for the last top visible entry
+    pub last_top_visible_entry: Option<Uuid>,
+
+    /// Tags of the group (KDBX 4.1)
+    pub tags: Vec<String>,
+
+    /// UUID of the group this group was in before it was last moved (KDBX 4.1)
+    pub previous_parent_group: Option<Uuid>,
+
+    /// XML elements and attributes of the group that are not known to this crate
+    pub unknown_xml: UnknownXml,
+
+    /// UUID of the parent group
+    pub parent: Option<Uuid>,
+}
+
+impl SharedGroup {
+    fn from_group(group: &Group, children: Vec<NodeId>) -> Self {
+        SharedGroup {
+            uuid: group.uuid,
+            name: group.name.clone(),
+            notes: group.notes.clone(),
+            icon_id: group.icon_id,
+            custom_icon_uuid: group.custom_icon_uuid,
+            children,
+            times: group.times.clone(),
+            custom_data: group.custom_data.clone(),
+            is_expanded: group.is_expanded,
+            default_autotype_sequence: group.default_autotype_sequence.clone(),
+            enable_autotype: group.enable_autotype.clone(),
+            enable_searching: group.enable_searching.clone(),
+            last_top_visible_entry: group.last_top_visible_entry,
+            tags: group.tags.clone(),
+            previous_parent_group: group.previous_parent_group,
+            unknown_xml: group.unknown_xml.clone(),
+            parent: group.parent,
+        }
+    }
+
+    /// Create a `Group` with the same properties but without children
+    fn to_group(&self) -> Group {
+        Group {
+            uuid: self.uuid,
+            name: self.name.clone(),
+            notes: self.notes.clone(),
+            icon_id: self.icon_id,
+            custom_icon_uuid: self.custom_icon_uuid,
+            children: Vec::new(),
+            times: self.times.clone(),
+            custom_data: self.custom_data.clone(),
+            is_expanded: self.is_expanded,
+            default_autotype_sequence: self.default_autotype_sequence.clone(),
+            enable_autotype: self.enable_autotype.clone(),
+            enable_searching: self.enable_searching.clone(),
+            last_top_visible_entry: self.last_top_visible_entry,
+            tags: self.tags.clone(),
+            previous_parent_group: self.previous_parent_group,
+            unknown_xml: self.unknown_xml.clone(),
+            parent: self.parent,
+        }
+    }
+}
+
+/// A node of a [`SharedDatabase`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum SharedNode {
+    Group(SharedGroup),
+    Entry(Entry),
+}
+
+impl SharedNode {
+    pub fn get_uuid(&self) -> Uuid {
+        match self {
+            SharedNode::Group(group) => group.uuid,
+            SharedNode::Entry(entry) => entry.get_uuid(),
+        }
+    }
+
+    pub fn as_group(&self) -> Option<&SharedGroup> {
+        match self {
+            SharedNode::Group(group) => Some(group),
+            SharedNode::Entry(_) => None,
+        }
+    }
+
+    pub fn as_group_mut(&mut self) -> Option<&mut SharedGroup> {
+        match self {
+            SharedNode::Group(group) => Some(group),
+            SharedNode::Entry(_) => None,
+        }
+    }
+
+    pub fn as_entry(&self) -> Option<&Entry> {
+        match self {
+            SharedNode::Entry(entry) => Some(entry),
+            SharedNode::Group(_) => None,
+        }
+    }
+
+    pub fn as_entry_mut(&mut self) -> Option<&mut Entry> {
+        match self {
+            SharedNode::Entry(entry) => Some(entry),
+            SharedNode::Group(_) => None,
+        }
+    }
+}
+
+/// A decrypted `KeePass` database that can be sent to and shared between threads.
+///
+/// Convert from and to [`Database`] with `From`/`Into` to use the rest of the API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct SharedDatabase {
+    /// Configuration settings of the database such as encryption and compression algorithms
+    pub config: DatabaseConfig,
+
+    /// Binary attachments in the inner header that are not referenced by any entry
+    pub header_attachments: Vec<HeaderAttachment>,
+
+    /// References to previously-deleted objects
+    pub deleted_objects: DeletedObjects,
+
+    /// Metadata of the KeePass database
+    pub meta: Meta,
+
+    /// Custom data in the unencrypted outer header of KDBX4 files
+    pub public_custom_data: VariantDictionary,
+
+    /// Unknown XML elements and attributes of the `Root` element of KDBX databases
+    pub unknown_xml: UnknownXml,
+
+    nodes: Vec<SharedNode>,
+
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    index: HashMap<Uuid, NodeId>,
+}
+
+impl SharedDatabase {
+    /// Open and parse a database from a `std::io::Read`, see [`Database::open`]
+    pub fn open(source: &mut dyn std::io::Read, key: DatabaseKey) -> Result<SharedDatabase, DatabaseOpenError> {
+        Database::open(source, key).map(SharedDatabase::from)
+    }
+
+    /// Save the database to a `std::io::Write`, see [`Database::save`]
+    #[cfg(feature = "save_kdbx4")]
+    pub fn save(&self, destination: &mut dyn std::io::Write, key: DatabaseKey) -> Result<(), crate::error::DatabaseSaveError> {
+        Database::from(self).save(destination, key)
+    }
+
+    /// The root group of the database
+    pub fn root(&self) -> Option<&SharedGroup> {
+        self.get_group(self.root_id())
+    }
+
+    /// The ID of the root group
+    pub fn root_id(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&SharedNode> {
+        self.nodes.get(id.0)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut SharedNode> {
+        self.nodes.get_mut(id.0)
+    }
+
+    pub fn get_group(&self, id: NodeId) -> Option<&SharedGroup> {
+        self.get(id).and_then(SharedNode::as_group)
+    }
+
+    pub fn get_entry(&self, id: NodeId) -> Option<&Entry> {
+        self.get(id).and_then(SharedNode::as_entry)
+    }
+
+    /// Find the ID of the node with the given UUID
+    pub fn find(&self, uuid: Uuid) -> Option<NodeId> {
+        self.index.get(&uuid).copied()
+    }
+
+    /// The children of a group, in order
+    pub fn children(&self, group: &SharedGroup) -> impl Iterator<Item = (NodeId, &SharedNode)> + '_ {
+        group.children.clone().into_iter().filter_map(|id| Some((id, self.get(id)?)))
+    }
+
+    /// All nodes of the database, in tree order starting with the root group
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &SharedNode)> + '_ {
+        self.nodes.iter().enumerate().map(|(index, node)| (NodeId(index), node))
+    }
+
+    /// All entries of the database, in tree order
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> + '_ {
+        self.nodes.iter().filter_map(SharedNode::as_entry)
+    }
+
+    /// Add the node `node` and its descendants to the arena, in tree order
+    fn add_node(&mut self, node: &NodePtr) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        let node = node.borrow();
+        self.index.entry(node.get_uuid()).or_insert(id);
+
+        if let Some(entry) = node.as_any().downcast_ref::<Entry>() {
+            self.nodes.push(SharedNode::Entry(entry.clone()));
+        } else if let Some(group) = node.as_any().downcast_ref::<Group>() {
+            self.nodes.push(SharedNode::Group(SharedGroup::from_group(group, Vec::new())));
+            let children = group.children.iter().map(|child| self.add_node(child)).collect();
+            if let Some(SharedNode::Group(shared)) = self.nodes.get_mut(id.0) {
+                shared.children = children;
+            }
+        }
+        id
+    }
+
+    /// Rebuild the subtree of the node with the given ID
+    fn build_node(&self, id: NodeId) -> Option<NodePtr> {
+        match self.get(id)? {
+            SharedNode::Entry(entry) => Some(rc_refcell_node!(entry.clone())),
+            SharedNode::Group(group) => {
+                let node = rc_refcell_node!(group.to_group());
+                for child in group.children.iter().filter_map(|child| self.build_node(*child)) {
+                    // adding to a group cannot fail
+                    _ = group_add_child(&node, child, usize::MAX);
+                }
+                Some(node)
+            }
+        }
+    }
+}
+
+impl From<&Database> for SharedDatabase {
+    fn from(db: &Database) -> Self {
+        let mut shared = SharedDatabase {
+            config: db.config.clone(),
+            header_attachments: db.header_attachments.clone(),
+            deleted_objects: db.deleted_objects.clone(),
+            meta: db.meta.clone(),
+            public_custom_data: db.public_custom_data.clone(),
+            unknown_xml: db.unknown_xml.clone(),
+            nodes: Vec::new(),
+            index: HashMap::new(),
+        };
+        shared.add_node(&db.root);
+        shared
+    }
+}
+
+impl From<Database> for SharedDatabase {
+    fn from(db: Database) -> Self {
+        SharedDatabase::from(&db)
+    }
+}
+
+impl From<&SharedDatabase> for Database {
+    fn from(shared: &SharedDatabase) -> Self {
+        let root = shared
+            .build_node(shared.root_id())
+            .unwrap_or_else(|| rc_refcell_node!(Group::new("Root")));
+        Database {
+            config: shared.config.clone(),
+            header_attachments: shared.header_attachments.clone(),
+            root: root.into(),
+            deleted_objects: shared.deleted_objects.clone(),
+            meta: shared.meta.clone(),
+            public_custom_data: shared.public_custom_data.clone(),
+            unknown_xml: shared.unknown_xml.clone(),
+        }
+    }
+}
+
+impl From<SharedDatabase> for Database {
+    fn from(shared: SharedDatabase) -> Self {
+        Database::from(&shared)
+    }
+}
+
+#[cfg(test)]
+mod shared_tests {
+    use std::sync::{Arc, RwLock};
+
+    use super::{SharedDatabase, SharedNode};
+    use crate::{
+        config::DatabaseConfig,
+        db::{group_add_child, Database, Entry, Group, Node, NodePtr},
+        rc_refcell_node,
+    };
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    fn sample_database() -> Database {
+        let db = Database::new(DatabaseConfig::default());
+        let group = rc_refcell_node!(Group::new("Internet"));
+        group_add_child(&db.root, group.clone(), usize::MAX).unwrap();
+        for title in ["Mail", "Shop"] {
+            let mut entry = Entry::default();
+            entry.set_title(Some(title));
+            entry.set_password(Some("secret"));
+            group_add_child(&group, rc_refcell_node!(entry), usize::MAX).unwrap();
+        }
+        let mut entry = Entry::default();
+        entry.set_title(Some("Root entry"));
+        group_add_child(&db.root, rc_refcell_node!(entry), usize::MAX).unwrap();
+        db
+    }
+
+    #[test]
+    fn conversions() {
+        assert_send_sync::<SharedDatabase>();
+
+        let db = sample_database();
+        let shared = SharedDatabase::from(&db);
+
+        let root = shared.root().unwrap();
+        assert_eq!(root.children.len(), 2);
+        let (internet_id, internet) = shared.children(root).next().unwrap();
+        assert_eq!(internet.as_group().unwrap().name, "Internet");
+        assert_eq!(shared.find(internet.get_uuid()), Some(internet_id));
+        let titles: Vec<_> = shared.entries().map(|entry| entry.get_title().unwrap()).collect();
+        assert_eq!(titles, ["Mail", "Shop", "Root entry"]);
+
+        assert_eq!(Database::from(&shared), db);
+        assert_eq!(SharedDatabase::from(Database::from(shared.clone())), shared);
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let shared = std::thread::spawn(|| SharedDatabase::from(sample_database())).join().unwrap();
+        let shared = Arc::new(RwLock::new(shared));
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                std::thread::spawn(move || shared.read().unwrap().entries().count())
+            })
+            .collect();
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), 3);
+        }
+
+        let mut db = shared.write().unwrap();
+        let id = db.find(db.entries().next().unwrap().get_uuid()).unwrap();
+        if let Some(SharedNode::Entry(entry)) = db.get_mut(id) {
+            entry.set_title(Some("Renamed"));
+        }
+        let db = Database::from(&*db);
+        assert!(Group::get(&db.root, &["Internet", "Renamed"]).is_some());
+    }
+}
@@ -48,6 +48,16 @@ pub enum Error {
     #[error("ParseColorError {0}")]
     ParseColorError(#[from] ParseColorError),
 
+    #[cfg(feature = "regex")]
+    #[error("Regex error {0}")]
+    Regex(#[from] regex::Error),
+
+    #[error("FieldReferenceError {0}")]
+    FieldReferenceError(#[from] FieldReferenceError),
+
+    #[error("DatabaseKeyError {0}")]
+    DatabaseKeyError(#[from] DatabaseKeyError),
+
     #[error("ParseIconIdError {}", icon_id)]
     ParseIconIdError { icon_id: usize },
 
@@ -95,6 +105,50 @@ pub enum DatabaseOpenError {
     /// The database version cannot be read by this library
     #[error("Opening this database version is not supported")]
     UnsupportedVersion,
+
+    /// Opening the database was cancelled through its `CancellationToken`
+    #[error("Opening the database was cancelled")]
+    Cancelled,
+
+    /// The database exceeds one of the limits it was opened with
+    #[error(transparent)]
+    ResourceLimit(#[from] ResourceLimitError),
+}
+
+/// Errors raised when a database exceeds the `OpenLimits` it is opened with
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ResourceLimitError {
+    /// The AES-KDF asks for more rounds than allowed
+    #[error("The key derivation asks for {rounds} AES rounds, more than the limit of {limit}")]
+    AesRounds { rounds: u64, limit: u64 },
+
+    /// Argon2 asks for more memory than allowed
+    #[error("The key derivation asks for {memory} bytes of memory, more than the limit of {limit}")]
+    Argon2Memory { memory: u64, limit: u64 },
+
+    /// Argon2 asks for more iterations than allowed
+    #[error("The key derivation asks for {iterations} iterations, more than the limit of {limit}")]
+    Argon2Iterations { iterations: u64, limit: u64 },
+
+    /// Argon2 asks for more lanes than allowed
+    #[error("The key derivation asks for a parallelism of {parallelism}, more than the limit of {limit}")]
+    Argon2Parallelism { parallelism: u32, limit: u32 },
+
+    /// The decompressed payload is larger than allowed
+    #[error("The decompressed payload is larger than the limit of {limit} bytes")]
+    PayloadSize { limit: u64 },
+
+    /// An attachment is larger than allowed
+    #[error("An attachment of {size} bytes is larger than the limit of {limit}")]
+    AttachmentSize { size: u64, limit: u64 },
+
+    /// The XML document is nested deeper than allowed
+    #[error("The XML document is nested deeper than {limit} levels")]
+    XmlDepth { limit: usize },
+
+    /// The database contains more entries than allowed
+    #[error("The database contains more than {limit} entries")]
+    Entries { limit: usize },
 }
 
 /// Errors stemming from corrupted databases
@@ -123,12 +177,22 @@ pub enum DatabaseIntegrityError {
         expected_field_size: u32,
     },
 
+    #[error("Incomplete field: {} bytes left, field needs {}", remaining, field_size)]
+    IncompleteKDBField { remaining: usize, field_size: usize },
+
     #[error("Missing group level")]
     MissingKDBGroupLevel,
 
     #[error("Invalid KDBX header field ID: {}", field_id)]
     InvalidKDBXHeaderFieldID { field_id: u8 },
 
+    #[error("Invalid length of header field {}: {} (expected {})", field_id, field_size, expected_field_size)]
+    InvalidHeaderFieldLength {
+        field_id: u8,
+        field_size: usize,
+        expected_field_size: usize,
+    },
+
     #[error("Invalid group level {} (current level {})", group_level, current_level)]
     InvalidKDBGroupLevel { group_level: u16, current_level: u16 },
 
@@ -203,6 +267,18 @@ pub enum DatabaseSaveError {
     #[error("Saving this database version is not supported")]
     UnsupportedVersion,
 
+    /// The configured key derivation function cannot be used with the database version
+    #[error("The key derivation function is not supported by this database version")]
+    UnsupportedKdf,
+
+    /// The configured outer cipher cannot be used with the database version
+    #[error("The cipher is not supported by this database version")]
+    UnsupportedCipher,
+
+    /// The database contains something that a `KeePass` 1.x database cannot store
+    #[error("Cannot save as KDB: {0}")]
+    UnsupportedByKdb(&'static str),
+
     /// Error while writing out the inner XML database
     #[error("Error while generating XML")]
     Xml(#[from] xml::writer::Error),
@@ -219,9 +295,25 @@ pub enum DatabaseSaveError {
     #[error(transparent)]
     Cryptography(#[from] CryptographyError),
 
+    /// The configured outer cipher is not available
+    #[error(transparent)]
+    OuterCipher(#[from] OuterCipherConfigError),
+
+    /// The configured inner cipher is not available
+    #[error(transparent)]
+    InnerCipher(#[from] InnerCipherConfigError),
+
+    /// The configured key derivation function is not available
+    #[error(transparent)]
+    KdfSettings(#[from] KdfConfigError),
+
     /// An error getting randomness for keys occurred
     #[error(transparent)]
     Random(#[from] getrandom::Error),
+
+    /// Saving the database was cancelled through its `CancellationToken`
+    #[error("Saving the database was cancelled")]
+    Cancelled,
 }
 
 /// Errors related to the database key
@@ -231,6 +323,10 @@ pub enum DatabaseKeyError {
     #[error("Incorrect key")]
     IncorrectKey,
 
+    /// The key does not contain any password, keyfile or challenge-response key
+    #[error("The key does not contain any key component")]
+    EmptyKey,
+
     /// An error occurred in an underlying cryptographic operation while computing the key
     #[error(transparent)]
     Cryptography(#[from] CryptographyError),
@@ -247,6 +343,17 @@ pub enum DatabaseKeyError {
     #[error("Could not obtain a key from the keyfile")]
     InvalidKeyFile,
 
+    /// The hash stored in a version 2 XML keyfile does not match its key data
+    #[error("The keyfile is corrupted: the hash of its key data does not match")]
+    KeyFileHashMismatch,
+
+    /// The key only consists of a transformed key, which was derived with other KDF parameters or
+    /// another KDF seed than the database uses, the transformed key stems from another password,
+    /// keyfile or challenge-response key than the rest of the key, or a transformed key was passed
+    /// as a new key
+    #[error("The transformed key does not match the key derivation parameters of the database")]
+    TransformedKeyMismatch,
+
     /// Could not get challenge response key.
     #[cfg(feature = "challenge_response")]
     #[error("Error with the challenge-response key: {0}")]
@@ -308,6 +415,10 @@ pub enum CryptographyError {
 
     #[error(transparent)]
     Argon2(#[from] argon2::Error),
+
+    /// An error of a cipher or KDF added to the [`registry`](crate::registry)
+    #[error(transparent)]
+    Other(#[from] BoxError),
 }
 
 /// Errors reading from the HMAC block stream
@@ -318,6 +429,9 @@ pub enum BlockStreamError {
 
     #[error("Block hash mismatch for block {}", block_index)]
     BlockHashMismatch { block_index: u64 },
+
+    #[error("Incomplete block {}", block_index)]
+    IncompleteBlock { block_index: u64 },
 }
 
 /// Errors while parsing a `VariantDictionary`
@@ -337,6 +451,12 @@ pub enum VariantDictionaryError {
 
     #[error("VariantDictionary did not end with null byte, when it should")]
     NotTerminated,
+
+    #[error("VariantDictionary ended in the middle of an item")]
+    Incomplete,
+
+    #[error("Invalid length of value {}: {}", key, length)]
+    InvalidValueLength { key: String, length: usize },
 }
 
 /// Errors while parsing the XML document inside of a `KeePass` database
@@ -369,6 +489,10 @@ pub enum XmlParseError {
     #[error("Decompression error: {}", _0)]
     Compression(#[source] std::io::Error),
 
+    /// A timestamp is out of the range of supported dates or its binary form has the wrong length
+    #[error("Invalid timestamp: '{}'", _0)]
+    InvalidTimestamp(String),
+
     /// An unexpected XML event occurred, such as opening an unexpected tag, or an error in the
     /// underlying XML reader
     #[error("Bad XML event: expected {}, got {:?}", expected, event)]
@@ -380,6 +504,14 @@ pub enum XmlParseError {
     /// The stream of XML events ended when more events were expected
     #[error("Unexpected end of XML document")]
     Eof,
+
+    /// The XML document exceeds the limits it is parsed with
+    #[error(transparent)]
+    ResourceLimit(#[from] ResourceLimitError),
+
+    /// An element or attribute that is not known to this crate occurred while unknown XML is rejected
+    #[error("Unknown XML in {}: {}", parent, xml)]
+    UnknownXml { parent: &'static str, xml: String },
 }
 
 /// Error parsing a color code
@@ -387,6 +519,18 @@ pub enum XmlParseError {
 #[error("Cannot parse color: '{}'", _0)]
 pub struct ParseColorError(pub String);
 
+/// Errors while resolving field references such as `{REF:P@I:<uuid>}`
+#[derive(Debug, thiserror::Error)]
+pub enum FieldReferenceError {
+    /// A field reference points back to a field that is being resolved already
+    #[error("Field reference {reference} forms a cycle")]
+    Cycle { reference: String },
+
+    /// Field references are nested deeper than the resolver allows
+    #[error("Field references are nested deeper than {limit} levels")]
+    RecursionLimit { limit: usize },
+}
+
 // move error type conversions to a module and exclude them from coverage counting.
 mod conversions {
     use super::{
@@ -408,7 +552,10 @@ mod conversions {
 
     impl From<XmlParseError> for DatabaseOpenError {
         fn from(e: XmlParseError) -> Self {
-            DatabaseIntegrityError::from(e).into()
+            match e {
+                XmlParseError::ResourceLimit(e) => e.into(),
+                e => DatabaseIntegrityError::from(e).into(),
+            }
         }
     }
 
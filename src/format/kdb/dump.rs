@@ -0,0 +1,501 @@
+use std::{collections::HashSet, io::Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use zeroize::Zeroizing;
+
+use crate::{
+    config::{KdfConfig, OuterCipherConfig},
+    crypt::calculate_sha256,
+    db::{group_get_children, iconid::IconId, node_is_group, Database, Entry, Group, KdbMetaStream, Node, NodePtr, Times},
+    error::{DatabaseKeyError, DatabaseSaveError},
+    format::{
+        kdb::{
+            composite_key, entry_field_name, get_group_id, never_expires, FLAG_RIJNDAEL, FLAG_TWOFISH, GROUP_FLAG_EXPANDED,
+            META_STREAM_BINARY_DESC, META_STREAM_TITLE, META_STREAM_URL, META_STREAM_USERNAME,
+        },
+        DatabaseVersion, KDBX_IDENTIFIER, KEEPASS_1_ID,
+    },
+    key::DatabaseKey,
+    progress::{ProgressMonitor, ProgressStage, ProgressWriter},
+};
+
+const FLAG_SHA2: u32 = 1;
+
+/// The file version written by `KeePass` 1.x
+const KDB_VERSION: u32 = 0x0003_0004;
+
+/// Pack a timestamp into 5 bytes, the inverse of `unpack_time`
+fn pack_time(time: NaiveDateTime) -> [u8; 5] {
+    #[allow(clippy::cast_sign_loss)]
+    let year = time.year().clamp(0, 0x3fff) as u32;
+    let (month, day) = (time.month(), time.day());
+    let (hour, minute, second) = (time.hour(), time.minute(), time.second().min(59));
+    #[allow(clippy::cast_possible_truncation)]
+    [
+        (year >> 6) as u8,
+        (((year & 0x3f) << 2) | (month >> 2)) as u8,
+        (((month & 0x03) << 6) | (day << 1) | (hour >> 4)) as u8,
+        (((hour & 0x0f) << 4) | (minute >> 2)) as u8,
+        (((minute & 0x03) << 6) | second) as u8,
+    ]
+}
+
+/// Write a field of type `field_type: u16`, `field_size: u32` and `field_value: [u8; field_size]`
+fn write_field(data: &mut Vec<u8>, field_type: u16, field_value: &[u8]) -> Result<(), DatabaseSaveError> {
+    let field_size = u32::try_from(field_value.len()).map_err(|_| DatabaseSaveError::UnsupportedByKdb("fields of 4 GiB or more"))?;
+    data.write_u16::<LittleEndian>(field_type)?;
+    data.write_u32::<LittleEndian>(field_size)?;
+    data.write_all(field_value)?;
+    Ok(())
+}
+
+/// Write a string field as a null-terminated UTF-8 string
+fn write_string_field(data: &mut Vec<u8>, field_type: u16, field_value: &str) -> Result<(), DatabaseSaveError> {
+    let mut field_value = Zeroizing::new(field_value.as_bytes().to_vec());
+    field_value.push(0);
+    write_field(data, field_type, &field_value)
+}
+
+/// Write the four timestamps of a group or entry, starting with the field type `first_field_type`.
+/// Missing timestamps are written as zeroes, which are read back as missing.
+fn write_time_fields(data: &mut Vec<u8>, first_field_type: u16, times: &Times) -> Result<(), DatabaseSaveError> {
+    let expiry_time = if times.get_expires() {
+        times.get_expiry_time().or_else(|| Some(never_expires()))
+    } else {
+        Some(never_expires())
+    };
+    let times = [
+        times.get_creation(),
+        times.get_last_modification(),
+        times.get_last_access(),
+        expiry_time,
+    ];
+    for (field_type, time) in (first_field_type..).zip(times) {
+        write_field(data, field_type, &time.map(pack_time).unwrap_or_default())?;
+    }
+    Ok(())
+}
+
+fn write_icon_field(data: &mut Vec<u8>, field_type: u16, icon_id: Option<IconId>) -> Result<(), DatabaseSaveError> {
+    let icon_id = icon_id.and_then(|icon_id| u32::try_from(icon_id.0).ok()).unwrap_or_default();
+    write_field(data, field_type, &icon_id.to_le_bytes())
+}
+
+/// Collect the groups below `group` in the order of the file, together with their level
+fn collect_groups(group: &NodePtr, level: usize, groups: &mut Vec<(NodePtr, usize)>) {
+    for child in group_get_children(group).unwrap_or_default() {
+        if node_is_group(&child) {
+            groups.push((child.clone(), level));
+            collect_groups(&child, level + 1, groups);
+        }
+    }
+}
+
+/// Assign a group ID to each group, keeping the IDs of groups read from KDB files
+fn assign_group_ids(groups: &[(NodePtr, usize)]) -> Vec<u32> {
+    let mut used = HashSet::new();
+    let mut group_ids: Vec<Option<u32>> = groups
+        .iter()
+        .map(|(group, _)| {
+            let group = group.borrow();
+            group
+                .as_any()
+                .downcast_ref::<Group>()
+                .and_then(get_group_id)
+                .filter(|group_id| *group_id != 0 && *group_id != u32::MAX && used.insert(*group_id))
+        })
+        .collect();
+
+    let mut next_group_id = 1;
+    for group_id in group_ids.iter_mut().filter(|group_id| group_id.is_none()) {
+        while used.contains(&next_group_id) {
+            next_group_id += 1;
+        }
+        used.insert(next_group_id);
+        *group_id = Some(next_group_id);
+    }
+    group_ids.into_iter().flatten().collect()
+}
+
+fn dump_group(data: &mut Vec<u8>, group: &Group, group_id: u32, level: usize) -> Result<(), DatabaseSaveError> {
+    let level = u16::try_from(level).map_err(|_| DatabaseSaveError::UnsupportedByKdb("groups nested too deeply"))?;
+    write_field(data, 0x0001, &group_id.to_le_bytes())?;
+    write_string_field(data, 0x0002, group.get_title().unwrap_or_default())?;
+    write_time_fields(data, 0x0003, &group.times)?;
+    write_icon_field(data, 0x0007, group.icon_id)?;
+    write_field(data, 0x0008, &level.to_le_bytes())?;
+    let flags = if group.is_expanded { GROUP_FLAG_EXPANDED } else { 0 };
+    write_field(data, 0x0009, &flags.to_le_bytes())?;
+    write_field(data, 0xffff, &[])
+}
+
+fn dump_entry(data: &mut Vec<u8>, entry: &Entry, group_id: u32) -> Result<(), DatabaseSaveError> {
+    let attachment = match entry.attachments.as_slice() {
+        [] => None,
+        [attachment] => Some(attachment),
+        _ => return Err(DatabaseSaveError::UnsupportedByKdb("entries with more than one attachment")),
+    };
+
+    write_field(data, 0x0001, entry.uuid.as_bytes())?;
+    write_field(data, 0x0002, &group_id.to_le_bytes())?;
+    write_icon_field(data, 0x0003, entry.icon_id)?;
+    for field_type in 0x0004..=0x0008 {
+        if let Some(name) = entry_field_name(field_type) {
+            write_string_field(data, field_type, entry.reveal(name).as_deref().unwrap_or_default())?;
+        }
+    }
+    write_time_fields(data, 0x0009, &entry.times)?;
+    write_string_field(data, 0x000d, attachment.map(|a| a.get_name()).unwrap_or_default())?;
+    write_field(data, 0x000e, attachment.map(|a| a.get_content()).unwrap_or_default())?;
+    write_field(data, 0xffff, &[])
+}
+
+fn dump_meta_stream(data: &mut Vec<u8>, meta_stream: &KdbMetaStream, group_id: u32) -> Result<(), DatabaseSaveError> {
+    let mut entry = Entry {
+        times: Times::default(),
+        icon_id: Some(IconId(0)),
+        ..Entry::default()
+    };
+    entry.set_field("Title", META_STREAM_TITLE, false);
+    entry.set_field("URL", META_STREAM_URL, false);
+    entry.set_field("UserName", META_STREAM_USERNAME, false);
+    entry.set_field("Notes", &meta_stream.name, false);
+    entry.add_attachment(META_STREAM_BINARY_DESC, meta_stream.content.clone(), false);
+    dump_entry(data, &entry, group_id)
+}
+
+/// Dump a `KeePass` 1.x database.
+///
+/// KDB files cannot store entries outside of groups, more than one attachment per entry, custom
+/// fields, tags, history or auto-type settings. Saving fails for the former two, the latter are
+/// left out of the file.
+pub(crate) fn dump_kdb(
+    db: &Database,
+    db_key: &DatabaseKey,
+    writer: &mut dyn Write,
+    monitor: &ProgressMonitor,
+) -> Result<(), DatabaseSaveError> {
+    if !matches!(db.config.version, DatabaseVersion::KDB(_)) {
+        return Err(DatabaseSaveError::UnsupportedVersion);
+    }
+
+    let transform_rounds = match db.config.kdf_config {
+        KdfConfig::Aes { rounds } => u32::try_from(rounds).map_err(|_| DatabaseSaveError::UnsupportedKdf)?,
+        _ => return Err(DatabaseSaveError::UnsupportedKdf),
+    };
+
+    let flags = FLAG_SHA2
+        | match db.config.outer_cipher_config {
+            OuterCipherConfig::AES256 => FLAG_RIJNDAEL,
+            OuterCipherConfig::Twofish => FLAG_TWOFISH,
+            _ => return Err(DatabaseSaveError::UnsupportedCipher),
+        };
+
+    let root = db.root.borrow();
+    let root = root
+        .as_any()
+        .downcast_ref::<Group>()
+        .ok_or(DatabaseSaveError::UnsupportedByKdb("root that is not a group"))?;
+    if !root.entries().is_empty() {
+        return Err(DatabaseSaveError::UnsupportedByKdb("entries outside of groups"));
+    }
+
+    // serialize the groups and then the entries, with the meta-streams at the end
+    let mut groups = Vec::new();
+    collect_groups(&db.root, 0, &mut groups);
+    let group_ids = assign_group_ids(&groups);
+
+    let mut payload = Zeroizing::new(Vec::new());
+    for ((group, level), group_id) in groups.iter().zip(&group_ids) {
+        if let Some(group) = group.borrow().as_any().downcast_ref::<Group>() {
+            dump_group(&mut payload, group, *group_id, *level)?;
+        }
+    }
+
+    let mut num_entries = 0;
+    for ((group, _), group_id) in groups.iter().zip(&group_ids) {
+        for entry in group
+            .borrow()
+            .as_any()
+            .downcast_ref::<Group>()
+            .map(Group::entries)
+            .unwrap_or_default()
+        {
+            if let Some(entry) = entry.borrow().as_any().downcast_ref::<Entry>() {
+                dump_entry(&mut payload, entry, *group_id)?;
+                num_entries += 1;
+            }
+        }
+    }
+
+    // meta-streams need a group, they are left out of databases without any
+    if let Some(group_id) = group_ids.first() {
+        for meta_stream in &db.meta.kdb_meta_streams {
+            dump_meta_stream(&mut payload, meta_stream, *group_id)?;
+            num_entries += 1;
+        }
+    }
+
+    let num_groups = u32::try_from(groups.len()).map_err(|_| DatabaseSaveError::UnsupportedByKdb("too many groups"))?;
+    let num_entries = u32::try_from(num_entries).map_err(|_| DatabaseSaveError::UnsupportedByKdb("too many entries"))?;
+
+    // generate encryption keys and seeds on the fly when saving
+    let mut master_seed = vec![0; 16];
+    getrandom::getrandom(&mut master_seed)?;
+
+    let mut encryption_iv = vec![0; db.config.outer_cipher_config.get_iv_size()?];
+    getrandom::getrandom(&mut encryption_iv)?;
+
+    // keep the seed of a cached transformed key, so that it can be used again after saving
+    let transform_seed = match db_key.transformed_key_seed(&db.config.kdf_config) {
+        Some(transform_seed) => transform_seed.to_vec(),
+        None => db.config.kdf_config.generate_seed()?,
+    };
+
+    // derive master key from composite key, transform_seed, transform_rounds and master_seed. The
+    // password is encoded like KeePass 1.x if possible, or like the cached transformed key.
+    let mut result = Err(DatabaseKeyError::TransformedKeyMismatch.into());
+    for db_key in db_key.kdb_keys() {
+        result = db_key.transform_key(&db.config.kdf_config, &transform_seed, monitor, || {
+            Ok::<_, DatabaseSaveError>(composite_key(&db_key)?)
+        });
+        if !matches!(result, Err(DatabaseSaveError::Key(DatabaseKeyError::TransformedKeyMismatch))) {
+            break;
+        }
+    }
+    let transformed_key = result?.ok_or(DatabaseSaveError::Cancelled)?;
+    let master_key = calculate_sha256(&[&master_seed, transformed_key.as_slice()]);
+
+    let contents_hash = calculate_sha256(&[&payload]);
+    let payload_encrypted = db
+        .config
+        .outer_cipher_config
+        .get_cipher(master_key.as_slice(), &encryption_iv)?
+        .encrypt(&payload)?;
+
+    let mut writer = ProgressWriter::new(writer, monitor, ProgressStage::Encryption);
+    writer.write_all(&KDBX_IDENTIFIER)?;
+    writer.write_u32::<LittleEndian>(KEEPASS_1_ID)?;
+    writer.write_u32::<LittleEndian>(flags)?;
+    writer.write_u32::<LittleEndian>(KDB_VERSION)?;
+    writer.write_all(&master_seed)?;
+    writer.write_all(&encryption_iv)?;
+    writer.write_u32::<LittleEndian>(num_groups)?;
+    writer.write_u32::<LittleEndian>(num_entries)?;
+    writer.write_all(contents_hash.as_slice())?;
+    writer.write_all(&transform_seed)?;
+    writer.write_u32::<LittleEndian>(transform_rounds)?;
+    writer.write_all(&payload_encrypted)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod kdb_dump_tests {
+    use super::{dump_kdb, pack_time};
+    use crate::format::kdb::{get_group_id, parse_kdb, unpack_time};
+    use crate::{
+        config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+        db::{group_add_child, group_get_children, node::*, Database, Entry, Group, KdbMetaStream, NodeIterator},
+        error::{DatabaseKeyError, DatabaseOpenError, DatabaseSaveError},
+        format::DatabaseVersion,
+        key::{DatabaseKey, PasswordEncoding},
+        limits::OpenLimits,
+        progress::ProgressMonitor,
+        rc_refcell_node,
+    };
+    use chrono::NaiveDate;
+
+    fn kdb_config(outer_cipher_config: OuterCipherConfig) -> DatabaseConfig {
+        DatabaseConfig {
+            version: DatabaseVersion::KDB(4),
+            outer_cipher_config,
+            compression_config: CompressionConfig::None,
+            inner_cipher_config: InnerCipherConfig::Plain,
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+        }
+    }
+
+    fn roundtrip(db: &Database, db_key: &DatabaseKey) -> Database {
+        let mut encrypted_db = Vec::new();
+        dump_kdb(db, db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+        parse_kdb(&encrypted_db, db_key, &ProgressMonitor::default(), &OpenLimits::default())
+            .unwrap()
+            .0
+    }
+
+    /// Give the groups of `db` the UUIDs of the groups of `other` with the same group IDs, as each
+    /// group gets a random UUID when a KDB file is opened
+    fn copy_group_uuids(db: &Database, other: &Database) {
+        for (node, other_node) in NodeIterator::new(&db.root).zip(NodeIterator::new(&other.root)) {
+            let mut node = node.borrow_mut();
+            let other_node = other_node.borrow();
+            if let (Some(group), Some(other_group)) = (
+                node.as_any_mut().downcast_mut::<Group>(),
+                other_node.as_any().downcast_ref::<Group>(),
+            ) {
+                assert_eq!(get_group_id(group), get_group_id(other_group));
+                group.uuid = other_group.uuid;
+            }
+        }
+    }
+
+    #[test]
+    fn packed_times() {
+        let time = NaiveDate::from_ymd_opt(2020, 5, 26).unwrap().and_hms_opt(16, 12, 53).unwrap();
+        assert_eq!(unpack_time(&pack_time(time)), Some(time));
+        assert_eq!(unpack_time(&[0; 5]), None);
+        assert_eq!(unpack_time(&[0; 4]), None);
+    }
+
+    #[test]
+    fn roundtrip_existing_database() {
+        let data = include_bytes!("../../../tests/resources/test_db_kdb_with_password.kdb");
+        let db_key = DatabaseKey::new().with_password("foobar");
+        let (db, _) = parse_kdb(data, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+
+        let decrypted_db = roundtrip(&db, &db_key);
+        copy_group_uuids(&decrypted_db, &db);
+
+        // the root group is not stored in the file
+        let children = group_get_children(&db.root).unwrap();
+        let decrypted_children = group_get_children(&decrypted_db.root).unwrap();
+        assert_eq!(children.len(), decrypted_children.len());
+        for (child, decrypted_child) in children.iter().zip(&decrypted_children) {
+            assert!(node_is_equals_to(child, decrypted_child));
+        }
+        assert_eq!(decrypted_db.meta.kdb_meta_streams, db.meta.kdb_meta_streams);
+        assert_eq!(decrypted_db.config.outer_cipher_config, db.config.outer_cipher_config);
+        assert_eq!(decrypted_db.config.kdf_config, db.config.kdf_config);
+    }
+
+    #[test]
+    fn roundtrip_ciphers() {
+        for outer_cipher_config in [OuterCipherConfig::AES256, OuterCipherConfig::Twofish] {
+            let mut db = Database::new(kdb_config(outer_cipher_config));
+            db.meta.kdb_meta_streams.push(KdbMetaStream {
+                name: "Simple UI State".to_string(),
+                content: vec![1, 2, 3],
+            });
+
+            let group = rc_refcell_node!(Group::new("Group"));
+            let subgroup = rc_refcell_node!(Group::new("Subgroup"));
+            let mut entry = Entry::default();
+            entry.set_title(Some("Demo entry"));
+            entry.set_username(Some("user"));
+            entry.set_password(Some("secret"));
+            entry.times.set_expires(true);
+            // the location change time is not stored in KDB files
+            let mut times = entry.times.clone();
+            times.set_location_changed(None);
+            entry.add_attachment("a.txt", b"attachment".to_vec(), false);
+            group_add_child(&subgroup, rc_refcell_node!(entry), 0).unwrap();
+            group_add_child(&group, subgroup, 0).unwrap();
+            group_add_child(&db.root, group, 0).unwrap();
+
+            let db_key = DatabaseKey::new().with_password("test");
+            let decrypted_db = roundtrip(&db, &db_key);
+
+            assert_eq!(decrypted_db.config, db.config);
+            assert_eq!(decrypted_db.meta.kdb_meta_streams, db.meta.kdb_meta_streams);
+
+            // groups get new IDs in the file, which are kept from then on, while their UUIDs are random
+            let group_id = |db: &Database| {
+                let group = Group::get(&db.root, &["Group", "Subgroup"]).unwrap();
+                let group = group.borrow();
+                let group = group.as_any().downcast_ref::<Group>().unwrap();
+                (group.get_uuid(), get_group_id(group))
+            };
+            let (uuid, id) = group_id(&decrypted_db);
+            assert_eq!(id, Some(2));
+            assert_ne!(uuid.as_u128(), 2);
+            let reopened_db = roundtrip(&decrypted_db, &db_key);
+            let (reopened_uuid, reopened_id) = group_id(&reopened_db);
+            assert_eq!(reopened_id, Some(2));
+            assert_ne!(reopened_uuid, uuid);
+
+            let entry = Group::get(&decrypted_db.root, &["Group", "Subgroup", "Demo entry"]).unwrap();
+            let entry = entry.borrow();
+            let entry = entry.as_any().downcast_ref::<Entry>().unwrap();
+            assert_eq!(entry.get_username(), Some("user"));
+            assert_eq!(entry.reveal_password().as_deref(), Some("secret"));
+            assert_eq!(entry.get_times(), &times);
+            assert_eq!(entry.get_attachment("a.txt").unwrap().get_content(), b"attachment");
+        }
+    }
+
+    #[test]
+    fn accented_passwords() {
+        let db = Database::new(kdb_config(OuterCipherConfig::AES256));
+        group_add_child(&db.root, rc_refcell_node!(Group::new("Group")), 0).unwrap();
+
+        let dump = |db_key: &DatabaseKey| {
+            let mut encrypted_db = Vec::new();
+            dump_kdb(&db, db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+            encrypted_db
+        };
+        let open = |encrypted_db: &[u8], db_key: DatabaseKey| {
+            parse_kdb(encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).map(|_| ())
+        };
+        let incorrect_key = |result| matches!(result, Err(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey)));
+
+        // saved in Windows-1252 like KeePass 1.x
+        let db_key = DatabaseKey::new().with_password("Größe");
+        let encrypted_db = dump(&db_key);
+        assert!(open(&encrypted_db, db_key.clone()).is_ok());
+        assert!(open(&encrypted_db, db_key.clone().with_password_encoding(PasswordEncoding::Windows1252)).is_ok());
+        assert!(incorrect_key(open(
+            &encrypted_db,
+            db_key.clone().with_password_encoding(PasswordEncoding::Utf8)
+        )));
+
+        // saved in UTF-8 like KeePassX
+        let encrypted_db = dump(&db_key.clone().with_password_encoding(PasswordEncoding::Utf8));
+        assert!(open(&encrypted_db, db_key.clone()).is_ok());
+        assert!(incorrect_key(open(
+            &encrypted_db,
+            db_key.with_password_encoding(PasswordEncoding::Windows1252)
+        )));
+
+        // passwords that cannot be encoded in Windows-1252 are saved in UTF-8
+        let db_key = DatabaseKey::new().with_password("пароль");
+        let encrypted_db = dump(&db_key);
+        assert!(open(&encrypted_db, db_key.clone()).is_ok());
+        assert!(open(&encrypted_db, db_key.clone().with_password_encoding(PasswordEncoding::Utf8)).is_ok());
+        assert!(incorrect_key(open(&encrypted_db, DatabaseKey::new().with_password("Größe"))));
+    }
+
+    #[test]
+    fn unsupported_databases() {
+        let db_key = DatabaseKey::new().with_password("test");
+        let dump = |db: &Database| dump_kdb(db, &db_key, &mut Vec::new(), &ProgressMonitor::default());
+
+        let mut db = Database::new(kdb_config(OuterCipherConfig::ChaCha20));
+        assert!(matches!(dump(&db), Err(DatabaseSaveError::UnsupportedCipher)));
+
+        db.config = DatabaseConfig {
+            kdf_config: KdfConfig::Argon2 {
+                iterations: 1,
+                memory: 1024,
+                parallelism: 1,
+                version: argon2::Version::Version13,
+            },
+            ..kdb_config(OuterCipherConfig::AES256)
+        };
+        assert!(matches!(dump(&db), Err(DatabaseSaveError::UnsupportedKdf)));
+
+        db.config = kdb_config(OuterCipherConfig::AES256);
+        group_add_child(&db.root, rc_refcell_node!(Entry::default()), 0).unwrap();
+        assert!(matches!(dump(&db), Err(DatabaseSaveError::UnsupportedByKdb(_))));
+
+        let db = Database::new(kdb_config(OuterCipherConfig::AES256));
+        let mut entry = Entry::default();
+        entry.add_attachment("a.txt", Vec::new(), false);
+        entry.add_attachment("b.txt", Vec::new(), false);
+        let group = rc_refcell_node!(Group::new("Group"));
+        group_add_child(&group, rc_refcell_node!(entry), 0).unwrap();
+        group_add_child(&db.root, group, 0).unwrap();
+        assert!(matches!(dump(&db), Err(DatabaseSaveError::UnsupportedByKdb(_))));
+    }
+}
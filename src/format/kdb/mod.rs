@@ -0,0 +1,597 @@
+#[cfg(feature = "save_kdbx4")]
+mod dump;
+
+use crate::{
+    config::{CompressionConfig, DatabaseConfig, DatabaseHeaderInfo, HeaderAlgorithm, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+    crypt::calculate_sha256,
+    db::{
+        group_add_child, group_get_children, iconid::IconId, CustomDataItem, Database, DeletedObjects, Entry, Group, KdbMetaStream, Meta,
+        Node, NodePtr, Times, UnknownXml, Value,
+    },
+    error::{DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError, ResourceLimitError},
+    format::DatabaseVersion,
+    key::{DatabaseKey, TransformedKey},
+    limits::OpenLimits,
+    progress::{ProgressMonitor, ProgressStage},
+    rc_refcell_node,
+    variant_dictionary::VariantDictionary,
+};
+
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{NaiveDate, NaiveDateTime};
+use cipher::generic_array::{typenum::U32, GenericArray};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use std::{collections::HashMap, convert::TryInto, io::Read, str};
+
+#[cfg(feature = "save_kdbx4")]
+pub(crate) use crate::format::kdb::dump::dump_kdb;
+
+#[derive(Debug)]
+struct KDBHeader {
+    // https://gist.github.com/lgg/e6ccc6e212d18dd2ecd8a8c116fb1e45
+    pub flags: u32,
+    pub subversion: u32,
+    pub master_seed: Vec<u8>,   // 16 bytes
+    pub encryption_iv: Vec<u8>, // 16 bytes
+    pub num_groups: u32,
+    pub num_entries: u32,
+    pub contents_hash: Vec<u8>,  // 32 bytes
+    pub transform_seed: Vec<u8>, // 32 bytes
+    pub transform_rounds: u32,
+}
+
+const HEADER_SIZE: usize = 4 + 4 + 4 + 4 + 16 + 16 + 4 + 4 + 32 + 32 + 4; // first 4 bytes are the KeePass magic
+
+const FLAG_RIJNDAEL: u32 = 2;
+const FLAG_TWOFISH: u32 = 8;
+
+const GROUP_FLAG_EXPANDED: u32 = 1;
+
+// Meta-streams are entries with these values and the name of the stream in their notes
+const META_STREAM_TITLE: &str = "Meta-Info";
+const META_STREAM_USERNAME: &str = "SYSTEM";
+const META_STREAM_URL: &str = "$";
+const META_STREAM_BINARY_DESC: &str = "bin-stream";
+
+fn parse_header(data: &[u8]) -> Result<KDBHeader, DatabaseIntegrityError> {
+    if data.len() < HEADER_SIZE {
+        return Err(DatabaseIntegrityError::InvalidFixedHeader { size: data.len() });
+    }
+
+    Ok(KDBHeader {
+        flags: LittleEndian::read_u32(&data[8..]),
+        subversion: LittleEndian::read_u32(&data[12..]),
+        master_seed: data[16..32].to_vec(),
+        encryption_iv: data[32..48].to_vec(),
+        num_groups: LittleEndian::read_u32(&data[48..]),
+        num_entries: LittleEndian::read_u32(&data[52..]),
+        contents_hash: data[56..88].to_vec(),
+        transform_seed: data[88..120].to_vec(),
+        transform_rounds: LittleEndian::read_u32(&data[120..]),
+    })
+}
+
+fn from_utf8(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).trim_end_matches('\0').to_owned()
+}
+
+fn ensure_length(field_type: u16, field_size: u32, expected_field_size: u32) -> Result<(), DatabaseIntegrityError> {
+    if field_size == expected_field_size {
+        Ok(())
+    } else {
+        Err(DatabaseIntegrityError::InvalidKDBFieldLength {
+            field_type,
+            field_size,
+            expected_field_size,
+        })
+    }
+}
+
+/// Names of the string fields of entries, by field type
+fn entry_field_name(field_type: u16) -> Option<&'static str> {
+    match field_type {
+        0x0004 => Some("Title"),
+        0x0005 => Some("URL"),
+        0x0006 => Some("UserName"),
+        0x0007 => Some("Password"),
+        0x0008 => Some("Notes"),
+        _ => None,
+    }
+}
+
+/// Read a field of type `field_type: u16`, `field_size: u32` and `field_value: [u8; field_size]`,
+/// advancing `data` past it
+fn read_field<'a>(data: &mut &'a [u8]) -> Result<(u16, u32, &'a [u8]), DatabaseIntegrityError> {
+    let bytes: &'a [u8] = data;
+    let incomplete = |field_size: usize| DatabaseIntegrityError::IncompleteKDBField {
+        remaining: bytes.len(),
+        field_size,
+    };
+    let header = bytes.get(0..6).ok_or_else(|| incomplete(6))?;
+    let field_type = LittleEndian::read_u16(&header[0..]);
+    let field_size = LittleEndian::read_u32(&header[2..]);
+    let field_end = (field_size as usize).saturating_add(6);
+    let field_value = bytes.get(6..field_end).ok_or_else(|| incomplete(field_end))?;
+    *data = &bytes[field_end..];
+    Ok((field_type, field_size, field_value))
+}
+
+/// Unpack a timestamp stored in 5 bytes as `yyyyyyyy yyyyyyMM MMdddddh hhhhmmmm mmssssss`.
+/// Returns `None` for dates that do not exist, e.g. the zeroed timestamps of some writers.
+fn unpack_time(data: &[u8]) -> Option<NaiveDateTime> {
+    let [b0, b1, b2, b3, b4] = data.try_into().ok()?;
+    let (b0, b1, b2, b3, b4) = (u32::from(b0), u32::from(b1), u32::from(b2), u32::from(b3), u32::from(b4));
+    let year = (b0 << 6) | (b1 >> 2);
+    let month = ((b1 & 0x03) << 2) | (b2 >> 6);
+    let day = (b2 >> 1) & 0x1f;
+    let hour = ((b2 & 0x01) << 4) | (b3 >> 4);
+    let minute = ((b3 & 0x0f) << 2) | (b4 >> 6);
+    let second = b4 & 0x3f;
+    #[allow(clippy::cast_possible_wrap)]
+    NaiveDate::from_ymd_opt(year as i32, month, day)?.and_hms_opt(hour, minute, second)
+}
+
+/// The expiry time of nodes that do not expire
+fn never_expires() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2999, 12, 28)
+        .and_then(|date| date.and_hms_opt(23, 59, 59))
+        .unwrap_or_default()
+}
+
+/// Set one of the timestamps with the field types 0x0009 to 0x000c of entries (0x0003 to 0x0006
+/// of groups), counted from `first_field_type`
+fn set_time(times: &mut Times, field_type: u16, first_field_type: u16, field_value: &[u8]) {
+    let time = unpack_time(field_value);
+    match field_type - first_field_type {
+        0 => times.set_creation(time),
+        1 => times.set_last_modification(time),
+        2 => times.set_last_access(time),
+        _ => {
+            times.set_expires(time.is_some_and(|time| time != never_expires()));
+            times.set_expiry_time(time);
+        }
+    }
+}
+
+/// Custom data item of the groups read from KDB files that keeps their 32-bit group ID
+const GROUP_ID_CUSTOM_DATA: &str = "KDB_GROUP_ID";
+
+/// Groups only have a 32-bit ID in KDB. Groups read from KDB files get random UUIDs like any other
+/// group, and keep their ID in their custom data, so that they are saved with it again.
+fn set_group_id(group: &mut Group, group_id: u32) {
+    group.custom_data.items.insert(
+        GROUP_ID_CUSTOM_DATA.to_string(),
+        CustomDataItem {
+            value: Some(Value::Unprotected(group_id.to_string())),
+            last_modification_time: None,
+        },
+    );
+}
+
+/// The group ID kept by `set_group_id`
+#[cfg(feature = "save_kdbx4")]
+fn get_group_id(group: &Group) -> Option<u32> {
+    match group.custom_data.items.get(GROUP_ID_CUSTOM_DATA)?.value.as_ref()? {
+        Value::Unprotected(group_id) => group_id.parse().ok(),
+        _ => None,
+    }
+}
+
+// Collapse the tail of a deque of Groups up to the given level
+fn collapse_tail_groups(branch: &mut Vec<NodePtr>, level: usize, root: &NodePtr) -> Option<()> {
+    while level < branch.len() {
+        let leaf = branch.pop()?; // guaranteed to be at least 1 element since 0 <= level < branch.len()
+        let parent = match branch.last() {
+            Some(parent) => parent,
+            None => root,
+        };
+        let count = group_get_children(parent)?.len();
+        group_add_child(parent, leaf, count).ok()?;
+    }
+    Some(())
+}
+
+// A map from a GroupId to the group with that ID
+type GidMap = HashMap<u32, NodePtr>;
+
+fn parse_groups(root: &NodePtr, header_num_groups: u32, data: &mut &[u8]) -> Result<GidMap, DatabaseIntegrityError> {
+    // Loop over group TLVs
+    let mut gid_map = GidMap::new(); // the gid to group map
+    let mut branch: Vec<NodePtr> = Vec::new(); // the current branch in the group tree
+    let mut group = Group::new(""); // the current group (will be added as a leaf of the branch)
+    group.times = Times::default();
+    let mut level: Option<u16> = None; // the current group's level
+    let mut gid: Option<u32> = None; // the current group's id
+    let mut num_groups = 0; // the total number of parsed groups
+    while num_groups < header_num_groups as usize {
+        // Read group TLV
+        let (field_type, field_size, field_value) = read_field(data)?;
+
+        match field_type {
+            0x0000 => {} // KeePass ignores this field type
+            0x0001 => {
+                // GroupId
+                ensure_length(field_type, field_size, 4)?;
+                gid = Some(LittleEndian::read_u32(field_value));
+            }
+            0x0002 => group.set_title(Some(&from_utf8(field_value))), // GroupName
+            0x0003..=0x0006 => {
+                // Creation/LastMod/LastAccess/Expire
+                ensure_length(field_type, field_size, 5)?;
+                set_time(&mut group.times, field_type, 0x0003, field_value);
+            }
+            0x0007 => {
+                // ImageId
+                ensure_length(field_type, field_size, 4)?;
+                group.icon_id = Some(IconId(LittleEndian::read_u32(field_value) as usize));
+            }
+            0x0008 => {
+                // Level
+                ensure_length(field_type, field_size, 2)?;
+                level = Some(LittleEndian::read_u16(field_value));
+            }
+            0x0009 => {
+                // Flags
+                ensure_length(field_type, field_size, 4)?;
+                group.is_expanded = LittleEndian::read_u32(field_value) & GROUP_FLAG_EXPANDED != 0;
+            }
+            0xffff => {
+                ensure_length(field_type, field_size, 0)?;
+
+                let level = level.ok_or(DatabaseIntegrityError::MissingKDBGroupLevel)? as usize;
+
+                // Update the current group tree branch (collapse previous sub-branch, initiate
+                // current sub-branch)
+                if level < branch.len() {
+                    collapse_tail_groups(&mut branch, level, root).ok_or(DatabaseIntegrityError::IncompleteKDBGroup)?;
+                }
+                if level != branch.len() {
+                    // Level is beyond the current depth, missing intermediate levels?
+                    #[allow(clippy::cast_possible_truncation)]
+                    return Err(DatabaseIntegrityError::InvalidKDBGroupLevel {
+                        group_level: level as u16,
+                        current_level: branch.len() as u16,
+                    });
+                }
+
+                // Update the GroupId map and reset state for the next group
+                let group_id = gid.ok_or(DatabaseIntegrityError::MissingKDBGroupId)?;
+                set_group_id(&mut group, group_id);
+                let node = rc_refcell_node!(group);
+                gid_map.insert(group_id, node.clone());
+                branch.push(node);
+
+                group = Group::new("");
+                group.times = Times::default();
+                gid = None;
+                num_groups += 1;
+            }
+            _ => {
+                return Err(DatabaseIntegrityError::InvalidKDBGroupFieldType { field_type });
+            }
+        }
+    }
+    if gid.is_some() {
+        return Err(DatabaseIntegrityError::IncompleteKDBGroup);
+    }
+    // Collapse last group tree branch into the root
+    collapse_tail_groups(&mut branch, 0, root).ok_or(DatabaseIntegrityError::IncompleteKDBGroup)?;
+
+    Ok(gid_map)
+}
+
+/// Whether an entry is a meta-stream, which stores application data in its attachment
+fn is_meta_stream(entry: &Entry, binary_desc: &str) -> bool {
+    binary_desc == META_STREAM_BINARY_DESC
+        && entry.get_title() == Some(META_STREAM_TITLE)
+        && entry.get_username() == Some(META_STREAM_USERNAME)
+        && entry.get_url() == Some(META_STREAM_URL)
+        && entry.get_notes().is_some_and(|notes| !notes.is_empty())
+}
+
+/// An entry without any timestamps, which are only set if they are in the file
+fn new_entry() -> Entry {
+    Entry {
+        times: Times::default(),
+        ..Entry::default()
+    }
+}
+
+fn parse_entries(gid_map: &GidMap, header_num_entries: u32, data: &mut &[u8]) -> Result<Vec<KdbMetaStream>, DatabaseIntegrityError> {
+    // Loop over entry TLVs
+    let mut meta_streams = Vec::new();
+    let mut entry = new_entry(); // the current entry
+    let mut binary_desc = String::new(); // the current entry's attachment name
+    let mut binary_data: Vec<u8> = Vec::new(); // the current entry's attachment content
+    let mut gid: Option<u32> = None; // the current entry's group id
+    let mut num_entries = 0;
+    while num_entries < header_num_entries {
+        // Read entry TLV
+        let (field_type, field_size, field_value) = read_field(data)?;
+
+        match field_type {
+            0x0000 => {} // KeePass ignores this field type
+            0x0001 => {
+                // uuid
+                ensure_length(field_type, field_size, 16)?;
+                entry.uuid = Uuid::from_slice(field_value).map_err(|_| DatabaseIntegrityError::IncompleteKDBEntry)?;
+            }
+            0x0002 => {
+                // GroupId
+                ensure_length(field_type, field_size, 4)?;
+                gid = Some(LittleEndian::read_u32(field_value));
+            }
+            0x0003 => {
+                // ImageId
+                ensure_length(field_type, field_size, 4)?;
+                entry.icon_id = Some(IconId(LittleEndian::read_u32(field_value) as usize));
+            }
+            0x0004..=0x0008 => {
+                // Title/URL/UserName/Password/Additional
+                let name = entry_field_name(field_type).ok_or(DatabaseIntegrityError::InvalidKDBEntryFieldType { field_type })?;
+                entry.set_field(name, &from_utf8(field_value), name == "Password");
+            }
+            0x0009..=0x000c => {
+                // Creation/LastMod/LastAccess/Expire
+                ensure_length(field_type, field_size, 5)?;
+                set_time(&mut entry.times, field_type, 0x0009, field_value);
+            }
+            0x000d => binary_desc = from_utf8(field_value), // BinaryDesc
+            0x000e => binary_data = field_value.to_vec(),   // BinaryData
+            0xffff => {
+                ensure_length(field_type, field_size, 0)?;
+
+                let group_id = gid.ok_or(DatabaseIntegrityError::MissingKDBGroupId)?;
+                let group = gid_map
+                    .get(&group_id)
+                    .ok_or(DatabaseIntegrityError::InvalidKDBGroupId { group_id })?;
+
+                if is_meta_stream(&entry, &binary_desc) {
+                    meta_streams.push(KdbMetaStream {
+                        name: entry.get_notes().unwrap_or_default().to_string(),
+                        content: std::mem::take(&mut binary_data),
+                    });
+                } else {
+                    if !binary_desc.is_empty() || !binary_data.is_empty() {
+                        entry.add_attachment(&binary_desc, std::mem::take(&mut binary_data), false);
+                    }
+                    let count = group_get_children(group).ok_or(DatabaseIntegrityError::IncompleteKDBGroup)?.len();
+                    group_add_child(group, rc_refcell_node!(entry), count).map_err(|_| DatabaseIntegrityError::IncompleteKDBGroup)?;
+                }
+
+                entry = new_entry();
+                binary_desc.clear();
+                binary_data.clear();
+                gid = None;
+                num_entries += 1;
+            }
+            _ => {
+                return Err(DatabaseIntegrityError::InvalidKDBEntryFieldType { field_type });
+            }
+        }
+    }
+    if gid.is_some() {
+        return Err(DatabaseIntegrityError::IncompleteKDBEntry);
+    }
+
+    Ok(meta_streams)
+}
+
+fn parse_db(header: &KDBHeader, data: &[u8]) -> Result<(NodePtr, Vec<KdbMetaStream>), DatabaseIntegrityError> {
+    let root = rc_refcell_node!(Group::new("Root"));
+
+    let mut pos = data;
+
+    let gid_map = parse_groups(&root, header.num_groups, &mut pos)?;
+
+    let meta_streams = parse_entries(&gid_map, header.num_entries, &mut pos)?;
+
+    Ok((root, meta_streams))
+}
+
+/// The composite key of KDB is the hash of a single password or keyfile, while a password and a
+/// keyfile are hashed together once more
+fn composite_key(db_key: &DatabaseKey) -> Result<GenericArray<u8, U32>, DatabaseKeyError> {
+    let key_elements = db_key.get_key_elements()?;
+    let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
+    if key_elements.len() == 1 {
+        let key_element: [u8; 32] = key_elements[0].try_into().map_err(|_| DatabaseKeyError::InvalidKeyFile)?;
+        Ok(GenericArray::from(key_element)) // single pass of SHA256, already done before the call to parse()
+    } else {
+        Ok(calculate_sha256(&key_elements)) // second pass of SHA256
+    }
+}
+
+/// Derive the master key with `db_key` and decrypt the payload, checking it against the contents hash
+fn decrypt_payload(
+    header: &KDBHeader,
+    data: &[u8],
+    db_key: &DatabaseKey,
+    kdf_config: &KdfConfig,
+    outer_cipher_config: &OuterCipherConfig,
+    monitor: &ProgressMonitor,
+) -> Result<(Zeroizing<Vec<u8>>, TransformedKey), DatabaseOpenError> {
+    // Rest of file after header is payload
+    let payload_encrypted = &data[HEADER_SIZE..];
+
+    // derive master key from composite key, transform_seed, transform_rounds and master_seed
+    let transformed_key = db_key
+        .transform_key(kdf_config, &header.transform_seed, monitor, || {
+            Ok::<_, DatabaseOpenError>(composite_key(db_key)?)
+        })?
+        .ok_or(DatabaseOpenError::Cancelled)?;
+
+    if !monitor.report(ProgressStage::Decryption, 0, None) {
+        return Err(DatabaseOpenError::Cancelled);
+    }
+
+    let master_key = calculate_sha256(&[&header.master_seed, transformed_key.as_slice()]);
+
+    // Decrypt payload, invalid padding means that the key is wrong
+    let mut payload = Zeroizing::new(
+        outer_cipher_config
+            .get_cipher(master_key.as_slice(), header.encryption_iv.as_ref())?
+            .decrypt(payload_encrypted)
+            .map_err(|_| DatabaseKeyError::IncorrectKey)?,
+    );
+    let padlen = payload.last().map_or(0, |padlen| *padlen as usize);
+    let payload_len = payload.len().checked_sub(padlen).ok_or(DatabaseKeyError::IncorrectKey)?;
+    payload.truncate(payload_len);
+
+    // Check if we decrypted correctly
+    let hash = calculate_sha256(&[&payload]);
+    if header.contents_hash != hash.as_slice() {
+        return Err(DatabaseKeyError::IncorrectKey.into());
+    }
+
+    Ok((payload, transformed_key))
+}
+
+fn outer_cipher_config(header: &KDBHeader) -> Result<OuterCipherConfig, DatabaseIntegrityError> {
+    if header.flags & FLAG_RIJNDAEL != 0 {
+        Ok(OuterCipherConfig::AES256)
+    } else if header.flags & FLAG_TWOFISH != 0 {
+        Ok(OuterCipherConfig::Twofish)
+    } else {
+        Err(DatabaseIntegrityError::InvalidFixedCipherID { cid: header.flags })
+    }
+}
+
+/// Read the settings of a `KeePass` 1.x database from its header, reading no further than the end of
+/// the header from `source`
+pub(crate) fn kdb_header_info(source: &mut dyn Read) -> Result<DatabaseHeaderInfo, DatabaseOpenError> {
+    let mut data = Vec::new();
+    source.take(HEADER_SIZE as u64).read_to_end(&mut data)?;
+    let header = parse_header(&data)?;
+
+    #[allow(clippy::cast_possible_truncation)]
+    Ok(DatabaseHeaderInfo {
+        version: DatabaseVersion::KDB(header.subversion as u16),
+        outer_cipher_config: HeaderAlgorithm::Known(outer_cipher_config(&header)?),
+        compression_config: CompressionConfig::None,
+        inner_cipher_config: Some(InnerCipherConfig::Plain),
+        kdf_config: HeaderAlgorithm::Known(KdfConfig::Aes {
+            rounds: u64::from(header.transform_rounds),
+        }),
+        comment: None,
+        public_custom_data: VariantDictionary::new(),
+    })
+}
+
+pub(crate) fn parse_kdb(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    monitor: &ProgressMonitor,
+    limits: &OpenLimits,
+) -> Result<(Database, TransformedKey), DatabaseOpenError> {
+    let header = parse_header(data)?;
+    if let Some(limit) = limits.max_entries.filter(|limit| header.num_entries as usize > *limit) {
+        return Err(ResourceLimitError::Entries { limit }.into());
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let version = DatabaseVersion::KDB(header.subversion as u16);
+
+    // KDF is always AES
+    let kdf_config = KdfConfig::Aes {
+        rounds: u64::from(header.transform_rounds),
+    };
+
+    limits.check_kdf(&kdf_config)?;
+
+    let outer_cipher_config = outer_cipher_config(&header)?;
+
+    // KeePass 1.x encodes the password in Windows-1252, other applications in UTF-8
+    let mut result = Err(DatabaseKeyError::IncorrectKey.into());
+    for db_key in db_key.kdb_keys() {
+        result = decrypt_payload(&header, data, &db_key, &kdf_config, &outer_cipher_config, monitor);
+        if !matches!(
+            result,
+            Err(DatabaseOpenError::Key(
+                DatabaseKeyError::IncorrectKey | DatabaseKeyError::TransformedKeyMismatch
+            ))
+        ) {
+            break;
+        }
+    }
+    let (payload, transformed_key) = result?;
+
+    let (root_group, kdb_meta_streams) = parse_db(&header, &payload)?;
+
+    let config = DatabaseConfig {
+        version,
+        outer_cipher_config,
+        compression_config: CompressionConfig::None,
+        inner_cipher_config: InnerCipherConfig::Plain,
+        kdf_config,
+    };
+
+    let db = Database {
+        config,
+        header_attachments: Vec::default(),
+        root: root_group.into(),
+        deleted_objects: DeletedObjects::default(),
+        meta: Meta {
+            kdb_meta_streams,
+            ..Meta::new()
+        },
+        public_custom_data: VariantDictionary::new(),
+        unknown_xml: UnknownXml::default(),
+    };
+
+    Ok((db, transformed_key))
+}
+
+#[cfg(test)]
+mod kdb_tests {
+    use super::{parse_kdb, unpack_time, GROUP_ID_CUSTOM_DATA};
+    use crate::{
+        config::{KdfConfig, OuterCipherConfig},
+        db::{node::*, Group, KdbMetaStream, Value},
+        error::{DatabaseKeyError, DatabaseOpenError},
+        format::DatabaseVersion,
+        key::DatabaseKey,
+        limits::OpenLimits,
+        progress::ProgressMonitor,
+    };
+    use chrono::NaiveDate;
+
+    #[test]
+    fn unpacked_times() {
+        let time = NaiveDate::from_ymd_opt(2020, 5, 26).unwrap().and_hms_opt(16, 12, 53).unwrap();
+        assert_eq!(unpack_time(&[0x1f, 0x91, 0x75, 0x03, 0x35]), Some(time));
+        assert_eq!(unpack_time(&[0; 5]), None);
+        assert_eq!(unpack_time(&[0; 4]), None);
+    }
+
+    #[test]
+    fn parse_existing_database() {
+        let data = include_bytes!("../../../tests/resources/test_db_kdb_with_password.kdb");
+        let db_key = DatabaseKey::new().with_password("foobar");
+        let (db, _) = parse_kdb(data, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+
+        assert!(matches!(db.config.version, DatabaseVersion::KDB(_)));
+        assert_eq!(db.config.outer_cipher_config, OuterCipherConfig::AES256);
+        assert!(matches!(db.config.kdf_config, KdfConfig::Aes { .. }));
+        assert!(!group_get_children(&db.root).unwrap().is_empty());
+        assert!(db.root.borrow().as_any().downcast_ref::<Group>().is_some());
+        assert!(db.meta.kdb_meta_streams.iter().all(|KdbMetaStream { name, .. }| !name.is_empty()));
+
+        // the ID of a group is kept in its custom data, while its UUID is random
+        let group = Group::get(&db.root, &["depth0"]).unwrap();
+        let group = group.borrow();
+        let group = group.as_any().downcast_ref::<Group>().unwrap();
+        assert_ne!(group.get_uuid().as_u128(), 0x12f6_faef);
+        let group_id = &group.custom_data.items[GROUP_ID_CUSTOM_DATA].value;
+        assert_eq!(group_id, &Some(Value::Unprotected(0x12f6_faef_u32.to_string())));
+
+        let result = parse_kdb(
+            data,
+            &DatabaseKey::new().with_password("wrong"),
+            &ProgressMonitor::default(),
+            &OpenLimits::default(),
+        );
+        assert!(matches!(result, Err(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey))));
+    }
+}
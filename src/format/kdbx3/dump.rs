@@ -0,0 +1,285 @@
+use std::io::Write;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::{
+    config::{CompressionConfig, KdfConfig},
+    crypt::calculate_sha256,
+    db::{attachment::AttachmentPool, BinaryAttachment, Database},
+    error::DatabaseSaveError,
+    format::{database_to_dump, kdbx_header_field_id::KDBXHeaderFieldID, DatabaseVersion},
+    key::DatabaseKey,
+    progress::{ProgressMonitor, ProgressStage, ProgressWriter},
+};
+
+/// Size for the master seed, transform seed, protected stream key and stream start bytes in bytes
+const HEADER_SEED_SIZE: usize = 32;
+
+/// Maximum size of a block in the hashed block stream
+const HASHED_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Content of the end of header field
+const HEADER_END_CONTENT: [u8; 4] = [0x0d, 0x0a, 0x0d, 0x0a];
+
+/// Dump a `KeePass` database as KDBX 3.1 using the key elements
+pub(crate) fn dump_kdbx3(
+    db: &Database,
+    db_key: &DatabaseKey,
+    writer: &mut dyn Write,
+    monitor: &ProgressMonitor,
+) -> Result<(), DatabaseSaveError> {
+    if !matches!(db.config.version, DatabaseVersion::KDB3(_)) {
+        return Err(DatabaseSaveError::UnsupportedVersion);
+    }
+
+    // KDF type is always AES for KDBX3
+    let KdfConfig::Aes { rounds } = db.config.kdf_config else {
+        return Err(DatabaseSaveError::UnsupportedKdf);
+    };
+
+    // generate encryption keys and seeds on the fly when saving
+    let mut master_seed = vec![0; HEADER_SEED_SIZE];
+    getrandom::getrandom(&mut master_seed)?;
+
+    // keep the seed of a cached transformed key, so that it can be used again after saving
+    let transform_seed = match db_key.transformed_key_seed(&db.config.kdf_config) {
+        Some(transform_seed) => transform_seed.to_vec(),
+        None => db.config.kdf_config.generate_seed()?,
+    };
+
+    let mut encryption_iv = vec![0; db.config.outer_cipher_config.get_iv_size()?];
+    getrandom::getrandom(&mut encryption_iv)?;
+
+    let mut inner_random_stream_key = vec![0; HEADER_SEED_SIZE];
+    getrandom::getrandom(&mut inner_random_stream_key)?;
+
+    let mut stream_start = vec![0; HEADER_SEED_SIZE];
+    getrandom::getrandom(&mut stream_start)?;
+
+    // dump the outer header - need to buffer so that SHA256 can be computed
+    let mut header_data = Vec::new();
+    db.config.version.dump(&mut header_data)?;
+
+    let write_field = |writer: &mut Vec<u8>, field_id: KDBXHeaderFieldID, field_buffer: &[u8]| -> Result<(), std::io::Error> {
+        writer.write_u8(field_id.into())?;
+        #[allow(clippy::cast_possible_truncation)]
+        writer.write_u16::<LittleEndian>(field_buffer.len() as u16)?;
+        writer.write_all(field_buffer)
+    };
+
+    write_field(&mut header_data, KDBXHeaderFieldID::CipherID, &db.config.outer_cipher_config.dump())?;
+    write_field(
+        &mut header_data,
+        KDBXHeaderFieldID::CompressionFlags,
+        &db.config.compression_config.dump(),
+    )?;
+    write_field(&mut header_data, KDBXHeaderFieldID::MasterSeed, &master_seed)?;
+    write_field(&mut header_data, KDBXHeaderFieldID::TransformSeed, &transform_seed)?;
+    write_field(&mut header_data, KDBXHeaderFieldID::TransformRounds, &rounds.to_le_bytes())?;
+    write_field(&mut header_data, KDBXHeaderFieldID::EncryptionIV, &encryption_iv)?;
+    write_field(&mut header_data, KDBXHeaderFieldID::InnerRandomStreamKey, &inner_random_stream_key)?;
+    write_field(&mut header_data, KDBXHeaderFieldID::StreamStartBytes, &stream_start)?;
+    write_field(
+        &mut header_data,
+        KDBXHeaderFieldID::InnerRandomStreamID,
+        &db.config.inner_cipher_config.dump().to_le_bytes(),
+    )?;
+    write_field(&mut header_data, KDBXHeaderFieldID::EndOfHeader, &HEADER_END_CONTENT)?;
+
+    // collect the entry attachments into the binaries of the metadata, keeping the
+    // unreferenced binaries at their current positions
+    let mut attachment_pool = AttachmentPool::default();
+    for binary in &db.meta.binaries.binaries {
+        attachment_pool.push(binary.content.clone(), binary.protected);
+    }
+    let root = attachment_pool.assign_refs(&db.root);
+
+    let compressed = matches!(db.config.compression_config, CompressionConfig::GZip);
+    let mut meta = db.meta.clone();
+    meta.header_hash = Some(calculate_sha256(&[&header_data]).to_vec());
+    meta.binaries.binaries = attachment_pool
+        .items
+        .into_iter()
+        .enumerate()
+        .map(|(position, (content, protected))| BinaryAttachment {
+            identifier: Some(position.to_string()),
+            compressed: db
+                .meta
+                .binaries
+                .binaries
+                .get(position)
+                .map_or(compressed, |binary| binary.compressed),
+            protected,
+            content,
+        })
+        .collect();
+
+    let mut db = database_to_dump(db, root);
+    db.meta = meta;
+
+    // Initialize inner encryptor from the protected stream key
+    let stream_key = calculate_sha256(&[inner_random_stream_key.as_ref()]);
+    let mut inner_cipher = db.config.inner_cipher_config.get_cipher(stream_key.as_slice())?;
+
+    let mut xml = Vec::new();
+    crate::xml_db::dump::dump(&db, &mut *inner_cipher, &mut xml)?;
+
+    let payload_compressed = db.config.compression_config.get_compression().compress(&xml)?;
+
+    // the payload starts with the stream start bytes, followed by the hashed block stream
+    let mut payload = stream_start;
+    write_hashed_block_stream(&payload_compressed, &mut payload)?;
+
+    // derive master key from composite key, transform_seed, transform_rounds and master_seed
+    let transformed_key = db_key
+        .transform_key(&db.config.kdf_config, &transform_seed, monitor, || {
+            let key_elements = db_key.get_key_elements()?;
+            let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
+            Ok::<_, DatabaseSaveError>(calculate_sha256(&key_elements))
+        })?
+        .ok_or(DatabaseSaveError::Cancelled)?;
+    let master_key = calculate_sha256(&[master_seed.as_ref(), transformed_key.as_slice()]);
+
+    let payload_encrypted = db
+        .config
+        .outer_cipher_config
+        .get_cipher(master_key.as_slice(), &encryption_iv)?
+        .encrypt(&payload)?;
+
+    let mut writer = ProgressWriter::new(writer, monitor, ProgressStage::Encryption);
+    writer.write_all(&header_data)?;
+    writer.write_all(&payload_encrypted)?;
+
+    Ok(())
+}
+
+/// Write data as a hashed block stream, where every block is prefixed with its index, its SHA-256
+/// and its size, and the stream is terminated by an empty block
+fn write_hashed_block_stream(data: &[u8], writer: &mut Vec<u8>) -> Result<(), std::io::Error> {
+    let mut block_index = 0;
+    for block in data.chunks(HASHED_BLOCK_SIZE) {
+        writer.write_u32::<LittleEndian>(block_index)?;
+        writer.write_all(calculate_sha256(&[block]).as_slice())?;
+        #[allow(clippy::cast_possible_truncation)]
+        writer.write_u32::<LittleEndian>(block.len() as u32)?;
+        writer.write_all(block)?;
+        block_index += 1;
+    }
+
+    writer.write_u32::<LittleEndian>(block_index)?;
+    writer.write_all(&[0; 32])?;
+    writer.write_u32::<LittleEndian>(0)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod kdbx3_dump_tests {
+    use super::dump_kdbx3;
+    use crate::format::kdbx3::{kdbx3_header_info, parse_kdbx3};
+    use crate::{
+        config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+        db::{group_add_child, group_get_children, node::*, Database, Entry},
+        error::{DatabaseIntegrityError, DatabaseOpenError, DatabaseSaveError},
+        format::DatabaseVersion,
+        key::DatabaseKey,
+        limits::OpenLimits,
+        progress::ProgressMonitor,
+        rc_refcell_node,
+    };
+
+    fn kdbx3_config(outer_cipher_config: OuterCipherConfig, compression_config: CompressionConfig) -> DatabaseConfig {
+        DatabaseConfig {
+            version: DatabaseVersion::KDB3(1),
+            outer_cipher_config,
+            compression_config,
+            inner_cipher_config: InnerCipherConfig::Salsa20,
+            kdf_config: KdfConfig::Aes { rounds: 10 },
+        }
+    }
+
+    #[test]
+    fn roundtrip_existing_database() {
+        let data = include_bytes!("../../../tests/resources/test_db_with_password.kdbx");
+        let db_key = DatabaseKey::new().with_password("demopass");
+        let (db, _) = parse_kdbx3(data, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+
+        let mut encrypted_db = Vec::new();
+        dump_kdbx3(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+        let (decrypted_db, _) = parse_kdbx3(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+
+        assert_eq!(decrypted_db, db);
+    }
+
+    #[test]
+    fn roundtrip_config_matrix() {
+        for outer_cipher_config in [OuterCipherConfig::AES128, OuterCipherConfig::AES256, OuterCipherConfig::Twofish] {
+            for compression_config in [CompressionConfig::None, CompressionConfig::GZip] {
+                let mut db = Database::new(kdbx3_config(outer_cipher_config.clone(), compression_config.clone()));
+
+                let mut entry = Entry::default();
+                entry.set_title(Some("Demo entry"));
+                entry.set_password(Some("secret"));
+                entry.add_attachment("a.txt", b"attachment".to_vec(), false);
+                entry.add_attachment("b.txt", b"attachment".to_vec(), false);
+                // the password is read after the protected attachment in the inner cipher stream
+                entry.add_attachment("protected.txt", b"protected attachment".to_vec(), true);
+                // a large attachment spans multiple blocks of the hashed block stream
+                entry.add_attachment("large.bin", vec![0x42; 1024 * 1024 + 1], false);
+                group_add_child(&db.root, rc_refcell_node!(entry), 0).unwrap();
+
+                let db_key = DatabaseKey::new().with_password("test");
+
+                let mut encrypted_db = Vec::new();
+                dump_kdbx3(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+                let (decrypted_db, _) = parse_kdbx3(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+
+                assert_eq!(decrypted_db, db);
+                assert!(decrypted_db.meta.binaries.binaries.is_empty());
+
+                let entry = &group_get_children(&decrypted_db.root).unwrap()[0];
+                let entry = entry.borrow();
+                let entry = entry.as_any().downcast_ref::<Entry>().unwrap();
+                assert_eq!(entry.get_attachments().len(), 4);
+                assert!(entry.get_attachment("protected.txt").unwrap().is_protected());
+                assert_eq!(entry.get_password(), Some("secret"));
+
+                db.config.kdf_config = KdfConfig::Argon2 {
+                    iterations: 1,
+                    memory: 1024,
+                    parallelism: 1,
+                    version: argon2::Version::Version13,
+                };
+                assert!(matches!(
+                    dump_kdbx3(&db, &db_key, &mut Vec::new(), &ProgressMonitor::default()),
+                    Err(DatabaseSaveError::UnsupportedKdf)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn header_hash_mismatch() {
+        let db = Database::new(kdbx3_config(OuterCipherConfig::AES256, CompressionConfig::GZip));
+        let db_key = DatabaseKey::new().with_password("test");
+
+        let mut encrypted_db = Vec::new();
+        dump_kdbx3(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+
+        // the comment field is not covered by any other integrity check
+        let mut tampered_db = encrypted_db[..12].to_vec();
+        tampered_db.extend_from_slice(&[1, 1, 0, 0x42]);
+        tampered_db.extend_from_slice(&encrypted_db[12..]);
+
+        assert!(matches!(
+            parse_kdbx3(&tampered_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()),
+            Err(DatabaseOpenError::DatabaseIntegrity(DatabaseIntegrityError::HeaderHashMismatch))
+        ));
+
+        // the comment can still be inspected without the key
+        assert_eq!(
+            kdbx3_header_info(&mut tampered_db.as_slice()).unwrap().comment.as_deref(),
+            Some("B")
+        );
+    }
+}
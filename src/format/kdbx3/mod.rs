@@ -0,0 +1,436 @@
+#[cfg(feature = "save_kdbx4")]
+mod dump;
+
+use crate::{
+    config::{CompressionConfig, DatabaseConfig, DatabaseHeaderInfo, HeaderAlgorithm, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+    crypt::{calculate_sha256, ciphers::Cipher},
+    db::{attachment::resolve_attachment_refs, Database},
+    error::{BlockStreamError, DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError},
+    format::{kdbx_header_field_id::KDBXHeaderFieldID, read_header_u32, read_header_u64, DatabaseVersion},
+    io::read_exact_vec,
+    key::{DatabaseKey, TransformedKey},
+    limits::{resource_limit_error, OpenLimits},
+    progress::{ProgressMonitor, ProgressReader, ProgressStage},
+    rc_refcell_node,
+    variant_dictionary::VariantDictionary,
+    NodePtr,
+};
+use byteorder::{ByteOrder, LittleEndian};
+use std::{
+    convert::{TryFrom, TryInto},
+    io::Read,
+};
+
+#[cfg(feature = "save_kdbx4")]
+pub(crate) use crate::format::kdbx3::dump::dump_kdbx3;
+
+#[derive(Debug)]
+struct KDBX3Header {
+    // https://gist.github.com/msmuenchen/9318327
+    outer_cipher: HeaderAlgorithm<OuterCipherConfig>,
+    compression: CompressionConfig,
+    master_seed: Vec<u8>,
+
+    transform_seed: Vec<u8>,
+    kdf_config: KdfConfig,
+
+    encryption_iv: Vec<u8>,
+    inner_random_stream_key: Vec<u8>,
+    stream_start: Vec<u8>,
+    inner_random_stream_id: InnerCipherConfig,
+    comment: Option<Vec<u8>>,
+    body_start: usize,
+}
+
+fn parse_outer_header(data: &[u8]) -> Result<KDBX3Header, DatabaseOpenError> {
+    let mut outer_cipher: Option<HeaderAlgorithm<OuterCipherConfig>> = None;
+    let mut compression: Option<CompressionConfig> = None;
+    let mut master_seed: Option<Vec<u8>> = None;
+    let mut transform_seed: Option<Vec<u8>> = None;
+    let mut transform_rounds: Option<u64> = None;
+    let mut encryption_iv: Option<Vec<u8>> = None;
+    let mut inner_random_stream_key: Option<Vec<u8>> = None;
+    let mut stream_start: Option<Vec<u8>> = None;
+    let mut inner_random_stream_id: Option<InnerCipherConfig> = None;
+    let mut comment: Option<Vec<u8>> = None;
+
+    // skip over the version header
+    let mut pos = DatabaseVersion::get_version_header_size();
+
+    // parse header
+    loop {
+        // parse header blocks.
+        //
+        // every block is a triplet of (3 + field_length) bytes with this structure:
+        //
+        // (
+        //   field_id: u8,                        // a numeric entry type identifier
+        //   field_length: u16,                     // length of the entry buffer
+        //   field_buffer: [u8; field_length]       // the entry buffer
+        // )
+
+        let err = DatabaseIntegrityError::IncompleteKDBEntry;
+        let field_id: KDBXHeaderFieldID = data.get(pos).ok_or(err)?.try_into()?;
+
+        let field_length = data
+            .get((pos + 1)..(pos + 3))
+            .ok_or(DatabaseIntegrityError::IncompleteKDBEntry)
+            .map(LittleEndian::read_u16)? as usize;
+
+        let err = DatabaseIntegrityError::IncompleteKDBEntry;
+        let field_buffer = data.get((pos + 3)..(pos + 3 + field_length)).ok_or(err)?;
+
+        pos += 3 + field_length;
+
+        match field_id {
+            // END - finished parsing header
+            KDBXHeaderFieldID::EndOfHeader => {
+                break;
+            }
+
+            // COMMENT
+            KDBXHeaderFieldID::Comment => comment = Some(field_buffer.to_vec()),
+
+            // CIPHERID - a UUID specifying which cipher suite
+            //            should be used to encrypt the payload
+            KDBXHeaderFieldID::CipherID => {
+                outer_cipher = Some(HeaderAlgorithm::outer_cipher(field_buffer));
+            }
+
+            // COMPRESSIONFLAGS - first byte determines compression of payload
+            KDBXHeaderFieldID::CompressionFlags => {
+                let cid = read_header_u32(field_id.into(), field_buffer)?;
+                compression = Some(CompressionConfig::try_from(cid).map_err(DatabaseIntegrityError::from)?);
+            }
+
+            // MASTERSEED - Master seed for deriving the master key
+            KDBXHeaderFieldID::MasterSeed => master_seed = Some(field_buffer.to_vec()),
+
+            // TRANSFORMSEED - Seed used in deriving the transformed key
+            KDBXHeaderFieldID::TransformSeed => transform_seed = Some(field_buffer.to_vec()),
+
+            // TRANSFORMROUNDS - Number of rounds used in derivation of transformed key
+            KDBXHeaderFieldID::TransformRounds => transform_rounds = Some(read_header_u64(field_id.into(), field_buffer)?),
+
+            // ENCRYPTIONIV - Initialization Vector for decrypting the payload
+            KDBXHeaderFieldID::EncryptionIV => encryption_iv = Some(field_buffer.to_vec()),
+
+            // PROTECTEDSTREAMKEY - Key for decrypting the inner protected values
+            KDBXHeaderFieldID::InnerRandomStreamKey => inner_random_stream_key = Some(field_buffer.to_vec()),
+
+            // STREAMSTARTBYTES - First bytes of decrypted payload (to check correct decryption)
+            KDBXHeaderFieldID::StreamStartBytes => stream_start = Some(field_buffer.to_vec()),
+
+            // INNERRANDOMSTREAMID - specifies which cipher suite
+            //                       to use for decrypting the inner protected values
+            KDBXHeaderFieldID::InnerRandomStreamID => {
+                let cid = read_header_u32(field_id.into(), field_buffer)?;
+                inner_random_stream_id = Some(InnerCipherConfig::try_from(cid).map_err(DatabaseIntegrityError::from)?);
+            }
+
+            _ => {
+                return Err(DatabaseIntegrityError::InvalidKDBXHeaderFieldID { field_id: field_id.into() }.into());
+            }
+        };
+    }
+
+    // at this point, the header needs to be fully defined - unwrap options and return errors if
+    // something is missing
+
+    fn get_or_err<T>(v: Option<T>, err: &str) -> Result<T, DatabaseIntegrityError> {
+        v.ok_or_else(|| DatabaseIntegrityError::IncompleteOuterHeader { missing_field: err.into() })
+    }
+
+    let outer_cipher = get_or_err(outer_cipher, "Outer Cipher ID")?;
+    let compression = get_or_err(compression, "Compression ID")?;
+    let master_seed = get_or_err(master_seed, "Master seed")?;
+    let transform_seed = get_or_err(transform_seed, "Transform seed")?;
+    let transform_rounds = get_or_err(transform_rounds, "Number of transformation rounds")?;
+    let encryption_iv = get_or_err(encryption_iv, "Outer cipher IV")?;
+    let inner_random_stream_key = get_or_err(inner_random_stream_key, "Protected stream key")?;
+    let stream_start = get_or_err(stream_start, "Stream start bytes")?;
+    let inner_random_stream_id = get_or_err(inner_random_stream_id, "Inner cipher ID")?;
+
+    // KDF type is always AES for KDBX3
+    let kdf_config = KdfConfig::Aes { rounds: transform_rounds };
+
+    Ok(KDBX3Header {
+        outer_cipher,
+        compression,
+        master_seed,
+        transform_seed,
+        kdf_config,
+        encryption_iv,
+        inner_random_stream_key,
+        stream_start,
+        inner_random_stream_id,
+        comment,
+        body_start: pos,
+    })
+}
+
+/// Read the settings of a KDBX3 database from its outer header, reading no further than the end of
+/// the header from `source`
+pub(crate) fn kdbx3_header_info(source: &mut dyn Read) -> Result<DatabaseHeaderInfo, DatabaseOpenError> {
+    let mut data = vec![0; DatabaseVersion::get_version_header_size()];
+    source.read_exact(&mut data)?;
+    let version = DatabaseVersion::parse(&data)?;
+
+    // collect the header fields up to the end of header field
+    loop {
+        let mut field_start = [0u8; 3];
+        source.read_exact(&mut field_start)?;
+        data.extend_from_slice(&field_start);
+        data.extend_from_slice(&read_exact_vec(source, LittleEndian::read_u16(&field_start[1..]) as usize)?);
+        if field_start[0] == u8::from(KDBXHeaderFieldID::EndOfHeader) {
+            break;
+        }
+    }
+    let header = parse_outer_header(&data)?;
+
+    Ok(DatabaseHeaderInfo {
+        version,
+        outer_cipher_config: header.outer_cipher,
+        compression_config: header.compression,
+        inner_cipher_config: Some(header.inner_random_stream_id),
+        kdf_config: HeaderAlgorithm::Known(header.kdf_config),
+        comment: header.comment.map(|comment| String::from_utf8_lossy(&comment).into_owned()),
+        public_custom_data: VariantDictionary::new(),
+    })
+}
+
+/// Open, decrypt and parse a `KeePass` database from a source and a password
+pub(crate) fn parse_kdbx3(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    monitor: &ProgressMonitor,
+    limits: &OpenLimits,
+) -> Result<(Database, TransformedKey), DatabaseOpenError> {
+    let (config, mut inner_decryptor, xml, transformed_key) = decrypt_kdbx3(data, db_key, monitor, limits)?;
+
+    // Parse XML data blocks
+    let xml_reader = ProgressReader::new(&xml[..], monitor, ProgressStage::Decryption, Some(xml.len() as u64));
+    let mut database_content = crate::xml_db::parse::parse(xml_reader, &mut *inner_decryptor, limits)?;
+
+    // attachments are part of the XML document in KDBX3
+    for binary in &database_content.meta.binaries.binaries {
+        limits.check_attachment_size(binary.content.len() as u64)?;
+    }
+
+    // KDBX 3.1 stores a hash of the outer header in the metadata
+    if let Some(header_hash) = database_content.meta.header_hash.take() {
+        let header = parse_outer_header(data)?;
+        if header_hash != calculate_sha256(&[&data[..header.body_start]]).as_slice() {
+            return Err(DatabaseIntegrityError::HeaderHashMismatch.into());
+        }
+    }
+
+    // entries reference the binaries in the metadata by their ID
+    let root = rc_refcell_node!(database_content.root.group);
+    let binaries = &mut database_content.meta.binaries.binaries;
+    let used_refs = resolve_attachment_refs(&root, &|pool_ref| {
+        binaries
+            .iter()
+            .find(|binary| binary.identifier.as_deref() == Some(pool_ref))
+            .map(|binary| (binary.content.clone(), binary.protected))
+    });
+    binaries.retain(|binary| !binary.identifier.as_ref().is_some_and(|id| used_refs.contains(id)));
+
+    let db = Database {
+        config,
+        header_attachments: Vec::new(),
+        root: root.into(),
+        deleted_objects: database_content.root.deleted_objects,
+        meta: database_content.meta,
+        public_custom_data: VariantDictionary::new(),
+        unknown_xml: database_content.root.unknown_xml,
+    };
+    db.apply_memory_protection();
+
+    Ok((db, transformed_key))
+}
+
+/// Open and decrypt a `KeePass` KDBX3 database from a source and a password
+#[allow(clippy::type_complexity)]
+pub(crate) fn decrypt_kdbx3(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    monitor: &ProgressMonitor,
+    limits: &OpenLimits,
+) -> Result<(DatabaseConfig, Box<dyn Cipher>, Vec<u8>, TransformedKey), DatabaseOpenError> {
+    let version = DatabaseVersion::parse(data)?;
+    let header = parse_outer_header(data)?;
+    limits.check_kdf(&header.kdf_config)?;
+
+    // Derive stream key for decrypting inner protected values and set up decryption context
+    let stream_key = calculate_sha256(&[header.inner_random_stream_key.as_ref()]);
+
+    let inner_decryptor = header.inner_random_stream_id.get_cipher(stream_key.as_slice())?;
+
+    let config = DatabaseConfig {
+        version,
+        outer_cipher_config: header.outer_cipher.into_known().map_err(DatabaseIntegrityError::from)?,
+        compression_config: header.compression,
+        inner_cipher_config: header.inner_random_stream_id,
+        kdf_config: header.kdf_config,
+    };
+
+    let pos = header.body_start;
+
+    // Turn enums into appropriate trait objects
+    let compression = config.compression_config.get_compression();
+
+    // Rest of file after header is payload
+    let payload_encrypted = data.get(pos..).ok_or_else(|| DatabaseIntegrityError::IncompleteOuterHeader {
+        missing_field: "Payload".into(),
+    })?;
+
+    // derive master key from composite key, transform_seed, transform_rounds and master_seed
+    let transformed_key = db_key
+        .transform_key(&config.kdf_config, &header.transform_seed, monitor, || {
+            let key_elements = db_key.get_key_elements()?;
+            let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
+            Ok::<_, DatabaseOpenError>(calculate_sha256(&key_elements))
+        })?
+        .ok_or(DatabaseOpenError::Cancelled)?;
+
+    if !monitor.report(ProgressStage::Decryption, 0, None) {
+        return Err(DatabaseOpenError::Cancelled);
+    }
+
+    let master_key = calculate_sha256(&[header.master_seed.as_ref(), transformed_key.as_slice()]);
+
+    // Decrypt payload
+    let payload = config
+        .outer_cipher_config
+        .get_cipher(master_key.as_slice(), header.encryption_iv.as_ref())?
+        .decrypt(payload_encrypted)?;
+
+    // Check if we decrypted correctly
+    let stream_start = payload.get(0..header.stream_start.len()).ok_or(DatabaseKeyError::IncorrectKey)?;
+    if stream_start != header.stream_start.as_slice() {
+        return Err(DatabaseKeyError::IncorrectKey.into());
+    }
+
+    let buf = read_hashed_block_stream(payload.get(32..).unwrap_or_default())?;
+
+    let mut xml = Vec::new();
+    limits
+        .limit_payload(compression.decompress_reader(Box::new(&buf[..])))
+        .read_to_end(&mut xml)
+        .map_err(|err| match resource_limit_error(&err) {
+            Some(err) => DatabaseOpenError::from(err),
+            None => err.into(),
+        })?;
+
+    Ok((config, inner_decryptor, xml, transformed_key))
+}
+
+/// Read the blocks of the hashed block stream of the payload of a KDBX3 database, verifying their hashes
+pub(crate) fn read_hashed_block_stream(payload: &[u8]) -> Result<Vec<u8>, BlockStreamError> {
+    let mut buf = Vec::new();
+
+    let mut pos = 0;
+    let mut block_index = 0;
+    loop {
+        // Parse blocks in payload.
+        //
+        // Each block is a tuple of size (40 + block_size) with structure:
+        //
+        // (
+        //   block_id: u32,                                 // a numeric block ID (starts at 0)
+        //   block_hash: [u8, 32],                          // SHA256 of block_buffer_compressed
+        //   block_size: u32,                               // block_size size in bytes
+        //   block_buffer_compressed: [u8, block_size]      // Block data, possibly compressed
+        // )
+
+        let incomplete = BlockStreamError::IncompleteBlock { block_index };
+        let block_header = payload.get(pos..(pos + 40)).ok_or(incomplete)?;
+
+        // let block_id = LittleEndian::read_u32(&block_header[0..4]);
+        let block_hash = &block_header[4..36];
+        let block_size = LittleEndian::read_u32(&block_header[36..40]) as usize;
+
+        // A block with size 0 means we have hit EOF
+        if block_size == 0 {
+            break;
+        }
+
+        let incomplete = BlockStreamError::IncompleteBlock { block_index };
+        let block_buffer_compressed = payload.get((pos + 40)..(pos + 40).saturating_add(block_size)).ok_or(incomplete)?;
+
+        // Test block hash
+        let block_hash_check = calculate_sha256(&[block_buffer_compressed]);
+        if block_hash != block_hash_check.as_slice() {
+            return Err(BlockStreamError::BlockHashMismatch { block_index });
+        }
+
+        // Decompress block_buffer_compressed
+        buf.append(&mut block_buffer_compressed.to_vec());
+
+        pos += 40 + block_size;
+        block_index += 1;
+    }
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod kdbx3_tests {
+    use super::{kdbx3_header_info, parse_kdbx3};
+    use crate::{
+        config::{CompressionConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+        db::{group_get_children, Entry},
+        error::{DatabaseIntegrityError, DatabaseOpenError},
+        format::DatabaseVersion,
+        key::DatabaseKey,
+        limits::OpenLimits,
+        progress::ProgressMonitor,
+    };
+
+    #[test]
+    fn parse_existing_database() {
+        let data = include_bytes!("../../../tests/resources/test_db_with_password.kdbx");
+        let db_key = DatabaseKey::new().with_password("demopass");
+        let (db, _) = parse_kdbx3(data, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+
+        assert_eq!(db.config.version, DatabaseVersion::KDB3(1));
+        assert_eq!(db.config.outer_cipher_config, OuterCipherConfig::AES256);
+        assert_eq!(db.config.compression_config, CompressionConfig::GZip);
+        assert_eq!(db.config.inner_cipher_config, InnerCipherConfig::Salsa20);
+        assert!(matches!(db.config.kdf_config, KdfConfig::Aes { .. }));
+
+        let children = group_get_children(&db.root).unwrap();
+        assert!(children.iter().any(|node| node.borrow().as_any().downcast_ref::<Entry>().is_some()));
+
+        let result = parse_kdbx3(
+            data,
+            &DatabaseKey::new().with_password("wrong"),
+            &ProgressMonitor::default(),
+            &OpenLimits::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn header_hash_mismatch() {
+        let data = include_bytes!("../../../tests/resources/test_db_with_password.kdbx");
+        let db_key = DatabaseKey::new().with_password("demopass");
+
+        // the comment field is not covered by any other integrity check
+        let mut tampered_db = data[..12].to_vec();
+        tampered_db.extend_from_slice(&[1, 1, 0, 0x42]);
+        tampered_db.extend_from_slice(&data[12..]);
+
+        assert!(matches!(
+            parse_kdbx3(&tampered_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()),
+            Err(DatabaseOpenError::DatabaseIntegrity(DatabaseIntegrityError::HeaderHashMismatch))
+        ));
+
+        // the comment can still be inspected without the key
+        assert_eq!(
+            kdbx3_header_info(&mut tampered_db.as_slice()).unwrap().comment.as_deref(),
+            Some("B")
+        );
+    }
+}
@@ -3,26 +3,28 @@ use std::io::Write;
 use byteorder::{LittleEndian, WriteBytesExt};
 
 use crate::{
-    crypt,
-    db::{Database, HeaderAttachment},
+    crypt::{self, ciphers::CipherWriter},
+    db::{attachment::AttachmentPool, CustomData, Database, Entry, Group, HeaderAttachment, NodeIterator},
     error::DatabaseSaveError,
     format::{
+        database_to_dump,
         kdbx4::{
-            KDBX4InnerHeader, KDBX4OuterHeader, HEADER_COMPRESSION_ID, HEADER_ENCRYPTION_IV, HEADER_END, HEADER_KDF_PARAMS,
-            HEADER_MASTER_SEED, HEADER_MASTER_SEED_SIZE, HEADER_OUTER_ENCRYPTION_ID, INNER_HEADER_BINARY_ATTACHMENTS, INNER_HEADER_END,
-            INNER_HEADER_RANDOM_STREAM_ID, INNER_HEADER_RANDOM_STREAM_KEY,
+            KDBX4InnerHeader, KDBX4OuterHeader, HEADER_ATTACHMENT_FLAG_PROTECTED, HEADER_COMPRESSION_ID, HEADER_ENCRYPTION_IV, HEADER_END,
+            HEADER_KDF_PARAMS, HEADER_MASTER_SEED, HEADER_MASTER_SEED_SIZE, HEADER_OUTER_ENCRYPTION_ID, HEADER_PUBLIC_CUSTOM_DATA,
+            INNER_HEADER_BINARY_ATTACHMENTS, INNER_HEADER_END, INNER_HEADER_RANDOM_STREAM_ID, INNER_HEADER_RANDOM_STREAM_KEY,
         },
-        DatabaseVersion,
+        DatabaseVersion, KDBX4_1_MINOR_VERSION, KDBX4_CURRENT_MINOR_VERSION,
     },
-    hmac_block_stream,
+    hmac_block_stream::{self, HmacBlockStreamWriter},
     io::WriteLengthTaggedExt,
     key::DatabaseKey,
+    progress::{ProgressMonitor, ProgressStage, ProgressWriter},
     variant_dictionary::VariantDictionary,
 };
 
 /// Dump a `KeePass` database using the key elements
 #[allow(dead_code)]
-pub fn dump_kdbx4(db: &Database, db_key: &DatabaseKey, writer: &mut dyn Write) -> Result<(), DatabaseSaveError> {
+pub fn dump_kdbx4(db: &Database, db_key: &DatabaseKey, writer: &mut dyn Write, monitor: &ProgressMonitor) -> Result<(), DatabaseSaveError> {
     if !matches!(db.config.version, DatabaseVersion::KDB4(_)) {
         return Err(DatabaseSaveError::UnsupportedVersion);
     }
@@ -31,41 +33,51 @@ pub fn dump_kdbx4(db: &Database, db_key: &DatabaseKey, writer: &mut dyn Write) -
     let mut master_seed = vec![0; HEADER_MASTER_SEED_SIZE];
     getrandom::getrandom(&mut master_seed)?;
 
-    let mut outer_iv = vec![0; db.config.outer_cipher_config.get_iv_size()];
+    let mut outer_iv = vec![0; db.config.outer_cipher_config.get_iv_size()?];
     getrandom::getrandom(&mut outer_iv)?;
 
-    let mut inner_random_stream_key = vec![0; db.config.inner_cipher_config.get_key_size()];
+    let mut inner_random_stream_key = vec![0; db.config.inner_cipher_config.get_key_size()?];
     getrandom::getrandom(&mut inner_random_stream_key)?;
 
-    let (kdf, kdf_seed) = db.config.kdf_config.get_kdf_and_seed()?;
+    // keep the seed of a cached transformed key, so that it can be used again after saving
+    let kdf_seed = match db_key.transformed_key_seed(&db.config.kdf_config) {
+        Some(kdf_seed) => kdf_seed.to_vec(),
+        None => db.config.kdf_config.generate_seed()?,
+    };
 
-    #[cfg(feature = "challenge_response")]
-    let db_key = db_key.clone().perform_challenge(&kdf_seed)?;
+    let mut writer = ProgressWriter::new(writer, monitor, ProgressStage::Encryption);
 
     // dump the outer header - need to buffer so that SHA256 can be computed
     let mut header_data = Vec::new();
     KDBX4OuterHeader {
-        version: db.config.version.clone(),
+        version: DatabaseVersion::KDB4(get_minor_version(db)),
         outer_cipher_config: db.config.outer_cipher_config.clone(),
         compression_config: db.config.compression_config.clone(),
         master_seed: master_seed.clone(),
         outer_iv: outer_iv.clone(),
         kdf_config: db.config.kdf_config.clone(),
-        kdf_seed,
+        kdf_seed: kdf_seed.clone(),
+        public_custom_data: db.public_custom_data.clone(),
     }
     .dump(&mut header_data)?;
 
     let header_sha256 = crypt::calculate_sha256(&[&header_data]);
 
     // write out header and header hash
-    _ = writer.write(&header_data)?;
-    _ = writer.write(header_sha256.as_slice())?;
+    writer.write_all(&header_data)?;
+    writer.write_all(header_sha256.as_slice())?;
 
     // derive master key from composite key, transform_seed, transform_rounds and master_seed
-    let key_elements = db_key.get_key_elements()?;
-    let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
-    let composite_key = crypt::calculate_sha256(&key_elements);
-    let transformed_key = kdf.transform_key(&composite_key)?;
+    let transformed_key = db_key
+        .transform_key(&db.config.kdf_config, &kdf_seed, monitor, || {
+            #[cfg(feature = "challenge_response")]
+            let db_key = &db_key.clone().perform_challenge(&kdf_seed)?;
+
+            let key_elements = db_key.get_key_elements()?;
+            let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
+            Ok::<_, DatabaseSaveError>(crypt::calculate_sha256(&key_elements))
+        })?
+        .ok_or(DatabaseSaveError::Cancelled)?;
     let master_key = crypt::calculate_sha256(&[&master_seed, transformed_key.as_slice()]);
 
     // verify credentials
@@ -73,40 +85,102 @@ pub fn dump_kdbx4(db: &Database, db_key: &DatabaseKey, writer: &mut dyn Write) -
     let header_hmac_key = hmac_block_stream::get_hmac_block_key(u64::MAX, &hmac_key);
     let header_hmac = crypt::calculate_hmac(&[&header_data], header_hmac_key.as_slice())?;
 
-    _ = writer.write(header_hmac.as_slice())?;
+    writer.write_all(header_hmac.as_slice())?;
 
     // Initialize inner encryptor from inner header params
-    let mut inner_cipher = db.config.inner_cipher_config.get_cipher(&inner_random_stream_key);
+    let mut inner_cipher = db.config.inner_cipher_config.get_cipher(&inner_random_stream_key)?;
+
+    // collect the entry attachments into the binary pool of the inner header, keeping the
+    // unreferenced header attachments at their current positions
+    let mut attachment_pool = AttachmentPool::default();
+    for attachment in &db.header_attachments {
+        attachment_pool.push(attachment.content.clone(), attachment.flags & HEADER_ATTACHMENT_FLAG_PROTECTED != 0);
+    }
+    let root = attachment_pool.assign_refs(&db.root);
+
+    let mut header_attachments = db.header_attachments.clone();
+    header_attachments.extend(
+        attachment_pool.items[db.header_attachments.len()..]
+            .iter()
+            .map(|(content, protected)| HeaderAttachment {
+                flags: if *protected { HEADER_ATTACHMENT_FLAG_PROTECTED } else { 0 },
+                content: content.clone(),
+            }),
+    );
+
+    // the payload is compressed, encrypted and split into HMAC-verified blocks while it is written
+    let payload_hmac = HmacBlockStreamWriter::new(&mut writer, hmac_key);
+    let payload_encrypted = CipherWriter::new(
+        Box::new(payload_hmac),
+        db.config.outer_cipher_config.get_encryptor(master_key.as_slice(), &outer_iv)?,
+    );
+    let mut payload = db
+        .config
+        .compression_config
+        .get_compression()
+        .compress_writer(Box::new(payload_encrypted));
 
-    // dump inner header into buffer
-    let mut payload = Vec::new();
     KDBX4InnerHeader {
         inner_random_stream: db.config.inner_cipher_config.clone(),
         inner_random_stream_key,
     }
-    .dump(&db.header_attachments, &mut payload)?;
+    .dump(&header_attachments, &mut *payload)?;
 
     // after inner header is one XML document
-    crate::xml_db::dump::dump(db, &mut *inner_cipher, &mut payload)?;
-
-    let payload_compressed = db.config.compression_config.get_compression().compress(&payload)?;
-
-    let payload_encrypted = db
-        .config
-        .outer_cipher_config
-        .get_cipher(master_key.as_slice(), &outer_iv)?
-        .encrypt(&payload_compressed)?;
+    let mut db = database_to_dump(db, root);
+    db.meta.header_hash = None;
+    crate::xml_db::dump::dump(&db, &mut *inner_cipher, &mut *payload)?;
 
-    let payload_hmac = hmac_block_stream::write_hmac_block_stream(&payload_encrypted, &hmac_key)?;
-    _ = writer.write(&payload_hmac)?;
+    payload.finish()?;
 
     Ok(())
 }
 
+/// Pick the minor version to save the database with. Like `KeePass`, KDBX 4.1 is only used if the
+/// database makes use of fields introduced with it, unless it was configured explicitly.
+fn get_minor_version(db: &Database) -> u16 {
+    let configured_minor_version = match db.config.version {
+        DatabaseVersion::KDB4(minor_version) => minor_version,
+        _ => KDBX4_CURRENT_MINOR_VERSION,
+    };
+
+    let has_timestamps = |custom_data: &CustomData| custom_data.items.values().any(|item| item.last_modification_time.is_some());
+    let entry_uses_kdbx4_1 =
+        |entry: &Entry| entry.previous_parent_group.is_some() || entry.quality_check.is_some() || has_timestamps(&entry.custom_data);
+
+    let uses_kdbx4_1 = db
+        .meta
+        .custom_icons
+        .icons
+        .iter()
+        .any(|icon| icon.name.is_some() || icon.last_modification_time.is_some())
+        || has_timestamps(&db.meta.custom_data)
+        || NodeIterator::new(&db.root).any(|node| {
+            let node = node.borrow();
+            if let Some(group) = node.as_any().downcast_ref::<Group>() {
+                !group.tags.is_empty() || group.previous_parent_group.is_some() || has_timestamps(&group.custom_data)
+            } else if let Some(entry) = node.as_any().downcast_ref::<Entry>() {
+                entry_uses_kdbx4_1(entry)
+                    || entry
+                        .history
+                        .as_ref()
+                        .is_some_and(|history| history.entries.iter().any(entry_uses_kdbx4_1))
+            } else {
+                false
+            }
+        });
+
+    if uses_kdbx4_1 {
+        configured_minor_version.max(KDBX4_1_MINOR_VERSION)
+    } else {
+        configured_minor_version
+    }
+}
+
 impl HeaderAttachment {
     fn dump(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
         writer.write_u8(self.flags)?;
-        _ = writer.write(&self.content)?;
+        writer.write_all(&self.content)?;
         Ok(())
     }
 }
@@ -135,6 +209,14 @@ impl KDBX4OuterHeader {
         writer.write_u8(HEADER_KDF_PARAMS)?;
         writer.write_with_len(&vd_buffer)?;
 
+        if !self.public_custom_data.data.is_empty() {
+            let mut vd_buffer = Vec::new();
+            self.public_custom_data.dump(&mut vd_buffer)?;
+
+            writer.write_u8(HEADER_PUBLIC_CUSTOM_DATA)?;
+            writer.write_with_len(&vd_buffer)?;
+        }
+
         writer.write_u8(HEADER_END)?;
         writer.write_with_len(&[])?;
 
@@ -145,7 +227,7 @@ impl KDBX4OuterHeader {
 impl KDBX4InnerHeader {
     #[allow(dead_code)]
     fn dump(&self, header_attachments: &[HeaderAttachment], writer: &mut dyn Write) -> Result<(), DatabaseSaveError> {
-        _ = writer.write(&[INNER_HEADER_RANDOM_STREAM_ID])?;
+        writer.write_u8(INNER_HEADER_RANDOM_STREAM_ID)?;
         writer.write_u32::<LittleEndian>(4)?;
         writer.write_u32::<LittleEndian>(self.inner_random_stream.dump())?;
 
@@ -4,11 +4,12 @@ mod parse;
 use crate::{
     config::{CompressionConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
     format::DatabaseVersion,
+    variant_dictionary::VariantDictionary,
 };
 
 #[allow(unused_imports)]
 pub(crate) use crate::format::kdbx4::dump::dump_kdbx4;
-pub(crate) use crate::format::kdbx4::parse::{decrypt_kdbx4, parse_kdbx4};
+pub(crate) use crate::format::kdbx4::parse::{decrypt_kdbx4, kdbx4_header_info, parse_kdbx4, parse_kdbx4_stream};
 
 /// Size for a master seed in bytes
 pub const HEADER_MASTER_SEED_SIZE: usize = 32;
@@ -27,6 +28,8 @@ pub const HEADER_MASTER_SEED: u8 = 4;
 pub const HEADER_ENCRYPTION_IV: u8 = 7;
 /// Parameters for the key derivation function
 pub const HEADER_KDF_PARAMS: u8 = 11;
+/// Unencrypted custom data of applications and plugins
+pub const HEADER_PUBLIC_CUSTOM_DATA: u8 = 12;
 
 /// Inner header entry denoting the end of the inner header
 pub const INNER_HEADER_END: u8 = 0x00;
@@ -37,6 +40,9 @@ pub const INNER_HEADER_RANDOM_STREAM_KEY: u8 = 0x02;
 /// Inner header entry denoting a binary attachment
 pub const INNER_HEADER_BINARY_ATTACHMENTS: u8 = 0x03;
 
+/// Binary attachment flag denoting that the attachment should be protected in memory
+pub const HEADER_ATTACHMENT_FLAG_PROTECTED: u8 = 0x01;
+
 struct KDBX4OuterHeader {
     version: DatabaseVersion,
     outer_cipher_config: OuterCipherConfig,
@@ -45,6 +51,7 @@ struct KDBX4OuterHeader {
     outer_iv: Vec<u8>,
     kdf_config: KdfConfig,
     kdf_seed: Vec<u8>,
+    public_custom_data: VariantDictionary,
 }
 
 struct KDBX4InnerHeader {
@@ -58,8 +65,11 @@ mod kdbx4_tests {
     use crate::{
         config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
         db::{group_add_child, node::*, Database, Entry, Group, HeaderAttachment},
-        format::{kdbx4::dump::dump_kdbx4, KDBX4_CURRENT_MINOR_VERSION},
+        error::{BlockStreamError, DatabaseIntegrityError, DatabaseOpenError},
+        format::{kdbx4::dump::dump_kdbx4, KDBX4_1_MINOR_VERSION, KDBX4_CURRENT_MINOR_VERSION},
         key::DatabaseKey,
+        limits::OpenLimits,
+        progress::ProgressMonitor,
         rc_refcell_node,
     };
 
@@ -90,9 +100,9 @@ mod kdbx4_tests {
                 ));
 
         let mut encrypted_db = Vec::new();
-        dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
 
-        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let (decrypted_db, _) = parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
 
         assert_eq!(group_get_children(&decrypted_db.root).unwrap().len(), 3);
     }
@@ -125,9 +135,9 @@ mod kdbx4_tests {
         let db_key = DatabaseKey::new().with_password(&password);
 
         let mut encrypted_db = Vec::new();
-        dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
 
-        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let (decrypted_db, _) = parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
 
         assert_eq!(group_get_children(&decrypted_db.root).unwrap().len(), 3);
 
@@ -140,7 +150,12 @@ mod kdbx4_tests {
 
     #[test]
     pub fn test_config_matrix() {
-        let outer_cipher_configs = [OuterCipherConfig::AES256, OuterCipherConfig::Twofish, OuterCipherConfig::ChaCha20];
+        let outer_cipher_configs = [
+            OuterCipherConfig::AES128,
+            OuterCipherConfig::AES256,
+            OuterCipherConfig::Twofish,
+            OuterCipherConfig::ChaCha20,
+        ];
 
         let compression_configs = [CompressionConfig::None, CompressionConfig::GZip];
 
@@ -208,9 +223,9 @@ mod kdbx4_tests {
         let db_key = DatabaseKey::new().with_password("test");
 
         let mut encrypted_db = Vec::new();
-        dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
 
-        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let (decrypted_db, _) = parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
 
         assert_eq!(group_get_children(&decrypted_db.root).unwrap().len(), 1);
 
@@ -219,4 +234,162 @@ mod kdbx4_tests {
         assert_eq!(header_attachments[0].flags, 1);
         assert_eq!(header_attachments[0].content, [0x01, 0x02, 0x03, 0x04]);
     }
+
+    #[test]
+    pub fn public_custom_data() {
+        let mut db = Database::new(DatabaseConfig::default());
+        db.public_custom_data.set("KPXC_DECRYPTION_TIME_PREFERENCE", 1000u32);
+        db.public_custom_data.set("Color", "#ff0000".to_string());
+
+        let db_key = DatabaseKey::new().with_password("test");
+
+        let mut encrypted_db = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+
+        // the public custom data is readable without the key
+        let info = kdbx4_header_info(&mut encrypted_db.as_slice()).unwrap();
+        assert_eq!(info.public_custom_data, db.public_custom_data);
+
+        let (decrypted_db, _) = parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+        assert_eq!(
+            decrypted_db
+                .public_custom_data
+                .get::<u32>("KPXC_DECRYPTION_TIME_PREFERENCE")
+                .unwrap(),
+            &1000
+        );
+        assert_eq!(decrypted_db.public_custom_data.get::<String>("Color").unwrap(), "#ff0000");
+
+        // the field is covered by the header HMAC
+        let mut tampered_db = encrypted_db.clone();
+        let position = tampered_db.windows(7).position(|window| window == b"#ff0000").unwrap();
+        tampered_db[position + 1] = b'0';
+        assert!(parse_kdbx4(&tampered_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).is_err());
+    }
+
+    #[test]
+    pub fn entry_attachments() {
+        let mut db = Database::new(DatabaseConfig::default());
+        db.header_attachments = vec![HeaderAttachment {
+            flags: 0,
+            content: b"unreferenced".to_vec(),
+        }];
+
+        let mut entry = Entry::default();
+        entry.set_title(Some("With attachments"));
+        entry.add_attachment("shared.txt", b"shared content".to_vec(), false);
+        entry.add_attachment("secret.bin", vec![0x00, 0xff, 0x10], true);
+        entry.update_history();
+        entry.add_attachment("shared-copy.txt", b"shared content".to_vec(), false);
+        let entry = rc_refcell_node!(entry);
+        group_add_child(&db.root, entry.clone(), 0).unwrap();
+
+        let db_key = DatabaseKey::new().with_password("test");
+
+        let mut encrypted_db = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+
+        let (decrypted_db, _) = parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+
+        // the unreferenced attachment is kept, the referenced ones are resolved into the entry
+        assert_eq!(decrypted_db.header_attachments, db.header_attachments);
+
+        let decrypted_entry = &group_get_children(&decrypted_db.root).unwrap()[0];
+        assert!(node_is_equals_to(decrypted_entry, &entry));
+
+        let decrypted_entry = decrypted_entry.borrow();
+        let decrypted_entry = decrypted_entry.as_any().downcast_ref::<Entry>().unwrap();
+        assert_eq!(decrypted_entry.get_attachments().len(), 3);
+        assert!(decrypted_entry.get_attachment("secret.bin").unwrap().is_protected());
+        assert_eq!(
+            decrypted_entry.get_history().as_ref().unwrap().get_entries()[0]
+                .get_attachments()
+                .len(),
+            2
+        );
+
+        // identical contents are only stored once
+        let (_, header_attachments, _, xml) = decrypt_kdbx4(&encrypted_db, &db_key).unwrap();
+        assert_eq!(header_attachments.len(), 3);
+        assert_eq!(header_attachments[2].flags, HEADER_ATTACHMENT_FLAG_PROTECTED);
+        let xml = String::from_utf8(xml).unwrap();
+        assert_eq!(xml.matches("<Value Ref=\"1\"").count(), 3);
+    }
+
+    #[test]
+    pub fn minor_version_selection() {
+        let db_key = DatabaseKey::new().with_password("test");
+
+        let db = Database::new(DatabaseConfig::default());
+        let mut encrypted_db = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+        let (decrypted_db, _) = parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+        assert_eq!(decrypted_db.config.version, DatabaseVersion::KDB4(KDBX4_CURRENT_MINOR_VERSION));
+
+        let mut group = Group::new("Tagged");
+        group.get_tags_mut().push("work".to_string());
+        let group = rc_refcell_node!(group);
+        group_add_child(&db.root, group.clone(), 0).unwrap();
+
+        let mut entry = Entry::default();
+        entry.set_previous_parent(Some(db.root.borrow().get_uuid()));
+        group_add_child(&group, rc_refcell_node!(entry), 0).unwrap();
+
+        let mut encrypted_db = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+        let (decrypted_db, _) = parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+        assert_eq!(decrypted_db.config.version, DatabaseVersion::KDB4(KDBX4_1_MINOR_VERSION));
+        assert!(node_is_equals_to(&decrypted_db.root, &db.root));
+    }
+
+    #[test]
+    pub fn streaming_large_payload() {
+        // incompressible content, so that the payload spans several blocks of the block stream
+        let mut content = vec![0; 3 * crate::hmac_block_stream::HMAC_BLOCK_SIZE];
+        getrandom::getrandom(&mut content).unwrap();
+
+        for outer_cipher_config in [OuterCipherConfig::AES256, OuterCipherConfig::Twofish, OuterCipherConfig::ChaCha20] {
+            let mut db = Database::new(DatabaseConfig {
+                outer_cipher_config,
+                kdf_config: KdfConfig::Aes { rounds: 10 },
+                ..DatabaseConfig::default()
+            });
+            let mut entry = Entry::default();
+            entry.add_attachment("large.bin", content.clone(), false);
+            group_add_child(&db.root, rc_refcell_node!(entry), 0).unwrap();
+            db.header_attachments.clear();
+
+            let db_key = DatabaseKey::new().with_password("test");
+            let mut encrypted_db = Vec::new();
+            dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+
+            let decrypted_db = Database::open(&mut encrypted_db.as_slice(), db_key.clone()).unwrap();
+            let entry = &group_get_children(&decrypted_db.root).unwrap()[0];
+            let entry = entry.borrow();
+            let entry = entry.as_any().downcast_ref::<Entry>().unwrap();
+            assert_eq!(entry.get_attachment("large.bin").unwrap().get_content(), content.as_slice());
+
+            // corrupting the second block is detected while streaming
+            let position = encrypted_db.len() - content.len() / 2;
+            encrypted_db[position] ^= 1;
+            assert!(matches!(
+                Database::open(&mut encrypted_db.as_slice(), db_key),
+                Err(DatabaseOpenError::DatabaseIntegrity(DatabaseIntegrityError::BlockStream(
+                    BlockStreamError::BlockHashMismatch { .. }
+                )))
+            ));
+        }
+    }
+
+    #[test]
+    fn header_attachment_without_flags() {
+        assert!(matches!(
+            HeaderAttachment::try_from(&[][..]),
+            Err(DatabaseIntegrityError::IncompleteInnerHeader { .. })
+        ));
+
+        let attachment = HeaderAttachment::try_from(&[1, 42][..]).unwrap();
+        assert_eq!(attachment.flags, 1);
+        assert_eq!(attachment.content, [42]);
+    }
 }
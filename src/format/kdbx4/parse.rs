@@ -1,52 +1,104 @@
-use std::convert::{TryFrom, TryInto};
+use std::{convert::TryFrom, io::Read};
 
-use byteorder::{ByteOrder, LittleEndian};
+use byteorder::{LittleEndian, ReadBytesExt};
 
 use crate::{
-    config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
-    crypt::{self, ciphers::Cipher},
-    db::{node::NodePtr, Database, HeaderAttachment},
-    error::{DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError},
+    config::{CompressionConfig, DatabaseConfig, DatabaseHeaderInfo, HeaderAlgorithm, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+    crypt::{
+        self,
+        ciphers::{Cipher, CipherReader},
+    },
+    db::{attachment::resolve_attachment_refs, node::NodePtr, Database, HeaderAttachment},
+    error::{DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError, XmlParseError},
     format::{
         kdbx4::{
-            KDBX4OuterHeader, HEADER_COMMENT, HEADER_COMPRESSION_ID, HEADER_ENCRYPTION_IV, HEADER_END, HEADER_KDF_PARAMS,
-            HEADER_MASTER_SEED, HEADER_OUTER_ENCRYPTION_ID, INNER_HEADER_BINARY_ATTACHMENTS, INNER_HEADER_END,
-            INNER_HEADER_RANDOM_STREAM_ID, INNER_HEADER_RANDOM_STREAM_KEY,
+            KDBX4OuterHeader, HEADER_ATTACHMENT_FLAG_PROTECTED, HEADER_COMMENT, HEADER_COMPRESSION_ID, HEADER_ENCRYPTION_IV, HEADER_END,
+            HEADER_KDF_PARAMS, HEADER_MASTER_SEED, HEADER_OUTER_ENCRYPTION_ID, HEADER_PUBLIC_CUSTOM_DATA, INNER_HEADER_BINARY_ATTACHMENTS,
+            INNER_HEADER_END, INNER_HEADER_RANDOM_STREAM_ID, INNER_HEADER_RANDOM_STREAM_KEY,
         },
-        DatabaseVersion,
+        read_header_u32, DatabaseVersion,
     },
-    hmac_block_stream,
-    key::DatabaseKey,
+    hmac_block_stream::{self, HmacBlockStreamReader},
+    io::{read_exact_vec, WriteLengthTaggedExt},
+    key::{DatabaseKey, TransformedKey},
+    limits::{resource_limit_error, OpenLimits},
+    progress::{ProgressMonitor, ProgressReader, ProgressStage},
     rc_refcell_node,
     variant_dictionary::VariantDictionary,
+    xml_db::parse::SimpleXmlEvent,
 };
 
 use super::KDBX4InnerHeader;
 
-impl From<&[u8]> for HeaderAttachment {
-    fn from(data: &[u8]) -> Self {
-        let flags = data[0];
-        let content = data[1..].to_vec();
+impl TryFrom<&[u8]> for HeaderAttachment {
+    type Error = DatabaseIntegrityError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let (flags, content) = data.split_first().ok_or_else(|| DatabaseIntegrityError::IncompleteInnerHeader {
+            missing_field: "Attachment flags".into(),
+        })?;
 
-        HeaderAttachment { flags, content }
+        Ok(HeaderAttachment {
+            flags: *flags,
+            content: content.to_vec(),
+        })
     }
 }
 
-/// Open, decrypt and parse a `KeePass` database from a source and key elements
-pub(crate) fn parse_kdbx4(data: &[u8], db_key: &DatabaseKey) -> Result<Database, DatabaseOpenError> {
-    let (config, header_attachments, mut inner_decryptor, xml) = decrypt_kdbx4(data, db_key)?;
+/// Open, decrypt and parse a `KeePass` database from a source and key elements, returning the
+/// transformed key along with the database
+pub(crate) fn parse_kdbx4(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    monitor: &ProgressMonitor,
+    limits: &OpenLimits,
+) -> Result<(Database, TransformedKey), DatabaseOpenError> {
+    parse_kdbx4_stream(&mut &data[..], db_key, monitor, limits)
+}
 
-    let database_content = crate::xml_db::parse::parse(&xml, &mut *inner_decryptor)?;
+/// Open, decrypt and parse a `KeePass` database while it is being read from `source`.
+///
+/// The payload is passed through a chain of readers (HMAC block stream, cipher, decompression)
+/// straight into the XML parser, so only the parsed database has to be held in memory.
+pub(crate) fn parse_kdbx4_stream(
+    source: &mut dyn Read,
+    db_key: &DatabaseKey,
+    monitor: &ProgressMonitor,
+    limits: &OpenLimits,
+) -> Result<(Database, TransformedKey), DatabaseOpenError> {
+    let mut payload = open_payload(source, db_key, monitor, limits)?;
+
+    let database_content = crate::xml_db::parse::parse(&mut payload.xml, &mut *payload.inner_decryptor, limits).map_err(xml_error)?;
+
+    // consume the rest of the stream, so that the final blocks are verified as well
+    std::io::copy(&mut payload.xml, &mut std::io::sink()).map_err(stream_error)?;
+
+    // entries reference header attachments by their position in the inner header
+    let header_attachments = payload.header_attachments;
+    let root = rc_refcell_node!(database_content.root.group);
+    let used_refs = resolve_attachment_refs(&root, &|pool_ref| {
+        let attachment = header_attachments.get(pool_ref.parse::<usize>().ok()?)?;
+        Some((attachment.content.clone(), attachment.flags & HEADER_ATTACHMENT_FLAG_PROTECTED != 0))
+    });
+    let header_attachments = header_attachments
+        .into_iter()
+        .enumerate()
+        .filter(|(position, _)| !used_refs.contains(&position.to_string()))
+        .map(|(_, attachment)| attachment)
+        .collect();
 
     let db = Database {
-        config,
+        config: payload.config,
         header_attachments,
-        root: rc_refcell_node!(database_content.root.group).into(),
+        root: root.into(),
         deleted_objects: database_content.root.deleted_objects,
         meta: database_content.meta,
+        public_custom_data: payload.public_custom_data,
+        unknown_xml: database_content.root.unknown_xml,
     };
+    db.apply_memory_protection();
 
-    Ok(db)
+    Ok((db, payload.transformed_key))
 }
 
 /// Open and decrypt a `KeePass` KDBX4 database from a source and key elements
@@ -55,64 +107,92 @@ pub(crate) fn decrypt_kdbx4(
     data: &[u8],
     db_key: &DatabaseKey,
 ) -> Result<(DatabaseConfig, Vec<HeaderAttachment>, Box<dyn Cipher>, Vec<u8>), DatabaseOpenError> {
-    // parse header
-    let (outer_header, inner_header_start) = parse_outer_header(data)?;
+    let mut source = data;
+    let monitor = ProgressMonitor::default();
+    let mut payload = open_payload(&mut source, db_key, &monitor, &OpenLimits::default())?;
+
+    let mut xml = Vec::new();
+    payload.xml.read_to_end(&mut xml).map_err(stream_error)?;
+
+    Ok((payload.config, payload.header_attachments, payload.inner_decryptor, xml))
+}
+
+/// The decrypted payload of a KDBX4 database, with the XML document still to be read
+struct Kdbx4Payload<'a> {
+    config: DatabaseConfig,
+    public_custom_data: VariantDictionary,
+    header_attachments: Vec<HeaderAttachment>,
+    inner_decryptor: Box<dyn Cipher>,
+    transformed_key: TransformedKey,
+    xml: Box<dyn Read + 'a>,
+}
 
-    // split file into segments:
+fn open_payload<'a>(
+    source: &'a mut dyn Read,
+    db_key: &DatabaseKey,
+    monitor: &'a ProgressMonitor,
+    limits: &OpenLimits,
+) -> Result<Kdbx4Payload<'a>, DatabaseOpenError> {
+    // the file consists of these segments:
     //      header_data         - The outer header data
     //      header_sha256       - A Sha256 hash of header_data (for verification of header integrity)
     //      header_hmac         - A HMAC of the header_data (for verification of the key_elements)
     //      hmac_block_stream   - A HMAC-verified block stream of encrypted and compressed blocks
-    let header_data = &data[0..inner_header_start];
-    let header_sha256 = &data[inner_header_start..(inner_header_start + 32)];
-    let header_hmac = &data[(inner_header_start + 32)..(inner_header_start + 64)];
-    let hmac_block_stream = &data[(inner_header_start + 64)..];
+    let (outer_header, header_data) = parse_outer_header(source)?;
+
+    let mut header_sha256 = [0u8; 32];
+    let mut header_hmac = [0u8; 32];
+    source.read_exact(&mut header_sha256)?;
+    source.read_exact(&mut header_hmac)?;
 
     // verify header
-    if header_sha256 != crypt::calculate_sha256(&[header_data]).as_slice() {
+    if header_sha256 != crypt::calculate_sha256(&[&header_data]).as_slice() {
         return Err(DatabaseIntegrityError::HeaderHashMismatch.into());
     }
 
-    #[cfg(feature = "challenge_response")]
-    let db_key = db_key.clone().perform_challenge(&outer_header.kdf_seed)?;
+    limits.check_kdf(&outer_header.kdf_config)?;
 
     // derive master key from composite key, transform_seed, transform_rounds and master_seed
-    let key_elements = db_key.get_key_elements()?;
-    let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
-    let composite_key = crypt::calculate_sha256(&key_elements);
-    let transformed_key = outer_header
-        .kdf_config
-        .get_kdf_seeded(&outer_header.kdf_seed)
-        .transform_key(&composite_key)?;
+    let transformed_key = db_key
+        .transform_key(&outer_header.kdf_config, &outer_header.kdf_seed, monitor, || {
+            #[cfg(feature = "challenge_response")]
+            let db_key = &db_key.clone().perform_challenge(&outer_header.kdf_seed)?;
+
+            let key_elements = db_key.get_key_elements()?;
+            let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
+            Ok::<_, DatabaseOpenError>(crypt::calculate_sha256(&key_elements))
+        })?
+        .ok_or(DatabaseOpenError::Cancelled)?;
     let t_k = transformed_key.as_slice();
     let master_key = crypt::calculate_sha256(&[outer_header.master_seed.as_ref(), t_k]);
 
     // verify credentials
     let hmac_key = crypt::calculate_sha512(&[&outer_header.master_seed, t_k, &hmac_block_stream::HMAC_KEY_END]);
     let header_hmac_key = hmac_block_stream::get_hmac_block_key(u64::MAX, &hmac_key);
-    if header_hmac != crypt::calculate_hmac(&[header_data], header_hmac_key.as_slice())?.as_slice() {
+    if header_hmac != crypt::calculate_hmac(&[&header_data], header_hmac_key.as_slice())?.as_slice() {
         return Err(DatabaseKeyError::IncorrectKey.into());
     }
 
-    // read encrypted payload from hmac-verified block stream
-    let payload_encrypted = hmac_block_stream::read_hmac_block_stream(hmac_block_stream, &hmac_key)?;
-
-    // Decrypt and decompress encrypted payload
-    let payload_compressed = outer_header
-        .outer_cipher_config
-        .get_cipher(master_key.as_slice(), &outer_header.outer_iv)?
-        .decrypt(&payload_encrypted)?;
-
-    let payload = outer_header.compression_config.get_compression().decompress(&payload_compressed)?;
-
-    // KDBX4 has inner header, too - parse it
-    let (header_attachments, inner_header, body_start) = parse_inner_header(&payload)?;
-
-    // after inner header is one XML document
-    let xml = &payload[body_start..];
+    // read encrypted payload from hmac-verified block stream, then decrypt and decompress it
+    let payload_encrypted = HmacBlockStreamReader::new(ProgressReader::new(source, monitor, ProgressStage::Decryption, None), hmac_key);
+    let payload_compressed = CipherReader::new(
+        payload_encrypted,
+        outer_header
+            .outer_cipher_config
+            .get_decryptor(master_key.as_slice(), &outer_header.outer_iv)?,
+    );
+    let mut payload = limits.limit_payload(
+        outer_header
+            .compression_config
+            .get_compression()
+            .decompress_reader(Box::new(payload_compressed)),
+    );
+
+    // KDBX4 has inner header, too - parse it. After the inner header is one XML document
+    let (header_attachments, inner_header) = parse_inner_header(&mut payload, limits)?;
 
     // initialize the inner decryptor
-    let inner_decryptor = inner_header.inner_random_stream.get_cipher(&inner_header.inner_random_stream_key);
+    let inner_decryptor = inner_header.inner_random_stream.get_cipher(&inner_header.inner_random_stream_key)?;
 
     let config = DatabaseConfig {
         version: outer_header.version,
@@ -122,21 +202,98 @@ pub(crate) fn decrypt_kdbx4(
         kdf_config: outer_header.kdf_config,
     };
 
-    Ok((config, header_attachments, inner_decryptor, xml.to_vec()))
+    Ok(Kdbx4Payload {
+        config,
+        public_custom_data: outer_header.public_custom_data,
+        header_attachments,
+        inner_decryptor,
+        transformed_key,
+        xml: payload,
+    })
+}
+
+/// Turn an I/O error from the payload reader chain into an error on opening the database
+fn stream_error(error: std::io::Error) -> DatabaseOpenError {
+    reader_error(&error).unwrap_or_else(|| error.into())
+}
+
+/// Recover the errors raised by the layers of the payload reader chain
+fn reader_error(error: &std::io::Error) -> Option<DatabaseOpenError> {
+    if let Some(error) = resource_limit_error(error) {
+        return Some(error.into());
+    }
+    hmac_block_stream::block_stream_error(error).map(Into::into)
+}
+
+/// Turn an XML error into an error on opening the database, recovering errors of the reader chain
+fn xml_error(error: XmlParseError) -> DatabaseOpenError {
+    let reader_error = match &error {
+        XmlParseError::Xml(e)
+        | XmlParseError::BadEvent {
+            event: SimpleXmlEvent::Err(e),
+            ..
+        } => match e.kind() {
+            xml::reader::ErrorKind::Io(e) => reader_error(e),
+            _ => None,
+        },
+        _ => None,
+    };
+    reader_error.unwrap_or_else(|| error.into())
+}
+
+/// Read a header entry of type `entry_type: u8`, `entry_length: u32` and `entry_buffer: [u8; entry_length]`
+fn read_header_entry(source: &mut dyn Read) -> Result<(u8, Vec<u8>), std::io::Error> {
+    let entry_type = source.read_u8()?;
+    let entry_length = source.read_u32::<LittleEndian>()? as usize;
+    let entry_buffer = read_exact_vec(source, entry_length)?;
+    Ok((entry_type, entry_buffer))
 }
 
-fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), DatabaseOpenError> {
-    let version = DatabaseVersion::parse(data)?;
+/// The outer header as it is read, whose cipher and KDF might not be known
+struct ReadOuterHeader {
+    version: DatabaseVersion,
+    outer_cipher: HeaderAlgorithm<OuterCipherConfig>,
+    compression_config: CompressionConfig,
+    master_seed: Vec<u8>,
+    outer_iv: Vec<u8>,
+    kdf: HeaderAlgorithm<(KdfConfig, Vec<u8>)>,
+    comment: Option<Vec<u8>>,
+    public_custom_data: VariantDictionary,
+}
 
-    // skip over the version header
-    let mut pos = DatabaseVersion::get_version_header_size();
+/// Read the outer header, returning it along with its raw data
+fn parse_outer_header(source: &mut dyn Read) -> Result<(KDBX4OuterHeader, Vec<u8>), DatabaseOpenError> {
+    let (header, header_data) = read_outer_header(source)?;
+    let (kdf_config, kdf_seed) = header.kdf.into_known()?;
 
-    let mut outer_cipher: Option<OuterCipherConfig> = None;
+    Ok((
+        KDBX4OuterHeader {
+            version: header.version,
+            outer_cipher_config: header.outer_cipher.into_known()?,
+            compression_config: header.compression_config,
+            master_seed: header.master_seed,
+            outer_iv: header.outer_iv,
+            kdf_config,
+            kdf_seed,
+            public_custom_data: header.public_custom_data,
+        },
+        header_data,
+    ))
+}
+
+/// Read the outer header without looking up its cipher and KDF, returning it along with its raw data
+fn read_outer_header(source: &mut dyn Read) -> Result<(ReadOuterHeader, Vec<u8>), DatabaseOpenError> {
+    let mut header_data = vec![0; DatabaseVersion::get_version_header_size()];
+    source.read_exact(&mut header_data)?;
+    let version = DatabaseVersion::parse(&header_data)?;
+
+    let mut outer_cipher: Option<HeaderAlgorithm<OuterCipherConfig>> = None;
     let mut compression_config: Option<CompressionConfig> = None;
     let mut master_seed: Option<Vec<u8>> = None;
     let mut outer_iv: Option<Vec<u8>> = None;
-    let mut kdf_config: Option<KdfConfig> = None;
-    let mut kdf_seed: Option<Vec<u8>> = None;
+    let mut kdf: Option<HeaderAlgorithm<(KdfConfig, Vec<u8>)>> = None;
+    let mut comment: Option<Vec<u8>> = None;
+    let mut public_custom_data = VariantDictionary::new();
 
     // parse header
     loop {
@@ -150,25 +307,25 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
         //   entry_buffer: [u8; entry_length]       // the entry buffer
         // )
 
-        let entry_type = data[pos];
-        let entry_length: usize = LittleEndian::read_u32(&data[pos + 1..(pos + 5)]) as usize;
-        let entry_buffer = &data[(pos + 5)..(pos + 5 + entry_length)];
+        let (entry_type, entry_buffer) = read_header_entry(source)?;
 
-        pos += 5 + entry_length;
+        header_data.push(entry_type);
+        header_data.write_with_len(&entry_buffer)?;
+        let entry_buffer = &entry_buffer[..];
 
         match entry_type {
             HEADER_END => {
                 break;
             }
 
-            HEADER_COMMENT => {}
+            HEADER_COMMENT => comment = Some(entry_buffer.to_vec()),
 
             HEADER_OUTER_ENCRYPTION_ID => {
-                outer_cipher = Some(OuterCipherConfig::try_from(entry_buffer)?);
+                outer_cipher = Some(HeaderAlgorithm::outer_cipher(entry_buffer));
             }
 
             HEADER_COMPRESSION_ID => {
-                compression_config = Some(CompressionConfig::try_from(LittleEndian::read_u32(entry_buffer))?);
+                compression_config = Some(CompressionConfig::try_from(read_header_u32(entry_type, entry_buffer)?)?);
             }
 
             HEADER_MASTER_SEED => master_seed = Some(entry_buffer.to_vec()),
@@ -176,12 +333,11 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
             HEADER_ENCRYPTION_IV => outer_iv = Some(entry_buffer.to_vec()),
 
             HEADER_KDF_PARAMS => {
-                let vd = VariantDictionary::parse(entry_buffer)?;
-                let (kconf, kseed) = vd.try_into()?;
-                kdf_config = Some(kconf);
-                kdf_seed = Some(kseed);
+                kdf = Some(HeaderAlgorithm::kdf(VariantDictionary::parse(entry_buffer)?)?);
             }
 
+            HEADER_PUBLIC_CUSTOM_DATA => public_custom_data = VariantDictionary::parse(entry_buffer)?,
+
             _ => {
                 return Err(DatabaseIntegrityError::InvalidOuterHeaderEntry { entry_type }.into());
             }
@@ -195,52 +351,72 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
         v.ok_or_else(|| DatabaseIntegrityError::IncompleteOuterHeader { missing_field: err.into() })
     }
 
-    let outer_cipher_config = get_or_err(outer_cipher, "Outer Cipher ID")?;
+    let outer_cipher = get_or_err(outer_cipher, "Outer Cipher ID")?;
     let compression_config = get_or_err(compression_config, "Compression ID")?;
     let master_seed = get_or_err(master_seed, "Master seed")?;
     let outer_iv = get_or_err(outer_iv, "Outer IV")?;
-    let kdf_config = get_or_err(kdf_config, "Key Derivation Function Parameters")?;
-    let kdf_seed = get_or_err(kdf_seed, "Key Derivation Function Seed")?;
+    let kdf = get_or_err(kdf, "Key Derivation Function Parameters")?;
 
     Ok((
-        KDBX4OuterHeader {
+        ReadOuterHeader {
             version,
-            outer_cipher_config,
+            outer_cipher,
             compression_config,
             master_seed,
             outer_iv,
-            kdf_config,
-            kdf_seed,
+            kdf,
+            comment,
+            public_custom_data,
         },
-        pos,
+        header_data,
     ))
 }
 
-fn parse_inner_header(data: &[u8]) -> Result<(Vec<HeaderAttachment>, KDBX4InnerHeader, usize), DatabaseOpenError> {
-    let mut pos = 0;
+/// Read the settings of a KDBX4 database from its outer header, reading no further than the end of
+/// the header from `source`
+pub(crate) fn kdbx4_header_info(source: &mut dyn Read) -> Result<DatabaseHeaderInfo, DatabaseOpenError> {
+    let (header, _) = read_outer_header(source)?;
+
+    Ok(DatabaseHeaderInfo {
+        version: header.version,
+        outer_cipher_config: header.outer_cipher,
+        compression_config: header.compression_config,
+        inner_cipher_config: None,
+        kdf_config: match header.kdf {
+            HeaderAlgorithm::Known((kdf_config, _)) => HeaderAlgorithm::Known(kdf_config),
+            HeaderAlgorithm::Unknown(uuid) => HeaderAlgorithm::Unknown(uuid),
+        },
+        comment: header.comment.map(|comment| String::from_utf8_lossy(&comment).into_owned()),
+        public_custom_data: header.public_custom_data,
+    })
+}
 
+fn parse_inner_header(source: &mut dyn Read, limits: &OpenLimits) -> Result<(Vec<HeaderAttachment>, KDBX4InnerHeader), DatabaseOpenError> {
     let mut inner_random_stream = None;
     let mut inner_random_stream_key = None;
     let mut header_attachments = Vec::new();
 
     loop {
-        let entry_type = data[pos];
-        let entry_length: usize = LittleEndian::read_u32(&data[pos + 1..(pos + 5)]) as usize;
-        let entry_buffer = &data[(pos + 5)..(pos + 5 + entry_length)];
-
-        pos += 5 + entry_length;
+        // same layout as the entries of the outer header, attachments are checked before they are read
+        let entry_type = source.read_u8().map_err(stream_error)?;
+        let entry_length = source.read_u32::<LittleEndian>().map_err(stream_error)?;
+        if entry_type == INNER_HEADER_BINARY_ATTACHMENTS {
+            limits.check_attachment_size(u64::from(entry_length.saturating_sub(1)))?;
+        }
+        let entry_buffer = read_exact_vec(source, entry_length as usize).map_err(stream_error)?;
+        let entry_buffer = &entry_buffer[..];
 
         match entry_type {
             INNER_HEADER_END => break,
 
             INNER_HEADER_RANDOM_STREAM_ID => {
-                inner_random_stream = Some(InnerCipherConfig::try_from(LittleEndian::read_u32(entry_buffer))?);
+                inner_random_stream = Some(InnerCipherConfig::try_from(read_header_u32(entry_type, entry_buffer)?)?);
             }
 
             INNER_HEADER_RANDOM_STREAM_KEY => inner_random_stream_key = Some(entry_buffer.to_vec()),
 
             INNER_HEADER_BINARY_ATTACHMENTS => {
-                let header_attachment = HeaderAttachment::from(entry_buffer);
+                let header_attachment = HeaderAttachment::try_from(entry_buffer)?;
                 header_attachments.push(header_attachment);
             }
 
@@ -262,5 +438,5 @@ fn parse_inner_header(data: &[u8]) -> Result<(Vec<HeaderAttachment>, KDBX4InnerH
         inner_random_stream_key,
     };
 
-    Ok((header_attachments, inner_header, pos))
+    Ok((header_attachments, inner_header))
 }
@@ -7,7 +7,10 @@ use std::io::Write;
 
 use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 
-use crate::error::DatabaseIntegrityError;
+use crate::{
+    db::{Database, NodePtr},
+    error::DatabaseIntegrityError,
+};
 
 const KDBX_IDENTIFIER: [u8; 4] = [0x03, 0xd9, 0xa2, 0x9a];
 
@@ -22,6 +25,20 @@ pub const KDBX3_MAJOR_VERSION: u16 = 3;
 pub const KDBX4_MAJOR_VERSION: u16 = 4;
 
 pub const KDBX4_CURRENT_MINOR_VERSION: u16 = 0;
+/// Minor version introducing group tags, previous parent groups, custom icon names and
+/// custom data timestamps
+pub const KDBX4_1_MINOR_VERSION: u16 = 1;
+
+/// Copy `db` with the tree `root` for writing it to a KDBX file. The fields of the copy are
+/// protected as the memory protection settings ask for, without changing the open database.
+pub(crate) fn database_to_dump(db: &Database, root: NodePtr) -> Database {
+    let db = Database {
+        root: root.into(),
+        ..db.clone()
+    };
+    db.apply_memory_protection();
+    db
+}
 
 /// Supported KDB database versions, with the associated
 /// minor version.
@@ -67,16 +84,18 @@ impl DatabaseVersion {
     }
 
     fn dump(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
-        if let DatabaseVersion::KDB4(minor_version) = self {
-            _ = writer.write(&crate::format::KDBX_IDENTIFIER)?;
-            writer.write_u32::<LittleEndian>(KEEPASS_LATEST_ID)?;
-            writer.write_u16::<LittleEndian>(*minor_version)?;
-            writer.write_u16::<LittleEndian>(KDBX4_MAJOR_VERSION)?;
-
-            Ok(())
-        } else {
-            panic!("DatabaseVersion::dump only supports dumping KDBX4.");
-        }
+        let (minor_version, major_version) = match self {
+            DatabaseVersion::KDB3(minor_version) => (minor_version, KDBX3_MAJOR_VERSION),
+            DatabaseVersion::KDB4(minor_version) => (minor_version, KDBX4_MAJOR_VERSION),
+            _ => panic!("DatabaseVersion::dump only supports dumping KDBX3 and KDBX4."),
+        };
+
+        _ = writer.write(&crate::format::KDBX_IDENTIFIER)?;
+        writer.write_u32::<LittleEndian>(KEEPASS_LATEST_ID)?;
+        writer.write_u16::<LittleEndian>(*minor_version)?;
+        writer.write_u16::<LittleEndian>(major_version)?;
+
+        Ok(())
     }
 
     pub(crate) fn get_version_header_size() -> usize {
@@ -84,6 +103,31 @@ impl DatabaseVersion {
     }
 }
 
+/// Ensure that a header field has the size of the value it holds
+fn ensure_header_field_length(field_id: u8, buffer: &[u8], expected_field_size: usize) -> Result<(), DatabaseIntegrityError> {
+    if buffer.len() == expected_field_size {
+        Ok(())
+    } else {
+        Err(DatabaseIntegrityError::InvalidHeaderFieldLength {
+            field_id,
+            field_size: buffer.len(),
+            expected_field_size,
+        })
+    }
+}
+
+/// Read the `u32` value of a header field
+pub(crate) fn read_header_u32(field_id: u8, buffer: &[u8]) -> Result<u32, DatabaseIntegrityError> {
+    ensure_header_field_length(field_id, buffer, 4)?;
+    Ok(LittleEndian::read_u32(buffer))
+}
+
+/// Read the `u64` value of a header field
+pub(crate) fn read_header_u64(field_id: u8, buffer: &[u8]) -> Result<u64, DatabaseIntegrityError> {
+    ensure_header_field_length(field_id, buffer, 8)?;
+    Ok(LittleEndian::read_u64(buffer))
+}
+
 impl std::fmt::Display for DatabaseVersion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
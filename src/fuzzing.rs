@@ -0,0 +1,39 @@
+//! Entry points into the parsers of the crate for the fuzz targets in `fuzz/`.
+//!
+//! They are only available with the `fuzzing` feature and are not part of the stable API.
+
+use std::io::Read;
+
+use cipher::generic_array::GenericArray;
+
+use crate::{
+    crypt::ciphers::PlainCipher,
+    error::{BlockStreamError, VariantDictionaryError, XmlParseError},
+    format::kdbx3,
+    hmac_block_stream::HmacBlockStreamReader,
+    limits::OpenLimits,
+    variant_dictionary::VariantDictionary,
+    xml_db::parse::parse,
+};
+
+/// Parse the XML document of a KDBX database, whose protected values are not encrypted
+pub fn parse_xml(data: &[u8]) -> Result<(), XmlParseError> {
+    parse(data, &mut PlainCipher::new(&[]), &OpenLimits::untrusted()).map(|_| ())
+}
+
+/// Parse a variant dictionary, e.g. the KDF parameters of a KDBX4 header
+pub fn parse_variant_dictionary(data: &[u8]) -> Result<VariantDictionary, VariantDictionaryError> {
+    VariantDictionary::parse(data)
+}
+
+/// Read the HMAC block stream of a KDBX4 payload with a key of zeros
+pub fn read_hmac_block_stream(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut content = Vec::new();
+    HmacBlockStreamReader::new(data, GenericArray::default()).read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Read the hashed block stream of a KDBX3 payload after its stream start bytes
+pub fn read_hashed_block_stream(data: &[u8]) -> Result<Vec<u8>, BlockStreamError> {
+    kdbx3::read_hashed_block_stream(data)
+}
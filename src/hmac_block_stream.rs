@@ -1,92 +1,175 @@
+use std::io::{Read, Write};
+
 use byteorder::{ByteOrder, LittleEndian};
 use cipher::generic_array::{typenum::U64, GenericArray};
 use hex_literal::hex;
 
-use crate::error::{BlockStreamError, CryptographyError};
+use crate::{
+    error::BlockStreamError,
+    io::{read_exact_vec, FinishWrite},
+};
 
 pub const HMAC_KEY_END: [u8; 1] = hex!("01");
 
-/// Read from a HMAC block stream into a raw buffer
-pub(crate) fn read_hmac_block_stream(data: &[u8], key: &GenericArray<u8, U64>) -> Result<Vec<u8>, BlockStreamError> {
-    // keepassxc src/streams/HmacBlockStream.cpp
+/// Size of the blocks written to a HMAC block stream, the same as used by `KeePass`
+pub(crate) const HMAC_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Reader for the raw data of a HMAC block stream, verifying every block before handing it out
+pub(crate) struct HmacBlockStreamReader<R: Read> {
+    inner: R,
+    key: GenericArray<u8, U64>,
+    block_index: u64,
+    block: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> HmacBlockStreamReader<R> {
+    pub(crate) fn new(inner: R, key: GenericArray<u8, U64>) -> Self {
+        HmacBlockStreamReader {
+            inner,
+            key,
+            block_index: 0,
+            block: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
 
-    let mut out = Vec::new();
+    fn read_block(&mut self) -> Result<(), std::io::Error> {
+        // keepassxc src/streams/HmacBlockStream.cpp
 
-    let mut pos = 0;
-    let mut block_index: u64 = 0;
+        let block_index = self.block_index;
+        let incomplete = |error: std::io::Error| match error.kind() {
+            std::io::ErrorKind::UnexpectedEof => stream_error(BlockStreamError::IncompleteBlock { block_index }),
+            _ => error,
+        };
 
-    while pos < data.len() {
-        let hmac = &data[pos..(pos + 32)];
-        let size_bytes = &data[(pos + 32)..(pos + 36)];
-        let size = LittleEndian::read_u32(size_bytes) as usize;
-        let block = &data[(pos + 36)..(pos + 36 + size)];
+        let mut hmac = [0u8; 32];
+        let mut size_bytes = [0u8; 4];
+        self.inner.read_exact(&mut hmac).map_err(incomplete)?;
+        self.inner.read_exact(&mut size_bytes).map_err(incomplete)?;
+        let size = LittleEndian::read_u32(&size_bytes) as usize;
+        let block = read_exact_vec(&mut self.inner, size).map_err(incomplete)?;
 
         // verify block hmac
-        let hmac_block_key = get_hmac_block_key(block_index, key);
+        let hmac_block_key = get_hmac_block_key(self.block_index, &self.key);
         let mut block_index_buf = [0u8; 8];
-        LittleEndian::write_u64(&mut block_index_buf, block_index);
-
-        if hmac != crate::crypt::calculate_hmac(&[&block_index_buf, size_bytes, block], hmac_block_key.as_slice())?.as_slice() {
-            return Err(BlockStreamError::BlockHashMismatch { block_index });
+        LittleEndian::write_u64(&mut block_index_buf, self.block_index);
+
+        let expected = crate::crypt::calculate_hmac(&[&block_index_buf, &size_bytes, &block], hmac_block_key.as_slice())
+            .map_err(|e| stream_error(e.into()))?;
+        if hmac != expected.as_slice() {
+            return Err(stream_error(BlockStreamError::BlockHashMismatch {
+                block_index: self.block_index,
+            }));
         }
 
-        pos += 36 + size;
-        block_index += 1;
+        self.block_index += 1;
+        self.finished = size == 0;
+        self.block = block;
+        self.position = 0;
+        Ok(())
+    }
+}
 
-        if size == 0 {
-            break;
+impl<R: Read> Read for HmacBlockStreamReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        while self.position == self.block.len() && !self.finished {
+            self.read_block()?;
         }
 
-        out.extend_from_slice(block);
+        let count = buf.len().min(self.block.len() - self.position);
+        buf[..count].copy_from_slice(&self.block[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
     }
-
-    Ok(out)
 }
 
-/// Write a raw buffer as a HMAC block stream
-#[allow(dead_code)]
-pub(crate) fn write_hmac_block_stream(data: &[u8], key: &GenericArray<u8, U64>) -> Result<Vec<u8>, CryptographyError> {
-    let mut out = Vec::new();
-
-    let mut pos = 0;
-    let mut block_index = 0;
-
-    while pos < data.len() {
-        let size = data.len() - pos;
+/// Writer splitting its input into the blocks of a HMAC block stream
+pub(crate) struct HmacBlockStreamWriter<W: Write> {
+    inner: W,
+    key: GenericArray<u8, U64>,
+    block_index: u64,
+    buffer: Vec<u8>,
+}
 
-        let block = &data[pos..(pos + size)];
+impl<W: Write> HmacBlockStreamWriter<W> {
+    pub(crate) fn new(inner: W, key: GenericArray<u8, U64>) -> Self {
+        HmacBlockStreamWriter {
+            inner,
+            key,
+            block_index: 0,
+            buffer: Vec::with_capacity(HMAC_BLOCK_SIZE),
+        }
+    }
 
-        let mut size_bytes: Vec<u8> = vec![0; 4];
+    /// Write the buffered data as the next block and empty the buffer
+    fn write_block(&mut self) -> Result<(), std::io::Error> {
+        let size = self.buffer.len();
+        let mut size_bytes = [0u8; 4];
         #[allow(clippy::cast_possible_truncation)]
         LittleEndian::write_u32(&mut size_bytes, size as u32);
 
         // Generate block hmac
-        let hmac_block_key = get_hmac_block_key(block_index, key);
+        let hmac_block_key = get_hmac_block_key(self.block_index, &self.key);
         let mut block_index_buf = [0u8; 8];
-        LittleEndian::write_u64(&mut block_index_buf, block_index);
+        LittleEndian::write_u64(&mut block_index_buf, self.block_index);
+
+        let block = self.buffer.as_slice();
+        let hmac = crate::crypt::calculate_hmac(&[&block_index_buf, &size_bytes, block], hmac_block_key.as_slice())
+            .map_err(|e| stream_error(e.into()))?;
 
-        let hmac = crate::crypt::calculate_hmac(&[&block_index_buf, &size_bytes, block], hmac_block_key.as_slice())?;
+        self.inner.write_all(hmac.as_slice())?;
+        self.inner.write_all(&size_bytes)?;
+        self.inner.write_all(block)?;
 
-        pos += 36 + size;
-        block_index += 1;
+        self.buffer.clear();
+        self.block_index += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for HmacBlockStreamWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        // never buffer more than one block, whatever the size of `buf`
+        let size = buf.len().min(HMAC_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..size]);
+        if self.buffer.len() == HMAC_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(size)
+    }
 
-        out.extend_from_slice(hmac.as_slice());
-        out.extend_from_slice(&size_bytes);
-        out.extend_from_slice(block);
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.inner.flush()
     }
+}
 
-    // the end of the HMAC block stream should be an empty block, but with a valid HMAC
-    let hmac_block_key = get_hmac_block_key(block_index, key);
-    let mut block_index_buf = [0u8; 8];
-    LittleEndian::write_u64(&mut block_index_buf, block_index);
+impl<W: Write> FinishWrite for HmacBlockStreamWriter<W> {
+    fn finish(mut self: Box<Self>) -> Result<(), std::io::Error> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
 
-    let size_bytes = vec![0; 4];
-    let hmac = crate::crypt::calculate_hmac(&[&block_index_buf, &size_bytes, &[]], hmac_block_key.as_slice())?;
+        // the end of the HMAC block stream should be an empty block, but with a valid HMAC
+        self.write_block()?;
+        self.inner.flush()
+    }
+}
 
-    out.extend_from_slice(hmac.as_slice());
-    out.extend_from_slice(&size_bytes);
+/// Wrap a block stream error so that it can be passed through a chain of readers
+fn stream_error(error: BlockStreamError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+}
 
-    Ok(out)
+/// Recover a block stream error that was passed through a chain of readers as an I/O error
+pub(crate) fn block_stream_error(error: &std::io::Error) -> Option<BlockStreamError> {
+    match error.get_ref()?.downcast_ref::<BlockStreamError>()? {
+        BlockStreamError::BlockHashMismatch { block_index } => Some(BlockStreamError::BlockHashMismatch { block_index: *block_index }),
+        BlockStreamError::IncompleteBlock { block_index } => Some(BlockStreamError::IncompleteBlock { block_index: *block_index }),
+        BlockStreamError::Cryptography(_) => None,
+    }
 }
 
 pub(crate) fn get_hmac_block_key(block_index: u64, key: &GenericArray<u8, U64>) -> GenericArray<u8, U64> {
@@ -94,3 +177,71 @@ pub(crate) fn get_hmac_block_key(block_index: u64, key: &GenericArray<u8, U64>)
     LittleEndian::write_u64(&mut buf, block_index);
     crate::crypt::calculate_sha512(&[&buf, key])
 }
+
+#[cfg(test)]
+mod hmac_block_stream_tests {
+    use std::io::{Read, Write};
+
+    use cipher::generic_array::GenericArray;
+
+    use super::{block_stream_error, HmacBlockStreamReader, HmacBlockStreamWriter, HMAC_BLOCK_SIZE};
+    use crate::{error::BlockStreamError, io::FinishWrite};
+
+    fn write_stream(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut writer = Box::new(HmacBlockStreamWriter::new(&mut out, GenericArray::default()));
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap();
+        out
+    }
+
+    #[test]
+    fn roundtrip_multiple_blocks() {
+        let data: Vec<u8> = (0..HMAC_BLOCK_SIZE * 2 + 1234).map(|i| (i % 251) as u8).collect();
+        let stream = write_stream(&data);
+        // three data blocks and the terminating empty block
+        assert_eq!(stream.len(), data.len() + 4 * 36);
+
+        let mut out = Vec::new();
+        HmacBlockStreamReader::new(&stream[..], GenericArray::default())
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn write_buffers_at_most_one_block() {
+        let data = vec![7u8; HMAC_BLOCK_SIZE * 3];
+        let mut out = Vec::new();
+        let mut writer = HmacBlockStreamWriter::new(&mut out, GenericArray::default());
+        assert_eq!(writer.write(&data[..10]).unwrap(), 10);
+        assert_eq!(writer.write(&data).unwrap(), HMAC_BLOCK_SIZE - 10);
+        assert!(writer.buffer.is_empty());
+        assert_eq!(writer.write(&data).unwrap(), HMAC_BLOCK_SIZE);
+        assert_eq!(out.len(), 2 * (HMAC_BLOCK_SIZE + 36));
+    }
+
+    #[test]
+    fn tampered_block() {
+        let data = vec![42u8; HMAC_BLOCK_SIZE + 10];
+        let mut stream = write_stream(&data);
+        let second_block = 36 + HMAC_BLOCK_SIZE + 36;
+        stream[second_block] ^= 1;
+
+        let mut reader = HmacBlockStreamReader::new(&stream[..], GenericArray::default());
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            block_stream_error(&error),
+            Some(BlockStreamError::BlockHashMismatch { block_index: 1 })
+        ));
+
+        // a stream without its terminating block is incomplete
+        let truncated = write_stream(b"data");
+        let mut reader = HmacBlockStreamReader::new(&truncated[..truncated.len() - 36], GenericArray::default());
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            block_stream_error(&error),
+            Some(BlockStreamError::IncompleteBlock { block_index: 1 })
+        ));
+    }
+}
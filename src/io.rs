@@ -1,4 +1,4 @@
-use std::io::Write;
+use std::io::{Read, Write};
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
@@ -13,3 +13,23 @@ pub trait WriteLengthTaggedExt: Write {
 }
 
 impl<W: Write + ?Sized> WriteLengthTaggedExt for W {}
+
+/// A layer of a writer chain that has to write out trailing data once all input has been written,
+/// e.g. the padding of a block cipher or the terminating block of a block stream.
+pub(crate) trait FinishWrite: Write {
+    /// Write out the trailing data of this layer and finish the layers below it
+    fn finish(self: Box<Self>) -> Result<(), std::io::Error>;
+}
+
+/// Read exactly `length` bytes into a new buffer.
+///
+/// The buffer grows with the data that is actually read, so that a corrupted length cannot cause a
+/// huge allocation up front.
+pub(crate) fn read_exact_vec(reader: &mut dyn Read, length: usize) -> Result<Vec<u8>, std::io::Error> {
+    let mut buffer = Vec::new();
+    reader.take(length as u64).read_to_end(&mut buffer)?;
+    if buffer.len() != length {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buffer)
+}
@@ -1,9 +1,9 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
 use base64::{engine::general_purpose as base64_engine, Engine as _};
 use xml::name::OwnedName;
 use xml::reader::{EventReader, XmlEvent};
-use zeroize::{Zeroize, ZeroizeOnDrop};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 #[cfg(feature = "challenge_response")]
 use challenge_response::{
@@ -11,7 +11,15 @@ use challenge_response::{
     ChallengeResponse,
 };
 
-use crate::{crypt::calculate_sha256, error::DatabaseKeyError};
+use cipher::generic_array::{typenum::U32, GenericArray};
+
+use crate::{
+    config::KdfConfig,
+    crypt::{calculate_hmac, calculate_sha256},
+    error::{CryptographyError, DatabaseKeyError, KdfConfigError},
+    progress::{ProgressMonitor, ProgressStage},
+    protected::ProtectedValue,
+};
 
 pub type KeyElement = Vec<u8>;
 pub type KeyElements = Vec<KeyElement>;
@@ -24,21 +32,31 @@ fn parse_yubikey_slot(slot_number: &str) -> Result<Slot, DatabaseKeyError> {
     Err(DatabaseKeyError::ChallengeResponseKeyError("Invalid slot number".to_string()))
 }
 
-fn parse_xml_keyfile(xml: &[u8]) -> Result<KeyElement, DatabaseKeyError> {
+/// Parse a keyfile in the XML format of `KeePass` 2.x, returning `None` if the data is not such a keyfile
+fn parse_xml_keyfile(xml: &[u8]) -> Result<Option<KeyElement>, DatabaseKeyError> {
     let parser = EventReader::new(xml);
 
     let mut tag_stack = Vec::new();
 
     let mut key_version: Option<String> = None;
     let mut key_value: Option<String> = None;
+    let mut key_hash: Option<String> = None;
 
     for ev in parser {
-        match ev? {
+        // anything that is not well-formed XML is a keyfile in a different format
+        let Ok(ev) = ev else {
+            return Ok(None);
+        };
+        match ev {
             XmlEvent::StartElement {
                 name: OwnedName { ref local_name, .. },
+                attributes,
                 ..
             } => {
                 tag_stack.push(local_name.clone());
+                if tag_stack == ["KeyFile", "Key", "Data"] {
+                    key_hash = attributes.into_iter().find(|a| a.name.local_name == "Hash").map(|a| a.value);
+                }
             }
             XmlEvent::EndElement { .. } => {
                 tag_stack.pop();
@@ -58,42 +76,79 @@ fn parse_xml_keyfile(xml: &[u8]) -> Result<KeyElement, DatabaseKeyError> {
         }
     }
 
-    let key_value = match key_value {
-        Some(k) => k,
-        None => return Err(DatabaseKeyError::InvalidKeyFile),
+    let Some(key_value) = key_value else {
+        return Ok(None);
     };
-    let key_bytes = key_value.as_bytes().to_vec();
 
-    if key_version == Some("2.0".to_string()) {
-        // TODO we should also validate the integrity of a v2 keyfile using the hash value
+    if key_version.as_deref().is_some_and(|version| version.trim().starts_with("2.")) {
+        // version 2 keyfiles store the key as hex, optionally verified by a hash
+        let trimmed_key: String = key_value.chars().filter(|c| !c.is_whitespace()).collect();
+        let key = hex::decode(trimmed_key).map_err(|_| DatabaseKeyError::InvalidKeyFile)?;
 
-        let trimmed_key = key_value.trim().replace(" ", "").replace("\n", "").replace("\r", "");
+        if let Some(hash) = key_hash {
+            if !hash.trim().eq_ignore_ascii_case(&keyfile_hash(&key)) {
+                return Err(DatabaseKeyError::KeyFileHashMismatch);
+            }
+        }
 
-        return if let Ok(key) = hex::decode(&trimmed_key) {
-            Ok(key)
-        } else {
-            Ok(key_bytes)
-        };
+        return Ok(Some(key));
     }
 
     // Check if the key is base64-encoded. If yes, return decoded bytes
+    let key_bytes = key_value.as_bytes().to_vec();
     if let Ok(key) = base64_engine::STANDARD.decode(&key_bytes) {
-        Ok(key)
+        Ok(Some(key))
     } else {
-        Ok(key_bytes)
+        Ok(Some(key_bytes))
     }
 }
 
-fn parse_keyfile(buffer: &[u8]) -> KeyElement {
+/// The hash of the key data stored in version 2 XML keyfiles: the first 4 bytes of its SHA-256, in hex
+fn keyfile_hash(key: &[u8]) -> String {
+    hex::encode_upper(&calculate_sha256(&[key])[..4])
+}
+
+fn parse_keyfile(buffer: &[u8]) -> Result<KeyElement, DatabaseKeyError> {
     // try to parse the buffer as XML, if successful, use that data instead of full file
-    if let Ok(v) = parse_xml_keyfile(buffer) {
-        v
-    } else if buffer.len() == 32 {
+    if let Some(key) = parse_xml_keyfile(buffer)? {
+        return Ok(key);
+    }
+
+    if buffer.len() == 32 {
         // legacy binary key format
-        buffer.to_vec()
-    } else {
-        calculate_sha256(&[buffer]).as_slice().to_vec()
+        return Ok(buffer.to_vec());
+    }
+
+    if buffer.len() == 64 {
+        // legacy hex key format
+        if let Ok(key) = hex::decode(buffer) {
+            return Ok(key);
+        }
+    }
+
+    Ok(calculate_sha256(&[buffer]).as_slice().to_vec())
+}
+
+/// Create a keyfile in the XML format of `KeePass` 2.x (version 2.0) holding `key`
+fn create_xml_keyfile(key: &[u8]) -> Zeroizing<String> {
+    let data = Zeroizing::new(hex::encode_upper(key));
+
+    // reserve enough space up front, so that no copies of the key are left behind by reallocations
+    let mut xml = Zeroizing::new(String::with_capacity(256 + 2 * data.len()));
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<KeyFile>\n\t<Meta>\n\t\t<Version>2.0</Version>\n\t</Meta>\n\t<Key>\n");
+    xml.push_str(&format!("\t\t<Data Hash=\"{}\">\n", keyfile_hash(key)));
+    for line in data.as_bytes().chunks(32) {
+        xml.push_str("\t\t\t");
+        for (index, group) in line.chunks(8).enumerate() {
+            if index > 0 {
+                xml.push(' ');
+            }
+            xml.push_str(std::str::from_utf8(group).unwrap_or_default());
+        }
+        xml.push('\n');
     }
+    xml.push_str("\t\t</Data>\n\t</Key>\n</KeyFile>\n");
+    xml
 }
 
 #[cfg(feature = "challenge_response")]
@@ -103,6 +158,7 @@ pub enum ChallengeResponseKey {
     YubikeyChallenge(Yubikey, String),
 }
 
+#[cfg(feature = "challenge_response")]
 #[derive(Debug, Clone, PartialEq, Zeroize, ZeroizeOnDrop)]
 pub struct Yubikey {
     pub serial_number: u32,
@@ -201,26 +257,142 @@ impl ChallengeResponseKey {
     }
 }
 
+/// A key transformed with the key derivation function of a database, to unlock the database again
+/// without running the key derivation ("quick unlock").
+///
+/// Get it from [`Database::open_for_quick_unlock`] and use it with [`DatabaseKey::with_transformed_key`].
+/// It stays valid as long as the KDF parameters and seed of the database are unchanged, which is the
+/// case until the database is saved with a key that does not contain it or with a different KDF.
+/// It also remembers an HMAC of the key it was transformed from, keyed with the transformed key, so
+/// that it is not used together with another password or keyfile.
+///
+/// [`Database::open_for_quick_unlock`]: crate::Database::open_for_quick_unlock
+#[derive(Clone, PartialEq, Eq)]
+pub struct TransformedKey {
+    kdf_config: KdfConfig,
+    kdf_seed: Vec<u8>,
+    composite_key_mac: Zeroizing<[u8; 32]>,
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl TransformedKey {
+    pub(crate) fn new(kdf_config: &KdfConfig, kdf_seed: &[u8], composite_key: &GenericArray<u8, U32>, key: &GenericArray<u8, U32>) -> Self {
+        let key = Zeroizing::new((*key).into());
+        TransformedKey {
+            kdf_config: kdf_config.clone(),
+            kdf_seed: kdf_seed.to_vec(),
+            composite_key_mac: Self::mac_composite_key(&key, composite_key),
+            key,
+        }
+    }
+
+    fn mac_composite_key(key: &[u8; 32], composite_key: &GenericArray<u8, U32>) -> Zeroizing<[u8; 32]> {
+        let mac = calculate_hmac(&[composite_key], key).expect("HMAC takes keys of any length");
+        Zeroizing::new(mac.into())
+    }
+
+    /// Whether the key was transformed from `composite_key`
+    fn derived_from(&self, composite_key: &GenericArray<u8, U32>) -> bool {
+        self.composite_key_mac == Self::mac_composite_key(&self.key, composite_key)
+    }
+
+    /// The key derivation function the key was transformed with
+    pub fn kdf_config(&self) -> &KdfConfig {
+        &self.kdf_config
+    }
+
+    /// Whether the key was transformed with these KDF parameters and seed
+    pub(crate) fn derived_with(&self, kdf_config: &KdfConfig, kdf_seed: &[u8]) -> bool {
+        self.kdf_config == *kdf_config && self.kdf_seed == kdf_seed
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        self.key.as_slice()
+    }
+}
+
+impl std::fmt::Debug for TransformedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransformedKey")
+            .field("kdf_config", &self.kdf_config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Zeroize for TransformedKey {
+    fn zeroize(&mut self) {
+        self.kdf_seed.zeroize();
+        self.composite_key_mac.zeroize();
+        self.key.zeroize();
+    }
+}
+
+/// The encoding of a password before it is hashed into the key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PasswordEncoding {
+    /// UTF-8 for KDBX databases. KDB databases are opened with Windows-1252 and UTF-8 like in
+    /// `KeePass` 1.x, and saved with Windows-1252 unless the password cannot be encoded in it.
+    #[default]
+    Auto,
+
+    /// UTF-8, as used by `KeePass` 2.x and `KeePassX`
+    Utf8,
+
+    /// Windows-1252, as used by `KeePass` 1.x on Western European systems. Characters that cannot
+    /// be encoded are replaced by `?` like in `KeePass` 1.x.
+    Windows1252,
+}
+
+/// The Windows-1252 byte for `c`, if there is one
+fn windows_1252_byte(c: char) -> Option<u8> {
+    const HIGH_CHARS: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•',
+        '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+    ];
+    match u32::from(c) {
+        #[allow(clippy::cast_possible_truncation)]
+        code @ (0x00..=0x7f | 0xa0..=0xff) => Some(code as u8),
+        _ => HIGH_CHARS
+            .iter()
+            .position(|high_char| *high_char == c)
+            .and_then(|index| u8::try_from(0x80 + index).ok()),
+    }
+}
+
+/// Encode a password in Windows-1252, replacing characters that cannot be encoded by `?`
+fn encode_windows_1252(password: &str) -> Zeroizing<Vec<u8>> {
+    Zeroizing::new(password.chars().map(|c| windows_1252_byte(c).unwrap_or(b'?')).collect())
+}
+
 /// A `KeePass` key, which might consist of a password and/or a keyfile
 #[derive(Debug, Clone, Default, PartialEq, Zeroize, ZeroizeOnDrop)]
 pub struct DatabaseKey {
-    password: Option<String>,
+    password: Option<ProtectedValue>,
+    #[zeroize(skip)]
+    password_encoding: PasswordEncoding,
     keyfile: Option<Vec<u8>>,
     #[cfg(feature = "challenge_response")]
     challenge_response_key: Option<ChallengeResponseKey>,
     #[cfg(feature = "challenge_response")]
     challenge_response_result: Option<KeyElement>,
+    transformed_key: Option<TransformedKey>,
 }
 
 impl DatabaseKey {
     pub fn with_password(mut self, password: &str) -> Self {
-        self.password = Some(password.to_string());
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Choose how the password is encoded, instead of the default rules of [`PasswordEncoding::Auto`]
+    pub fn with_password_encoding(mut self, password_encoding: PasswordEncoding) -> Self {
+        self.password_encoding = password_encoding;
         self
     }
 
     #[cfg(feature = "utilities")]
     pub fn with_password_from_prompt(mut self, prompt_message: &str) -> Result<Self, std::io::Error> {
-        self.password = Some(rpassword::prompt_password(prompt_message)?);
+        self.password = Some(rpassword::prompt_password(prompt_message)?.into());
         Ok(self)
     }
 
@@ -239,6 +411,21 @@ impl DatabaseKey {
         Ok(self)
     }
 
+    /// Generate a new keyfile in the XML format of `KeePass` 2.x (version 2.0) from 32 random bytes,
+    /// write it to `keyfile` and use it as part of this key
+    pub fn with_generated_keyfile(mut self, keyfile: &mut dyn Write) -> Result<Self, std::io::Error> {
+        let mut key = Zeroizing::new(vec![0; 32]);
+        getrandom::getrandom(&mut key)?;
+
+        let xml = create_xml_keyfile(&key);
+        keyfile.write_all(xml.as_bytes())?;
+        keyfile.flush()?;
+
+        self.keyfile = Some(xml.as_bytes().to_vec());
+
+        Ok(self)
+    }
+
     #[cfg(feature = "challenge_response")]
     pub fn with_challenge_response_key(mut self, challenge_response_key: ChallengeResponseKey) -> Self {
         self.challenge_response_key = Some(challenge_response_key);
@@ -255,19 +442,92 @@ impl DatabaseKey {
         Ok(self)
     }
 
+    /// Use a key transformed when the database was opened before, which skips the key derivation
+    /// function while the KDF parameters and seed of the database are unchanged.
+    ///
+    /// The transformed key has to stem from the same password, keyfile and challenge-response key
+    /// as the other components of this key, otherwise it fails with
+    /// [`DatabaseKeyError::TransformedKeyMismatch`]. The other components are only used to derive the
+    /// key again once the transformed key no longer matches the database, a key without them fails
+    /// with [`DatabaseKeyError::TransformedKeyMismatch`] in that case.
+    pub fn with_transformed_key(mut self, transformed_key: TransformedKey) -> Self {
+        self.transformed_key = Some(transformed_key);
+        self
+    }
+
     pub fn new() -> Self {
         DatabaseKey::default()
     }
 
+    /// Transform the key with the key derivation function of a database, returning `None` if the
+    /// operation was cancelled.
+    ///
+    /// The cached transformed key is taken if it was derived with the same KDF parameters and seed
+    /// and, unless this key only consists of the transformed key, from the result of `composite_key`.
+    /// Otherwise the result of `composite_key` is transformed.
+    pub(crate) fn transform_key<E>(
+        &self,
+        kdf_config: &KdfConfig,
+        kdf_seed: &[u8],
+        monitor: &ProgressMonitor,
+        composite_key: impl FnOnce() -> Result<GenericArray<u8, U32>, E>,
+    ) -> Result<Option<TransformedKey>, E>
+    where
+        E: From<CryptographyError> + From<DatabaseKeyError> + From<KdfConfigError>,
+    {
+        if self.is_empty() {
+            return match &self.transformed_key {
+                Some(transformed_key) if transformed_key.derived_with(kdf_config, kdf_seed) => {
+                    let completed = monitor.report(ProgressStage::KeyDerivation, 1, Some(1));
+                    Ok(completed.then(|| transformed_key.clone()))
+                }
+                _ => Err(DatabaseKeyError::TransformedKeyMismatch.into()),
+            };
+        }
+
+        let composite_key = composite_key()?;
+        if let Some(transformed_key) = &self.transformed_key {
+            if transformed_key.derived_with(kdf_config, kdf_seed) {
+                if !transformed_key.derived_from(&composite_key) {
+                    return Err(DatabaseKeyError::TransformedKeyMismatch.into());
+                }
+                let completed = monitor.report(ProgressStage::KeyDerivation, 1, Some(1));
+                return Ok(completed.then(|| transformed_key.clone()));
+            }
+        }
+
+        let transformed_key = monitor.transform_key(&*kdf_config.get_kdf_seeded(kdf_seed)?, &composite_key)?;
+        Ok(transformed_key.map(|key| TransformedKey::new(kdf_config, kdf_seed, &composite_key, &key)))
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    pub(crate) fn has_transformed_key(&self) -> bool {
+        self.transformed_key.is_some()
+    }
+
+    /// The KDF seed of the cached transformed key if it was derived with `kdf_config`, so that
+    /// saving with this seed keeps the transformed key valid
+    pub(crate) fn transformed_key_seed(&self, kdf_config: &KdfConfig) -> Option<&[u8]> {
+        self.transformed_key
+            .as_ref()
+            .filter(|transformed_key| transformed_key.kdf_config == *kdf_config)
+            .map(|transformed_key| transformed_key.kdf_seed.as_slice())
+    }
+
     pub(crate) fn get_key_elements(&self) -> Result<KeyElements, DatabaseKeyError> {
         let mut out = Vec::new();
 
         if let Some(p) = &self.password {
-            out.push(calculate_sha256(&[p.as_bytes()]).to_vec());
+            let password = p.reveal();
+            let hash = match self.password_encoding {
+                PasswordEncoding::Auto | PasswordEncoding::Utf8 => calculate_sha256(&[password.as_bytes()]),
+                PasswordEncoding::Windows1252 => calculate_sha256(&[&encode_windows_1252(&password)]),
+            };
+            out.push(hash.to_vec());
         }
 
         if let Some(ref f) = self.keyfile {
-            out.push(parse_keyfile(f));
+            out.push(parse_keyfile(f)?);
         }
 
         if out.is_empty() {
@@ -286,6 +546,28 @@ impl DatabaseKey {
         Ok(out)
     }
 
+    /// The keys to try for a `KeePass` 1.x database, in order, with [`PasswordEncoding::Auto`]
+    /// resolved to the legacy encodings. The first one is used for saving.
+    pub(crate) fn kdb_keys(&self) -> Vec<DatabaseKey> {
+        let encodings = match &self.password {
+            Some(password) if self.password_encoding == PasswordEncoding::Auto => {
+                let password = password.reveal();
+                if password.is_ascii() {
+                    vec![PasswordEncoding::Utf8]
+                } else if password.chars().all(|c| windows_1252_byte(c).is_some()) {
+                    vec![PasswordEncoding::Windows1252, PasswordEncoding::Utf8]
+                } else {
+                    vec![PasswordEncoding::Utf8, PasswordEncoding::Windows1252]
+                }
+            }
+            _ => vec![self.password_encoding],
+        };
+        encodings
+            .into_iter()
+            .map(|password_encoding| self.clone().with_password_encoding(password_encoding))
+            .collect()
+    }
+
     /// Returns true if the database key is not associated with any key component.
     pub fn is_empty(&self) -> bool {
         if self.password.is_some() || self.keyfile.is_some() {
@@ -304,7 +586,10 @@ mod key_tests {
 
     use crate::error::DatabaseKeyError;
 
-    use super::DatabaseKey;
+    use cipher::generic_array::GenericArray;
+
+    use super::{encode_windows_1252, DatabaseKey, PasswordEncoding, TransformedKey};
+    use crate::config::KdfConfig;
 
     #[test]
     fn test_key() -> Result<(), DatabaseKeyError> {
@@ -356,6 +641,24 @@ mod key_tests {
             .get_key_elements()?;
         assert_eq!(ke.len(), 1);
 
+        // the hash of version 2 keyfiles is verified
+        let corrupted_keyfile_v2 = xml_keyfile_v2.replace("36057B1C", "36057B1D");
+        assert!(matches!(
+            DatabaseKey::new()
+                .with_keyfile(&mut corrupted_keyfile_v2.trim().as_bytes())?
+                .get_key_elements(),
+            Err(DatabaseKeyError::KeyFileHashMismatch)
+        ));
+
+        // hex keyfiles hold the key directly
+        let ke = DatabaseKey::new()
+            .with_keyfile(&mut "36057B1C35037FD962257893C0A22403EE3F8FBB504D998108B821CB00D28F89".as_bytes())?
+            .get_key_elements()?;
+        assert_eq!(
+            ke[0],
+            hex::decode("36057B1C35037FD962257893C0A22403EE3F8FBB504D998108B821CB00D28F89").unwrap()
+        );
+
         // other XML files will just be hashed as a "bare" keyfile
         let ke = DatabaseKey::new()
             .with_keyfile(&mut "<Not><A><KeyFile></KeyFile></A></Not>".as_bytes())?
@@ -365,15 +668,76 @@ mod key_tests {
 
         assert!(DatabaseKey {
             password: None,
+            password_encoding: PasswordEncoding::Auto,
             keyfile: None,
             #[cfg(feature = "challenge_response")]
             challenge_response_key: None,
             #[cfg(feature = "challenge_response")]
             challenge_response_result: None,
+            transformed_key: None,
         }
         .get_key_elements()
         .is_err());
 
         Ok(())
     }
+
+    #[test]
+    fn generated_keyfile() -> Result<(), DatabaseKeyError> {
+        let mut keyfile = Vec::new();
+        let key = DatabaseKey::new().with_generated_keyfile(&mut keyfile)?;
+
+        let xml = String::from_utf8(keyfile.clone()).unwrap();
+        assert!(xml.contains("<Version>2.0</Version>"));
+        assert!(xml.contains("<Data Hash=\""));
+
+        // the written keyfile is the one used by the key
+        let ke = key.get_key_elements()?;
+        assert_eq!(ke[0].len(), 32);
+        assert_eq!(DatabaseKey::new().with_keyfile(&mut keyfile.as_slice())?.get_key_elements()?, ke);
+
+        let mut other_keyfile = Vec::new();
+        DatabaseKey::new().with_generated_keyfile(&mut other_keyfile)?;
+        assert_ne!(keyfile, other_keyfile);
+
+        Ok(())
+    }
+
+    #[test]
+    fn password_encodings() -> Result<(), DatabaseKeyError> {
+        assert_eq!(*encode_windows_1252("Größe 5€"), b"Gr\xf6\xdfe 5\x80");
+        assert_eq!(*encode_windows_1252("пароль"), b"??????");
+
+        let utf8 = DatabaseKey::new().with_password("Größe").get_key_elements()?;
+        let windows_1252 = DatabaseKey::new()
+            .with_password("Größe")
+            .with_password_encoding(PasswordEncoding::Windows1252)
+            .get_key_elements()?;
+        assert_ne!(utf8, windows_1252);
+
+        // KDB keys try the legacy encoding first if the password can be encoded in it
+        let encodings = |password: &str| -> Vec<PasswordEncoding> {
+            let key = DatabaseKey::new().with_password(password);
+            key.kdb_keys().iter().map(|key| key.password_encoding).collect()
+        };
+        assert_eq!(encodings("ascii"), vec![PasswordEncoding::Utf8]);
+        assert_eq!(encodings("Größe"), vec![PasswordEncoding::Windows1252, PasswordEncoding::Utf8]);
+        assert_eq!(encodings("пароль"), vec![PasswordEncoding::Utf8, PasswordEncoding::Windows1252]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transformed_key_derived_from() {
+        let composite_key = GenericArray::from([1u8; 32]);
+        let kdf_config = KdfConfig::Aes { rounds: 1 };
+        let key = TransformedKey::new(&kdf_config, b"seed", &composite_key, &GenericArray::from([2u8; 32]));
+        assert!(key.derived_from(&composite_key));
+        assert!(!key.derived_from(&GenericArray::from([3u8; 32])));
+
+        // the same composite key is remembered differently for every transformed key
+        let other = TransformedKey::new(&kdf_config, b"seed", &composite_key, &GenericArray::from([4u8; 32]));
+        assert!(other.derived_from(&composite_key));
+        assert_ne!(key.composite_key_mac, other.composite_key_mac);
+    }
 }
@@ -7,19 +7,26 @@ pub(crate) mod crypt;
 pub mod db;
 pub mod error;
 pub(crate) mod format;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
 pub(crate) mod hmac_block_stream;
 mod io;
 mod key;
-pub(crate) mod variant_dictionary;
+pub mod limits;
+pub mod progress;
+pub mod protected;
+pub mod registry;
+mod variant_dictionary;
 pub(crate) mod xml_db;
 
 #[cfg(feature = "challenge_response")]
 pub use self::key::ChallengeResponseKey;
 pub use self::{
-    config::DatabaseConfig,
+    config::{DatabaseConfig, DatabaseHeaderInfo},
     db::{group::Group, iconid::IconId, node::*, Database},
     error::{BoxError, Error, Result},
-    key::DatabaseKey,
+    key::{DatabaseKey, PasswordEncoding, TransformedKey},
 };
 pub use chrono::NaiveDateTime;
 pub use uuid::Uuid;
@@ -0,0 +1,184 @@
+//! Limits on the resources a database may use while it is opened
+
+use std::io::Read;
+
+use crate::{config::KdfConfig, error::ResourceLimitError};
+
+/// Caps on the work and memory that opening a database may take, for opening files from untrusted
+/// sources with [`Database::open_with_limits`].
+///
+/// Every limit is optional, the default value does not limit anything. The key derivation
+/// parameters are checked before the key is transformed, and attachments in the inner header
+/// before they are read, so that a malicious file cannot make the library allocate or compute
+/// anything large before it is rejected.
+///
+/// ```
+/// use keepass_ng::limits::OpenLimits;
+///
+/// let limits = OpenLimits {
+///     max_entries: Some(10_000),
+///     ..OpenLimits::untrusted()
+/// };
+/// ```
+///
+/// [`Database::open_with_limits`]: crate::Database::open_with_limits
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpenLimits {
+    /// Maximum number of rounds of the AES-KDF
+    pub max_aes_rounds: Option<u64>,
+
+    /// Maximum memory of Argon2, in bytes
+    pub max_argon2_memory: Option<u64>,
+
+    /// Maximum number of Argon2 iterations
+    pub max_argon2_iterations: Option<u64>,
+
+    /// Maximum Argon2 parallelism
+    pub max_argon2_parallelism: Option<u32>,
+
+    /// Maximum size of the decrypted and decompressed payload, in bytes
+    pub max_payload_size: Option<u64>,
+
+    /// Maximum size of a single attachment, in bytes
+    pub max_attachment_size: Option<u64>,
+
+    /// Maximum nesting depth of the XML document
+    pub max_xml_depth: Option<usize>,
+
+    /// Maximum number of entries, including the entries in the history of other entries
+    pub max_entries: Option<usize>,
+}
+
+impl OpenLimits {
+    /// Limits for files from untrusted sources, which still allow databases created with the default
+    /// settings of the common `KeePass` clients
+    pub fn untrusted() -> Self {
+        OpenLimits {
+            max_aes_rounds: Some(100_000_000),
+            max_argon2_memory: Some(1024 * 1024 * 1024),
+            max_argon2_iterations: Some(1000),
+            max_argon2_parallelism: Some(64),
+            max_payload_size: Some(256 * 1024 * 1024),
+            max_attachment_size: Some(64 * 1024 * 1024),
+            max_xml_depth: Some(256),
+            max_entries: Some(1_000_000),
+        }
+    }
+
+    /// Check the parameters of the key derivation function
+    pub(crate) fn check_kdf(&self, kdf_config: &KdfConfig) -> Result<(), ResourceLimitError> {
+        match kdf_config {
+            KdfConfig::Aes { rounds } => {
+                if let Some(limit) = self.max_aes_rounds.filter(|limit| rounds > limit) {
+                    return Err(ResourceLimitError::AesRounds { rounds: *rounds, limit });
+                }
+            }
+            KdfConfig::Argon2 {
+                iterations,
+                memory,
+                parallelism,
+                ..
+            }
+            | KdfConfig::Argon2id {
+                iterations,
+                memory,
+                parallelism,
+                ..
+            } => {
+                if let Some(limit) = self.max_argon2_memory.filter(|limit| memory > limit) {
+                    return Err(ResourceLimitError::Argon2Memory { memory: *memory, limit });
+                }
+                if let Some(limit) = self.max_argon2_iterations.filter(|limit| iterations > limit) {
+                    return Err(ResourceLimitError::Argon2Iterations {
+                        iterations: *iterations,
+                        limit,
+                    });
+                }
+                if let Some(limit) = self.max_argon2_parallelism.filter(|limit| parallelism > limit) {
+                    return Err(ResourceLimitError::Argon2Parallelism {
+                        parallelism: *parallelism,
+                        limit,
+                    });
+                }
+            }
+            // the parameters of registered KDFs are up to the application that registered them
+            KdfConfig::Registered { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Check the size of an attachment
+    pub(crate) fn check_attachment_size(&self, size: u64) -> Result<(), ResourceLimitError> {
+        match self.max_attachment_size {
+            Some(limit) if size > limit => Err(ResourceLimitError::AttachmentSize { size, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Wrap the reader of the decompressed payload so that it fails once the payload exceeds
+    /// `max_payload_size`
+    pub(crate) fn limit_payload<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+        match self.max_payload_size {
+            Some(limit) => Box::new(PayloadLimitReader {
+                inner: reader,
+                remaining: limit,
+                limit,
+            }),
+            None => reader,
+        }
+    }
+}
+
+/// Reader failing with [`ResourceLimitError::PayloadSize`] once more than `limit` bytes are read
+struct PayloadLimitReader<R> {
+    inner: R,
+    remaining: u64,
+    limit: u64,
+}
+
+impl<R: Read> Read for PayloadLimitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        // read one byte more than allowed to tell a payload of exactly `limit` bytes from a larger one
+        let max = usize::try_from(self.remaining.saturating_add(1))
+            .unwrap_or(usize::MAX)
+            .min(buf.len());
+        let count = self.inner.read(&mut buf[..max])?;
+        if count as u64 > self.remaining {
+            return Err(std::io::Error::other(ResourceLimitError::PayloadSize { limit: self.limit }));
+        }
+        self.remaining -= count as u64;
+        Ok(count)
+    }
+}
+
+/// Recover a [`ResourceLimitError`] that was raised inside of a reader chain
+pub(crate) fn resource_limit_error(error: &std::io::Error) -> Option<ResourceLimitError> {
+    error.get_ref()?.downcast_ref::<ResourceLimitError>().cloned()
+}
+
+/// Tracks the nesting depth and the number of entries while an XML document is parsed
+#[derive(Debug, Default)]
+pub(crate) struct XmlLimitTracker {
+    depth: usize,
+    entries: usize,
+}
+
+impl XmlLimitTracker {
+    pub(crate) fn start_element(&mut self, name: &str, limits: &OpenLimits) -> Result<(), ResourceLimitError> {
+        self.depth += 1;
+        if let Some(limit) = limits.max_xml_depth.filter(|limit| self.depth > *limit) {
+            return Err(ResourceLimitError::XmlDepth { limit });
+        }
+        if name == "Entry" {
+            self.entries += 1;
+            if let Some(limit) = limits.max_entries.filter(|limit| self.entries > *limit) {
+                return Err(ResourceLimitError::Entries { limit });
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn end_element(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+}
@@ -0,0 +1,203 @@
+//! Progress reporting and cancellation for opening and saving databases
+
+use std::{
+    cell::RefCell,
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use cipher::generic_array::{typenum::U32, GenericArray};
+
+use crate::{crypt::kdf::Kdf, error::CryptographyError};
+
+/// The step of opening or saving a database that is in progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressStage {
+    /// Transforming the key with the key derivation function. The amount of work is counted in
+    /// rounds for AES-KDF, Argon2 only reports whether it is done (`0` or `1` out of `1`).
+    KeyDerivation,
+
+    /// Reading, decrypting and parsing the payload of the database. The amount of work is counted in
+    /// bytes read from the source.
+    Decryption,
+
+    /// Serializing, encrypting and writing the payload of the database. The amount of work is
+    /// counted in bytes written to the destination.
+    Encryption,
+}
+
+/// A progress update passed to the callback of [`Database::open_with_progress`] and
+/// [`Database::save_with_progress`]
+///
+/// [`Database::open_with_progress`]: crate::Database::open_with_progress
+/// [`Database::save_with_progress`]: crate::Database::save_with_progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// The current step
+    pub stage: ProgressStage,
+
+    /// Amount of work done in the current step
+    pub completed: u64,
+
+    /// Total amount of work of the current step, if it is known in advance
+    pub total: Option<u64>,
+}
+
+/// A token to cancel opening or saving a database from another thread.
+///
+/// Clones of a token share their state, so that one clone can be handed to the operation while
+/// another one is kept to cancel it.
+///
+/// ```
+/// use keepass_ng::progress::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let handle = token.clone();
+/// handle.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// A new token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the cancellation of the operations using this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the cancellation has been requested
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Error put into `std::io::Error` by the readers and writers of this module when the operation
+/// was cancelled. The caller is expected to check [`ProgressMonitor::is_cancelled`] when an
+/// operation fails, so that the error is reported as a cancellation.
+#[derive(Debug, thiserror::Error)]
+#[error("The operation was cancelled")]
+pub(crate) struct Cancelled;
+
+/// Passes progress updates on to a callback and checks for cancellation
+#[derive(Default)]
+pub(crate) struct ProgressMonitor<'a> {
+    callback: Option<RefCell<&'a mut dyn FnMut(Progress)>>,
+    token: Option<&'a CancellationToken>,
+}
+
+impl<'a> ProgressMonitor<'a> {
+    pub(crate) fn new(callback: &'a mut dyn FnMut(Progress), token: &'a CancellationToken) -> Self {
+        ProgressMonitor {
+            callback: Some(RefCell::new(callback)),
+            token: Some(token),
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.token.is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Report progress, returning `false` if the operation was cancelled
+    pub(crate) fn report(&self, stage: ProgressStage, completed: u64, total: Option<u64>) -> bool {
+        if let Some(callback) = &self.callback {
+            (callback.borrow_mut())(Progress { stage, completed, total });
+        }
+        !self.is_cancelled()
+    }
+
+    /// Transform `composite_key` with `kdf`, returning `None` if the operation was cancelled
+    pub(crate) fn transform_key(
+        &self,
+        kdf: &dyn Kdf,
+        composite_key: &GenericArray<u8, U32>,
+    ) -> Result<Option<GenericArray<u8, U32>>, CryptographyError> {
+        if self.callback.is_none() && self.token.is_none() {
+            // nobody is watching, so there is no need to poll the key derivation
+            return kdf.transform_key(composite_key).map(Some);
+        }
+        if !self.report(ProgressStage::KeyDerivation, 0, None) {
+            return Ok(None);
+        }
+        kdf.transform_key_with_progress(composite_key, &mut |completed, total| {
+            self.report(ProgressStage::KeyDerivation, completed, Some(total))
+        })
+    }
+}
+
+/// Reader reporting the number of bytes read, which fails with [`Cancelled`] once the operation
+/// is cancelled
+pub(crate) struct ProgressReader<'m, 'a, R> {
+    inner: R,
+    monitor: &'m ProgressMonitor<'a>,
+    stage: ProgressStage,
+    total: Option<u64>,
+    completed: u64,
+}
+
+impl<'m, 'a, R> ProgressReader<'m, 'a, R> {
+    pub(crate) fn new(inner: R, monitor: &'m ProgressMonitor<'a>, stage: ProgressStage, total: Option<u64>) -> Self {
+        ProgressReader {
+            inner,
+            monitor,
+            stage,
+            total,
+            completed: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, '_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        if self.monitor.is_cancelled() {
+            return Err(std::io::Error::other(Cancelled));
+        }
+        let count = self.inner.read(buf)?;
+        self.completed += count as u64;
+        self.monitor.report(self.stage, self.completed, self.total);
+        Ok(count)
+    }
+}
+
+/// Writer reporting the number of bytes written, which fails with [`Cancelled`] once the operation
+/// is cancelled
+pub(crate) struct ProgressWriter<'m, 'a, W> {
+    inner: W,
+    monitor: &'m ProgressMonitor<'a>,
+    stage: ProgressStage,
+    completed: u64,
+}
+
+impl<'m, 'a, W> ProgressWriter<'m, 'a, W> {
+    pub(crate) fn new(inner: W, monitor: &'m ProgressMonitor<'a>, stage: ProgressStage) -> Self {
+        ProgressWriter {
+            inner,
+            monitor,
+            stage,
+            completed: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<'_, '_, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        if self.monitor.is_cancelled() {
+            return Err(std::io::Error::other(Cancelled));
+        }
+        let count = self.inner.write(buf)?;
+        self.completed += count as u64;
+        self.monitor.report(self.stage, self.completed, None);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,302 @@
+//! Protected values, which can be kept encrypted in memory while a database is open
+//!
+//! Passwords and other fields marked as protected are stored as [`ProtectedValue`]s. Once memory
+//! encryption is enabled for a database with [`Database::set_memory_encryption`], its protected
+//! values are encrypted with a random key of the running process and only decrypted into a
+//! short-lived [`Revealed`] guard, which wipes the plaintext when it is dropped.
+//!
+//! ```
+//! use keepass_ng::{
+//!     db::{Entry, Group},
+//!     Database, DatabaseKey,
+//! };
+//! use std::fs::File;
+//!
+//! let mut file = File::open("tests/resources/test_db_with_password.kdbx")?;
+//! let db = Database::open(&mut file, DatabaseKey::new().with_password("demopass"))?;
+//! db.set_memory_encryption(true)?;
+//!
+//! let node = Group::get(&db.root, &["Sample Entry"]).unwrap();
+//! let entry = node.borrow();
+//! let entry = entry.as_any().downcast_ref::<Entry>().unwrap();
+//!
+//! // encrypted fields have to be revealed explicitly
+//! assert_eq!(entry.get_password(), None);
+//! assert_eq!(entry.reveal_password().as_deref(), Some("Password"));
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`Database::set_memory_encryption`]: crate::Database::set_memory_encryption
+
+use std::{
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
+use chacha20::{
+    cipher::{KeyIvInit, StreamCipher},
+    ChaCha20,
+};
+use secstr::SecStr;
+use zeroize::{Zeroize, Zeroizing};
+
+/// The key of the running process that sealed values are encrypted with
+static SESSION_KEY: OnceLock<Zeroizing<[u8; 32]>> = OnceLock::new();
+
+/// Counter for the nonces of sealed values, which must never repeat under the session key
+static NONCE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Generate the session key unless it exists already
+fn init_session_key() -> Result<&'static [u8; 32], getrandom::Error> {
+    if let Some(key) = SESSION_KEY.get() {
+        return Ok(&**key);
+    }
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    getrandom::getrandom(key.as_mut())?;
+
+    // another thread may have won the race, in which case its key is used
+    Ok(&**SESSION_KEY.get_or_init(|| key))
+}
+
+fn apply_keystream(key: &[u8; 32], nonce: &[u8; 12], buffer: &mut [u8]) {
+    ChaCha20::new(key.into(), nonce.into()).apply_keystream(buffer);
+}
+
+/// A value that is protected in memory, e.g. a password.
+///
+/// It is either kept in a memory region that is wiped on drop or, once it is sealed, encrypted
+/// under the session key. Both are read with [`ProtectedValue::reveal`].
+#[derive(Clone)]
+pub struct ProtectedValue(Protection);
+
+#[derive(Clone)]
+enum Protection {
+    Plain(SecStr),
+    Sealed {
+        key: &'static [u8; 32],
+        nonce: [u8; 12],
+        ciphertext: Vec<u8>,
+    },
+}
+
+impl ProtectedValue {
+    /// Protect a value in memory that is wiped on drop, without encrypting it
+    pub fn new(value: impl Into<String>) -> Self {
+        ProtectedValue(Protection::Plain(SecStr::from(value.into())))
+    }
+
+    /// Encrypt the value with the session key unless it is encrypted already.
+    ///
+    /// The session key is generated when the first value is sealed, which fails if the system has no
+    /// random numbers available.
+    pub fn seal(&mut self) -> Result<(), getrandom::Error> {
+        if let Protection::Plain(value) = &self.0 {
+            let key = init_session_key()?;
+            *self = ProtectedValue::sealed(key, value_str(value.unsecure()).to_string());
+        }
+        Ok(())
+    }
+
+    /// Decrypt the value back into memory that is wiped on drop
+    pub fn unseal(&mut self) {
+        if self.is_sealed() {
+            *self = ProtectedValue::new(self.reveal().to_string());
+        }
+    }
+
+    /// Protect `value` the same way as this value, i.e. encrypted if this value is sealed
+    pub(crate) fn replace_with(&self, value: impl Into<String>) -> Self {
+        match &self.0 {
+            Protection::Sealed { key, .. } => ProtectedValue::sealed(key, value.into()),
+            Protection::Plain(_) => ProtectedValue::new(value),
+        }
+    }
+
+    /// Protect a value and encrypt it with the session key
+    fn sealed(key: &'static [u8; 32], value: String) -> Self {
+        let mut nonce = [0u8; 12];
+        nonce[..8].copy_from_slice(&NONCE_COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+
+        let mut ciphertext = value.into_bytes();
+        apply_keystream(key, &nonce, &mut ciphertext);
+        ProtectedValue(Protection::Sealed { key, nonce, ciphertext })
+    }
+
+    /// Decrypt the value into a guard that wipes the plaintext when it is dropped
+    pub fn reveal(&self) -> Revealed<'_> {
+        match &self.0 {
+            Protection::Plain(value) => Revealed::borrowed(value_str(value.unsecure())),
+            Protection::Sealed { key, nonce, ciphertext } => {
+                let mut plaintext = Zeroizing::new(ciphertext.clone());
+                apply_keystream(key, nonce, &mut plaintext);
+                Revealed(RevealedValue::Owned(Zeroizing::new(value_str(&plaintext).to_string())))
+            }
+        }
+    }
+
+    /// Whether the value is encrypted in memory
+    pub fn is_sealed(&self) -> bool {
+        matches!(self.0, Protection::Sealed { .. })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match &self.0 {
+            Protection::Plain(value) => value.unsecure().is_empty(),
+            Protection::Sealed { ciphertext, .. } => ciphertext.is_empty(),
+        }
+    }
+
+    /// The value if it is not encrypted in memory
+    pub(crate) fn as_plain(&self) -> Option<&str> {
+        match &self.0 {
+            Protection::Plain(value) => Some(value_str(value.unsecure())),
+            Protection::Sealed { .. } => None,
+        }
+    }
+}
+
+/// Protected values are always created from strings, so they are valid UTF-8
+fn value_str(value: &[u8]) -> &str {
+    std::str::from_utf8(value).unwrap_or_default()
+}
+
+impl From<&str> for ProtectedValue {
+    fn from(value: &str) -> Self {
+        ProtectedValue::new(value)
+    }
+}
+
+impl From<String> for ProtectedValue {
+    fn from(value: String) -> Self {
+        ProtectedValue::new(value)
+    }
+}
+
+impl PartialEq for ProtectedValue {
+    fn eq(&self, other: &Self) -> bool {
+        *self.reveal() == *other.reveal()
+    }
+}
+
+impl Eq for ProtectedValue {}
+
+#[cfg(feature = "serialization")]
+impl serde::Serialize for ProtectedValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.reveal())
+    }
+}
+
+impl std::fmt::Debug for ProtectedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("***SECRET***")
+    }
+}
+
+impl Zeroize for ProtectedValue {
+    fn zeroize(&mut self) {
+        match &mut self.0 {
+            Protection::Plain(value) => value.zero_out(),
+            Protection::Sealed { nonce, ciphertext, .. } => {
+                nonce.zeroize();
+                ciphertext.zeroize();
+            }
+        }
+    }
+}
+
+/// A revealed protected value, which is wiped from memory when it goes out of scope.
+///
+/// It dereferences to the plaintext `str`; keep it only for as long as the plaintext is needed.
+pub struct Revealed<'a>(RevealedValue<'a>);
+
+enum RevealedValue<'a> {
+    Borrowed(&'a str),
+    Owned(Zeroizing<String>),
+}
+
+impl<'a> Revealed<'a> {
+    pub(crate) fn borrowed(value: &'a str) -> Self {
+        Revealed(RevealedValue::Borrowed(value))
+    }
+}
+
+impl Deref for Revealed<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match &self.0 {
+            RevealedValue::Borrowed(value) => value,
+            RevealedValue::Owned(value) => value,
+        }
+    }
+}
+
+impl AsRef<str> for Revealed<'_> {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl std::fmt::Debug for Revealed<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("***SECRET***")
+    }
+}
+
+#[cfg(test)]
+mod protected_tests {
+    use super::{init_session_key, ProtectedValue, Protection};
+
+    fn ciphertext(value: &ProtectedValue) -> &[u8] {
+        match &value.0 {
+            Protection::Sealed { ciphertext, .. } => ciphertext,
+            Protection::Plain(_) => panic!("Expected a sealed value"),
+        }
+    }
+
+    #[test]
+    fn sealed_values() {
+        let key = init_session_key().unwrap();
+        let plain = ProtectedValue::from("secret");
+        let sealed = ProtectedValue::sealed(key, "secret".to_string());
+        assert!(sealed.is_sealed());
+        assert_eq!(sealed.as_plain(), None);
+        assert_eq!(&*sealed.reveal(), "secret");
+
+        // the same plaintext is encrypted differently every time, but the values are equal
+        let other = ProtectedValue::sealed(key, "secret".to_string());
+        assert_ne!(ciphertext(&sealed), b"secret");
+        assert_ne!(ciphertext(&sealed), ciphertext(&other));
+        assert_eq!(sealed, other);
+        assert_eq!(sealed, plain);
+        assert_ne!(sealed, ProtectedValue::sealed(key, "other".to_string()));
+
+        assert!(ProtectedValue::sealed(key, String::new()).is_empty());
+        assert_eq!(format!("{sealed:?} {:?}", sealed.reveal()), "***SECRET*** ***SECRET***");
+    }
+
+    #[test]
+    fn seal_and_unseal() {
+        let mut value = ProtectedValue::new("secret");
+        assert!(!value.is_sealed());
+        value.seal().unwrap();
+        assert!(value.is_sealed());
+        assert_ne!(ciphertext(&value), b"secret");
+
+        // new values are protected the same way as the value they replace
+        assert!(value.replace_with("other").is_sealed());
+        assert_eq!(&*value.replace_with("other").reveal(), "other");
+
+        value.unseal();
+        assert_eq!(value.as_plain(), Some("secret"));
+        assert!(!value.replace_with("other").is_sealed());
+    }
+}
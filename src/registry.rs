@@ -0,0 +1,153 @@
+//! Registry of additional ciphers and key derivation functions, e.g. the ones added by `KeePass` plugins
+//!
+//! Databases refer to their outer cipher and KDF by a UUID and to their inner stream cipher by a
+//! number. Besides the built-in algorithms, the readers and the KDBX4 writer look these up in this
+//! registry, so that an application can support more of them by implementing [`Cipher`] or
+//! [`Kdf`] and registering them once at startup. The built-in algorithms always take precedence.
+//!
+//! ```
+//! use keepass_ng::{
+//!     error::CryptographyError,
+//!     registry::{self, Cipher},
+//!     Uuid,
+//! };
+//!
+//! /// An inner stream cipher that leaves protected values as they are
+//! struct Identity;
+//!
+//! impl Cipher for Identity {
+//!     fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+//!         Ok(plaintext.to_vec())
+//!     }
+//!
+//!     fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+//!         Ok(ciphertext.to_vec())
+//!     }
+//!
+//!     fn iv_size() -> usize {
+//!         0
+//!     }
+//!
+//!     fn key_size() -> usize {
+//!         32
+//!     }
+//! }
+//!
+//! registry::register_inner_cipher(0x1000, |_key| Ok(Identity));
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use uuid::Uuid;
+
+pub use crate::{
+    crypt::{ciphers::Cipher, kdf::Kdf},
+    variant_dictionary::{VariantDictionary, VariantDictionaryValue},
+};
+pub use cipher::generic_array::{typenum::U32, GenericArray};
+
+use crate::error::{CryptographyError, KdfConfigError};
+
+type OuterCipherFactory = dyn Fn(&[u8], &[u8]) -> Result<Box<dyn Cipher>, CryptographyError> + Send + Sync;
+type InnerCipherFactory = dyn Fn(&[u8]) -> Result<Box<dyn Cipher>, CryptographyError> + Send + Sync;
+type KdfFactory = dyn Fn(&VariantDictionary) -> Result<Box<dyn Kdf>, KdfConfigError> + Send + Sync;
+
+/// A registered outer cipher
+#[derive(Clone)]
+pub(crate) struct OuterCipher {
+    pub(crate) iv_size: usize,
+    pub(crate) factory: Arc<OuterCipherFactory>,
+}
+
+/// A registered inner stream cipher
+#[derive(Clone)]
+pub(crate) struct InnerCipher {
+    pub(crate) key_size: usize,
+    pub(crate) factory: Arc<InnerCipherFactory>,
+}
+
+#[derive(Default)]
+struct Registry {
+    outer_ciphers: HashMap<Uuid, OuterCipher>,
+    inner_ciphers: HashMap<u32, InnerCipher>,
+    kdfs: HashMap<Uuid, Arc<KdfFactory>>,
+}
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Register an outer cipher for the payload of databases with the cipher UUID `uuid`.
+///
+/// `factory` creates the cipher from the 32 byte master key and an IV of `C::iv_size()` bytes.
+/// Unlike the built-in ciphers, the payload is encrypted and decrypted at once.
+pub fn register_outer_cipher<C, F>(uuid: Uuid, factory: F)
+where
+    C: Cipher + 'static,
+    F: Fn(&[u8], &[u8]) -> Result<C, CryptographyError> + Send + Sync + 'static,
+{
+    let cipher = OuterCipher {
+        iv_size: C::iv_size(),
+        factory: Arc::new(move |key, iv| Ok(Box::new(factory(key, iv)?))),
+    };
+    registry()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .outer_ciphers
+        .insert(uuid, cipher);
+}
+
+/// Register an inner stream cipher for the protected values of databases with the stream ID `id`.
+///
+/// `factory` creates the cipher from a random key of `C::key_size()` bytes, which is stored in the
+/// database header.
+pub fn register_inner_cipher<C, F>(id: u32, factory: F)
+where
+    C: Cipher + 'static,
+    F: Fn(&[u8]) -> Result<C, CryptographyError> + Send + Sync + 'static,
+{
+    let cipher = InnerCipher {
+        key_size: C::key_size(),
+        factory: Arc::new(move |key| Ok(Box::new(factory(key)?))),
+    };
+    registry()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .inner_ciphers
+        .insert(id, cipher);
+}
+
+/// Register a key derivation function for KDBX4 databases with the KDF UUID `uuid`.
+///
+/// `factory` creates the KDF from its parameters in the database header, with the random seed under
+/// the key `"S"` like the built-in KDFs. The other parameters are kept in
+/// [`KdfConfig::Registered`](crate::config::KdfConfig::Registered) and written back when saving.
+pub fn register_kdf<K, F>(uuid: Uuid, factory: F)
+where
+    K: Kdf + 'static,
+    F: Fn(&VariantDictionary) -> Result<K, KdfConfigError> + Send + Sync + 'static,
+{
+    let factory: Arc<KdfFactory> = Arc::new(move |parameters| Ok(Box::new(factory(parameters)?)));
+    registry().write().unwrap_or_else(|e| e.into_inner()).kdfs.insert(uuid, factory);
+}
+
+pub(crate) fn outer_cipher(uuid: &Uuid) -> Option<OuterCipher> {
+    registry()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .outer_ciphers
+        .get(uuid)
+        .cloned()
+}
+
+pub(crate) fn inner_cipher(id: u32) -> Option<InnerCipher> {
+    registry().read().unwrap_or_else(|e| e.into_inner()).inner_ciphers.get(&id).cloned()
+}
+
+pub(crate) fn kdf(uuid: &Uuid) -> Option<Arc<KdfFactory>> {
+    registry().read().unwrap_or_else(|e| e.into_inner()).kdfs.get(uuid).cloned()
+}
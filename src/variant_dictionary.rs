@@ -14,19 +14,37 @@ pub const I64_TYPE_ID: u8 = 0x0d;
 pub const STR_TYPE_ID: u8 = 0x18;
 pub const BYTES_TYPE_ID: u8 = 0x42;
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) struct VariantDictionary {
+/// Read a `u32` length at `pos`, advancing `pos` past it
+fn read_length(buffer: &[u8], pos: &mut usize) -> Result<usize, VariantDictionaryError> {
+    let length = read_slice(buffer, pos, 4)?;
+    Ok(LittleEndian::read_u32(length) as usize)
+}
+
+/// Read `length` bytes at `pos`, advancing `pos` past them
+fn read_slice<'a>(buffer: &'a [u8], pos: &mut usize, length: usize) -> Result<&'a [u8], VariantDictionaryError> {
+    let end = pos.checked_add(length).ok_or(VariantDictionaryError::Incomplete)?;
+    let slice = buffer.get(*pos..end).ok_or(VariantDictionaryError::Incomplete)?;
+    *pos = end;
+    Ok(slice)
+}
+
+/// A dictionary of typed values, as used for the KDF parameters of KDBX4 databases
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct VariantDictionary {
     pub data: HashMap<String, VariantDictionaryValue>,
 }
 
 impl VariantDictionary {
-    #[allow(dead_code)]
-    pub(crate) fn new() -> Self {
+    pub fn new() -> Self {
         Self { data: HashMap::new() }
     }
 
     pub(crate) fn parse(buffer: &[u8]) -> Result<VariantDictionary, VariantDictionaryError> {
-        let version = LittleEndian::read_u16(&buffer[0..2]);
+        let version = buffer
+            .get(0..2)
+            .map(LittleEndian::read_u16)
+            .ok_or(VariantDictionaryError::Incomplete)?;
 
         if version != VARIANT_DICTIONARY_VERSION {
             return Err(VariantDictionaryError::InvalidVersion { version });
@@ -39,17 +57,20 @@ impl VariantDictionary {
             let value_type = buffer[pos];
             pos += 1;
 
-            let key_length = LittleEndian::read_u32(&buffer[pos..(pos + 4)]) as usize;
-            pos += 4;
-
-            let key = String::from_utf8_lossy(&buffer[pos..(pos + key_length)]).to_string();
-            pos += key_length;
+            let key_length = read_length(buffer, &mut pos)?;
+            let key = String::from_utf8_lossy(read_slice(buffer, &mut pos, key_length)?).to_string();
 
-            let value_length = LittleEndian::read_u32(&buffer[pos..(pos + 4)]) as usize;
-            pos += 4;
+            let value_length = read_length(buffer, &mut pos)?;
+            let value_buffer = read_slice(buffer, &mut pos, value_length)?;
 
-            let value_buffer = &buffer[pos..(pos + value_length)];
-            pos += value_length;
+            let expected_length = match value_type {
+                U32_TYPE_ID | I32_TYPE_ID => Some(4),
+                U64_TYPE_ID | I64_TYPE_ID => Some(8),
+                _ => None,
+            };
+            if expected_length.is_some_and(|length| length != value_length) {
+                return Err(VariantDictionaryError::InvalidValueLength { key, length: value_length });
+            }
 
             let value = match value_type {
                 U32_TYPE_ID => VariantDictionaryValue::UInt32(LittleEndian::read_u32(value_buffer)),
@@ -131,7 +152,8 @@ impl VariantDictionary {
         Ok(())
     }
 
-    pub(crate) fn get<'a, T: 'a>(&'a self, key: &str) -> Result<&'a T, VariantDictionaryError>
+    /// Get the value of `key`, which has to be of type `T`
+    pub fn get<'a, T: 'a>(&'a self, key: &str) -> Result<&'a T, VariantDictionaryError>
     where
         &'a VariantDictionaryValue: Into<Option<&'a T>>,
     {
@@ -143,8 +165,7 @@ impl VariantDictionary {
         vdv.into().ok_or_else(|| VariantDictionaryError::Mistyped { key: key.to_owned() })
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn set<T>(&mut self, key: &str, value: T)
+    pub fn set<T>(&mut self, key: &str, value: T)
     where
         T: Into<VariantDictionaryValue>,
     {
@@ -152,8 +173,9 @@ impl VariantDictionary {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) enum VariantDictionaryValue {
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub enum VariantDictionaryValue {
     UInt32(u32),
     UInt64(u64),
     Bool(bool),
@@ -296,6 +318,17 @@ mod variant_dictionary_tests {
         dbg!(&res);
         assert!(matches!(res, Err(VariantDictionaryError::InvalidValueType { value_type: 0xAA })));
 
+        let res = VariantDictionary::parse(&hex!("00"));
+        assert!(matches!(res, Err(VariantDictionaryError::Incomplete)));
+
+        // key length pointing past the end of the buffer
+        let res = VariantDictionary::parse(&hex!("000104FFFFFFFF41424304000000"));
+        assert!(matches!(res, Err(VariantDictionaryError::Incomplete)));
+
+        // u32 value with only two bytes
+        let res = VariantDictionary::parse(&hex!("000104030000004142430200000015CD00"));
+        assert!(matches!(res, Err(VariantDictionaryError::InvalidValueLength { length: 2, .. })));
+
         Ok(())
     }
 
@@ -3,11 +3,11 @@ use xml::writer::{EventWriter, XmlEvent as WriterEvent};
 
 use crate::{
     crypt::ciphers::Cipher,
-    db::{AutoType, AutoTypeAssociation, Entry, History, Value},
-    xml_db::dump::{DumpXml, SimpleTag},
+    db::{Attachment, AutoType, AutoTypeAssociation, Entry, History, Value},
+    xml_db::dump::{start_element, DumpXml, SimpleTag, UnknownElements},
 };
 
-fn escape_xml(input: &str) -> String {
+pub(crate) fn escape_xml(input: &str) -> String {
     input
         .chars()
         .map(|c| match c {
@@ -26,58 +26,72 @@ fn escape_xml(input: &str) -> String {
 
 impl DumpXml for Entry {
     fn dump_xml<E: std::io::Write>(&self, writer: &mut EventWriter<E>, inner_cipher: &mut dyn Cipher) -> Result<(), xml::writer::Error> {
-        writer.write(WriterEvent::start_element("Entry"))?;
+        writer.write(start_element("Entry", &self.unknown_xml))?;
+        let mut unknown_elements = UnknownElements::new(&self.unknown_xml);
+        unknown_elements.dump_first(writer, inner_cipher)?;
+
+        unknown_elements.dump_tag("UUID", Some(&self.uuid), writer, inner_cipher)?;
+        unknown_elements.dump_tag("Tags", Some(&escape_xml(&self.tags.join(";"))), writer, inner_cipher)?;
+        unknown_elements.dump_tag("PreviousParentGroup", self.previous_parent_group.as_ref(), writer, inner_cipher)?;
+
+        let fields = self.fields.iter().map(|(key, value)| StringField(key, value)).collect::<Vec<_>>();
+        unknown_elements.dump_children("String", &fields, writer, inner_cipher)?;
+        unknown_elements.dump_children("Binary", &self.attachments, writer, inner_cipher)?;
+        unknown_elements.dump_children("CustomData", [&self.custom_data], writer, inner_cipher)?;
+        unknown_elements.dump_children("AutoType", &self.autotype, writer, inner_cipher)?;
+        unknown_elements.dump_children("Times", [&self.times], writer, inner_cipher)?;
+
+        unknown_elements.dump_tag("IconID", self.icon_id.map(usize::from), writer, inner_cipher)?;
+        unknown_elements.dump_tag("CustomIconUUID", self.custom_icon_uuid.as_ref(), writer, inner_cipher)?;
+        let foreground_color = self.foreground_color.map(|color| escape_xml(&color.to_string()));
+        unknown_elements.dump_tag("ForegroundColor", foreground_color.as_ref(), writer, inner_cipher)?;
+        let background_color = self.background_color.map(|color| escape_xml(&color.to_string()));
+        unknown_elements.dump_tag("BackgroundColor", background_color.as_ref(), writer, inner_cipher)?;
+        let override_url = self.override_url.as_deref().map(escape_xml);
+        unknown_elements.dump_tag("OverrideURL", override_url.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("QualityCheck", self.quality_check, writer, inner_cipher)?;
+
+        unknown_elements.dump_children("History", &self.history, writer, inner_cipher)?;
+
+        unknown_elements.dump_rest(writer, inner_cipher)?;
 
-        SimpleTag("UUID", &self.uuid).dump_xml(writer, inner_cipher)?;
-
-        SimpleTag("Tags", &escape_xml(&self.tags.join(";"))).dump_xml(writer, inner_cipher)?;
-
-        for (field_name, field_value) in &self.fields {
-            writer.write(WriterEvent::start_element("String"))?;
-
-            SimpleTag("Key", &escape_xml(field_name)).dump_xml(writer, inner_cipher)?;
-            field_value.dump_xml(writer, inner_cipher)?;
+        writer.write(WriterEvent::end_element())?; // Entry
 
-            writer.write(WriterEvent::end_element())?; // String
-        }
+        Ok(())
+    }
+}
 
-        self.custom_data.dump_xml(writer, inner_cipher)?;
+/// A string field of an entry, written as a `String` element with its key and value
+struct StringField<'a>(&'a str, &'a Value);
 
-        if let Some(ref value) = self.autotype {
-            value.dump_xml(writer, inner_cipher)?;
-        }
+impl DumpXml for StringField<'_> {
+    fn dump_xml<E: std::io::Write>(&self, writer: &mut EventWriter<E>, inner_cipher: &mut dyn Cipher) -> Result<(), xml::writer::Error> {
+        writer.write(WriterEvent::start_element("String"))?;
 
-        self.times.dump_xml(writer, inner_cipher)?;
+        SimpleTag("Key", &escape_xml(self.0)).dump_xml(writer, inner_cipher)?;
+        self.1.dump_xml(writer, inner_cipher)?;
 
-        if let Some(value) = self.icon_id {
-            SimpleTag("IconID", usize::from(value)).dump_xml(writer, inner_cipher)?;
-        }
+        writer.write(WriterEvent::end_element())?; // String
 
-        if let Some(ref value) = self.custom_icon_uuid {
-            SimpleTag("CustomIconUUID", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.foreground_color {
-            SimpleTag("ForegroundColor", &escape_xml(&value.to_string())).dump_xml(writer, inner_cipher)?;
-        }
+        Ok(())
+    }
+}
 
-        if let Some(ref value) = self.background_color {
-            SimpleTag("BackgroundColor", &escape_xml(&value.to_string())).dump_xml(writer, inner_cipher)?;
-        }
+impl DumpXml for Attachment {
+    fn dump_xml<E: std::io::Write>(&self, writer: &mut EventWriter<E>, inner_cipher: &mut dyn Cipher) -> Result<(), xml::writer::Error> {
+        // attachments are only written once they were assigned a position in the binary pool
+        let Some(ref pool_ref) = self.pool_ref else {
+            return Ok(());
+        };
 
-        if let Some(ref value) = self.override_url {
-            SimpleTag("OverrideURL", &escape_xml(value)).dump_xml(writer, inner_cipher)?;
-        }
+        writer.write(WriterEvent::start_element("Binary"))?;
 
-        if let Some(value) = self.quality_check {
-            SimpleTag("QualityCheck", value).dump_xml(writer, inner_cipher)?;
-        }
+        SimpleTag("Key", &escape_xml(&self.name)).dump_xml(writer, inner_cipher)?;
 
-        if let Some(ref value) = self.history {
-            value.dump_xml(writer, inner_cipher)?;
-        }
+        writer.write(WriterEvent::start_element("Value").attr("Ref", pool_ref))?;
+        writer.write(WriterEvent::end_element())?; // Value
 
-        writer.write(WriterEvent::end_element())?; // Entry
+        writer.write(WriterEvent::end_element())?; // Binary
 
         Ok(())
     }
@@ -91,7 +105,7 @@ impl DumpXml for Value {
             Value::Protected(p) => {
                 writer.write(WriterEvent::start_element("Value").attr("Protected", "True"))?;
 
-                let encrypted_value = inner_cipher.encrypt(p.unsecure()).expect("Encrypt with inner cipher");
+                let encrypted_value = inner_cipher.encrypt(p.reveal().as_bytes()).expect("Encrypt with inner cipher");
 
                 let protected_value = base64_engine::STANDARD.encode(encrypted_value);
 
@@ -1,53 +1,42 @@
 use crate::{
     crypt::ciphers::Cipher,
-    db::{Entry, Group, NodePtr},
-    xml_db::dump::{DumpXml, SimpleTag},
+    db::{Entry, Group, NodePtr, SerializableNodePtr},
+    xml_db::dump::{entry::escape_xml, start_element, DumpXml, UnknownElements},
 };
 use xml::writer::{EventWriter, XmlEvent as WriterEvent};
 
 impl DumpXml for Group {
     fn dump_xml<E: std::io::Write>(&self, writer: &mut EventWriter<E>, inner_cipher: &mut dyn Cipher) -> Result<(), xml::writer::Error> {
-        writer.write(WriterEvent::start_element("Group"))?;
-
-        SimpleTag("Name", &self.name).dump_xml(writer, inner_cipher)?;
-        SimpleTag("UUID", &self.uuid).dump_xml(writer, inner_cipher)?;
-
-        if let Some(ref value) = self.notes {
-            SimpleTag("Notes", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(value) = self.icon_id {
-            SimpleTag("IconID", usize::from(value)).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.custom_icon_uuid {
-            SimpleTag("CustomIconUUID", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        self.times.dump_xml(writer, inner_cipher)?;
-        self.custom_data.dump_xml(writer, inner_cipher)?;
-
-        SimpleTag("IsExpanded", self.is_expanded).dump_xml(writer, inner_cipher)?;
-
-        if let Some(ref value) = self.default_autotype_sequence {
-            SimpleTag("DefaultAutoTypeSequence", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.enable_autotype {
-            SimpleTag("EnableAutoType", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.enable_searching {
-            SimpleTag("EnableSearching", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.last_top_visible_entry {
-            SimpleTag("LastTopVisibleEntry", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        for child in &self.children {
-            child.dump_xml(writer, inner_cipher)?;
-        }
+        writer.write(start_element("Group", &self.unknown_xml))?;
+        let mut unknown_elements = UnknownElements::new(&self.unknown_xml);
+        unknown_elements.dump_first(writer, inner_cipher)?;
+
+        unknown_elements.dump_tag("Name", Some(&self.name), writer, inner_cipher)?;
+        unknown_elements.dump_tag("UUID", Some(&self.uuid), writer, inner_cipher)?;
+        unknown_elements.dump_tag("Notes", self.notes.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("IconID", self.icon_id.map(usize::from), writer, inner_cipher)?;
+        unknown_elements.dump_tag("CustomIconUUID", self.custom_icon_uuid.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_children("Times", [&self.times], writer, inner_cipher)?;
+        unknown_elements.dump_children("CustomData", [&self.custom_data], writer, inner_cipher)?;
+        unknown_elements.dump_tag("IsExpanded", Some(self.is_expanded), writer, inner_cipher)?;
+        unknown_elements.dump_tag(
+            "DefaultAutoTypeSequence",
+            self.default_autotype_sequence.as_ref(),
+            writer,
+            inner_cipher,
+        )?;
+        unknown_elements.dump_tag("EnableAutoType", self.enable_autotype.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("EnableSearching", self.enable_searching.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("LastTopVisibleEntry", self.last_top_visible_entry.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("PreviousParentGroup", self.previous_parent_group.as_ref(), writer, inner_cipher)?;
+        let tags = (!self.tags.is_empty()).then(|| escape_xml(&self.tags.join(";")));
+        unknown_elements.dump_tag("Tags", tags.as_ref(), writer, inner_cipher)?;
+
+        // the children are entries and groups in the order of the tree
+        unknown_elements.dump_children("Entry", &self.children, writer, inner_cipher)?;
+        unknown_elements.dump_after("Group", writer, inner_cipher)?;
+
+        unknown_elements.dump_rest(writer, inner_cipher)?;
 
         writer.write(WriterEvent::end_element())?; // Group
 
@@ -66,3 +55,9 @@ impl DumpXml for NodePtr {
         }
     }
 }
+
+impl DumpXml for SerializableNodePtr {
+    fn dump_xml<E: std::io::Write>(&self, writer: &mut EventWriter<E>, inner_cipher: &mut dyn Cipher) -> Result<(), xml::writer::Error> {
+        self.as_ref().dump_xml(writer, inner_cipher)
+    }
+}
@@ -5,110 +5,60 @@ use crate::{
     compression::{Compression, GZipCompression},
     crypt::ciphers::Cipher,
     db::meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, Meta},
-    xml_db::dump::{DumpXml, SimpleTag},
+    xml_db::dump::{start_element, DumpXml, SimpleTag, UnknownElements},
 };
 
 impl DumpXml for Meta {
     fn dump_xml<E: std::io::Write>(&self, writer: &mut EventWriter<E>, inner_cipher: &mut dyn Cipher) -> Result<(), xml::writer::Error> {
-        writer.write(WriterEvent::start_element("Meta"))?;
-
-        if let Some(ref value) = self.generator {
-            SimpleTag("Generator", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.database_name {
-            SimpleTag("DatabaseName", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.database_name_changed {
-            SimpleTag("DatabaseNameChanged", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.database_description {
-            SimpleTag("DatabaseDescription", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.database_description_changed {
-            SimpleTag("DatabaseDescriptionChanged", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.default_username {
-            SimpleTag("DefaultUserName", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.default_username_changed {
-            SimpleTag("DefaultUserNameChanged", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(value) = self.maintenance_history_days {
-            SimpleTag("MaintenanceHistoryDays", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.color {
-            SimpleTag("Color", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.master_key_changed {
-            SimpleTag("MasterKeyChanged", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(value) = self.master_key_change_rec {
-            SimpleTag("MasterKeyChangeRec", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(value) = self.master_key_change_force {
-            SimpleTag("MasterKeyChangeForce", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.memory_protection {
-            value.dump_xml(writer, inner_cipher)?;
-        }
-
-        self.custom_icons.dump_xml(writer, inner_cipher)?;
-
-        if let Some(value) = self.recyclebin_enabled {
-            SimpleTag("RecycleBinEnabled", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.recyclebin_uuid {
-            SimpleTag("RecycleBinUUID", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.recyclebin_changed {
-            SimpleTag("RecycleBinChanged", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.entry_templates_group {
-            SimpleTag("EntryTemplatesGroup", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.entry_templates_group_changed {
-            SimpleTag("EntryTemplatesGroupChanged", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.last_selected_group {
-            SimpleTag("LastSelectedGroup", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.last_top_visible_group {
-            SimpleTag("LastTopVisibleGroup", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(value) = self.history_max_items {
-            SimpleTag("HistoryMaxItems", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(value) = self.history_max_size {
-            SimpleTag("HistoryMaxSize", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        if let Some(ref value) = self.settings_changed {
-            SimpleTag("SettingsChanged", value).dump_xml(writer, inner_cipher)?;
-        }
-
-        self.binaries.dump_xml(writer, inner_cipher)?;
-
-        self.custom_data.dump_xml(writer, inner_cipher)?;
+        writer.write(start_element("Meta", &self.unknown_xml))?;
+        let mut unknown_elements = UnknownElements::new(&self.unknown_xml);
+        unknown_elements.dump_first(writer, inner_cipher)?;
+
+        unknown_elements.dump_tag("Generator", self.generator.as_ref(), writer, inner_cipher)?;
+        let header_hash = self.header_hash.as_ref().map(|value| base64_engine::STANDARD.encode(value));
+        unknown_elements.dump_tag("HeaderHash", header_hash.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("DatabaseName", self.database_name.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("DatabaseNameChanged", self.database_name_changed.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("DatabaseDescription", self.database_description.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag(
+            "DatabaseDescriptionChanged",
+            self.database_description_changed.as_ref(),
+            writer,
+            inner_cipher,
+        )?;
+        unknown_elements.dump_tag("DefaultUserName", self.default_username.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag(
+            "DefaultUserNameChanged",
+            self.default_username_changed.as_ref(),
+            writer,
+            inner_cipher,
+        )?;
+        unknown_elements.dump_tag("MaintenanceHistoryDays", self.maintenance_history_days, writer, inner_cipher)?;
+        unknown_elements.dump_tag("Color", self.color.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("MasterKeyChanged", self.master_key_changed.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("MasterKeyChangeRec", self.master_key_change_rec, writer, inner_cipher)?;
+        unknown_elements.dump_tag("MasterKeyChangeForce", self.master_key_change_force, writer, inner_cipher)?;
+        unknown_elements.dump_children("MemoryProtection", &self.memory_protection, writer, inner_cipher)?;
+        unknown_elements.dump_children("CustomIcons", [&self.custom_icons], writer, inner_cipher)?;
+        unknown_elements.dump_tag("RecycleBinEnabled", self.recyclebin_enabled, writer, inner_cipher)?;
+        unknown_elements.dump_tag("RecycleBinUUID", self.recyclebin_uuid.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("RecycleBinChanged", self.recyclebin_changed.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("EntryTemplatesGroup", self.entry_templates_group.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag(
+            "EntryTemplatesGroupChanged",
+            self.entry_templates_group_changed.as_ref(),
+            writer,
+            inner_cipher,
+        )?;
+        unknown_elements.dump_tag("LastSelectedGroup", self.last_selected_group.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("LastTopVisibleGroup", self.last_top_visible_group.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_tag("HistoryMaxItems", self.history_max_items, writer, inner_cipher)?;
+        unknown_elements.dump_tag("HistoryMaxSize", self.history_max_size, writer, inner_cipher)?;
+        unknown_elements.dump_tag("SettingsChanged", self.settings_changed.as_ref(), writer, inner_cipher)?;
+        unknown_elements.dump_children("Binaries", [&self.binaries], writer, inner_cipher)?;
+        unknown_elements.dump_children("CustomData", [&self.custom_data], writer, inner_cipher)?;
+
+        unknown_elements.dump_rest(writer, inner_cipher)?;
 
         writer.write(WriterEvent::end_element())?;
 
@@ -145,7 +95,7 @@ impl DumpXml for BinaryAttachments {
 }
 
 impl DumpXml for BinaryAttachment {
-    fn dump_xml<E: std::io::Write>(&self, writer: &mut EventWriter<E>, _inner_cipher: &mut dyn Cipher) -> Result<(), xml::writer::Error> {
+    fn dump_xml<E: std::io::Write>(&self, writer: &mut EventWriter<E>, inner_cipher: &mut dyn Cipher) -> Result<(), xml::writer::Error> {
         let start_tag = WriterEvent::start_element("Binary");
 
         let start_tag = if let Some(ref id) = self.identifier {
@@ -160,6 +110,12 @@ impl DumpXml for BinaryAttachment {
             start_tag
         };
 
+        let start_tag = if self.protected {
+            start_tag.attr("Protected", "True")
+        } else {
+            start_tag
+        };
+
         writer.write(start_tag)?;
 
         let data = if self.compressed {
@@ -168,6 +124,12 @@ impl DumpXml for BinaryAttachment {
             self.content.clone()
         };
 
+        let data = if self.protected {
+            inner_cipher.encrypt(&data).expect("Encrypt with inner cipher")
+        } else {
+            data
+        };
+
         let buf = base64_engine::STANDARD.encode(data);
 
         writer.write(WriterEvent::characters(&buf))?;
@@ -199,6 +161,14 @@ impl DumpXml for Icon {
         let buf = base64_engine::STANDARD.encode(&self.data);
         SimpleTag("Data", &buf).dump_xml(writer, inner_cipher)?;
 
+        if let Some(ref value) = self.name {
+            SimpleTag("Name", value).dump_xml(writer, inner_cipher)?;
+        }
+
+        if let Some(ref value) = self.last_modification_time {
+            SimpleTag("LastModificationTime", value).dump_xml(writer, inner_cipher)?;
+        }
+
         writer.write(WriterEvent::end_element())?;
         Ok(())
     }
@@ -2,19 +2,23 @@ mod entry;
 mod group;
 mod meta;
 
-use std::io::Write;
+use std::{collections::BTreeMap, io::Write};
 
 use base64::{engine::general_purpose as base64_engine, Engine as _};
 use uuid::Uuid;
 use xml::{
-    writer::{EventWriter, XmlEvent as WriterEvent},
-    EmitterConfig,
+    reader::XmlEvent as ReaderEvent,
+    writer::{events::StartElementBuilder, EventWriter, XmlEvent as WriterEvent},
+    EmitterConfig, EventReader,
 };
 
 use crate::{
     crypt::ciphers::Cipher,
-    db::{Color, CustomData, CustomDataItem, Database, DeletedObject, DeletedObjects, Times},
-    xml_db::get_epoch_baseline,
+    db::{Color, CustomData, CustomDataItem, Database, DeletedObject, DeletedObjects, Times, UnknownXml, UnknownXmlElement},
+    xml_db::{
+        get_epoch_baseline,
+        parse::{namespace_declarations, qualified_name, FragmentNamespaces},
+    },
 };
 
 /// Format a timestamp suitable for an XML database
@@ -108,11 +112,14 @@ impl DumpXml for Database {
 
         self.meta.dump_xml(writer, inner_cipher)?;
 
-        writer.write(WriterEvent::start_element("Root"))?;
+        writer.write(start_element("Root", &self.unknown_xml))?;
+        let mut unknown_elements = UnknownElements::new(&self.unknown_xml);
+        unknown_elements.dump_first(writer, inner_cipher)?;
 
-        self.root.dump_xml(writer, inner_cipher)?;
+        unknown_elements.dump_children("Group", [&self.root], writer, inner_cipher)?;
+        unknown_elements.dump_children("DeletedObjects", [&self.deleted_objects], writer, inner_cipher)?;
 
-        self.deleted_objects.dump_xml(writer, inner_cipher)?;
+        unknown_elements.dump_rest(writer, inner_cipher)?;
 
         writer.write(WriterEvent::end_element())?; // Root
 
@@ -122,6 +129,166 @@ impl DumpXml for Database {
     }
 }
 
+/// Start an element with its attributes that are not known to this crate
+fn start_element<'a>(name: &'a str, unknown_xml: &'a UnknownXml) -> StartElementBuilder<'a> {
+    unknown_xml
+        .attributes
+        .iter()
+        .fold(WriterEvent::start_element(name), |element, (name, value)| {
+            element.attr(name.as_str(), value)
+        })
+}
+
+/// Writes the unknown child elements of an object back in place, i.e. each one right after the known
+/// child element that preceded it when the database was read
+struct UnknownElements<'a> {
+    elements: &'a [UnknownXmlElement],
+    written: Vec<bool>,
+}
+
+impl<'a> UnknownElements<'a> {
+    fn new(unknown_xml: &'a UnknownXml) -> Self {
+        UnknownElements {
+            elements: &unknown_xml.elements,
+            written: vec![false; unknown_xml.elements.len()],
+        }
+    }
+
+    /// Write the unknown elements that preceded all known child elements
+    fn dump_first<E: std::io::Write>(
+        &mut self,
+        writer: &mut EventWriter<E>,
+        inner_cipher: &mut dyn Cipher,
+    ) -> Result<(), xml::writer::Error> {
+        self.dump_where(|element| element.after.is_none(), writer, inner_cipher)
+    }
+
+    /// Write the known child element `name` with `value` if there is one, followed by the unknown
+    /// elements that followed it
+    fn dump_tag<E: std::io::Write, D: DumpXml>(
+        &mut self,
+        name: &str,
+        value: Option<D>,
+        writer: &mut EventWriter<E>,
+        inner_cipher: &mut dyn Cipher,
+    ) -> Result<(), xml::writer::Error> {
+        if let Some(value) = value {
+            SimpleTag(name, value).dump_xml(writer, inner_cipher)?;
+        }
+        self.dump_after(name, writer, inner_cipher)
+    }
+
+    /// Write the known child elements named `name`, which write their own tags, followed by the
+    /// unknown elements that followed them
+    fn dump_children<'c, E: std::io::Write, D: DumpXml + 'c>(
+        &mut self,
+        name: &str,
+        children: impl IntoIterator<Item = &'c D>,
+        writer: &mut EventWriter<E>,
+        inner_cipher: &mut dyn Cipher,
+    ) -> Result<(), xml::writer::Error> {
+        for child in children {
+            child.dump_xml(writer, inner_cipher)?;
+        }
+        self.dump_after(name, writer, inner_cipher)
+    }
+
+    /// Write the unknown elements that followed the known child elements named `name`
+    fn dump_after<E: std::io::Write>(
+        &mut self,
+        name: &str,
+        writer: &mut EventWriter<E>,
+        inner_cipher: &mut dyn Cipher,
+    ) -> Result<(), xml::writer::Error> {
+        self.dump_where(|element| element.after.as_deref() == Some(name), writer, inner_cipher)
+    }
+
+    /// Write the unknown elements that have not been written yet, e.g. because they followed a known
+    /// child element that is not written anymore
+    fn dump_rest<E: std::io::Write>(
+        &mut self,
+        writer: &mut EventWriter<E>,
+        inner_cipher: &mut dyn Cipher,
+    ) -> Result<(), xml::writer::Error> {
+        self.dump_where(|_| true, writer, inner_cipher)
+    }
+
+    fn dump_where<E: std::io::Write>(
+        &mut self,
+        predicate: impl Fn(&UnknownXmlElement) -> bool,
+        writer: &mut EventWriter<E>,
+        inner_cipher: &mut dyn Cipher,
+    ) -> Result<(), xml::writer::Error> {
+        for (element, written) in self.elements.iter().zip(self.written.iter_mut()) {
+            if !*written && predicate(element) {
+                element.dump_xml(writer, inner_cipher)?;
+                *written = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write an unknown element, encrypting its protected values with the inner cipher
+impl DumpXml for UnknownXmlElement {
+    fn dump_xml<E: std::io::Write>(&self, writer: &mut EventWriter<E>, inner_cipher: &mut dyn Cipher) -> Result<(), xml::writer::Error> {
+        let mut protected_values = self.protected_values.iter();
+        // whether the values of the currently open elements are protected by the inner cipher
+        let mut protected = Vec::new();
+        let mut namespaces = FragmentNamespaces::default();
+
+        for event in EventReader::new(self.xml.as_bytes()) {
+            match event.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))? {
+                ReaderEvent::StartElement {
+                    name,
+                    attributes,
+                    namespace,
+                } => {
+                    let is_protected = attributes
+                        .iter()
+                        .any(|a| a.name.local_name == "Protected" && a.value.eq_ignore_ascii_case("true"));
+                    protected.push(is_protected);
+
+                    let element_name = qualified_name(&name);
+                    let mut attributes: BTreeMap<String, String> =
+                        attributes.iter().map(|a| (qualified_name(&a.name), a.value.clone())).collect();
+                    let declarations = namespace_declarations(Some(&element_name), attributes.keys().map(String::as_str), &namespace);
+                    attributes.extend(namespaces.enter(declarations));
+                    let element = attributes
+                        .iter()
+                        .fold(WriterEvent::start_element(element_name.as_str()), |element, (name, value)| {
+                            element.attr(name.as_str(), value)
+                        });
+                    writer.write(element)?;
+
+                    // the protected values are not part of the fragment
+                    if let Some(value) = is_protected.then(|| protected_values.next()).flatten() {
+                        let encrypted_value = inner_cipher.encrypt(value.reveal().as_bytes()).expect("Encrypt with inner cipher");
+                        writer.write(WriterEvent::characters(&base64_engine::STANDARD.encode(encrypted_value)))?;
+                    }
+                }
+                ReaderEvent::EndElement { .. } => {
+                    protected.pop();
+                    namespaces.leave();
+                    writer.write(WriterEvent::end_element())?;
+                }
+                ReaderEvent::Characters(text) | ReaderEvent::CData(text) => {
+                    if protected.last() == Some(&true) {
+                        let encrypted_value = inner_cipher.encrypt(text.as_bytes()).expect("Encrypt with inner cipher");
+                        writer.write(WriterEvent::characters(&base64_engine::STANDARD.encode(encrypted_value)))?;
+                    } else {
+                        writer.write(WriterEvent::characters(&text))?;
+                    }
+                }
+                // ignore the document events, whitespace, comments, ...
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl DumpXml for Times {
     fn dump_xml<E: std::io::Write>(&self, writer: &mut EventWriter<E>, inner_cipher: &mut dyn Cipher) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("Times"))?;
@@ -11,6 +11,7 @@ pub fn get_epoch_baseline() -> chrono::NaiveDateTime {
 mod tests {
     use crate::{
         config::DatabaseConfig,
+        crypt::ciphers::PlainCipher,
         db::{
             entry::History,
             group_get_children,
@@ -18,14 +19,17 @@ mod tests {
             meta::{BinaryAttachments, CustomIcons, Icon, MemoryProtection},
             node::*,
             node_is_equals_to, AutoType, AutoTypeAssociation, BinaryAttachment, CustomData, CustomDataItem, Database, DeletedObject, Entry,
-            Group, Meta, NodePtr, Times, Value,
+            Group, Meta, NodePtr, Times, UnknownXml, UnknownXmlElement, Value,
         },
+        error::{DatabaseIntegrityError, DatabaseOpenError, XmlParseError},
         format::kdbx4,
         key::DatabaseKey,
+        limits::OpenLimits,
+        progress::ProgressMonitor,
         rc_refcell_node,
+        xml_db::dump,
     };
     use chrono::NaiveDateTime;
-    use secstr::SecStr;
     use std::collections::HashMap;
     use uuid::uuid;
 
@@ -47,10 +51,7 @@ mod tests {
 
         entry.set_title(Some("ASDF"));
         entry.fields.insert("UserName".to_string(), Value::Unprotected("ghj".to_string()));
-        entry.fields.insert(
-            "Password".to_string(),
-            Value::Protected(std::str::from_utf8(b"klmno").unwrap().into()),
-        );
+        entry.fields.insert("Password".to_string(), Value::Protected("klmno".into()));
         entry.tags.push("test".to_string());
         entry.tags.push("keepass-ng".to_string());
         entry.times.set_expires(true);
@@ -109,8 +110,8 @@ mod tests {
         let db_key = make_key();
 
         let mut encrypted_db = Vec::new();
-        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+        let (decrypted_db, _) = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
 
         assert_eq!(group_get_children(&decrypted_db.root).unwrap().len(), 1);
 
@@ -164,8 +165,8 @@ mod tests {
         let db_key = make_key();
 
         let mut encrypted_db = Vec::new();
-        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+        let (decrypted_db, _) = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
 
         assert_eq!(group_get_children(&decrypted_db.root).unwrap().len(), 2);
 
@@ -182,6 +183,7 @@ mod tests {
 
         let meta = Meta {
             generator: Some("test-generator".to_string()),
+            header_hash: None,
             database_name: Some("test-database-name".to_string()),
             database_name_changed: Some("2000-12-31T12:34:56".parse().unwrap()),
             database_description: Some("test-database-description".to_string()),
@@ -204,6 +206,8 @@ mod tests {
                 icons: vec![Icon {
                     uuid: uuid!("a1a2a3a4b1bffffffffffff4d5d6d7d8"),
                     data: b"fake-data".to_vec(),
+                    name: Some("fake-icon".to_string()),
+                    last_modification_time: Some("2000-12-31T12:34:59".parse().unwrap()),
                 }],
             },
             recyclebin_enabled: Some(true),
@@ -221,16 +225,25 @@ mod tests {
                     BinaryAttachment {
                         identifier: Some("1".to_string()),
                         compressed: false,
+                        protected: false,
                         content: b"i am binary data".to_vec(),
                     },
                     BinaryAttachment {
                         identifier: Some("2".to_string()),
                         compressed: true,
+                        protected: false,
                         content: b"i am compressed binary data".to_vec(),
                     },
+                    BinaryAttachment {
+                        identifier: Some("3".to_string()),
+                        compressed: false,
+                        protected: true,
+                        content: b"i am protected binary data".to_vec(),
+                    },
                     BinaryAttachment {
                         identifier: None,
                         compressed: true,
+                        protected: false,
                         content: b"i am compressed binary data without an identifier".to_vec(),
                     },
                 ],
@@ -254,12 +267,14 @@ mod tests {
                     (
                         "custom-data-protected-key".to_string(),
                         CustomDataItem {
-                            value: Some(Value::Protected(SecStr::new(b"custom-data-value".to_vec()))),
+                            value: Some(Value::Protected("custom-data-value".into())),
                             last_modification_time: Some("2000-12-31T12:35:03".parse().unwrap()),
                         },
                     ),
                 ]),
             },
+            kdb_meta_streams: Vec::new(),
+            unknown_xml: UnknownXml::default(),
         };
 
         db.meta = meta.clone();
@@ -267,12 +282,101 @@ mod tests {
         let db_key = make_key();
 
         let mut encrypted_db = Vec::new();
-        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+        let (decrypted_db, _) = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
 
         assert_eq!(decrypted_db.meta, meta);
     }
 
+    #[test]
+    fn test_unknown_xml() {
+        let mut db = Database::new(DatabaseConfig::default());
+        db.meta.generator = Some("Test".to_string());
+        db.meta.unknown_xml.attributes.insert("p:Version".to_string(), "5".to_string());
+        db.meta
+            .unknown_xml
+            .attributes
+            .insert("xmlns:p".to_string(), "urn:plugin".to_string());
+        db.meta.unknown_xml.elements.push(UnknownXmlElement {
+            after: Some("Generator".to_string()),
+            xml: "<p:Settings p:theme=\"dark\" xmlns:p=\"urn:plugin\" />".to_string(),
+            ..UnknownXmlElement::default()
+        });
+        db.unknown_xml.attributes.insert("Origin".to_string(), "sync".to_string());
+        db.unknown_xml.elements.push(UnknownXmlElement {
+            after: Some("Group".to_string()),
+            xml: "<Archive />".to_string(),
+            ..UnknownXmlElement::default()
+        });
+
+        let mut entry = Entry::default();
+        entry.set_title(Some("First"));
+        entry.unknown_xml.attributes.insert("Pinned".to_string(), "True".to_string());
+        entry.unknown_xml.elements.push(UnknownXmlElement {
+            after: Some("Times".to_string()),
+            xml: "<PasskeyData><Value Protected=\"True\" /><Key Protected=\"True\" /></PasskeyData>".to_string(),
+            protected_values: vec!["private key".into(), "".into()],
+        });
+        group_add_child(&db.root, rc_refcell_node!(entry), 0).unwrap();
+
+        // the inner cipher stream must stay in sync after the protected values of the unknown element
+        let mut entry = Entry::default();
+        entry.set_title(Some("Second"));
+        entry.fields.insert("Password".to_string(), Value::Protected("secret".into()));
+        group_add_child(&db.root, rc_refcell_node!(entry), 1).unwrap();
+
+        if let Some(group) = db.root.borrow_mut().as_any_mut().downcast_mut::<Group>() {
+            group.name = "Root".to_string();
+            group.unknown_xml.elements.push(UnknownXmlElement {
+                after: Some("Name".to_string()),
+                xml: "<Sorting>Title</Sorting>".to_string(),
+                ..UnknownXmlElement::default()
+            });
+        }
+
+        // the unknown elements are written back in place, with the namespaces they use
+        let mut xml = Vec::new();
+        dump::dump(&db, &mut PlainCipher, &mut xml).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains("<Meta p:Version=\"5\" xmlns:p=\"urn:plugin\"><Generator>Test</Generator><p:Settings p:theme=\"dark\" xmlns:p=\"urn:plugin\" />"));
+        assert!(xml.contains("<Name>Root</Name><Sorting>Title</Sorting><UUID>"));
+        assert!(xml.contains("</Times><PasskeyData><Value Protected=\"True\">cHJpdmF0ZSBrZXk=</Value>"));
+        assert!(xml.contains("</Group><Archive /><DeletedObjects"));
+
+        let db_key = make_key();
+
+        let mut encrypted_db = Vec::new();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+        let (decrypted_db, _) = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
+
+        assert_eq!(decrypted_db.meta.unknown_xml, db.meta.unknown_xml);
+        assert_eq!(decrypted_db.unknown_xml, db.unknown_xml);
+        assert!(node_is_equals_to(&decrypted_db.root, &db.root));
+
+        let first = Group::get(&decrypted_db.root, &["First"]).unwrap();
+        let first = first.borrow();
+        let passkey = &first.as_any().downcast_ref::<Entry>().unwrap().unknown_xml.elements[0];
+        assert_eq!(
+            passkey.xml,
+            "<PasskeyData><Value Protected=\"True\" /><Key Protected=\"True\" /></PasskeyData>"
+        );
+        assert_eq!(&*passkey.protected_values[0].reveal(), "private key");
+
+        let second = Group::get(&decrypted_db.root, &["Second"]).unwrap();
+        assert_eq!(
+            second.borrow().as_any().downcast_ref::<Entry>().unwrap().get_password(),
+            Some("secret")
+        );
+
+        let result = Database::open_strict(&mut encrypted_db.as_slice(), db_key, &OpenLimits::default());
+        assert!(matches!(
+            result,
+            Err(DatabaseOpenError::DatabaseIntegrity(DatabaseIntegrityError::Xml(
+                XmlParseError::UnknownXml { parent: "Meta", .. }
+            )))
+        ));
+    }
+
     #[test]
     fn test_deleted_objects() {
         let mut db = Database::new(DatabaseConfig::default());
@@ -290,8 +394,8 @@ mod tests {
         let db_key = make_key();
 
         let mut encrypted_db = Vec::new();
-        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &ProgressMonitor::default()).unwrap();
+        let (decrypted_db, _) = kdbx4::parse_kdbx4(&encrypted_db, &db_key, &ProgressMonitor::default(), &OpenLimits::default()).unwrap();
 
         assert_eq!(decrypted_db, db);
     }
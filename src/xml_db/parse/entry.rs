@@ -1,10 +1,11 @@
 use crate::{
     crypt::ciphers::Cipher,
-    db::{iconid::IconId, AutoType, AutoTypeAssociation, Color, Entry, History, Times, Value},
-    xml_db::parse::{bad_event, CustomData, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, XmlParseError},
+    db::{iconid::IconId, Attachment, AutoType, AutoTypeAssociation, Color, Entry, History, Times, Value},
+    xml_db::parse::{
+        bad_event, unknown_attributes, CustomData, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, UnknownElement, XmlParseError,
+    },
 };
 use base64::{engine::general_purpose as base64_engine, Engine as _};
-use secstr::SecStr;
 use std::{convert::TryFrom, iter::Peekable};
 use uuid::Uuid;
 
@@ -25,68 +26,89 @@ impl FromXml for Entry {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Entry") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "Entry") {
             return Err(bad_event("Open entry tag", open_tag));
         }
 
         let mut out = Entry::default();
+        if let SimpleXmlEvent::Start(_, attributes, namespace) = open_tag {
+            out.unknown_xml.attributes = unknown_attributes(attributes, &namespace);
+        }
 
+        // the last known child element, after which the unknown elements that follow it are written back
+        let mut preceding = None;
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
-                    "UUID" => {
-                        out.uuid = SimpleTag::<Uuid>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "Tags" => {
-                        if let Some(tags) = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value {
-                            out.tags = tags.split(|c| c == ';' || c == ',').map(std::borrow::ToOwned::to_owned).collect();
+                SimpleXmlEvent::Start(name, ..) => {
+                    let name = name.clone();
+                    match &name[..] {
+                        "UUID" => {
+                            out.uuid = SimpleTag::<Uuid>::from_xml(iterator, inner_cipher)?.value;
                         }
-                    }
-                    "String" => {
-                        let field = StringField::from_xml(iterator, inner_cipher)?;
-                        if let Some(value) = field.value {
-                            out.fields.insert(field.key, value);
+                        "Tags" => {
+                            if let Some(tags) = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value {
+                                out.tags = tags.split([';', ',']).map(std::borrow::ToOwned::to_owned).collect();
+                            }
+                        }
+                        "String" => {
+                            let field = StringField::from_xml(iterator, inner_cipher)?;
+                            if let Some(value) = field.value {
+                                out.fields.insert(field.key, value);
+                            }
+                        }
+                        "CustomData" => {
+                            out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
+                        }
+                        "Binary" => {
+                            // the reference into the binary pool is resolved once the whole database is parsed
+                            let field = BinaryField::from_xml(iterator, inner_cipher)?;
+                            out.attachments.push(Attachment {
+                                name: decode_xml(&field.key),
+                                pool_ref: Some(field.identifier),
+                                ..Attachment::default()
+                            });
+                        }
+                        "AutoType" => {
+                            out.autotype = Some(AutoType::from_xml(iterator, inner_cipher)?);
+                        }
+                        "Times" => {
+                            out.times = Times::from_xml(iterator, inner_cipher)?;
+                        }
+                        "IconID" => {
+                            out.icon_id = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?
+                                .value
+                                .map(|id| IconId::try_from(id).unwrap_or(IconId::KEY));
+                        }
+                        "CustomIconUUID" => {
+                            out.custom_icon_uuid = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "PreviousParentGroup" => {
+                            out.previous_parent_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "ForegroundColor" => {
+                            out.foreground_color = SimpleTag::<Option<Color>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "BackgroundColor" => {
+                            out.background_color = SimpleTag::<Option<Color>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "OverrideURL" => {
+                            out.override_url = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "QualityCheck" => {
+                            out.quality_check = SimpleTag::<Option<bool>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "History" => {
+                            out.history = Some(History::from_xml(iterator, inner_cipher)?);
+                        }
+                        _ => {
+                            let mut element = UnknownElement::from_xml(iterator, inner_cipher)?;
+                            element.after = preceding.clone();
+                            out.unknown_xml.elements.push(element);
+                            continue;
                         }
                     }
-                    "CustomData" => {
-                        out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
-                    }
-                    "Binary" => {
-                        let _field = BinaryField::from_xml(iterator, inner_cipher)?;
-                        // TODO reference into a binary field from the Meta. Might only appear in
-                        // kdbx3
-                    }
-                    "AutoType" => {
-                        out.autotype = Some(AutoType::from_xml(iterator, inner_cipher)?);
-                    }
-                    "Times" => {
-                        out.times = Times::from_xml(iterator, inner_cipher)?;
-                    }
-                    "IconID" => {
-                        out.icon_id = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?
-                            .value
-                            .map(|id| IconId::try_from(id).unwrap_or(IconId::KEY));
-                    }
-                    "CustomIconUUID" => {
-                        out.custom_icon_uuid = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "ForegroundColor" => {
-                        out.foreground_color = SimpleTag::<Option<Color>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "BackgroundColor" => {
-                        out.background_color = SimpleTag::<Option<Color>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "OverrideURL" => {
-                        out.override_url = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "QualityCheck" => {
-                        out.quality_check = SimpleTag::<Option<bool>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "History" => {
-                        out.history = Some(History::from_xml(iterator, inner_cipher)?);
-                    }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
-                },
+                    preceding = Some(name);
+                }
                 SimpleXmlEvent::End(name) if name == "Entry" => break,
                 _ => return Err(bad_event("start tag or close entry", event.clone())),
             }
@@ -113,7 +135,7 @@ impl FromXml for StringField {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "String") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "String") {
             return Err(bad_event("Open string tag", open_tag));
         }
 
@@ -121,7 +143,7 @@ impl FromXml for StringField {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "Key" => {
                         out.key = decode_xml(&SimpleTag::<String>::from_xml(iterator, inner_cipher)?.value);
                     }
@@ -146,7 +168,6 @@ impl FromXml for StringField {
 }
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub(crate) struct BinaryField {
     pub key: String,
     pub identifier: String,
@@ -160,7 +181,7 @@ impl FromXml for BinaryField {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Binary") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "Binary") {
             return Err(bad_event("Open Binary tag", open_tag));
         }
 
@@ -168,7 +189,7 @@ impl FromXml for BinaryField {
 
         let value_event = iterator.next().ok_or(XmlParseError::Eof)?;
         let identifier = match value_event {
-            SimpleXmlEvent::Start(ref name, ref attributes) if name == "Value" => attributes.get("Ref").cloned(),
+            SimpleXmlEvent::Start(ref name, ref attributes, _) if name == "Value" => attributes.get("Ref").cloned(),
             _ => None,
         }
         .ok_or(bad_event("Open Value tag with \"Ref\" attribute", value_event))?;
@@ -194,7 +215,7 @@ impl FromXml for Value {
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
 
-        if let SimpleXmlEvent::Start(ref tag, ref attributes) = open_tag {
+        if let SimpleXmlEvent::Start(ref tag, ref attributes, _) = open_tag {
             if tag == "Value" {
                 let protected: bool = attributes
                     .get("Protected")
@@ -207,7 +228,7 @@ impl FromXml for Value {
                     let buf = base64_engine::STANDARD.decode(&decoded_content)?;
                     let buf_decrypted = inner_cipher.decrypt(&buf)?;
                     let value = String::from_utf8_lossy(&buf_decrypted).to_string();
-                    Value::Protected(SecStr::from(value))
+                    Value::Protected(value.into())
                 } else {
                     Value::Unprotected(decoded_content)
                 };
@@ -232,7 +253,7 @@ impl FromXml for AutoType {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "AutoType") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "AutoType") {
             return Err(bad_event("Open AutoType tag", open_tag));
         }
 
@@ -240,7 +261,7 @@ impl FromXml for AutoType {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "Enabled" => {
                         out.enabled = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
                     }
@@ -278,7 +299,7 @@ impl FromXml for AutoTypeAssociation {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Association") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "Association") {
             return Err(bad_event("Open Association tag", open_tag));
         }
 
@@ -286,7 +307,7 @@ impl FromXml for AutoTypeAssociation {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "Window" => {
                         let window = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
                         out.window = window.map(|w| decode_xml(&w));
@@ -317,7 +338,7 @@ impl FromXml for History {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "History") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "History") {
             return Err(bad_event("Open History tag", open_tag));
         }
 
@@ -325,7 +346,7 @@ impl FromXml for History {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "Entry" => {
                         let entry = Entry::from_xml(iterator, inner_cipher)?;
                         entries.push(entry);
@@ -1,7 +1,7 @@
 use crate::{
     db::{iconid::IconId, node::NodePtr, CustomData, Entry, Group, Times},
     rc_refcell_node,
-    xml_db::parse::{bad_event, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, XmlParseError},
+    xml_db::parse::{bad_event, unknown_attributes, FromXml, SimpleTag, SimpleXmlEvent, UnknownElement, XmlParseError},
 };
 use std::convert::TryFrom;
 use uuid::Uuid;
@@ -14,63 +14,85 @@ impl FromXml for Group {
         inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
     ) -> Result<Self::Parses, super::XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Group") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "Group") {
             return Err(bad_event("Open Group tag", open_tag));
         }
 
         let mut out = Group::default();
+        if let SimpleXmlEvent::Start(_, attributes, namespace) = open_tag {
+            out.unknown_xml.attributes = unknown_attributes(attributes, &namespace);
+        }
 
+        // the last known child element, after which the unknown elements that follow it are written back
+        let mut preceding = None;
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
-                    "UUID" => {
-                        out.uuid = SimpleTag::<Uuid>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "Name" => {
-                        out.name = SimpleTag::<String>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "Notes" => {
-                        out.notes = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "IconID" => {
-                        out.icon_id = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?
-                            .value
-                            .map(|v| IconId::try_from(v).unwrap_or(IconId::FOLDER));
-                    }
-                    "CustomIconUUID" => {
-                        out.custom_icon_uuid = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "Times" => {
-                        out.times = Times::from_xml(iterator, inner_cipher)?;
-                    }
-                    "IsExpanded" => {
-                        out.is_expanded = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "DefaultAutoTypeSequence" => {
-                        out.default_autotype_sequence = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                SimpleXmlEvent::Start(name, ..) => {
+                    let name = name.clone();
+                    match &name[..] {
+                        "UUID" => {
+                            out.uuid = SimpleTag::<Uuid>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "Name" => {
+                            out.name = SimpleTag::<String>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "Notes" => {
+                            out.notes = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "IconID" => {
+                            out.icon_id = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?
+                                .value
+                                .map(|v| IconId::try_from(v).unwrap_or(IconId::FOLDER));
+                        }
+                        "CustomIconUUID" => {
+                            out.custom_icon_uuid = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "Times" => {
+                            out.times = Times::from_xml(iterator, inner_cipher)?;
+                        }
+                        "IsExpanded" => {
+                            out.is_expanded = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "DefaultAutoTypeSequence" => {
+                            out.default_autotype_sequence = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "EnableAutoType" => {
+                            out.enable_autotype = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "EnableSearching" => {
+                            out.enable_searching = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "LastTopVisibleEntry" => {
+                            out.last_top_visible_entry = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "PreviousParentGroup" => {
+                            out.previous_parent_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "Tags" => {
+                            if let Some(tags) = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value {
+                                out.tags = tags.split([';', ',']).map(std::borrow::ToOwned::to_owned).collect();
+                            }
+                        }
+                        "Entry" => {
+                            let entry = rc_refcell_node!(Entry::from_xml(iterator, inner_cipher)?);
+                            out.children.push(entry.into());
+                        }
+                        "Group" => {
+                            let group = rc_refcell_node!(Group::from_xml(iterator, inner_cipher)?);
+                            out.children.push(group.into());
+                        }
+                        "CustomData" => {
+                            out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
+                        }
+                        _ => {
+                            let mut element = UnknownElement::from_xml(iterator, inner_cipher)?;
+                            element.after = preceding.clone();
+                            out.unknown_xml.elements.push(element);
+                            continue;
+                        }
                     }
-                    "EnableAutoType" => {
-                        out.enable_autotype = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "EnableSearching" => {
-                        out.enable_searching = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "LastTopVisibleEntry" => {
-                        out.last_top_visible_entry = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "Entry" => {
-                        let entry = rc_refcell_node!(Entry::from_xml(iterator, inner_cipher)?);
-                        out.children.push(entry.into());
-                    }
-                    "Group" => {
-                        let group = rc_refcell_node!(Group::from_xml(iterator, inner_cipher)?);
-                        out.children.push(group.into());
-                    }
-                    "CustomData" => {
-                        out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
-                    }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
-                },
+                    preceding = Some(name);
+                }
                 SimpleXmlEvent::End(name) if name == "Group" => break,
                 _ => return Err(bad_event("start tag or close Group", event.clone())),
             }
@@ -107,6 +129,12 @@ mod parse_group_test {
         let value = parse_test_xml::<Group>("<Group><CustomIconUUID>oaKjpLGywcLR0tPU1dbX2A==</CustomIconUUID></Group>")?;
         assert_eq!(value.custom_icon_uuid, Some(uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8")));
 
+        let value = parse_test_xml::<Group>(
+            "<Group><Tags>first;second</Tags><PreviousParentGroup>oaKjpLGywcLR0tPU1dbX2A==</PreviousParentGroup></Group>",
+        )?;
+        assert_eq!(value.tags, vec!["first".to_string(), "second".to_string()]);
+        assert_eq!(value.previous_parent_group, Some(uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8")));
+
         let value = parse_test_xml::<Group>("");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
@@ -119,7 +147,11 @@ mod parse_group_test {
         let value = parse_test_xml::<Group>("<Group>No-Characters-Allowed</Group>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
-        let _value = parse_test_xml::<Group>("<Group><UnkownChildTag/></Group>")?;
+        let value = parse_test_xml::<Group>("<Group Plugin=\"yes\"><UnkownChildTag>Data</UnkownChildTag></Group>")?;
+        assert_eq!(value.unknown_xml.attributes.get("Plugin").map(String::as_str), Some("yes"));
+        assert_eq!(value.unknown_xml.elements.len(), 1);
+        assert_eq!(value.unknown_xml.elements[0].xml, "<UnkownChildTag>Data</UnkownChildTag>");
+        assert_eq!(value.unknown_xml.elements[0].after, None);
 
         Ok(())
     }
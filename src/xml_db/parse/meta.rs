@@ -8,7 +8,9 @@ use crate::{
         meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, Meta},
         Color,
     },
-    xml_db::parse::{bad_event, CustomData, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, XmlParseError},
+    xml_db::parse::{
+        bad_event, unknown_attributes, CustomData, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, UnknownElement, XmlParseError,
+    },
 };
 
 impl FromXml for Meta {
@@ -20,97 +22,116 @@ impl FromXml for Meta {
         inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
     ) -> Result<Self::Parses, crate::xml_db::parse::XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Meta") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "Meta") {
             return Err(bad_event("Open Meta tag", open_tag));
         }
 
         let mut out = Meta::new();
+        if let SimpleXmlEvent::Start(_, attributes, namespace) = open_tag {
+            out.unknown_xml.attributes = unknown_attributes(attributes, &namespace);
+        }
 
+        // the last known child element, after which the unknown elements that follow it are written back
+        let mut preceding = None;
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
-                    "Generator" => {
-                        out.generator = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "DatabaseName" => {
-                        out.database_name = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "DatabaseNameChanged" => {
-                        out.database_name_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "DatabaseDescription" => {
-                        out.database_description = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "DatabaseDescriptionChanged" => {
-                        out.database_description_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "DefaultUserName" => {
-                        out.default_username = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "DefaultUserNameChanged" => {
-                        out.default_username_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "MaintenanceHistoryDays" => {
-                        out.maintenance_history_days = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "Color" => {
-                        out.color = SimpleTag::<Option<Color>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "MasterKeyChanged" => {
-                        out.master_key_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "MasterKeyChangeRec" => {
-                        out.master_key_change_rec = SimpleTag::<Option<isize>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "MasterKeyChangeForce" => {
-                        out.master_key_change_force = SimpleTag::<Option<isize>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "MemoryProtection" => {
-                        out.memory_protection = Some(MemoryProtection::from_xml(iterator, inner_cipher)?);
-                    }
-                    "CustomIcons" => {
-                        out.custom_icons = CustomIcons::from_xml(iterator, inner_cipher)?;
-                    }
-                    "RecycleBinEnabled" => {
-                        out.recyclebin_enabled = SimpleTag::<Option<bool>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "RecycleBinUUID" => {
-                        out.recyclebin_uuid = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "RecycleBinChanged" => {
-                        out.recyclebin_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "EntryTemplatesGroup" => {
-                        out.entry_templates_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "EntryTemplatesGroupChanged" => {
-                        out.entry_templates_group_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "LastSelectedGroup" => {
-                        out.last_selected_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "LastTopVisibleGroup" => {
-                        out.last_top_visible_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "HistoryMaxItems" => {
-                        out.history_max_items = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "HistoryMaxSize" => {
-                        out.history_max_size = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "SettingsChanged" => {
-                        out.settings_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
-                    }
-                    "Binaries" => {
-                        out.binaries = BinaryAttachments::from_xml(iterator, inner_cipher)?;
-                        // TODO figure out where this is needed. Is it only in KDBX3? How to
-                        // migrate to KDBX4?
-                    }
-                    "CustomData" => {
-                        out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
-                    }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
-                },
+                SimpleXmlEvent::Start(name, ..) => {
+                    let name = name.clone();
+                    match &name[..] {
+                        "Generator" => {
+                            out.generator = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "HeaderHash" => {
+                            if let Some(data) = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value {
+                                out.header_hash = Some(base64_engine::STANDARD.decode(data)?);
+                            }
+                        }
+                        "DatabaseName" => {
+                            out.database_name = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "DatabaseNameChanged" => {
+                            out.database_name_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "DatabaseDescription" => {
+                            out.database_description = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "DatabaseDescriptionChanged" => {
+                            out.database_description_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "DefaultUserName" => {
+                            out.default_username = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "DefaultUserNameChanged" => {
+                            out.default_username_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "MaintenanceHistoryDays" => {
+                            out.maintenance_history_days = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "Color" => {
+                            out.color = SimpleTag::<Option<Color>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "MasterKeyChanged" => {
+                            out.master_key_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "MasterKeyChangeRec" => {
+                            out.master_key_change_rec = SimpleTag::<Option<isize>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "MasterKeyChangeForce" => {
+                            out.master_key_change_force = SimpleTag::<Option<isize>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "MemoryProtection" => {
+                            out.memory_protection = Some(MemoryProtection::from_xml(iterator, inner_cipher)?);
+                        }
+                        "CustomIcons" => {
+                            out.custom_icons = CustomIcons::from_xml(iterator, inner_cipher)?;
+                        }
+                        "RecycleBinEnabled" => {
+                            out.recyclebin_enabled = SimpleTag::<Option<bool>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "RecycleBinUUID" => {
+                            out.recyclebin_uuid = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "RecycleBinChanged" => {
+                            out.recyclebin_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "EntryTemplatesGroup" => {
+                            out.entry_templates_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "EntryTemplatesGroupChanged" => {
+                            out.entry_templates_group_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "LastSelectedGroup" => {
+                            out.last_selected_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "LastTopVisibleGroup" => {
+                            out.last_top_visible_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "HistoryMaxItems" => {
+                            out.history_max_items = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "HistoryMaxSize" => {
+                            out.history_max_size = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "SettingsChanged" => {
+                            out.settings_changed = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                        }
+                        "Binaries" => {
+                            out.binaries = BinaryAttachments::from_xml(iterator, inner_cipher)?;
+                            // TODO figure out where this is needed. Is it only in KDBX3? How to
+                            // migrate to KDBX4?
+                        }
+                        "CustomData" => {
+                            out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
+                        }
+                        _ => {
+                            let mut element = UnknownElement::from_xml(iterator, inner_cipher)?;
+                            element.after = preceding.clone();
+                            out.unknown_xml.elements.push(element);
+                            continue;
+                        }
+                    }
+                    preceding = Some(name);
+                }
                 SimpleXmlEvent::End(name) if name == "Meta" => break,
                 _ => return Err(bad_event("start tag or close Meta", event.clone())),
             }
@@ -131,7 +152,7 @@ impl FromXml for MemoryProtection {
         inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "MemoryProtection") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "MemoryProtection") {
             return Err(bad_event("Open MemoryProtection tag", open_tag));
         }
 
@@ -139,7 +160,7 @@ impl FromXml for MemoryProtection {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "ProtectTitle" => {
                         out.protect_title = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
                     }
@@ -177,7 +198,7 @@ impl FromXml for BinaryAttachments {
         inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Binaries") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "Binaries") {
             return Err(bad_event("Open Binaries tag", open_tag));
         }
 
@@ -185,7 +206,7 @@ impl FromXml for BinaryAttachments {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "Binary" => {
                         let binary = BinaryAttachment::from_xml(iterator, inner_cipher)?;
                         out.binaries.push(binary);
@@ -214,7 +235,7 @@ impl FromXml for BinaryAttachment {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
 
         let mut out = BinaryAttachment::default();
-        let (identifier, compressed) = if let SimpleXmlEvent::Start(ref name, ref attributes) = open_tag {
+        let (identifier, compressed, protected) = if let SimpleXmlEvent::Start(ref name, ref attributes, _) = open_tag {
             if name != "Binary" {
                 return Err(bad_event("Open Binary tag", open_tag));
             }
@@ -223,16 +244,21 @@ impl FromXml for BinaryAttachment {
 
             let compressed = attributes.get("Compressed").map_or(Ok(false), |v| v.to_lowercase().parse())?;
 
-            (identifier, compressed)
+            let protected = attributes.get("Protected").map_or(Ok(false), |v| v.to_lowercase().parse())?;
+
+            (identifier, compressed, protected)
         } else {
             return Err(bad_event("Open Binary tag", open_tag));
         };
 
         let data = String::from_xml(iterator, inner_cipher)?;
         let buf = base64_engine::STANDARD.decode(data)?;
+        // protected binaries take their part of the inner cipher stream in document order
+        let buf = if protected { inner_cipher.decrypt(&buf)? } else { buf };
 
         out.identifier = identifier;
         out.compressed = compressed;
+        out.protected = protected;
         out.content = if compressed {
             Compression::decompress(&GZipCompression, &buf).map_err(XmlParseError::Compression)?
         } else {
@@ -254,7 +280,7 @@ impl FromXml for CustomIcons {
         inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "CustomIcons") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "CustomIcons") {
             return Err(bad_event("Open CustomIcons tag", open_tag));
         }
 
@@ -262,7 +288,7 @@ impl FromXml for CustomIcons {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "Icon" => {
                         let icon = Icon::from_xml(iterator, inner_cipher)?;
                         out.icons.push(icon);
@@ -289,7 +315,7 @@ impl FromXml for Icon {
         inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Icon") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "Icon") {
             return Err(bad_event("Open Icon tag", open_tag));
         }
 
@@ -297,7 +323,7 @@ impl FromXml for Icon {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "UUID" => {
                         out.uuid = SimpleTag::<Uuid>::from_xml(iterator, inner_cipher)?.value;
                     }
@@ -306,6 +332,12 @@ impl FromXml for Icon {
                         let buf = base64_engine::STANDARD.decode(&data)?;
                         out.data = buf;
                     }
+                    "Name" => {
+                        out.name = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                    }
+                    "LastModificationTime" => {
+                        out.last_modification_time = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                    }
                     _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
                 },
                 SimpleXmlEvent::End(name) if name == "Icon" => break,
@@ -324,9 +356,15 @@ impl FromXml for Icon {
 mod parse_meta_test {
 
     use crate::{
-        db::meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, Meta},
-        xml_db::parse::{parse_test::parse_test_xml, XmlParseError},
+        config::InnerCipherConfig,
+        db::{
+            meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, Meta},
+            Value,
+        },
+        limits::OpenLimits,
+        xml_db::parse::{parse_from_reader, parse_test::parse_test_xml, XmlParseError},
     };
+    use base64::{engine::general_purpose as base64_engine, Engine as _};
     use uuid::{uuid, Uuid};
 
     #[test]
@@ -383,6 +421,29 @@ mod parse_meta_test {
         Ok(())
     }
 
+    #[test]
+    fn test_protected_binary_attachment() -> Result<(), XmlParseError> {
+        let key = [0x42; 32];
+        let mut cipher = InnerCipherConfig::Salsa20.get_cipher(&key).unwrap();
+        let binary = base64_engine::STANDARD.encode(cipher.encrypt(b"Binary Data").unwrap());
+        let password = base64_engine::STANDARD.encode(cipher.encrypt(b"secret").unwrap());
+
+        // the protected value after the binary is only readable if the binary advanced the cipher stream
+        let xml = format!(
+            "<Meta><Binaries><Binary ID=\"0\" Protected=\"True\">{binary}</Binary></Binaries>\
+             <CustomData><Item><Key>Password</Key><Value Protected=\"True\">{password}</Value></Item></CustomData></Meta>"
+        );
+        let mut cipher = InnerCipherConfig::Salsa20.get_cipher(&key).unwrap();
+        let value = parse_from_reader::<Meta, _>(xml.as_bytes(), &mut *cipher, &OpenLimits::default())?;
+
+        let binary = &value.binaries.binaries[0];
+        assert!(binary.protected);
+        assert_eq!(binary.content, b"Binary Data");
+        assert_eq!(value.custom_data.items["Password"].value, Some(Value::Protected("secret".into())));
+
+        Ok(())
+    }
+
     #[test]
     fn test_binary_attachment() -> Result<(), XmlParseError> {
         let value = parse_test_xml::<BinaryAttachment>("<Binary ID=\"1\">QmluYXJ5IERhdGE=</Binary>")?;
@@ -2,17 +2,31 @@ mod entry;
 mod group;
 mod meta;
 
-use std::{collections::HashMap, iter::Peekable};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufReader, Read},
+    iter::Peekable,
+};
 
 use base64::{engine::general_purpose as base64_engine, Engine as _};
 use chrono::NaiveDateTime;
 use uuid::Uuid;
-use xml::{name::OwnedName, reader::XmlEvent, EventReader};
+use xml::{
+    name::OwnedName,
+    namespace::{Namespace, NS_NO_PREFIX, NS_XMLNS_PREFIX, NS_XML_PREFIX},
+    reader::XmlEvent,
+    writer::{EventWriter, XmlEvent as WriterEvent},
+    EmitterConfig, EventReader,
+};
 
 use crate::{
     crypt::ciphers::Cipher,
-    db::{Color, CustomData, CustomDataItem, CustomDataItemDenormalized, DeletedObject, DeletedObjects, Group, Meta, Times, Value},
+    db::{
+        Color, CustomData, CustomDataItem, CustomDataItemDenormalized, DeletedObject, DeletedObjects, Entry, Group, Meta, Times,
+        UnknownXml, UnknownXmlElement, Value,
+    },
     error::XmlParseError,
+    limits::{OpenLimits, XmlLimitTracker},
     xml_db::get_epoch_baseline,
 };
 
@@ -24,10 +38,11 @@ pub fn xml_timestamp(t: &str) -> Result<chrono::NaiveDateTime, XmlParseError> {
         let v = base64_engine::STANDARD.decode(t)?;
 
         // Cast the decoded base64 Vec into the array expected by i64::from_le_bytes
-        let mut a: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
-        a.copy_from_slice(&v[0..8]);
-        let ndt = get_epoch_baseline() + chrono::Duration::seconds(i64::from_le_bytes(a));
-        Ok(ndt)
+        let invalid = || XmlParseError::InvalidTimestamp(t.to_string());
+        let a: [u8; 8] = v.get(0..8).and_then(|v| v.try_into().ok()).ok_or_else(invalid)?;
+        chrono::Duration::try_seconds(i64::from_le_bytes(a))
+            .and_then(|seconds| get_epoch_baseline().checked_add_signed(seconds))
+            .ok_or_else(invalid)
     }
 }
 
@@ -50,7 +65,8 @@ pub(crate) trait FromXml {
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[must_use]
 pub enum SimpleXmlEvent {
-    Start(String, HashMap<String, String>),
+    /// An element with its attributes and the namespaces in scope, by their qualified names
+    Start(String, HashMap<String, String>, Namespace),
     End(String),
     Characters(String),
     Err(xml::reader::Error),
@@ -60,27 +76,90 @@ pub(crate) fn bad_event(expected: &'static str, event: SimpleXmlEvent) -> XmlPar
     XmlParseError::BadEvent { expected, event }
 }
 
-pub(crate) fn parse(xml: &[u8], inner_cipher: &mut dyn Cipher) -> Result<KeePassXml, XmlParseError> {
-    parse_from_bytes::<KeePassXml>(xml, inner_cipher)
+pub(crate) fn parse<R: Read>(xml: R, inner_cipher: &mut dyn Cipher, limits: &OpenLimits) -> Result<KeePassXml, XmlParseError> {
+    parse_from_reader::<KeePassXml, R>(xml, inner_cipher, limits)
+}
+
+/// Fail with the first unknown attribute or element of the meta data, the `Root` element or the
+/// groups and entries below `group`, for opening databases in strict mode
+pub(crate) fn reject_unknown_xml(meta: &UnknownXml, root: &UnknownXml, group: &Group) -> Result<(), XmlParseError> {
+    check_unknown_xml("Meta", meta)?;
+    check_unknown_xml("Root", root)?;
+    check_group_unknown_xml(group)
+}
+
+fn check_unknown_xml(parent: &'static str, unknown_xml: &UnknownXml) -> Result<(), XmlParseError> {
+    let attribute = unknown_xml.attributes.iter().map(|(name, value)| format!("{name}=\"{value}\""));
+    match attribute.chain(unknown_xml.elements.iter().map(|e| e.xml.clone())).next() {
+        Some(xml) => Err(XmlParseError::UnknownXml { parent, xml }),
+        None => Ok(()),
+    }
+}
+
+fn check_entry_unknown_xml(entry: &Entry) -> Result<(), XmlParseError> {
+    check_unknown_xml("Entry", &entry.unknown_xml)?;
+    if let Some(history) = &entry.history {
+        history.entries.iter().try_for_each(check_entry_unknown_xml)?;
+    }
+    Ok(())
+}
+
+fn check_group_unknown_xml(group: &Group) -> Result<(), XmlParseError> {
+    check_unknown_xml("Group", &group.unknown_xml)?;
+    for child in &group.children {
+        let child = child.borrow();
+        if let Some(group) = child.as_any().downcast_ref::<Group>() {
+            check_group_unknown_xml(group)?;
+        } else if let Some(entry) = child.as_any().downcast_ref::<Entry>() {
+            check_entry_unknown_xml(entry)?;
+        }
+    }
+    Ok(())
 }
 
-pub(crate) fn parse_from_bytes<P: FromXml>(xml: &[u8], inner_cipher: &mut dyn Cipher) -> Result<<P as FromXml>::Parses, XmlParseError> {
-    let mut reader = EventReader::new(xml)
+/// Parse an XML document while it is being read, so that it never has to be held in memory at once.
+///
+/// Parsing stops as soon as the document exceeds the nesting depth or the number of entries
+/// allowed by `limits`.
+pub(crate) fn parse_from_reader<P: FromXml, R: Read>(
+    xml: R,
+    inner_cipher: &mut dyn Cipher,
+    limits: &OpenLimits,
+) -> Result<<P as FromXml>::Parses, XmlParseError> {
+    let mut tracker = XmlLimitTracker::default();
+    let mut limit_error = None;
+    let mut reader = EventReader::new(BufReader::new(xml))
         .into_iter()
+        .map_while(|e| {
+            let checked = match &e {
+                Ok(XmlEvent::StartElement { name, .. }) => tracker.start_element(&name.local_name, limits),
+                Ok(XmlEvent::EndElement { .. }) => {
+                    tracker.end_element();
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            match checked {
+                Ok(()) => Some(e),
+                Err(err) => {
+                    limit_error = Some(err);
+                    None
+                }
+            }
+        })
         .filter_map(|e| {
             // simplify iterator by ignoring unneeded events and flattening the structure
             match e {
                 Ok(XmlEvent::StartElement {
-                    name: OwnedName { local_name, .. },
+                    name,
                     attributes,
-                    ..
+                    namespace,
                 }) => Some(SimpleXmlEvent::Start(
-                    local_name,
-                    attributes.into_iter().map(|a| (a.name.local_name, a.value)).collect(),
+                    qualified_name(&name),
+                    attributes.iter().map(|a| (qualified_name(&a.name), a.value.clone())).collect(),
+                    namespace,
                 )),
-                Ok(XmlEvent::EndElement {
-                    name: OwnedName { local_name, .. },
-                }) => Some(SimpleXmlEvent::End(local_name)),
+                Ok(XmlEvent::EndElement { name }) => Some(SimpleXmlEvent::End(qualified_name(&name))),
                 Ok(XmlEvent::Characters(c)) => Some(SimpleXmlEvent::Characters(c)),
                 Err(e) => Some(SimpleXmlEvent::Err(e)),
 
@@ -90,7 +169,81 @@ pub(crate) fn parse_from_bytes<P: FromXml>(xml: &[u8], inner_cipher: &mut dyn Ci
         })
         .peekable();
 
-    P::from_xml(&mut reader, inner_cipher)
+    let parsed = P::from_xml(&mut reader, inner_cipher);
+    drop(reader);
+
+    match limit_error {
+        Some(err) => Err(err.into()),
+        None => parsed,
+    }
+}
+
+/// The name of an element or attribute with its namespace prefix, as it is written in the document
+pub(crate) fn qualified_name(name: &OwnedName) -> String {
+    match name.prefix {
+        Some(ref prefix) => format!("{prefix}:{}", name.local_name),
+        None => name.local_name.clone(),
+    }
+}
+
+/// The `xmlns` declarations of the namespaces in `namespace` that an element named `name` and its
+/// attributes use, by their qualified names. They are written on elements that are captured as
+/// unknown fragments, so that the fragments can be written back outside of the scope of their
+/// ancestors. Without a `name`, only the namespaces of the attributes are declared.
+pub(crate) fn namespace_declarations<'a>(
+    name: Option<&str>,
+    attribute_names: impl Iterator<Item = &'a str>,
+    namespace: &Namespace,
+) -> BTreeMap<String, String> {
+    let mut declarations = BTreeMap::new();
+    let element_prefix = name.and_then(|name| name.split_once(':')).map(|(prefix, _)| prefix);
+    // only elements are in the default namespace, unprefixed attributes have no namespace
+    if name.is_some() && element_prefix.is_none() {
+        if let Some(uri) = namespace.get(NS_NO_PREFIX).filter(|uri| !uri.is_empty()) {
+            declarations.insert("xmlns".to_string(), uri.to_string());
+        }
+    }
+    let attribute_prefixes = attribute_names.filter_map(|name| name.split_once(':').map(|(prefix, _)| prefix));
+    for prefix in element_prefix.into_iter().chain(attribute_prefixes) {
+        if matches!(prefix, NS_XML_PREFIX | NS_XMLNS_PREFIX) {
+            continue;
+        }
+        if let Some(uri) = namespace.get(prefix) {
+            declarations.insert(format!("xmlns:{prefix}"), uri.to_string());
+        }
+    }
+    declarations
+}
+
+/// The attributes of a known element that are not known to this crate, together with the
+/// declarations of the namespaces they use
+pub(crate) fn unknown_attributes(attributes: HashMap<String, String>, namespace: &Namespace) -> BTreeMap<String, String> {
+    let declarations = namespace_declarations(None, attributes.keys().map(String::as_str), namespace);
+    attributes.into_iter().chain(declarations).collect()
+}
+
+/// The namespace declarations in scope while a captured fragment is written, so that each element
+/// only declares the namespaces that the elements around it in the fragment did not declare already
+#[derive(Default)]
+pub(crate) struct FragmentNamespaces(Vec<BTreeMap<String, String>>);
+
+impl FragmentNamespaces {
+    /// Enter an element that needs `declarations` and return the ones it has to write itself
+    pub(crate) fn enter(&mut self, declarations: BTreeMap<String, String>) -> BTreeMap<String, String> {
+        let mut scope = self.0.last().cloned().unwrap_or_default();
+        let missing: BTreeMap<String, String> = declarations
+            .into_iter()
+            .filter(|(name, uri)| scope.get(name) != Some(uri))
+            .collect();
+        scope.extend(missing.clone());
+        self.0.push(scope);
+        missing
+    }
+
+    /// Leave the innermost element
+    pub(crate) fn leave(&mut self) {
+        self.0.pop();
+    }
 }
 
 /// Helper trait for converting `SimpleXmlEvent::Characters` into types that can be parsed from
@@ -195,7 +348,7 @@ impl<V: FromXml> FromXml for SimpleTag<V> {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if let SimpleXmlEvent::Start(name, _) = open_tag {
+        if let SimpleXmlEvent::Start(name, ..) = open_tag {
             let value = V::from_xml(iterator, inner_cipher)?;
 
             let close_tag = iterator.next().ok_or(XmlParseError::Eof)?;
@@ -224,7 +377,7 @@ impl FromXml for KeePassXml {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "KeePassFile") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "KeePassFile") {
             return Err(bad_event("Open KeePassFile tag", open_tag));
         }
 
@@ -232,7 +385,7 @@ impl FromXml for KeePassXml {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "Meta" => {
                         out.meta = Meta::from_xml(iterator, inner_cipher)?;
                     }
@@ -261,7 +414,7 @@ impl FromXml for Times {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Times") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "Times") {
             return Err(bad_event("Open Times tag", open_tag));
         }
 
@@ -269,7 +422,7 @@ impl FromXml for Times {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "Expires" => {
                         out.expires = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
                     }
@@ -298,6 +451,7 @@ impl FromXml for Times {
 pub(crate) struct Root {
     pub(crate) group: Group,
     pub(crate) deleted_objects: DeletedObjects,
+    pub(crate) unknown_xml: UnknownXml,
 }
 
 impl FromXml for Root {
@@ -308,23 +462,37 @@ impl FromXml for Root {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Root") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "Root") {
             return Err(bad_event("Open Root tag", open_tag));
         }
 
         let mut out = Root::default();
+        if let SimpleXmlEvent::Start(_, attributes, namespace) = open_tag {
+            out.unknown_xml.attributes = unknown_attributes(attributes, &namespace);
+        }
 
+        // the last known child element, after which the unknown elements that follow it are written back
+        let mut preceding = None;
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
-                    "Group" => {
-                        out.group = Group::from_xml(iterator, inner_cipher)?;
-                    }
-                    "DeletedObjects" => {
-                        out.deleted_objects = DeletedObjects::from_xml(iterator, inner_cipher)?;
+                SimpleXmlEvent::Start(name, ..) => {
+                    let name = name.clone();
+                    match &name[..] {
+                        "Group" => {
+                            out.group = Group::from_xml(iterator, inner_cipher)?;
+                        }
+                        "DeletedObjects" => {
+                            out.deleted_objects = DeletedObjects::from_xml(iterator, inner_cipher)?;
+                        }
+                        _ => {
+                            let mut element = UnknownElement::from_xml(iterator, inner_cipher)?;
+                            element.after = preceding.clone();
+                            out.unknown_xml.elements.push(element);
+                            continue;
+                        }
                     }
-                    _ => return Err(bad_event("valid Root child", event.clone())),
-                },
+                    preceding = Some(name);
+                }
                 SimpleXmlEvent::End(name) if name == "Root" => break,
                 _ => return Err(bad_event("start tag or close Root", event.clone())),
             }
@@ -345,7 +513,7 @@ impl FromXml for DeletedObjects {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "DeletedObjects") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "DeletedObjects") {
             return Err(bad_event("Open DeletedObjects tag", open_tag));
         }
 
@@ -353,7 +521,7 @@ impl FromXml for DeletedObjects {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) if name == "DeletedObject" => {
+                SimpleXmlEvent::Start(name, ..) if name == "DeletedObject" => {
                     let object = DeletedObject::from_xml(iterator, inner_cipher)?;
                     out.objects.push(object);
                 }
@@ -377,7 +545,7 @@ impl FromXml for DeletedObject {
         inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "DeletedObject") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "DeletedObject") {
             return Err(bad_event("Open DeletedObject tag", open_tag));
         }
 
@@ -385,7 +553,7 @@ impl FromXml for DeletedObject {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "UUID" => {
                         out.uuid = SimpleTag::<Uuid>::from_xml(iterator, inner_cipher)?.value;
                     }
@@ -414,7 +582,7 @@ impl FromXml for CustomData {
         inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "CustomData") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "CustomData") {
             return Err(bad_event("Open CustomData tag", open_tag));
         }
 
@@ -422,7 +590,7 @@ impl FromXml for CustomData {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "Item" => {
                         let item = CustomDataItemDenormalized::from_xml(iterator, inner_cipher)?;
                         out.items.insert(
@@ -455,7 +623,7 @@ impl FromXml for CustomDataItemDenormalized {
         inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Item") {
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, ..) if tag == "Item") {
             return Err(bad_event("Open Item tag", open_tag));
         }
 
@@ -463,7 +631,7 @@ impl FromXml for CustomDataItemDenormalized {
 
         while let Some(event) = iterator.peek() {
             match event {
-                SimpleXmlEvent::Start(name, _) => match &name[..] {
+                SimpleXmlEvent::Start(name, ..) => match &name[..] {
                     "Key" => {
                         out.key = SimpleTag::<String>::from_xml(iterator, inner_cipher)?.value;
                     }
@@ -499,12 +667,12 @@ impl FromXml for IgnoreSubfield {
         _inner_cipher: &mut dyn Cipher,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
-        if let SimpleXmlEvent::Start(_, _) = open_tag {
+        if let SimpleXmlEvent::Start(..) = open_tag {
             let mut stack = Vec::new();
 
             for event in iterator.by_ref() {
                 match event {
-                    SimpleXmlEvent::Start(t, _) => stack.push(t),
+                    SimpleXmlEvent::Start(t, ..) => stack.push(t),
                     SimpleXmlEvent::End(_) => {
                         // ascend the stack of inner elements. matching closing tag is ensured
                         // by XmlReader
@@ -525,28 +693,207 @@ impl FromXml for IgnoreSubfield {
     }
 }
 
+/// A helper parser that captures an element that is not known to this crate as an XML fragment, so
+/// that it can be written back when the database is saved.
+pub(crate) struct UnknownElement;
+
+impl FromXml for UnknownElement {
+    type Parses = UnknownXmlElement;
+
+    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
+        iterator: &mut Peekable<I>,
+        inner_cipher: &mut dyn Cipher,
+    ) -> Result<Self::Parses, XmlParseError> {
+        if !matches!(iterator.peek(), Some(SimpleXmlEvent::Start(..))) {
+            let event = iterator.next().ok_or(XmlParseError::Eof)?;
+            return Err(bad_event("Open tag (to be preserved)", event));
+        }
+
+        let mut out = UnknownXmlElement::default();
+        let mut fragment = Vec::new();
+        let mut writer = EmitterConfig::new()
+            .write_document_declaration(false)
+            .perform_indent(false)
+            .create_writer(&mut fragment);
+
+        // the decrypted values of the currently open elements that are protected by the inner cipher
+        let mut protected: Vec<Option<String>> = Vec::new();
+        let mut namespaces = FragmentNamespaces::default();
+
+        loop {
+            let event = iterator.next().ok_or(XmlParseError::Eof)?;
+            match event {
+                SimpleXmlEvent::Start(name, attributes, namespace) => {
+                    let is_protected = attributes.get("Protected").is_some_and(|v| v.eq_ignore_ascii_case("true"));
+                    protected.push(is_protected.then(String::new));
+                    let declarations = namespace_declarations(Some(&name), attributes.keys().map(String::as_str), &namespace);
+                    let mut attributes: BTreeMap<String, String> = attributes.into_iter().collect();
+                    attributes.extend(namespaces.enter(declarations));
+                    let element = attributes
+                        .iter()
+                        .fold(WriterEvent::start_element(name.as_str()), |element, (name, value)| {
+                            element.attr(name.as_str(), value)
+                        });
+                    write_fragment_event(&mut writer, element);
+                }
+                SimpleXmlEvent::End(_) => {
+                    // matching closing tag is ensured by XmlReader
+                    write_fragment_event(&mut writer, WriterEvent::end_element());
+                    namespaces.leave();
+                    if let Some(Some(value)) = protected.pop() {
+                        out.protected_values.push(value.into());
+                    }
+                    if protected.is_empty() {
+                        break;
+                    }
+                }
+                SimpleXmlEvent::Characters(text) => {
+                    if let Some(Some(value)) = protected.last_mut() {
+                        // the value is kept protected outside of the fragment
+                        let buf = base64_engine::STANDARD.decode(&text)?;
+                        let buf_decrypted = inner_cipher.decrypt(&buf)?;
+                        value.push_str(&String::from_utf8_lossy(&buf_decrypted));
+                    } else {
+                        write_fragment_event(&mut writer, WriterEvent::characters(&text));
+                    }
+                }
+                SimpleXmlEvent::Err(e) => return Err(e.into()),
+            }
+        }
+
+        drop(writer);
+        out.xml = String::from_utf8_lossy(&fragment).into_owned();
+        Ok(out)
+    }
+}
+
+fn write_fragment_event<'a, E: Into<WriterEvent<'a>>>(writer: &mut EventWriter<&mut Vec<u8>>, event: E) {
+    // the events are well-formed and written to memory, so this does not fail
+    writer.write(event).expect("Write XML fragment");
+}
+
 #[cfg(test)]
 mod parse_test {
     use crate::{
         config::InnerCipherConfig,
         crypt::ciphers::PlainCipher,
-        db::{AutoType, AutoTypeAssociation, CustomData, CustomDataItemDenormalized, Entry, History, Times, Value},
+        db::{AutoType, AutoTypeAssociation, CustomData, CustomDataItemDenormalized, Entry, History, Meta, Times, Value},
+        error::ResourceLimitError,
+        limits::OpenLimits,
         xml_db::parse::{entry::StringField, DeletedObject, DeletedObjects, IgnoreSubfield, Root},
     };
 
-    use super::{entry::BinaryField, parse, parse_from_bytes, FromXml, KeePassXml, SimpleTag, XmlParseError};
+    use super::{
+        entry::BinaryField, parse, parse_from_reader, reject_unknown_xml, FromXml, KeePassXml, SimpleTag, UnknownElement, XmlParseError,
+    };
 
     pub(crate) fn parse_test_xml<P: FromXml>(xml: &str) -> Result<<P as FromXml>::Parses, XmlParseError> {
-        parse_from_bytes::<P>(xml.as_bytes(), &mut PlainCipher)
+        parse_from_reader::<P, _>(xml.as_bytes(), &mut PlainCipher, &OpenLimits::default())
     }
 
     #[test]
     fn test_custom_xml_fields() -> Result<(), XmlParseError> {
         let xml = include_bytes!("../../../tests/resources/inner_xml_with_custom_fields.xml");
 
-        let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]);
+        let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
+
+        let _database_content = parse(&xml[..], &mut *inner_cipher, &OpenLimits::default())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml_limits() {
+        let xml = include_bytes!("../../../tests/resources/inner_xml_with_custom_fields.xml");
+
+        let limits = OpenLimits {
+            max_xml_depth: Some(3),
+            ..OpenLimits::default()
+        };
+        let result = parse(&xml[..], &mut PlainCipher, &limits);
+        assert!(matches!(
+            result,
+            Err(XmlParseError::ResourceLimit(ResourceLimitError::XmlDepth { limit: 3 }))
+        ));
+
+        let limits = OpenLimits {
+            max_entries: Some(0),
+            ..OpenLimits::default()
+        };
+        let result = parse(&xml[..], &mut PlainCipher, &limits);
+        assert!(matches!(
+            result,
+            Err(XmlParseError::ResourceLimit(ResourceLimitError::Entries { limit: 0 }))
+        ));
+    }
 
-        let _database_content = parse(&xml[..], &mut *inner_cipher)?;
+    #[test]
+    fn test_unknown_xml() -> Result<(), XmlParseError> {
+        let value = parse_test_xml::<UnknownElement>("<Plugin b=\"2\" a=\"1\"><Setting>x &amp; y</Setting><!-- a comment --></Plugin>")?;
+        assert_eq!(value.xml, "<Plugin a=\"1\" b=\"2\"><Setting>x &amp; y</Setting></Plugin>");
+
+        // protected values are kept out of the fragment
+        let value =
+            parse_test_xml::<UnknownElement>("<Passkey><Key Protected=\"True\">c2VjcmV0</Key><Empty Protected=\"True\"/></Passkey>")?;
+        assert_eq!(
+            value.xml,
+            "<Passkey><Key Protected=\"True\" /><Empty Protected=\"True\" /></Passkey>"
+        );
+        assert_eq!(value.protected_values, vec!["secret".into(), "".into()]);
+
+        // the namespaces declared by ancestors are declared by the fragment itself
+        let value = parse_test_xml::<Meta>(
+            "<Meta xmlns:p=\"urn:plugin\" xmlns:q=\"urn:unused\" p:Version=\"5\"><Generator>Test</Generator>\
+             <p:Settings xmlns=\"urn:default\"><Theme p:mode=\"dark\"/></p:Settings></Meta>",
+        )?;
+        assert_eq!(
+            value.unknown_xml.attributes.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("p:Version".to_string(), "5".to_string()),
+                ("xmlns:p".to_string(), "urn:plugin".to_string())
+            ]
+        );
+        assert_eq!(value.unknown_xml.elements[0].after.as_deref(), Some("Generator"));
+        assert_eq!(
+            value.unknown_xml.elements[0].xml,
+            "<p:Settings xmlns:p=\"urn:plugin\"><Theme p:mode=\"dark\" xmlns=\"urn:default\" /></p:Settings>"
+        );
+
+        // known elements do not pick up the default namespace of the document
+        let value = parse_test_xml::<Meta>("<Meta xmlns=\"urn:keepass\"><Generator>Test</Generator><Plugin><Option/></Plugin></Meta>")?;
+        assert!(value.unknown_xml.attributes.is_empty());
+        assert_eq!(
+            value.unknown_xml.elements[0].xml,
+            "<Plugin xmlns=\"urn:keepass\"><Option /></Plugin>"
+        );
+
+        let value = parse_test_xml::<UnknownElement>("Not a tag");
+        assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
+
+        let xml = "<KeePassFile><Meta Version=\"5\"><Generator>Test</Generator><PluginSettings/></Meta>\
+                   <Root><Group><Name>Root</Name><Entry><Future>Value</Future></Entry></Group><Recent/></Root></KeePassFile>";
+
+        let value = parse(xml.as_bytes(), &mut PlainCipher, &OpenLimits::default())?;
+        assert_eq!(value.meta.unknown_xml.attributes.get("Version").map(String::as_str), Some("5"));
+        assert_eq!(value.meta.unknown_xml.elements.len(), 1);
+        assert_eq!(value.root.unknown_xml.elements.len(), 1);
+
+        let strict_parse = |xml: &str| {
+            let value = parse(xml.as_bytes(), &mut PlainCipher, &OpenLimits::default())?;
+            reject_unknown_xml(&value.meta.unknown_xml, &value.root.unknown_xml, &value.root.group)
+        };
+        let value = strict_parse(xml);
+        assert!(matches!(value, Err(XmlParseError::UnknownXml { parent: "Meta", xml }) if xml == "Version=\"5\""));
+
+        let xml = xml
+            .replace(" Version=\"5\"", "")
+            .replace("<PluginSettings/>", "")
+            .replace("<Recent/>", "");
+        let value = strict_parse(&xml);
+        assert!(matches!(value, Err(XmlParseError::UnknownXml { parent: "Entry", xml }) if xml == "<Future>Value</Future>"));
+
+        let xml = xml.replace("<Future>Value</Future>", "");
+        strict_parse(&xml)?;
 
         Ok(())
     }
@@ -644,6 +991,13 @@ mod parse_test {
         let value = parse_test_xml::<Times>("<Times>No-Characters-Allowed</Times>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
+        // binary timestamps that are too short or out of range
+        let value = parse_test_xml::<Times>("<Times><TestTime>AAAA</TestTime></Times>");
+        assert!(matches!(value, Err(XmlParseError::InvalidTimestamp(_))));
+
+        let value = parse_test_xml::<Times>("<Times><TestTime>/////////38=</TestTime></Times>");
+        assert!(matches!(value, Err(XmlParseError::InvalidTimestamp(_))));
+
         Ok(())
     }
 
@@ -658,8 +1012,9 @@ mod parse_test {
         let value = parse_test_xml::<Root>("<Root>No-Characters-Allowed</Root>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
-        let value = parse_test_xml::<Root>("<Root><UnkownChildTag/></Root>");
-        assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
+        // unknown children are kept instead
+        let value = parse_test_xml::<Root>("<Root><UnkownChildTag/></Root>")?;
+        assert_eq!(value.unknown_xml.elements.len(), 1);
 
         Ok(())
     }
@@ -3,8 +3,13 @@ mod file_read_tests {
     use keepass_ng::ChallengeResponseKey;
     use keepass_ng::{
         db::{Database, Entry, Group, Node, NodeIterator, NodePtr},
-        error::{DatabaseIntegrityError, DatabaseOpenError},
-        group_get_children, DatabaseKey,
+        error::{
+            BlockStreamError, DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError, ResourceLimitError, VariantDictionaryError,
+        },
+        group_get_children,
+        limits::OpenLimits,
+        progress::{CancellationToken, Progress, ProgressStage},
+        DatabaseKey, PasswordEncoding,
     };
     use std::{fs::File, path::Path};
     use uuid::uuid;
@@ -258,6 +263,77 @@ mod file_read_tests {
         Database::open(&mut File::open(path).unwrap(), key).unwrap();
     }
 
+    fn open_broken(name: &str) -> DatabaseIntegrityError {
+        let path = Path::new("tests/resources").join(name);
+        let key = DatabaseKey::new().with_password("demopass");
+        match Database::open(&mut File::open(path).unwrap(), key) {
+            Err(DatabaseOpenError::DatabaseIntegrity(e)) => e,
+            other => panic!("{name}: expected an integrity error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn open_broken_kdb_truncated_field() {
+        let error = open_broken("broken_kdb_truncated_field.kdb");
+        assert!(matches!(error, DatabaseIntegrityError::IncompleteKDBField { field_size: 106, .. }));
+    }
+
+    #[test]
+    fn open_broken_kdbx3_files() {
+        let error = open_broken("broken_kdbx3_truncated_block.kdbx");
+        assert!(matches!(
+            error,
+            DatabaseIntegrityError::BlockStream(BlockStreamError::IncompleteBlock { block_index: 0 })
+        ));
+
+        let error = open_broken("broken_kdbx3_short_transform_seed.kdbx");
+        assert!(matches!(error, DatabaseIntegrityError::Cryptography(_)));
+    }
+
+    #[test]
+    fn open_broken_kdbx4_files() {
+        let error = open_broken("broken_kdbx4_short_compression_flags.kdbx");
+        assert!(matches!(
+            error,
+            DatabaseIntegrityError::InvalidHeaderFieldLength {
+                field_id: 3,
+                field_size: 2,
+                expected_field_size: 4
+            }
+        ));
+
+        let error = open_broken("broken_kdbx4_truncated_kdf_parameters.kdbx");
+        assert!(matches!(
+            error,
+            DatabaseIntegrityError::VariantDictionary(VariantDictionaryError::Incomplete)
+        ));
+
+        let error = open_broken("broken_kdbx4_truncated_block.kdbx");
+        assert!(matches!(
+            error,
+            DatabaseIntegrityError::BlockStream(BlockStreamError::IncompleteBlock { block_index: 0 })
+        ));
+    }
+
+    /// Every prefix of a database has to be rejected with an error
+    #[test]
+    fn open_truncated_databases() {
+        for (path, password) in [
+            ("tests/resources/test_db_kdb_with_password.kdb", "foobar"),
+            ("tests/resources/test_db_with_password.kdbx", "demopass"),
+            ("tests/resources/test_db_kdbx4_with_password_aes.kdbx", "demopass"),
+        ] {
+            let data = std::fs::read(path).unwrap();
+            // every position in the header, then a sample of positions in the payload
+            let positions = (0..256).chain((256..data.len()).step_by(127));
+            for length in positions.filter(|length| *length < data.len()) {
+                let key = DatabaseKey::new().with_password(password);
+                let result = Database::parse(&data[..length], key);
+                assert!(result.is_err(), "{path} truncated to {length} bytes was opened");
+            }
+        }
+    }
+
     #[test]
     fn open_kdb_with_password() -> Result<(), DatabaseOpenError> {
         let path = Path::new("tests/resources/test_db_kdb_with_password.kdb");
@@ -284,13 +360,50 @@ mod file_read_tests {
         }
 
         assert_eq!(total_groups, 12);
-        assert_eq!(total_entries, 5);
+        assert_eq!(total_entries, 3);
+
+        // the meta-streams of KeePass 1.x are kept apart from the entries
+        let meta_streams: Vec<&str> = db.meta.kdb_meta_streams.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(meta_streams, vec!["Simple UI State", "Default User Name"]);
+
+        let entry = Group::get(&db.root, &["depth0", "depth1.1", "depth2", "title"]).unwrap();
+        let entry = entry.borrow();
+        let entry = entry.as_any().downcast_ref::<Entry>().unwrap();
+        assert_eq!(entry.get_notes(), Some("notes"));
+        assert_eq!(entry.get_url(), Some("url"));
+        let created = chrono::NaiveDate::from_ymd_opt(2020, 5, 26).unwrap().and_hms_opt(16, 12, 53);
+        assert_eq!(entry.get_times().get_creation(), created);
+        assert!(!entry.get_times().get_expires());
+
+        // groups get random UUIDs instead of UUIDs made from their IDs
+        let group = Group::get(&db.root, &["depth0"]).unwrap();
+        assert_ne!(group.borrow().get_uuid().as_u128(), 0x12f6_faef);
 
         println!("{:?}", db);
 
         Ok(())
     }
 
+    /// The file of `open_kdb_with_password`, encrypted again with a password containing characters
+    /// outside of ASCII, which KeePass 1.x encodes in Windows-1252
+    #[test]
+    fn open_kdb_with_accented_password() -> Result<(), DatabaseOpenError> {
+        let path = Path::new("tests/resources/test_db_kdb_with_accented_password.kdb");
+        let key = DatabaseKey::new().with_password("Pässwörd€");
+        let db = Database::open(&mut File::open(path)?, key.clone())?;
+        assert_eq!(group_get_children(&db.root).unwrap().len(), 3);
+
+        let db = Database::open(
+            &mut File::open(path)?,
+            key.clone().with_password_encoding(PasswordEncoding::Windows1252),
+        )?;
+        assert_eq!(group_get_children(&db.root).unwrap().len(), 3);
+
+        let result = Database::open(&mut File::open(path)?, key.with_password_encoding(PasswordEncoding::Utf8));
+        assert!(matches!(result, Err(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey))));
+        Ok(())
+    }
+
     #[test]
     fn open_kdb_with_larger_than_1mb_file_does_not_crash() -> Result<(), DatabaseOpenError> {
         let path = Path::new("tests/resources/test_db_kdb3_with_file_larger_1mb.kdbx");
@@ -318,6 +431,13 @@ mod file_read_tests {
         assert_eq!(total_groups, 1);
         assert_eq!(total_entries, 1);
 
+        let entry = &group_get_children(&db.root).unwrap()[0];
+        let entry = entry.borrow();
+        let attachments = entry.as_any().downcast_ref::<Entry>().unwrap().get_attachments();
+        assert_eq!(attachments.len(), 1);
+        assert!(attachments[0].get_content().len() > 1024 * 1024);
+        assert!(db.meta.binaries.binaries.is_empty());
+
         println!("{:?}", db);
         Ok(())
     }
@@ -391,6 +511,179 @@ mod file_read_tests {
         Ok(())
     }
 
+    #[test]
+    fn open_kdbx4_with_progress() -> Result<(), DatabaseOpenError> {
+        let path = Path::new("tests/resources/test_db_kdbx4_with_password_aes.kdbx");
+        let key = DatabaseKey::new().with_password("demopass");
+        let mut updates = Vec::new();
+        let db = Database::open_with_progress(
+            &mut File::open(path)?,
+            key,
+            &mut |progress| updates.push(progress),
+            &CancellationToken::new(),
+        )?;
+
+        assert_eq!(db.root.borrow().get_title().unwrap(), "Root");
+        let rounds = updates
+            .iter()
+            .find_map(|progress| progress.total.filter(|_| progress.stage == ProgressStage::KeyDerivation));
+        assert!(updates.contains(&Progress {
+            stage: ProgressStage::KeyDerivation,
+            completed: rounds.unwrap(),
+            total: rounds,
+        }));
+        let decrypted = updates
+            .iter()
+            .rev()
+            .find(|progress| progress.stage == ProgressStage::Decryption)
+            .unwrap();
+        assert!(decrypted.completed > 0 && decrypted.completed < std::fs::metadata(path)?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_with_transformed_key() -> Result<(), DatabaseOpenError> {
+        for (path, password) in [
+            ("tests/resources/test_db_kdb_with_password.kdb", "foobar"),
+            ("tests/resources/test_db_with_password.kdbx", "demopass"),
+            ("tests/resources/test_db_kdbx4_with_password_aes.kdbx", "demopass"),
+            ("tests/resources/test_db_kdbx4_with_password_argon2.kdbx", "demopass"),
+        ] {
+            let key = DatabaseKey::new().with_password(password);
+            let (db, transformed_key) = Database::open_for_quick_unlock(&mut File::open(path)?, key)?;
+            assert_eq!(transformed_key.kdf_config(), &db.config.kdf_config);
+
+            // the key derivation is skipped, so it only reports a single step
+            let mut updates = Vec::new();
+            let key = DatabaseKey::new().with_transformed_key(transformed_key.clone());
+            let reopened = Database::open_with_progress(
+                &mut File::open(path)?,
+                key,
+                &mut |progress| updates.push(progress),
+                &CancellationToken::new(),
+            )?;
+            // KDB groups and entries get new UUIDs every time they are opened
+            assert_eq!(reopened.config, db.config, "{path}");
+            assert_eq!(
+                group_get_children(&reopened.root).map(|children| children.len()),
+                group_get_children(&db.root).map(|children| children.len()),
+                "{path}"
+            );
+            assert!(updates.contains(&Progress {
+                stage: ProgressStage::KeyDerivation,
+                completed: 1,
+                total: Some(1),
+            }));
+
+            // the transformed key does not unlock other databases
+            let key = DatabaseKey::new().with_transformed_key(transformed_key);
+            let result = Database::open(&mut File::open("tests/resources/test_db_kdbx4_with_keyfile_v2.kdbx")?, key);
+            assert!(
+                matches!(result, Err(DatabaseOpenError::Key(DatabaseKeyError::TransformedKeyMismatch))),
+                "{path}: {result:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_cancelled() -> Result<(), DatabaseOpenError> {
+        for (path, cancel_at) in [
+            (
+                "tests/resources/test_db_kdbx4_with_password_argon2.kdbx",
+                ProgressStage::KeyDerivation,
+            ),
+            ("tests/resources/test_db_kdbx4_with_password_aes.kdbx", ProgressStage::KeyDerivation),
+            ("tests/resources/test_db_kdbx4_with_password_aes.kdbx", ProgressStage::Decryption),
+            ("tests/resources/test_db_with_password.kdbx", ProgressStage::Decryption),
+        ] {
+            let key = DatabaseKey::new().with_password("demopass");
+            let token = CancellationToken::new();
+            let result = Database::open_with_progress(
+                &mut File::open(path)?,
+                key,
+                &mut |progress| {
+                    if progress.stage == cancel_at {
+                        token.cancel();
+                    }
+                },
+                &token,
+            );
+            assert!(matches!(result, Err(DatabaseOpenError::Cancelled)), "{path}: {result:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_with_limits() -> Result<(), DatabaseOpenError> {
+        let open = |path: &str, password: &str, limits: OpenLimits| -> Result<Database, DatabaseOpenError> {
+            let key = DatabaseKey::new().with_password(password);
+            Database::open_with_limits(&mut File::open(path)?, key, &limits)
+        };
+        let argon2 = "tests/resources/test_db_kdbx4_with_password_argon2.kdbx";
+        let large_attachment = "tests/resources/test_db_kdb3_with_file_larger_1mb.kdbx";
+
+        open(argon2, "demopass", OpenLimits::untrusted())?;
+        open(large_attachment, "samplepassword", OpenLimits::untrusted())?;
+
+        let limits = OpenLimits {
+            max_argon2_memory: Some(1024),
+            ..OpenLimits::default()
+        };
+        assert!(matches!(
+            open(argon2, "demopass", limits),
+            Err(DatabaseOpenError::ResourceLimit(ResourceLimitError::Argon2Memory {
+                limit: 1024,
+                ..
+            }))
+        ));
+
+        let limits = OpenLimits {
+            max_payload_size: Some(1024),
+            ..OpenLimits::default()
+        };
+        assert!(matches!(
+            open(argon2, "demopass", limits.clone()),
+            Err(DatabaseOpenError::ResourceLimit(ResourceLimitError::PayloadSize { limit: 1024 }))
+        ));
+        assert!(matches!(
+            open(large_attachment, "samplepassword", limits),
+            Err(DatabaseOpenError::ResourceLimit(ResourceLimitError::PayloadSize { limit: 1024 }))
+        ));
+
+        let limits = OpenLimits {
+            max_attachment_size: Some(1024 * 1024),
+            ..OpenLimits::default()
+        };
+        assert!(matches!(
+            open(large_attachment, "samplepassword", limits),
+            Err(DatabaseOpenError::ResourceLimit(ResourceLimitError::AttachmentSize { .. }))
+        ));
+
+        let limits = OpenLimits {
+            max_entries: Some(0),
+            ..OpenLimits::default()
+        };
+        assert!(matches!(
+            open("tests/resources/test_db_kdb_with_password.kdb", "foobar", limits),
+            Err(DatabaseOpenError::ResourceLimit(ResourceLimitError::Entries { limit: 0 }))
+        ));
+
+        // databases of the common clients do not contain unknown XML
+        let open_strict = |path: &str, password: &str| -> Result<Database, DatabaseOpenError> {
+            let key = DatabaseKey::new().with_password(password);
+            Database::open_strict(&mut File::open(path)?, key, &OpenLimits::untrusted())
+        };
+        open_strict(argon2, "demopass")?;
+        open_strict(large_attachment, "samplepassword")?;
+        open_strict("tests/resources/test_db_kdbx4_with_totp_entry.kdbx", "test")?;
+
+        Ok(())
+    }
+
     #[test]
     fn test_get_version() -> Result<(), DatabaseIntegrityError> {
         let path = Path::new("tests/resources/test_db_with_password.kdbx");
@@ -403,4 +696,52 @@ mod file_read_tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_get_header_info() -> Result<(), DatabaseOpenError> {
+        use keepass_ng::config::{CompressionConfig, DatabaseVersion, HeaderAlgorithm, InnerCipherConfig, KdfConfig, OuterCipherConfig};
+
+        let path = Path::new("tests/resources/test_db_kdb_with_password.kdb");
+        let info = Database::get_header_info(&mut File::open(path)?)?;
+        assert!(matches!(info.version, DatabaseVersion::KDB(_)));
+        assert_eq!(info.outer_cipher_config, HeaderAlgorithm::Known(OuterCipherConfig::AES256));
+        assert_eq!(info.kdf_config, HeaderAlgorithm::Known(KdfConfig::Aes { rounds: 6000 }));
+
+        let path = Path::new("tests/resources/test_db_with_password.kdbx");
+        let info = Database::get_header_info(&mut File::open(path)?)?;
+        assert_eq!(info.version, DatabaseVersion::KDB3(1));
+        assert_eq!(info.compression_config, CompressionConfig::GZip);
+        assert_eq!(info.inner_cipher_config, Some(InnerCipherConfig::Salsa20));
+        assert_eq!(info.comment, None);
+
+        // the inner cipher of KDBX4 is only known after decryption
+        let path = Path::new("tests/resources/test_db_kdbx4_with_password_argon2id_chacha20.kdbx");
+        let info = Database::get_header_info(&mut File::open(path)?)?;
+        assert_eq!(info.version, DatabaseVersion::KDB4(0));
+        assert_eq!(info.outer_cipher_config, HeaderAlgorithm::Known(OuterCipherConfig::ChaCha20));
+        assert_eq!(info.inner_cipher_config, None);
+        assert!(matches!(
+            info.kdf_config,
+            HeaderAlgorithm::Known(KdfConfig::Argon2id { iterations: 1, .. })
+        ));
+
+        // only the header is read, and ciphers and KDFs of plugins that are not registered are reported
+        let mut data = std::fs::read(path)?;
+        let chacha20 = hex_literal::hex!("d6038a2b8b6f4cb5a524339a31dbb59a");
+        let argon2id = hex_literal::hex!("9e298b1956db4773b23dfc3ec6f0a1e6");
+        for (id, unknown) in [(chacha20, [0x11; 16]), (argon2id, [0x22; 16])] {
+            let position = data.windows(16).position(|window| window == id).unwrap();
+            data[position..position + 16].copy_from_slice(&unknown);
+        }
+        let mut source = data.as_slice();
+        let info = Database::get_header_info(&mut source)?;
+        assert_eq!(info.outer_cipher_config, HeaderAlgorithm::Unknown(vec![0x11; 16]));
+        assert_eq!(info.kdf_config, HeaderAlgorithm::Unknown(vec![0x22; 16]));
+        assert!(!source.is_empty());
+
+        let path = Path::new("tests/resources/broken_random_data.kdbx");
+        assert!(Database::get_header_info(&mut File::open(path)?).is_err());
+
+        Ok(())
+    }
 }
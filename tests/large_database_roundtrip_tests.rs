@@ -27,7 +27,7 @@ mod large_file_roundtrip_tests {
                 entry.set_username(Some(&format!("UserName_{i}")));
                 entry.set_password(Some(&format!("Password_{i}")));
             }
-            group_add_child(&mut db.root, entry, i)?;
+            group_add_child(&db.root, entry, i)?;
         }
 
         // Define database key.
@@ -0,0 +1,102 @@
+mod memory_encryption_tests {
+    use keepass_ng::{
+        db::{group_add_child, Database, Entry, EntryQuery, Group, Node, NodePtr},
+        protected::ProtectedValue,
+        rc_refcell_node, DatabaseKey,
+    };
+    use std::fs::File;
+
+    fn open_sample_database() -> Result<Database, Box<dyn std::error::Error>> {
+        let key = DatabaseKey::new().with_password("demopass");
+        Ok(Database::open(&mut File::open("tests/resources/test_db_with_password.kdbx")?, key)?)
+    }
+
+    fn with_entry<T>(db: &Database, title: &str, f: impl FnOnce(&mut Entry) -> T) -> T {
+        let node = Group::get(&db.root, &[title]).unwrap();
+        let mut entry = node.borrow_mut();
+        f(entry.as_any_mut().downcast_mut::<Entry>().unwrap())
+    }
+
+    fn check_sample_entry(db: &Database) {
+        with_entry(db, "Sample Entry", |entry| {
+            assert_eq!(entry.get_title(), Some("Sample Entry"));
+            assert_eq!(entry.get_password(), None);
+            assert_eq!(entry.reveal_password().as_deref(), Some("Password"));
+            assert_eq!(entry.reveal("UserName").as_deref(), Some("User Name"));
+        });
+    }
+
+    #[test]
+    fn open_with_memory_encryption() -> Result<(), Box<dyn std::error::Error>> {
+        let db = open_sample_database()?;
+        db.set_memory_encryption(true)?;
+        check_sample_entry(&db);
+
+        // other databases and new values are not affected
+        let other = open_sample_database()?;
+        assert_eq!(
+            with_entry(&other, "Sample Entry", |entry| entry.get_password().map(str::to_string)).as_deref(),
+            Some("Password")
+        );
+        assert!(!ProtectedValue::new("secret").is_sealed());
+
+        // searching and field references see the sealed values
+        let matches = db.search(&EntryQuery::new().field_contains("Password", "passw"));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].entry.borrow().get_title(), Some("Sample Entry"));
+
+        let mut entry = Entry::default();
+        entry.set_password(Some("{REF:P@T:Sample Entry}"));
+        assert_eq!(entry.get_password_resolved(&db)?.as_deref(), Some("Password"));
+
+        // including protected URLs of entries added later on
+        let mut web_entry = Entry::default();
+        web_entry.set_title(Some("Web Entry"));
+        web_entry.set_field("URL", "https://login.example.com/", true);
+        group_add_child(&db.root, rc_refcell_node!(web_entry), usize::MAX)?;
+        db.set_memory_encryption(true)?;
+        with_entry(&db, "Web Entry", |entry| assert_eq!(entry.get_url(), None));
+        let matches = db.search(&EntryQuery::new().url_host("example.com"));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].entry.borrow().get_title(), Some("Web Entry"));
+
+        // fields that are set again stay encrypted
+        with_entry(&db, "Sample Entry", |entry| {
+            entry.set_password(Some("Password"));
+            assert_eq!(entry.get_password(), None);
+        });
+
+        #[cfg(feature = "save_kdbx4")]
+        {
+            let key = DatabaseKey::new().with_password("demopass");
+            let mut data = Vec::new();
+            db.save(&mut data, key.clone())?;
+            let saved = Database::parse(&data, key)?;
+            saved.set_memory_encryption(true)?;
+            check_sample_entry(&saved);
+        }
+
+        // decrypting the fields makes them available to the getters again
+        db.set_memory_encryption(false)?;
+        assert_eq!(
+            with_entry(&db, "Sample Entry", |entry| entry.get_password().map(str::to_string)).as_deref(),
+            Some("Password")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_entries_by_protected_title() -> Result<(), Box<dyn std::error::Error>> {
+        let db = open_sample_database()?;
+        with_entry(&db, "Sample Entry", |entry| entry.set_field("Title", "Protected Title", true));
+        db.set_memory_encryption(true)?;
+
+        let node: NodePtr = Group::get(&db.root, &["Protected Title"]).unwrap();
+        assert_eq!(node.borrow().get_title(), None);
+        with_entry(&db, "Protected Title", |entry| {
+            assert_eq!(entry.reveal("Title").as_deref(), Some("Protected Title"));
+        });
+        Ok(())
+    }
+}
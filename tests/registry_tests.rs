@@ -0,0 +1,126 @@
+// the algorithms are only useful when they are used to save databases, too
+#[cfg(feature = "save_kdbx4")]
+mod registry_tests {
+    use keepass_ng::{
+        config::{DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+        error::{CryptographyError, KdfConfigError},
+        registry::{self, Cipher, GenericArray, Kdf, VariantDictionary, U32},
+        Uuid,
+    };
+
+    const XOR_CIPHER: Uuid = Uuid::from_u128(0x6c1b_0e34_5f4e_4b8a_9a0f_0d6a_3f3e_0001);
+    const XOR_STREAM: u32 = 0x7800;
+    const XOR_KDF: Uuid = Uuid::from_u128(0x6c1b_0e34_5f4e_4b8a_9a0f_0d6a_3f3e_0002);
+
+    /// A toy cipher XOR-ing the data with the key and IV, which is its own inverse
+    struct XorCipher {
+        pad: Vec<u8>,
+    }
+
+    impl XorCipher {
+        fn new(key: &[u8], iv: &[u8]) -> Self {
+            let pad = key.iter().zip(iv.iter().cycle()).map(|(k, i)| k ^ i).collect();
+            XorCipher { pad }
+        }
+    }
+
+    impl Cipher for XorCipher {
+        fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+            Ok(plaintext.iter().zip(self.pad.iter().cycle()).map(|(b, p)| b ^ p).collect())
+        }
+
+        fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, CryptographyError> {
+            self.encrypt(ciphertext)
+        }
+
+        fn iv_size() -> usize {
+            8
+        }
+
+        fn key_size() -> usize {
+            32
+        }
+    }
+
+    /// A toy KDF XOR-ing the key with the seed and a parameter
+    struct XorKdf {
+        seed: Vec<u8>,
+        tweak: u8,
+    }
+
+    impl Kdf for XorKdf {
+        fn transform_key(&self, composite_key: &GenericArray<u8, U32>) -> Result<GenericArray<u8, U32>, CryptographyError> {
+            let mut key = *composite_key;
+            for (k, s) in key.iter_mut().zip(self.seed.iter().cycle()) {
+                *k ^= s ^ self.tweak;
+            }
+            Ok(key)
+        }
+    }
+
+    fn register() {
+        registry::register_outer_cipher(XOR_CIPHER, |key, iv| Ok(XorCipher::new(key, iv)));
+        registry::register_inner_cipher(XOR_STREAM, |key| Ok(XorCipher::new(key, &[0])));
+        registry::register_kdf(XOR_KDF, |parameters: &VariantDictionary| {
+            Ok::<_, KdfConfigError>(XorKdf {
+                seed: parameters.get::<Vec<u8>>("S")?.clone(),
+                tweak: *parameters.get::<u32>("T")? as u8,
+            })
+        });
+    }
+
+    fn registered_config() -> DatabaseConfig {
+        let mut parameters = VariantDictionary::new();
+        parameters.set("T", 42u32);
+        DatabaseConfig {
+            outer_cipher_config: OuterCipherConfig::Registered(XOR_CIPHER),
+            inner_cipher_config: InnerCipherConfig::Registered(XOR_STREAM),
+            kdf_config: KdfConfig::Registered { uuid: XOR_KDF, parameters },
+            ..DatabaseConfig::default()
+        }
+    }
+
+    #[test]
+    fn roundtrip_registered_algorithms() -> Result<(), Box<dyn std::error::Error>> {
+        use keepass_ng::{
+            db::{Database, Entry, Group, Node, NodePtr},
+            rc_refcell_node, DatabaseKey,
+        };
+
+        register();
+
+        let db = Database::new(registered_config());
+        let mut entry = Entry::default();
+        entry.set_title(Some("Plugin entry"));
+        entry.set_password(Some("secret"));
+        keepass_ng::db::group_add_child(&db.root, rc_refcell_node!(entry), 0)?;
+
+        let key = DatabaseKey::new().with_password("plugin");
+        let mut data = Vec::new();
+        db.save(&mut data, key.clone())?;
+
+        let reopened = Database::open(&mut data.as_slice(), key)?;
+        assert_eq!(reopened.config, registered_config());
+        let entry = Group::get(&reopened.root, &["Plugin entry"]).unwrap();
+        assert_eq!(
+            entry.borrow().as_any().downcast_ref::<Entry>().unwrap().get_password(),
+            Some("secret")
+        );
+
+        assert!(Database::open(&mut data.as_slice(), DatabaseKey::new().with_password("wrong")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn save_with_unregistered_cipher() {
+        use keepass_ng::{db::Database, error::DatabaseSaveError, DatabaseKey};
+
+        let db = Database::new(DatabaseConfig {
+            outer_cipher_config: OuterCipherConfig::Registered(Uuid::from_u128(1)),
+            ..registered_config()
+        });
+        let result = db.save(&mut Vec::new(), DatabaseKey::new().with_password("plugin"));
+        assert!(matches!(result, Err(DatabaseSaveError::OuterCipher(_))));
+    }
+}